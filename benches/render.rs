@@ -0,0 +1,53 @@
+// Render and save paths, so that a slower frame or save shows up in numbers
+//
+//     cargo bench
+//     cargo bench -- process_rows
+
+use bresson::{
+    globe::{Canvas, Globe},
+    state::Document,
+};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::path::{Path, PathBuf};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn render_sphere(c: &mut Criterion) {
+    let mut globe = Globe::new(1., 0., false);
+    globe.camera.update(1.5, 0., 0.);
+    // About the size of the globe panel in a 160x50 terminal
+    let (width, height) = (80, 40);
+    c.bench_function("render_sphere", |b| {
+        b.iter(|| {
+            let mut canvas = Canvas::new(width, height, Some((1, 1))).with_globe_width(width);
+            globe.render_sphere(&mut canvas);
+            canvas
+        })
+    });
+}
+
+fn process_rows(c: &mut Criterion) {
+    let mut document = Document::open(&fixture("gps.jpg")).unwrap();
+    document.randomize_all();
+    c.bench_function("process_rows", |b| {
+        b.iter(|| document.process_rows(160, true, "").len())
+    });
+}
+
+fn save_state(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("bresson-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let image = dir.join("gps.jpg");
+    std::fs::copy(fixture("gps.jpg"), &image).unwrap();
+    let mut document = Document::open(&image).unwrap();
+    document.clear_gps();
+    c.bench_function("save_state", |b| b.iter(|| document.save_state().unwrap()));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+criterion_group!(benches, render_sphere, process_rows, save_state);
+criterion_main!(benches);
@@ -0,0 +1,87 @@
+#ifndef BRESSON_H
+#define BRESSON_H
+
+/* Generated with cbindgen from src/ffi.rs, don't edit by hand */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// An image opened from a buffer, with the fields that will be written
+typedef struct BressonImage BressonImage;
+
+// Why the last call that failed on this thread failed, or NULL if none did.
+// The message stays valid until the next call that fails.
+const char *bresson_last_error(void);
+
+// Reads the metadata of the `len` bytes at `data`, the contents of an image.
+// The buffer is copied, it can be freed once this returns.
+//
+// # Safety
+//
+// `data` must point to `len` readable bytes.
+struct BressonImage *bresson_open(const uint8_t *data, size_t len);
+
+// Frees an image from `bresson_open`
+//
+// # Safety
+//
+// `image` must come from `bresson_open` and not have been freed, or be NULL.
+void bresson_free(struct BressonImage *image);
+
+// Number of tags the copy will be written with
+//
+// # Safety
+//
+// `image` must come from `bresson_open`.
+size_t bresson_tag_count(const struct BressonImage *image);
+
+// Name of the tag at `index`, e.g. "GPSLatitude". Free it with
+// `bresson_string_free`.
+//
+// # Safety
+//
+// `image` must come from `bresson_open`.
+char *bresson_tag_name(const struct BressonImage *image, size_t index);
+
+// Value of the tag at `index` as bresson shows it, with its unit. Free it
+// with `bresson_string_free`.
+//
+// # Safety
+//
+// `image` must come from `bresson_open`.
+char *bresson_tag_value(const struct BressonImage *image, size_t index);
+
+// Leaves out every tag whose name matches `pattern` from the copy, `*`
+// standing for any number of characters like in a policy, e.g. "GPS*".
+// Returns how many were cleared. Tags that point at other data in the file
+// can't be cleared and are kept.
+//
+// # Safety
+//
+// `image` must come from `bresson_open` and `pattern` be a NUL terminated string.
+int bresson_clear_tags(struct BressonImage *image, const char *pattern);
+
+// Writes a copy of the image with the remaining tags into a new buffer,
+// stored in `out` and its length in `out_len`. Free it with
+// `bresson_buffer_free`. Returns 0, or -1 if it couldn't be written.
+//
+// # Safety
+//
+// `image` must come from `bresson_open`, `out` and `out_len` must be writable.
+int bresson_write(const struct BressonImage *image, uint8_t **out, size_t *out_len);
+
+// Frees a string from `bresson_tag_name` or `bresson_tag_value`
+//
+// # Safety
+//
+// `s` must come from one of them and not have been freed, or be NULL.
+void bresson_string_free(char *s);
+
+// Frees a buffer from `bresson_write`, `len` being the length it was given with
+//
+// # Safety
+//
+// `buf` must come from `bresson_write` and not have been freed, or be NULL.
+void bresson_buffer_free(uint8_t *buf, size_t len);
+
+#endif  /* BRESSON_H */
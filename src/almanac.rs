@@ -0,0 +1,98 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use std::fmt::Display;
+
+// Almanac
+//
+// Calendar and astronomy facts derived from when and where a photo was taken.
+// They are only ever displayed, never written into the image.
+
+/// Length of a lunar cycle in days
+const SYNODIC_MONTH: f64 = 29.530_588_853;
+/// A known new moon, 2000-01-06 18:14 UTC, as a unix timestamp
+const NEW_MOON_EPOCH: i64 = 947_182_440;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoonPhase {
+    NewMoon,
+    WaxingCrescent,
+    FirstQuarter,
+    WaxingGibbous,
+    FullMoon,
+    WaningGibbous,
+    LastQuarter,
+    WaningCrescent,
+}
+
+impl Display for MoonPhase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            MoonPhase::NewMoon => "New Moon",
+            MoonPhase::WaxingCrescent => "Waxing Crescent",
+            MoonPhase::FirstQuarter => "First Quarter",
+            MoonPhase::WaxingGibbous => "Waxing Gibbous",
+            MoonPhase::FullMoon => "Full Moon",
+            MoonPhase::WaningGibbous => "Waning Gibbous",
+            MoonPhase::LastQuarter => "Last Quarter",
+            MoonPhase::WaningCrescent => "Waning Crescent",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Phase of the moon at `time` and the illuminated fraction of its disc
+pub fn moon_phase(time: DateTime<Utc>) -> (MoonPhase, f64) {
+    let days = (time.timestamp() - NEW_MOON_EPOCH) as f64 / 86_400.;
+    let age = days.rem_euclid(SYNODIC_MONTH) / SYNODIC_MONTH;
+    let illumination = (1. - (age * 2. * std::f64::consts::PI).cos()) / 2.;
+    let phase = match (age * 8.).round() as u8 % 8 {
+        0 => MoonPhase::NewMoon,
+        1 => MoonPhase::WaxingCrescent,
+        2 => MoonPhase::FirstQuarter,
+        3 => MoonPhase::WaxingGibbous,
+        4 => MoonPhase::FullMoon,
+        5 => MoonPhase::WaningGibbous,
+        6 => MoonPhase::LastQuarter,
+        _ => MoonPhase::WaningCrescent,
+    };
+    (phase, illumination)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Display for Season {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Season::Spring => "Spring",
+            Season::Summer => "Summer",
+            Season::Autumn => "Autumn",
+            Season::Winter => "Winter",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Astronomical season on `date`, which is flipped south of the equator
+pub fn season(date: NaiveDate, southern: bool) -> Season {
+    // Approximate equinox and solstice dates
+    let northern = match (date.month(), date.day()) {
+        (3, 20..) | (4..=5, _) | (6, ..=20) => Season::Spring,
+        (6, 21..) | (7..=8, _) | (9, ..=21) => Season::Summer,
+        (9, 22..) | (10..=11, _) | (12, ..=20) => Season::Autumn,
+        _ => Season::Winter,
+    };
+    if !southern {
+        return northern;
+    }
+    match northern {
+        Season::Spring => Season::Autumn,
+        Season::Summer => Season::Winter,
+        Season::Autumn => Season::Spring,
+        Season::Winter => Season::Summer,
+    }
+}
@@ -0,0 +1,648 @@
+use anyhow::{bail, Context as _, Result};
+use chrono::NaiveDateTime;
+use exif::{Field, In, Tag, Value};
+use id3::TagLike;
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::{backend::MetadataBackend, batch, utils, vault::Manifest};
+
+// Audio
+//
+// Tags of MP3 and FLAC files, which name the artist and the recording date just
+// like Exif does for photos, and field recorders add the location to.
+//
+// MP3 files carry an ID3v2 tag in front of the audio, made of frames like TPE1
+// (artist) or TXXX (any named text), and possibly an old ID3v1 tag at the end.
+// FLAC files keep Vorbis comments, `KEY=value` pairs in one of the metadata
+// blocks that follow the `fLaC` marker, and embedded pictures in others. The
+// pictures are removed when scrubbing, since they are images with their own
+// metadata.
+//
+// As a backend, frames and comments are shown and edited as the Exif tags
+// closest to them, see `ID3_TAGS` and `VORBIS_TAGS`, and a named location as
+// GPSAreaInformation. Copies keep what has no tag but is harmless, like the
+// album or the track number, and drop the rest along with the pictures and any
+// ID3v1 tag, since they can't be shown.
+//
+// https://id3.org/id3v2.4.0-frames
+// https://xiph.org/flac/format.html#metadata_block
+
+pub const EXTENSIONS: [&str; 2] = ["mp3", "flac"];
+
+/// ID3v2 frames that name people or say when something was recorded
+const SENSITIVE_FRAMES: [&str; 15] = [
+    "TPE1", "TPE2", "TPE3", "TPE4", "TCOM", "TEXT", "TOLY", "TOPE", "TENC", "TDRC", "TDRL", "TDOR",
+    "TYER", "TDAT", "TIME",
+];
+/// Vorbis comments that do the same
+const SENSITIVE_COMMENTS: [&str; 9] = [
+    "ARTIST",
+    "PERFORMER",
+    "COMPOSER",
+    "LYRICIST",
+    "ENCODED-BY",
+    "DATE",
+    "LOCATION",
+    "CONTACT",
+    "COPYRIGHT",
+];
+
+/// ID3v2 frames and the tags they're shown as. TSSE is the encoding software,
+/// TENC the person who encoded the file.
+const ID3_TAGS: [(&str, Tag); 6] = [
+    ("TIT2", utils::XP_TAGS[0]),
+    ("TPE1", Tag::Artist),
+    ("TCOP", Tag::Copyright),
+    ("TSSE", Tag::Software),
+    ("TDRC", Tag::DateTimeOriginal),
+    ("COMM", utils::XP_TAGS[1]),
+];
+/// Vorbis comments and the tags they're shown as
+const VORBIS_TAGS: [(&str, Tag); 6] = [
+    ("TITLE", utils::XP_TAGS[0]),
+    ("ARTIST", Tag::Artist),
+    ("COPYRIGHT", Tag::Copyright),
+    ("ENCODER", Tag::Software),
+    ("DATE", Tag::DateTimeOriginal),
+    ("COMMENT", utils::XP_TAGS[1]),
+];
+
+const FLAC_MARKER: &[u8] = b"fLaC";
+const FLAC_VORBIS_COMMENT: u8 = 4;
+const FLAC_PICTURE: u8 = 6;
+
+/// Whether `file` is an MP3 or FLAC file, by its first bytes. MP3 files start
+/// with an ID3v2 tag or right away with the sync bits of an audio frame.
+pub fn is_mp3_or_flac(file: &[u8]) -> bool {
+    match file {
+        [b'I', b'D', b'3', ..] => true,
+        [0xFF, sync, ..] => sync & 0xE0 == 0xE0,
+        _ => file.starts_with(FLAC_MARKER),
+    }
+}
+
+pub fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Audio files in `paths`, searching directories recursively
+pub fn collect_audio(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    batch::collect_files(paths, is_audio)
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioTag {
+    /// Frame ID or comment name, which `--keep` refers to
+    pub key: String,
+    pub value: String,
+    /// Names someone, says when or where it was recorded
+    pub sensitive: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    pub removed: Vec<String>,
+    pub output: Option<PathBuf>,
+}
+
+fn is_flac(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("flac"))
+}
+
+/// Mentions of a location in a free form name, e.g. a TXXX description
+fn is_location(name: &str) -> bool {
+    let name = name.to_uppercase();
+    ["GPS", "LOCATION", "LATITUDE", "LONGITUDE", "COORDINATES"]
+        .iter()
+        .any(|word| name.contains(word))
+}
+
+pub fn read_tags(path: &Path) -> Result<Vec<AudioTag>> {
+    if is_flac(path) {
+        let data = std::fs::read(path)?;
+        return Ok(parse_flac(&data)?
+            .iter()
+            .flat_map(|block| flac_tags(block))
+            .collect());
+    }
+
+    let mut tags = Vec::new();
+    match id3::Tag::read_from_path(path) {
+        Ok(tag) => tags.extend(tag.frames().map(|frame| AudioTag {
+            key: frame.id().to_string(),
+            value: frame.content().to_string(),
+            sensitive: is_sensitive_frame(frame),
+        })),
+        Err(id3::Error {
+            kind: id3::ErrorKind::NoTag,
+            ..
+        }) => {}
+        Err(e) => return Err(e).context("Invalid ID3 tag"),
+    }
+    let file = std::fs::File::open(path)?;
+    if let Ok(v1) = id3::v1::Tag::read_from(file) {
+        for (name, value, sensitive) in [
+            ("Title", &v1.title, false),
+            ("Artist", &v1.artist, true),
+            ("Album", &v1.album, false),
+            ("Year", &v1.year, true),
+            ("Comment", &v1.comment, true),
+        ] {
+            if !value.trim().is_empty() {
+                tags.push(AudioTag {
+                    key: format!("ID3v1 {}", name),
+                    value: value.trim().to_string(),
+                    sensitive,
+                });
+            }
+        }
+    }
+    Ok(tags)
+}
+
+fn is_sensitive_frame(frame: &id3::Frame) -> bool {
+    SENSITIVE_FRAMES.contains(&frame.id())
+        || match frame.content() {
+            id3::Content::ExtendedText(text) => is_location(&text.description),
+            id3::Content::EncapsulatedObject(_) | id3::Content::Comment(_) => true,
+            _ => false,
+        }
+}
+
+fn is_sensitive_comment(key: &str) -> bool {
+    SENSITIVE_COMMENTS.contains(&key) || is_location(key)
+}
+
+/// Scrubs the tags of `path` into a copy, keeping the `keep` ones. An ID3v1 tag
+/// is always removed, it's too small to be worth keeping parts of.
+pub fn scrub_file(
+    path: &Path,
+    keep: &[String],
+    out_dir: Option<&Path>,
+    dry_run: bool,
+) -> Result<ScrubReport> {
+    let mut report = ScrubReport {
+        removed: read_tags(path)?
+            .into_iter()
+            .map(|tag| tag.key)
+            .filter(|key| !keep.contains(key) || key.starts_with("ID3v1"))
+            .collect(),
+        output: None,
+    };
+    if dry_run || report.removed.is_empty() {
+        return Ok(report);
+    }
+
+    let output = batch::output_path(path, out_dir)?;
+    if is_flac(path) {
+        std::fs::write(&output, scrub_flac(&std::fs::read(path)?, keep)?)?;
+    } else {
+        std::fs::copy(path, &output)?;
+        scrub_mp3(&output, keep)?;
+    }
+    report.output = Some(output);
+    Ok(report)
+}
+
+fn scrub_mp3(path: &Path, keep: &[String]) -> Result<()> {
+    id3::v1::Tag::remove_from_path(path)?;
+    let tag = match id3::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(id3::Error {
+            kind: id3::ErrorKind::NoTag,
+            ..
+        }) => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    id3::Tag::remove_from_path(path)?;
+
+    let mut kept = id3::Tag::new();
+    for frame in tag.frames().filter(|f| keep.iter().any(|k| k == f.id())) {
+        kept.add_frame(frame.clone());
+    }
+    if kept.frames().next().is_some() {
+        kept.write_to_path(path, tag.version())?;
+    }
+    Ok(())
+}
+
+/// Name and value of a Vorbis comment
+type Comment = (String, String);
+
+struct FlacBlock<'a> {
+    kind: u8,
+    data: &'a [u8],
+}
+
+/// Metadata blocks of a FLAC file, followed by a block holding the audio frames
+fn parse_flac(data: &[u8]) -> Result<Vec<FlacBlock<'_>>> {
+    // Some taggers put an ID3v2 tag in front anyway
+    let start = match data {
+        [b'I', b'D', b'3', _, _, flags, size @ ..] if size.len() >= 4 => {
+            let size = size[..4]
+                .iter()
+                .fold(0usize, |acc, b| (acc << 7) | usize::from(b & 0x7F));
+            let footer = if flags & 0x10 != 0 { 10 } else { 0 };
+            10 + size + footer
+        }
+        _ => 0,
+    };
+    if data.get(start..start + 4) != Some(FLAC_MARKER) {
+        bail!("Not a FLAC file");
+    }
+
+    let mut blocks = Vec::new();
+    let mut offset = start + 4;
+    loop {
+        let Some(header) = data.get(offset..offset + 4) else {
+            bail!("Truncated FLAC metadata");
+        };
+        let last = header[0] & 0x80 != 0;
+        let length =
+            usize::from(header[1]) << 16 | usize::from(header[2]) << 8 | usize::from(header[3]);
+        let Some(block) = data.get(offset + 4..offset + 4 + length) else {
+            bail!("Truncated FLAC metadata");
+        };
+        blocks.push(FlacBlock {
+            kind: header[0] & 0x7F,
+            data: block,
+        });
+        offset += 4 + length;
+        if last {
+            break;
+        }
+    }
+    // Anything that isn't metadata is kept as it is
+    blocks.push(FlacBlock {
+        kind: u8::MAX,
+        data: &data[offset..],
+    });
+    Ok(blocks)
+}
+
+/// `KEY=value` comments of a Vorbis comment block
+fn vorbis_comments(block: &[u8]) -> Option<(&[u8], Vec<Comment>)> {
+    let read_u32 = |at: usize| -> Option<usize> {
+        Some(u32::from_le_bytes(block.get(at..at + 4)?.try_into().ok()?) as usize)
+    };
+    let vendor_length = read_u32(0)?;
+    let vendor = block.get(4..4 + vendor_length)?;
+    let count = read_u32(4 + vendor_length)?;
+    let mut offset = 8 + vendor_length;
+    let mut comments = Vec::new();
+    for _ in 0..count {
+        let length = read_u32(offset)?;
+        let comment = String::from_utf8_lossy(block.get(offset + 4..offset + 4 + length)?);
+        let (key, value) = comment.split_once('=').unwrap_or((&comment, ""));
+        comments.push((key.to_uppercase(), value.to_string()));
+        offset += 4 + length;
+    }
+    Some((vendor, comments))
+}
+
+fn flac_tags(block: &FlacBlock) -> Vec<AudioTag> {
+    match block.kind {
+        FLAC_VORBIS_COMMENT => vorbis_comments(block.data).map_or(Vec::new(), |(_, comments)| {
+            comments
+                .into_iter()
+                .map(|(key, value)| AudioTag {
+                    sensitive: is_sensitive_comment(&key),
+                    key,
+                    value,
+                })
+                .collect()
+        }),
+        FLAC_PICTURE => vec![AudioTag {
+            key: String::from("PICTURE"),
+            value: format!("{} bytes", block.data.len()),
+            sensitive: true,
+        }],
+        _ => Vec::new(),
+    }
+}
+
+fn scrub_flac(data: &[u8], keep: &[String]) -> Result<Vec<u8>> {
+    let keep_pictures = keep.iter().any(|k| k == "PICTURE");
+    rewrite_flac(data, keep_pictures, |comments| {
+        comments
+            .iter()
+            .filter(|(key, _)| keep.contains(key))
+            .cloned()
+            .collect()
+    })
+}
+
+/// Copy of the FLAC file `data` with the Vorbis comments `comments` returns for
+/// the ones it has
+fn rewrite_flac(
+    data: &[u8],
+    keep_pictures: bool,
+    comments: impl Fn(&[Comment]) -> Vec<Comment>,
+) -> Result<Vec<u8>> {
+    let blocks = parse_flac(data)?;
+    let mut metadata = Vec::new();
+    for block in &blocks[..blocks.len() - 1] {
+        match block.kind {
+            FLAC_VORBIS_COMMENT => {
+                let Some((vendor, old)) = vorbis_comments(block.data) else {
+                    continue;
+                };
+                let kept = comments(&old);
+                let mut rebuilt = Vec::new();
+                rebuilt.extend((vendor.len() as u32).to_le_bytes());
+                rebuilt.extend(vendor);
+                rebuilt.extend((kept.len() as u32).to_le_bytes());
+                for (key, value) in kept {
+                    let comment = format!("{}={}", key, value);
+                    rebuilt.extend((comment.len() as u32).to_le_bytes());
+                    rebuilt.extend(comment.as_bytes());
+                }
+                metadata.push((block.kind, rebuilt));
+            }
+            FLAC_PICTURE if !keep_pictures => {}
+            kind => metadata.push((kind, block.data.to_vec())),
+        }
+    }
+
+    // The ID3v2 tag in front, if there was one, is left behind
+    let mut output = FLAC_MARKER.to_vec();
+    let count = metadata.len();
+    for (i, (kind, block)) in metadata.into_iter().enumerate() {
+        let last = if i + 1 == count { 0x80 } else { 0 };
+        output.push(kind | last);
+        output.extend(&(block.len() as u32).to_be_bytes()[1..]);
+        output.extend(block);
+    }
+    output.extend(blocks[blocks.len() - 1].data);
+    Ok(output)
+}
+
+/// The tags of an MP3 or FLAC file as fields
+pub struct AudioBackend {
+    fields: Vec<Field>,
+    flac: bool,
+}
+
+impl AudioBackend {
+    pub fn parse(file: &[u8]) -> Result<Self> {
+        let mut fields: Vec<Field> = Vec::new();
+        // Only the first frame or comment for a tag is shown
+        let mut push = |tag: Tag, text: &str| {
+            if !fields.iter().any(|f| f.tag == tag) {
+                fields.push(to_field(tag, text));
+            }
+        };
+        let flac = parse_flac(file).is_ok();
+        if flac {
+            let blocks = parse_flac(file)?;
+            let comments = blocks
+                .iter()
+                .filter(|block| block.kind == FLAC_VORBIS_COMMENT)
+                .filter_map(|block| vorbis_comments(block.data));
+            for (_, comments) in comments {
+                for (key, value) in comments {
+                    if let Some(tag) = comment_tag(&key) {
+                        push(tag, &value);
+                    }
+                }
+            }
+        } else {
+            for frame in read_id3(file)?.frames() {
+                if let Some(tag) = frame_tag(frame) {
+                    push(tag, &frame_text(frame));
+                }
+            }
+        }
+        Ok(Self { fields, flac })
+    }
+}
+
+impl MetadataBackend for AudioBackend {
+    fn name(&self) -> &'static str {
+        match self.flac {
+            true => "Vorbis",
+            false => "ID3",
+        }
+    }
+
+    fn fields(&self) -> Vec<Field> {
+        self.fields.clone()
+    }
+
+    fn is_editable(&self, tag: Tag) -> bool {
+        // Frames and comments are shown as the same tags
+        tag == Tag::GPSAreaInformation || ID3_TAGS.iter().any(|(_, t)| *t == tag)
+    }
+
+    fn display(&self, field: &Field) -> String {
+        match &field.value {
+            Value::Undefined(bytes, _) => utils::decode_encoded_text(bytes, false),
+            _ => utils::display_text(field),
+        }
+    }
+
+    fn write(&self, file: &[u8], fields: &mut dyn Iterator<Item = &Field>) -> Result<Vec<u8>> {
+        let fields: Vec<&Field> = fields.filter(|f| self.is_editable(f.tag)).collect();
+        match self.flac {
+            true => write_flac(file, &fields),
+            false => write_id3(file, &fields),
+        }
+    }
+
+    fn manifest(&self, source: &Path) -> Manifest {
+        Manifest::from_fields(source, &self.fields, false)
+    }
+}
+
+/// The ID3v2 tag at the start of `file`, an empty one if it has none
+fn read_id3(file: &[u8]) -> Result<id3::Tag> {
+    match id3::Tag::read_from2(Cursor::new(file)) {
+        Ok(tag) => Ok(tag),
+        Err(id3::Error {
+            kind: id3::ErrorKind::NoTag,
+            ..
+        }) => Ok(id3::Tag::new()),
+        Err(e) => Err(e).context("Invalid ID3 tag"),
+    }
+}
+
+fn frame_tag(frame: &id3::Frame) -> Option<Tag> {
+    match frame.content() {
+        id3::Content::ExtendedText(text) if is_location(&text.description) => {
+            Some(Tag::GPSAreaInformation)
+        }
+        _ => ID3_TAGS
+            .iter()
+            .find(|(id, _)| *id == frame.id())
+            .map(|(_, tag)| *tag),
+    }
+}
+
+fn frame_text(frame: &id3::Frame) -> String {
+    match frame.content() {
+        // Text frames separate several values with NULs
+        id3::Content::Text(text) => text.replace('\0', ", "),
+        id3::Content::Comment(comment) => comment.text.clone(),
+        id3::Content::ExtendedText(text) => text.value.clone(),
+        content => content.to_string(),
+    }
+}
+
+fn comment_tag(key: &str) -> Option<Tag> {
+    match VORBIS_TAGS.iter().find(|(k, _)| *k == key) {
+        Some((_, tag)) => Some(*tag),
+        None if is_location(key) => Some(Tag::GPSAreaInformation),
+        None => None,
+    }
+}
+
+/// Whether `field` holds `text`, the value it was read from. Untouched values
+/// are written back as they were, with their encoding and precision.
+fn is_unchanged(field: &Field, text: &str) -> bool {
+    from_field(&to_field(field.tag, text)) == from_field(field)
+}
+
+fn write_id3(file: &[u8], fields: &[&Field]) -> Result<Vec<u8>> {
+    let old = read_id3(file)?;
+    let mut tag = id3::Tag::new();
+    for frame in old.frames() {
+        if frame_tag(frame).is_none() && !is_sensitive_frame(frame) {
+            tag.add_frame(frame.clone());
+        }
+    }
+    for field in fields {
+        let original = old.frames().find(|f| frame_tag(f) == Some(field.tag));
+        let frame = match original {
+            Some(frame) if is_unchanged(field, &frame_text(frame)) => frame.clone(),
+            _ => new_frame(field, original),
+        };
+        tag.add_frame(frame);
+    }
+
+    let mut copy = Cursor::new(file.to_vec());
+    id3::Tag::remove_from_file(&mut copy)?;
+    id3::v1::Tag::remove_from_file(&mut copy)?;
+    if tag.frames().next().is_some() {
+        tag.write_to_file(&mut copy, old.version())?;
+    }
+    Ok(copy.into_inner())
+}
+
+/// The frame for an edited `field`, in place of `original`
+fn new_frame(field: &Field, original: Option<&id3::Frame>) -> id3::Frame {
+    let text = from_field(field);
+    match field.tag {
+        Tag::GPSAreaInformation => {
+            let description = match original.map(id3::Frame::content) {
+                Some(id3::Content::ExtendedText(old)) => old.description.clone(),
+                _ => String::from("LOCATION"),
+            };
+            id3::Frame::with_content(
+                "TXXX",
+                id3::Content::ExtendedText(id3::frame::ExtendedText {
+                    description,
+                    value: text,
+                }),
+            )
+        }
+        tag if tag == utils::XP_TAGS[1] => {
+            let (lang, description) = match original.map(id3::Frame::content) {
+                Some(id3::Content::Comment(old)) => (old.lang.clone(), old.description.clone()),
+                _ => (String::from("eng"), String::new()),
+            };
+            id3::Frame::with_content(
+                "COMM",
+                id3::Content::Comment(id3::frame::Comment {
+                    lang,
+                    description,
+                    text,
+                }),
+            )
+        }
+        tag => {
+            let (id, _) = ID3_TAGS.iter().find(|(_, t)| *t == tag).unwrap();
+            id3::Frame::text(*id, text)
+        }
+    }
+}
+
+fn write_flac(file: &[u8], fields: &[&Field]) -> Result<Vec<u8>> {
+    rewrite_flac(file, false, |comments| {
+        let mut kept: Vec<Comment> = comments
+            .iter()
+            .filter(|(key, _)| comment_tag(key).is_none() && !is_sensitive_comment(key))
+            .cloned()
+            .collect();
+        for field in fields {
+            let original = comments
+                .iter()
+                .find(|(key, _)| comment_tag(key) == Some(field.tag));
+            kept.push(match original {
+                Some(comment) if is_unchanged(field, &comment.1) => comment.clone(),
+                Some((key, _)) => (key.clone(), from_field(field)),
+                None => {
+                    let key = VORBIS_TAGS
+                        .iter()
+                        .find(|(_, tag)| *tag == field.tag)
+                        .map_or("LOCATION", |(key, _)| key);
+                    (key.to_string(), from_field(field))
+                }
+            });
+        }
+        kept
+    })
+}
+
+/// `text` of a frame or comment as a field for `tag`
+fn to_field(tag: Tag, text: &str) -> Field {
+    let value = match tag {
+        _ if utils::XP_TAGS.contains(&tag) => Value::Byte(utils::encode_xp(text)),
+        // Text behind a character code, UCS-2 in big endian for anything
+        // that isn't ASCII
+        Tag::GPSAreaInformation => {
+            let mut area = b"UNICODE\0".to_vec();
+            area.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+            Value::Undefined(area, 0)
+        }
+        // Recording dates are ISO 8601, of which a year alone is enough
+        Tag::DateTimeOriginal => {
+            let date = text
+                .get(..19)
+                .and_then(|t| NaiveDateTime::parse_from_str(t, "%Y-%m-%dT%H:%M:%S").ok())
+                .map(|d| d.format("%Y:%m:%d %H:%M:%S").to_string());
+            Value::Ascii(vec![date.unwrap_or_else(|| text.to_owned()).into_bytes()])
+        }
+        _ => Value::Ascii(vec![text.as_bytes().to_vec()]),
+    };
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    }
+}
+
+/// `field` as the text of its frame or comment, the reverse of `to_field`
+fn from_field(field: &Field) -> String {
+    let text = match &field.value {
+        Value::Byte(bytes) if utils::XP_TAGS.contains(&field.tag) => utils::decode_xp(bytes),
+        Value::Undefined(bytes, _) => utils::decode_encoded_text(bytes, false),
+        Value::Ascii(strings) => strings
+            .iter()
+            .map(|s| utils::decode_ascii(s))
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => utils::display_text(field),
+    };
+    match NaiveDateTime::parse_from_str(&text, "%Y:%m:%d %H:%M:%S") {
+        Ok(date) if field.tag == Tag::DateTimeOriginal => {
+            date.format("%Y-%m-%dT%H:%M:%S").to_string()
+        }
+        _ => text,
+    }
+}
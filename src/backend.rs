@@ -0,0 +1,202 @@
+use anyhow::Result;
+use exif::{Exif, Field, Reader, Tag, Value};
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{
+    audio::{self, AudioBackend},
+    document::{self, PdfBackend},
+    utils,
+    vault::Manifest,
+    writer::{self, ByteOrder},
+};
+
+// Backend
+//
+// Where the fields shown in the table come from and where the edited ones go.
+// The application only deals in fields - a backend reads them out of a file,
+// formats them for display and writes a copy of the file carrying the edited
+// fields instead.
+//
+// Fields are keyed by Exif tag whatever the format, so the ordering, the
+// randomizer and policies work the same for every backend. A format that
+// doesn't use Exif maps its own fields onto the closest tags, e.g. the author
+// of a document onto Artist.
+//
+// The backend is picked by the first bytes of the file, not its name. What only
+// Exif data has, like a byte order or a size limit in JPEGs, is on ExifBackend
+// itself and reached through `as_exif`.
+
+/// Backends are shared with the thread that saves a copy in the background
+pub trait MetadataBackend: Send + Sync {
+    /// Name of the metadata format, e.g. Exif
+    fn name(&self) -> &'static str;
+
+    /// Every field read from the file
+    fn fields(&self) -> Vec<Field>;
+
+    /// Whether `tag` can be changed and written back
+    fn is_editable(&self, tag: Tag) -> bool;
+
+    /// Value of `field` as shown in the table, with its unit
+    fn display(&self, field: &Field) -> String;
+
+    /// Copy of `file`, the contents of the file the backend was read from, with
+    /// `fields` in place of the ones read
+    fn write(&self, file: &[u8], fields: &mut dyn Iterator<Item = &Field>) -> Result<Vec<u8>>;
+
+    /// The fields as read, for archiving in a vault
+    fn manifest(&self, source: &Path) -> Manifest;
+
+    /// The backend as the Exif one, if it is
+    fn as_exif(&self) -> Option<&ExifBackend> {
+        None
+    }
+}
+
+/// How a file keeps its metadata, and so which backend reads it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Images, or anything else kamadak-exif finds Exif data in
+    Exif,
+    Pdf,
+    /// MP3 and FLAC files
+    Audio,
+}
+
+impl Format {
+    /// Format of `file`, told by its first bytes
+    pub fn of(file: &[u8]) -> Self {
+        match file {
+            _ if document::is_pdf(file) => Format::Pdf,
+            _ if audio::is_mp3_or_flac(file) => Format::Audio,
+            _ => Format::Exif,
+        }
+    }
+}
+
+/// Reads the metadata of `path` with the backend for its format
+pub fn open(path: &Path) -> Result<Arc<dyn MetadataBackend>> {
+    parse(&std::fs::read(path)?)
+}
+
+/// Reads the metadata of `file`, the contents of a file already in memory
+pub fn parse(file: &[u8]) -> Result<Arc<dyn MetadataBackend>> {
+    Ok(match Format::of(file) {
+        Format::Exif => Arc::new(ExifBackend::parse(file)?),
+        Format::Pdf => Arc::new(PdfBackend::parse(file)?),
+        Format::Audio => Arc::new(AudioBackend::parse(file)?),
+    })
+}
+
+pub struct ExifBackend {
+    /// Exif caches values it decodes lazily, so it can't be shared as it is
+    exif: Mutex<Exif>,
+}
+
+impl ExifBackend {
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let mut bufreader = std::io::BufReader::new(&file);
+        let exif = Reader::new().read_from_container(&mut bufreader)?;
+        Ok(Self::new(exif))
+    }
+
+    pub fn parse(file: &[u8]) -> Result<Self> {
+        let exif = Reader::new().read_from_container(&mut std::io::Cursor::new(file))?;
+        Ok(Self::new(exif))
+    }
+
+    pub fn new(exif: Exif) -> Self {
+        Self {
+            exif: Mutex::new(exif),
+        }
+    }
+
+    fn exif(&self) -> MutexGuard<'_, Exif> {
+        self.exif.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Byte order the Exif data was read in
+    pub fn byte_order(&self) -> ByteOrder {
+        ByteOrder::of(&self.exif())
+    }
+
+    /// Bytes the Exif data takes once written with `fields`, `None` if they
+    /// can't be written
+    pub fn encoded_len(&self, fields: &mut dyn Iterator<Item = &Field>) -> Option<usize> {
+        let exif = self.exif();
+        let tiff = writer::encode(fields, Some(&exif), exif.little_endian()).ok()?;
+        Some(tiff.len())
+    }
+
+    /// Like `write`, with the Exif data converted to `byte_order`
+    pub fn write_as(
+        &self,
+        file: &[u8],
+        fields: &mut dyn Iterator<Item = &Field>,
+        byte_order: ByteOrder,
+    ) -> Result<Vec<u8>> {
+        let exif = self.exif();
+        let mut fields: Vec<Field> = fields.cloned().collect();
+        writer::convert_byte_order(&mut fields, ByteOrder::of(&exif), byte_order);
+        writer::rewrite(file, Some(&exif), &fields, byte_order.is_little_endian())
+    }
+}
+
+impl MetadataBackend for ExifBackend {
+    fn name(&self) -> &'static str {
+        "Exif"
+    }
+
+    fn fields(&self) -> Vec<Field> {
+        self.exif().fields().cloned().collect()
+    }
+
+    fn is_editable(&self, tag: Tag) -> bool {
+        !writer::is_structural(tag)
+    }
+
+    fn display(&self, field: &Field) -> String {
+        let exif = self.exif();
+        match (field.tag, &field.value) {
+            (Tag::GPSProcessingMethod | Tag::GPSAreaInformation, Value::Undefined(bytes, _)) => {
+                utils::decode_encoded_text(bytes, exif.little_endian())
+            }
+            (_, Value::Ascii(_)) => utils::display_text(field),
+            (tag, Value::Byte(_)) if utils::XP_TAGS.contains(&tag) => utils::display_text(field),
+            (Tag::Flash, value) if value.get_uint(0).is_some() => {
+                utils::describe_flash(value.get_uint(0).unwrap())
+            }
+            (Tag::CompositeImage, value) if value.get_uint(0).is_some() => {
+                utils::describe_composite(value.get_uint(0).unwrap())
+            }
+            (Tag::SourceImageNumberOfCompositeImage, value) if value.get_uint(1).is_some() => {
+                format!(
+                    "{} frames, {} used",
+                    value.get_uint(0).unwrap(),
+                    value.get_uint(1).unwrap()
+                )
+            }
+            (Tag::SceneType, Value::Undefined(bytes, _)) if bytes.first() == Some(&1) => {
+                String::from("Directly photographed")
+            }
+            _ => utils::clean_disp(&field.display_value().with_unit(&*exif).to_string()),
+        }
+    }
+
+    fn write(&self, file: &[u8], fields: &mut dyn Iterator<Item = &Field>) -> Result<Vec<u8>> {
+        let exif = self.exif();
+        writer::rewrite(file, Some(&exif), fields, exif.little_endian())
+    }
+
+    fn manifest(&self, source: &Path) -> Manifest {
+        Manifest::from_exif(source, &self.exif())
+    }
+
+    fn as_exif(&self) -> Option<&ExifBackend> {
+        Some(self)
+    }
+}
@@ -0,0 +1,637 @@
+use anyhow::{Context as _, Result};
+use chrono::{Duration, FixedOffset, Local, TimeZone, Utc};
+use exif::{Context, Field, In, Reader, Tag, Value};
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    audio,
+    backend::{self, ExifBackend, Format, MetadataBackend},
+    document,
+    gpx::{self, Track, TrackPoint},
+    grep::Query,
+    icc, jpeg,
+    motion::{self, MotionPhoto},
+    png,
+    policy::{Policy, PolicyProcessor, Rule},
+    processor::{TagProcessor, Verdict},
+    stego, utils, webp,
+    writer::{self, ByteOrder, Oversized},
+};
+
+// Batch
+//
+// Runs a set of tag processors over many images at once. Every image is read,
+// each of its fields is passed through the processor pipeline, and a scrubbed
+// copy is written unless this is a dry run.
+//
+// Copies can be converted to JPEG on the way, so one pass over a folder of
+// PNGs or TIFFs produces files that are both clean and easy to share, and
+// downscaled, as the resolution of an image can point to the camera it came
+// from. Those two are the only ways pixels are ever re-encoded, everything
+// else swaps metadata segments and leaves the image data byte for byte as it
+// was, which is checked before a copy is written. Formats the image crate can't decode, like
+// HEIC, fail instead of being copied as they are. The video appended to a
+// motion photo is another thing a copy can be written without.
+
+pub const IMAGE_EXTENSIONS: [&str; 8] =
+    ["jpg", "jpeg", "png", "tif", "tiff", "webp", "heic", "heif"];
+
+#[derive(Debug, Default)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub output: Option<PathBuf>,
+    pub dropped: Vec<Tag>,
+    pub replaced: Vec<Tag>,
+    pub flagged: Vec<(Tag, String)>,
+    /// Description of the color profile that was dropped or replaced
+    pub icc: Option<String>,
+    /// Width and height the image was downscaled to
+    pub resized: Option<(u32, u32)>,
+    /// Places data may hide in, which are copied along unless the image is
+    /// re-encoded
+    pub extra: Vec<stego::Finding>,
+    /// Text that was dropped from outside the Exif data, like "XMP" or the
+    /// keywords of PNG text chunks
+    pub text: Vec<String>,
+    /// The image is a motion photo, whose video keeps its own metadata unless
+    /// it was embedded and dropped
+    pub motion: Option<MotionPhoto>,
+    /// Size of the embedded video that was dropped
+    pub video_dropped: Option<usize>,
+    /// What was done to fit the Exif data into its JPEG segment, see
+    /// `writer::Oversized`
+    pub made_room: Option<String>,
+}
+
+impl FileReport {
+    pub fn changed(&self) -> bool {
+        !self.dropped.is_empty() || !self.replaced.is_empty()
+    }
+}
+
+pub fn is_image(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Expands directories (recursively) into the images they contain
+pub fn collect_images(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    collect_files(paths, is_image)
+}
+
+/// Whether `path` is a file one of the backends reads, an image, a document or
+/// an audio file
+pub fn is_supported(path: &Path) -> bool {
+    is_image(path) || document::is_document(path) || audio::is_audio(path)
+}
+
+/// Expands directories (recursively) into the files one of the backends reads
+pub fn collect_supported(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    collect_files(paths, is_supported)
+}
+
+/// Files in `paths` that pass `filter`, searching directories recursively
+pub fn collect_files(paths: &[PathBuf], filter: fn(&Path) -> bool) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            let mut entries = std::fs::read_dir(path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            entries.sort();
+            files.extend(collect_files(&entries, filter)?);
+        } else if filter(path) {
+            files.push(path.clone());
+        }
+    }
+    Ok(files)
+}
+
+/// Passes a single field through the pipeline. Returns the field to write, if any
+pub fn apply(
+    processors: &mut [Box<dyn TagProcessor>],
+    path: &Path,
+    field: &Field,
+    report: &mut FileReport,
+) -> Option<Field> {
+    let mut field = field.clone();
+    let mut replaced = false;
+    for processor in processors.iter_mut() {
+        match processor.process(path, &field) {
+            Verdict::Keep => {}
+            Verdict::Protect => break,
+            Verdict::Replace(value) => {
+                field.value = value;
+                replaced = true;
+            }
+            // Files missing these may not open at all, whatever the policy says
+            Verdict::Drop if writer::is_required(field.tag) => {
+                report.flagged.push((
+                    field.tag,
+                    format!("{}: kept, needed to read the image", processor.name()),
+                ));
+                break;
+            }
+            Verdict::Drop => {
+                report.dropped.push(field.tag);
+                return None;
+            }
+            Verdict::Flag(reason) => report
+                .flagged
+                .push((field.tag, format!("{}: {}", processor.name(), reason))),
+        }
+    }
+    if replaced {
+        report.replaced.push(field.tag);
+    }
+    Some(field)
+}
+
+/// Quality of re-encoded JPEGs unless another one is given
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// How copies are written besides their metadata. By default only the Exif
+/// data is swapped and the pixels are left exactly as they were.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Export {
+    /// Convert images in other formats to JPEG
+    pub to_jpeg: bool,
+    /// Quality of the JPEGs that are re-encoded, from 1 to 100
+    pub quality: u8,
+    /// Downscale images whose width or height is larger, which re-encodes them
+    pub max_dimension: Option<u32>,
+    /// Drop or replace the color profile
+    pub icc: Option<icc::Change>,
+    /// Written into the Software tag, so whoever gets the copy can tell it was
+    /// scrubbed on purpose
+    pub stamp: Option<String>,
+    /// Convert the Exif data to this byte order, instead of keeping the one it has
+    pub byte_order: Option<ByteOrder>,
+    /// Drop XMP packets and other text outside the Exif data, see `strip_text`
+    pub strip_text: bool,
+    /// Drop the video of motion photos, see `motion::strip_video`
+    pub strip_video: bool,
+    /// What to do with Exif data too large for the segment of a JPEG
+    pub oversized_exif: Oversized,
+}
+
+impl Default for Export {
+    fn default() -> Self {
+        Self {
+            to_jpeg: false,
+            quality: DEFAULT_JPEG_QUALITY,
+            max_dimension: None,
+            icc: None,
+            stamp: None,
+            byte_order: None,
+            strip_text: false,
+            strip_video: false,
+            oversized_exif: Oversized::Refuse,
+        }
+    }
+}
+
+/// Provenance stamp of a scrub with `processors` or under the policy named
+/// `policy`, e.g. "Scrubbed by bresson 0.1.0 on 2024-03-02 per policy newsroom"
+pub fn stamp(policy: Option<&str>, processors: &[String]) -> String {
+    let how = match policy {
+        Some(name) => format!("per policy {}", name),
+        None => format!("with {}", processors.join(", ")),
+    };
+    format!(
+        "Scrubbed by bresson {} on {} {}",
+        env!("CARGO_PKG_VERSION"),
+        Local::now().format("%Y-%m-%d"),
+        how
+    )
+}
+
+/// Scrubs the image at `path` into a copy, written as `export` says
+pub fn process_file(
+    processors: &mut [Box<dyn TagProcessor>],
+    path: &Path,
+    out_dir: Option<&Path>,
+    dry_run: bool,
+    export: &Export,
+) -> Result<FileReport> {
+    let img_buf = std::fs::read(path)?;
+    // Everything else here is about images, other formats only have their
+    // fields passed through the pipeline
+    if Format::of(&img_buf) != Format::Exif {
+        let (mut report, copy) = scrub_buffer(processors, path, &img_buf)?;
+        if !dry_run && report.changed() {
+            let output = output_path(path, out_dir)?;
+            std::fs::write(&output, copy)?;
+            report.output = Some(output);
+        }
+        return Ok(report);
+    }
+    let mut report = FileReport {
+        path: path.to_path_buf(),
+        motion: motion::detect(path, &img_buf),
+        ..Default::default()
+    };
+    // Everything after this works on the still alone
+    let img_buf = match export.strip_video {
+        true => match motion::strip_video(&img_buf) {
+            Some(still) => {
+                report.video_dropped = Some(img_buf.len() - still.len());
+                still
+            }
+            None => img_buf,
+        },
+        false => img_buf,
+    };
+    let is_jpeg = jpeg::is_jpeg(&img_buf);
+    report.extra = stego::scan(&img_buf);
+    let convert = export.to_jpeg && !is_jpeg;
+    let reencode = convert || export.max_dimension.is_some();
+    if export.icc.is_some() && !is_jpeg && !reencode {
+        anyhow::bail!(
+            "Color profiles can only be changed in JPEG images, convert it with --to-jpeg"
+        );
+    }
+    // Re-encoded images lose their profile along with everything else
+    let old_profile = match export.icc {
+        Some(_) if !reencode => jpeg::icc_profile(&img_buf)?,
+        _ => None,
+    };
+    let new_profile = export.icc.and_then(|change| change.profile());
+    let change_profile = export.icc.is_some() && old_profile != new_profile;
+    if change_profile {
+        report.icc = old_profile
+            .as_deref()
+            .map(|p| icc::description(p).unwrap_or_else(|_| String::from("unnamed")));
+    }
+    let stripped = match export.strip_text {
+        true => {
+            let (stripped, text) = strip_text(&img_buf)?;
+            report.text = text;
+            Some(stripped)
+        }
+        false => None,
+    };
+    let exif = match Reader::new().read_from_container(&mut Cursor::new(&img_buf)) {
+        Ok(exif) => Some(exif),
+        // Nothing to scrub, but maybe something to convert
+        Err(exif::Error::NotFound(_)) => None,
+        Err(e) => return Err(e.into()),
+    };
+    let mut fields: Vec<Field> = exif
+        .iter()
+        .flat_map(|exif| exif.fields())
+        .filter(|f| !writer::is_structural(f.tag))
+        .filter_map(|f| apply(processors, path, f, &mut report))
+        .collect();
+
+    if let Some(stamp) = &export.stamp {
+        fields.retain(|f| !(f.tag == Tag::Software && f.ifd_num == In::PRIMARY));
+        fields.push(Field {
+            tag: Tag::Software,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![stamp.clone().into_bytes()]),
+        });
+    }
+
+    let read_order = exif.as_ref().map(ByteOrder::of);
+    let byte_order = export
+        .byte_order
+        .or(read_order)
+        .unwrap_or(ByteOrder::BigEndian);
+    let convert_order = read_order.is_some_and(|read| read != byte_order);
+    if let Some(read) = read_order {
+        writer::convert_byte_order(&mut fields, read, byte_order);
+    }
+
+    let stamped = export.stamp.is_some();
+    let unchanged = !report.changed()
+        && !reencode
+        && !change_profile
+        && !convert_order
+        && report.text.is_empty()
+        && report.video_dropped.is_none();
+    if dry_run || (unchanged && !stamped) {
+        return Ok(report);
+    }
+
+    let reencoded = if reencode {
+        let reencoded = reencode_jpeg(&img_buf, export.quality, export.max_dimension)?;
+        report.resized = reencoded.resized;
+        // The Exif data has to agree with the pixels it describes
+        if let Some((width, height)) = reencoded.resized {
+            for field in fields.iter_mut().filter(|f| f.ifd_num == In::PRIMARY) {
+                match field.tag {
+                    Tag::PixelXDimension => field.value = Value::Long(vec![width]),
+                    Tag::PixelYDimension => field.value = Value::Long(vec![height]),
+                    _ => {}
+                }
+            }
+        }
+        Some(reencoded.buf)
+    } else {
+        None
+    };
+    let pixels = reencoded
+        .as_deref()
+        .or(stripped.as_deref())
+        .unwrap_or(&img_buf);
+    let new_img_buf = match &exif {
+        _ if !fields.is_empty() => {
+            let encode = |fields: &[Field]| {
+                writer::encode(fields, exif.as_ref(), byte_order.is_little_endian())
+            };
+            let mut tiff = encode(&fields)?;
+            let taken = match jpeg::is_jpeg(pixels) {
+                true => writer::make_room(
+                    &mut fields,
+                    jpeg::EXIF_HEADER.len() + tiff.len(),
+                    export.oversized_exif,
+                )?,
+                false => None,
+            };
+            if let Some(field) = &taken {
+                report.made_room = Some(export.oversized_exif.describe(field));
+                tiff = encode(&fields)?;
+            }
+            let copy = writer::splice(pixels, exif.as_ref(), &tiff)?;
+            writer::put_back(copy, taken.as_ref())?
+        }
+        Some(_) => writer::strip(pixels)?,
+        None => pixels.to_vec(),
+    };
+    let new_img_buf = if change_profile {
+        jpeg::replace_icc(&new_img_buf, new_profile.as_deref())?
+    } else {
+        new_img_buf
+    };
+    // Only the metadata may change unless the pixels were asked to
+    if is_jpeg && !reencode && jpeg::image_data(&img_buf)? != jpeg::image_data(&new_img_buf)? {
+        anyhow::bail!("Scrubbing changed the image data, no copy was written");
+    }
+    let mut output = output_path(path, out_dir)?;
+    if reencode && !is_jpeg {
+        output.set_extension("jpg");
+    }
+    std::fs::write(&output, new_img_buf)?;
+    report.output = Some(output);
+    Ok(report)
+}
+
+/// Copy of `file`, the contents of an image, with `policy` applied to its
+/// metadata. Nothing is read from or written to disk, so this works the same
+/// for a pipe, an upload or a buffer handed over from C.
+pub fn scrub_bytes(file: &[u8], policy: &Policy) -> Result<Vec<u8>> {
+    let mut processors: Vec<Box<dyn TagProcessor>> =
+        vec![Box::new(PolicyProcessor::new(policy.clone()))];
+    let (_, copy) = scrub_buffer(&mut processors, Path::new(""), file)?;
+    Ok(copy)
+}
+
+/// Passes the fields of `file` through the pipeline and writes the copy into a
+/// new buffer, `path` being where the file came from for the report
+pub fn scrub_buffer(
+    processors: &mut [Box<dyn TagProcessor>],
+    path: &Path,
+    file: &[u8],
+) -> Result<(FileReport, Vec<u8>)> {
+    let backend = backend::parse(file)?;
+    let mut report = FileReport {
+        path: path.to_path_buf(),
+        ..Default::default()
+    };
+    let fields: Vec<Field> = backend
+        .fields()
+        .iter()
+        .filter(|f| !writer::is_structural(f.tag))
+        .filter_map(|f| apply(processors, path, f, &mut report))
+        .collect();
+    // Other backends write a copy without any fields as well
+    let copy = match fields.is_empty() && backend.as_exif().is_some() {
+        true => writer::strip(file)?,
+        false => write_bytes(&*backend, file, &fields, None)?,
+    };
+    Ok((report, copy))
+}
+
+/// Copy of `file` with `fields` in place of the ones `backend` read from it,
+/// converted to `byte_order` if one is given. Fails rather than return a copy
+/// whose image data differs from the original.
+pub fn write_bytes(
+    backend: &dyn MetadataBackend,
+    file: &[u8],
+    fields: &[Field],
+    byte_order: Option<ByteOrder>,
+) -> Result<Vec<u8>> {
+    let (copy, _) = write_fitting(backend, file, fields, byte_order, Oversized::Refuse)?;
+    Ok(copy)
+}
+
+/// Like `write_bytes`, making room the way `oversized` says when the Exif data
+/// doesn't fit into the segment of a JPEG. Returns the copy and the field taken
+/// out of the Exif data, if any.
+pub fn write_fitting(
+    backend: &dyn MetadataBackend,
+    file: &[u8],
+    fields: &[Field],
+    byte_order: Option<ByteOrder>,
+    oversized: Oversized,
+) -> Result<(Vec<u8>, Option<Field>)> {
+    let mut fields = fields.to_vec();
+    let exif = backend.as_exif();
+    let len = match jpeg::is_jpeg(file) {
+        true => exif.and_then(|exif| exif.encoded_len(&mut fields.iter())),
+        false => None,
+    };
+    let taken = match len {
+        Some(len) => writer::make_room(&mut fields, jpeg::EXIF_HEADER.len() + len, oversized)?,
+        None => None,
+    };
+    let copy = match exif.zip(byte_order) {
+        Some((exif, order)) => exif.write_as(file, &mut fields.iter(), order)?,
+        None => backend.write(file, &mut fields.iter())?,
+    };
+    let copy = writer::put_back(copy, taken.as_ref())?;
+    if jpeg::is_jpeg(file) && jpeg::image_data(file)? != jpeg::image_data(&copy)? {
+        anyhow::bail!("Writing the metadata changed the image data, no copy was made");
+    }
+    Ok((copy, taken))
+}
+
+/// Drops the XMP packets of `buf` along with other text outside the Exif data:
+/// the text chunks and modification time of a PNG and the comments of a JPEG.
+/// Returns the copy and what was dropped, formats without such text are copied
+/// as they are.
+pub fn strip_text(buf: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+    if jpeg::is_jpeg(buf) {
+        jpeg::remove_xmp(buf)
+    } else if png::is_png(buf) {
+        png::remove_text(buf)
+    } else if webp::is_webp(buf) {
+        webp::remove_xmp(buf)
+    } else {
+        Ok((buf.to_vec(), Vec::new()))
+    }
+}
+
+/// An image encoded again by `reencode_jpeg`
+pub struct Reencoded {
+    pub buf: Vec<u8>,
+    /// Width and height, if the image was downscaled
+    pub resized: Option<(u32, u32)>,
+}
+
+/// Decodes `img_buf` and encodes it again as a JPEG of the given quality, from
+/// 1 to 100, downscaled to fit within `max_dimension` if it's larger. Only the
+/// pixels are carried over, transparency is dropped.
+pub fn reencode_jpeg(img_buf: &[u8], quality: u8, max_dimension: Option<u32>) -> Result<Reencoded> {
+    let mut image =
+        image::load_from_memory(img_buf).context("Unable to decode the image to re-encode it")?;
+    let mut resized = None;
+    if let Some(max) = max_dimension.filter(|&max| image.width().max(image.height()) > max) {
+        image = image.resize(max, max, FilterType::Lanczos3);
+        resized = Some((image.width(), image.height()));
+    }
+    let mut out = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, quality).encode_image(&image.to_rgb8())?;
+    Ok(Reencoded { buf: out, resized })
+}
+
+/// Lists the fields of `path` that break `policy`, without changing anything
+pub fn audit_file(policy: &Policy, path: &Path) -> Result<Vec<(Tag, &'static str)>> {
+    let backend = match backend::open(path) {
+        Ok(backend) => backend,
+        Err(e) if matches!(e.downcast_ref(), Some(exif::Error::NotFound(_))) => {
+            return Ok(Vec::new())
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(backend
+        .fields()
+        .iter()
+        .filter_map(|f| policy.audit(f).map(|reason| (f.tag, reason)))
+        .collect())
+}
+
+/// The fields of `path` that match `query`, with their values as shown in the table
+pub fn grep_file(query: &Query, path: &Path) -> Result<Vec<(Tag, String)>> {
+    let img_buf = std::fs::read(path)?;
+    let exif = match Reader::new().read_from_container(&mut Cursor::new(&img_buf)) {
+        Ok(exif) => exif,
+        Err(exif::Error::NotFound(_)) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let backend = ExifBackend::new(exif);
+    Ok(backend
+        .fields()
+        .iter()
+        .filter_map(|f| {
+            let value = backend.display(f);
+            query.matches(f.tag, &value).then_some((f.tag, value))
+        })
+        .collect())
+}
+
+/// What's left of the fields of an image after `policy` was applied to it
+#[derive(Debug, Default)]
+pub struct Simulation {
+    /// Fields in the image, not counting the ones that only hold its structure
+    pub fields: usize,
+    pub kept: Vec<Tag>,
+    pub randomized: Vec<Tag>,
+}
+
+/// Works out what `policy` would do to `path`, without changing anything
+pub fn simulate_file(policy: &Policy, path: &Path) -> Result<Simulation> {
+    let img_buf = std::fs::read(path)?;
+    let exif = match Reader::new().read_from_container(&mut Cursor::new(&img_buf)) {
+        Ok(exif) => exif,
+        Err(exif::Error::NotFound(_)) => return Ok(Simulation::default()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut simulation = Simulation::default();
+    for field in exif.fields().filter(|f| !writer::is_structural(f.tag)) {
+        simulation.fields += 1;
+        match policy.rule_for(field.tag) {
+            None | Some(Rule::Keep) => simulation.kept.push(field.tag),
+            Some(Rule::Randomize) => simulation.randomized.push(field.tag),
+            Some(Rule::Forbid) | Some(Rule::Clear) => {}
+        }
+    }
+    Ok(simulation)
+}
+
+#[derive(Debug)]
+pub enum Geotag {
+    /// Placed at this point, written to the output unless this was a dry run
+    Tagged(TrackPoint, Option<PathBuf>),
+    Skipped(String),
+}
+
+/// Writes the position recorded in `track` at the capture time of `path`
+pub fn geotag_file(
+    track: &Track,
+    path: &Path,
+    utc_offset: FixedOffset,
+    max_gap: Duration,
+    overwrite: bool,
+    out_dir: Option<&Path>,
+    dry_run: bool,
+) -> Result<Geotag> {
+    let img_buf = std::fs::read(path)?;
+    let exif = match Reader::new().read_from_container(&mut Cursor::new(&img_buf)) {
+        Ok(exif) => exif,
+        Err(exif::Error::NotFound(_)) => return Ok(Geotag::Skipped(String::from("no Exif data"))),
+        Err(e) => return Err(e.into()),
+    };
+    if !overwrite && exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some() {
+        return Ok(Geotag::Skipped(String::from("already has GPS data")));
+    }
+    // Cameras usually record local time, without saying which
+    let time =
+        utils::capture_time(|tag| exif.get_field(tag, In::PRIMARY)).and_then(|(local, offset)| {
+            offset
+                .unwrap_or(utc_offset)
+                .from_local_datetime(&local)
+                .single()
+        });
+    let Some(time) = time.map(|t| t.with_timezone(&Utc)) else {
+        return Ok(Geotag::Skipped(String::from("no capture time")));
+    };
+    let Some(point) = track.position_at(time, max_gap) else {
+        return Ok(Geotag::Skipped(format!(
+            "{} is not covered by the track",
+            time
+        )));
+    };
+    if dry_run {
+        return Ok(Geotag::Tagged(point, None));
+    }
+
+    let gps_fields = gpx::gps_fields(&point);
+    let fields = exif
+        .fields()
+        .filter(|f| !writer::is_structural(f.tag) && f.tag.context() != Context::Gps)
+        .chain(&gps_fields);
+    let new_img_buf = writer::rewrite(&img_buf, Some(&exif), fields, exif.little_endian())?;
+    let output = output_path(path, out_dir)?;
+    std::fs::write(&output, new_img_buf)?;
+    Ok(Geotag::Tagged(point, Some(output)))
+}
+
+/// Where a scrubbed copy of `path` goes, in `out_dir` or next to the original
+pub fn output_path(path: &Path, out_dir: Option<&Path>) -> Result<PathBuf> {
+    match out_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            Ok(dir.join(path.file_name().unwrap_or_default()))
+        }
+        None => Ok(utils::copy_file_name(path)),
+    }
+}
@@ -0,0 +1,1668 @@
+use anyhow::Result;
+use chrono::{Duration, FixedOffset, NaiveDate};
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use exif::Tag;
+use ratatui::widgets::TableState;
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    audio, backend,
+    batch::{self, Export, Geotag},
+    cluster::{self, Clusters},
+    config::{self, Config},
+    document::{self, Document},
+    elevation::Profile,
+    fixture,
+    globe::Globe,
+    gpx::Track,
+    grep::Query,
+    home::Home,
+    icc, index,
+    journal::{self, Journal},
+    motion::Video,
+    policy::{Policy, PolicyProcessor, Rule},
+    processor::{Registry, TagProcessor},
+    randomize::RandomMetadata,
+    record::Recording,
+    repl::{Library, Outcome},
+    report::{self, Report},
+    serve,
+    sidecar::{Flag, Sidecar},
+    stats::{Count, Stats},
+    stego,
+    timeline::{Timeline, View},
+    tui, ui,
+    undo::UndoJournal,
+    utils, vault,
+    writer::{ByteOrder, Oversized},
+};
+
+/// EXIF Metadata Processing Tool in your Terminal
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    disable_version_flag = true,
+    group(ArgGroup::new("source").args(["images", "from_clipboard"]))
+)]
+pub struct Cli {
+    /// Images to inspect, or directories of them. `[` and `]` switch between them
+    pub images: Vec<PathBuf>,
+
+    /// Open the images copied to the clipboard instead, and put the last copy
+    /// saved from them back on the clipboard on exit
+    #[arg(long)]
+    pub from_clipboard: bool,
+
+    /// Export the original metadata into an encrypted vault next to every saved copy.
+    /// The passphrase is read from BRESSON_VAULT_PASSPHRASE or prompted for
+    #[arg(long)]
+    pub vault: bool,
+
+    /// Save over the original with `s` as well, not only with `w`
+    #[arg(long)]
+    pub in_place: bool,
+
+    /// Keep the original as <name>.bak when saving over it
+    #[arg(long)]
+    pub backup: bool,
+
+    /// What to do when the Exif data of a JPEG outgrows its 64 KB segment: refuse
+    /// to save, drop the MakerNote or move the XMP packet into a segment of its own
+    #[arg(
+        long,
+        value_name = "STRATEGY",
+        value_parser = clap::builder::PossibleValuesParser::new(Oversized::ALL.map(|o| o.name()))
+    )]
+    pub oversized_exif: Option<String>,
+
+    /// Enforce the scrubbing rules of a TOML policy file, or of a built-in preset
+    #[arg(long, value_name = "FILE")]
+    pub policy: Option<PathBuf>,
+
+    /// Read preferences from this TOML file instead of ~/.config/bresson/config.toml
+    #[arg(long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Allow fetching an OpenStreetMap of the image location, shown with `t`
+    #[arg(long)]
+    pub online: bool,
+
+    /// Print the metadata table of the image as Markdown instead of opening it,
+    /// `m` writes it next to the image from the TUI
+    #[arg(long, requires = "source")]
+    pub markdown: bool,
+
+    /// Print version
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// With --version, also list the features, formats and graphics protocol
+    #[arg(long, requires = "version")]
+    pub verbose: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Show or scrub the tags of MP3 and FLAC files
+    Audio(AudioArgs),
+    /// Audit images for sensitive metadata and fail if any is found
+    Check(CheckArgs),
+    /// Group geotagged images by location and show the groups on the globe
+    Clusters(ClustersArgs),
+    /// Manage the config file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Show or scrub the metadata of PDF documents
+    Document(DocumentArgs),
+    /// Chart the altitude over time of a set of geotagged photos
+    Elevation(ElevationArgs),
+    /// Create a small synthetic JPEG with controlled metadata, for tests and bug reports
+    GenFixture(GenFixtureArgs),
+    /// List the images flagged to keep, scrub or review
+    Flags(FlagsArgs),
+    /// Write GPS positions from a GPX track into images taken along it
+    Geotag(GeotagArgs),
+    /// Find images whose metadata matches a tag and value pattern
+    Grep(GrepArgs),
+    /// Write the metadata of a set of images into an SQLite database
+    Index(IndexArgs),
+    /// List the notes taken about images, e.g. while auditing them
+    Notes(NotesArgs),
+    /// Record the globe turning around the location of an image, as a GIF or asciicast
+    Record(RecordArgs),
+    /// Type queries and operations against a set of images, e.g. `count by Model`
+    Repl(ReplArgs),
+    /// Re-apply metadata archived in a vault or manifest to a scrubbed image
+    Restore(RestoreArgs),
+    /// Scrub many images at once by running their fields through tag processors
+    Scrub(ScrubArgs),
+    /// Answer JSON-RPC requests on stdin to inspect and scrub images, for editors
+    /// and other programs
+    Serve(ServeArgs),
+    /// Show which fields would survive uploading images to popular platforms
+    Simulate(SimulateArgs),
+    /// Summarize the cameras, lenses, locations and dates of a set of images
+    Stats(StatsArgs),
+    /// Browse images by capture date
+    Timeline(TimelineArgs),
+    /// Put the values a batch scrub changed back into its copies
+    UndoBatch(UndoBatchArgs),
+}
+
+impl Command {
+    pub fn run(&self) -> Result<()> {
+        match self {
+            Command::Audio(args) => args.run(),
+            Command::Check(args) => args.run(),
+            Command::Clusters(args) => args.run(),
+            Command::Config(command) => command.run(),
+            Command::Document(args) => args.run(),
+            Command::Elevation(args) => args.run(),
+            Command::GenFixture(args) => args.run(),
+            Command::Flags(args) => args.run(),
+            Command::Geotag(args) => args.run(),
+            Command::Grep(args) => args.run(),
+            Command::Index(args) => args.run(),
+            Command::Notes(args) => args.run(),
+            Command::Record(args) => args.run(),
+            Command::Repl(args) => args.run(),
+            Command::Restore(args) => args.run(),
+            Command::Scrub(args) => args.run(),
+            Command::Serve(args) => args.run(),
+            Command::Simulate(args) => args.run(),
+            Command::Stats(args) => args.run(),
+            Command::Timeline(args) => args.run(),
+            Command::UndoBatch(args) => args.run(),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct AudioArgs {
+    /// Audio files or directories of them
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Remove the tags instead of showing them
+    #[arg(long)]
+    pub scrub: bool,
+
+    /// Frame or comment to leave in place when scrubbing, e.g. TIT2 or TITLE
+    #[arg(long, value_name = "KEY", requires = "scrub")]
+    pub keep: Vec<String>,
+
+    /// Write scrubbed copies into this directory instead of next to the originals
+    #[arg(short, long, requires = "scrub")]
+    pub out_dir: Option<PathBuf>,
+
+    /// Report what would be removed without writing anything
+    #[arg(short = 'n', long, requires = "scrub")]
+    pub dry_run: bool,
+}
+
+impl AudioArgs {
+    pub fn run(&self) -> Result<()> {
+        let mut failures = 0;
+        for path in audio::collect_audio(&self.paths)? {
+            let result = if self.scrub {
+                self.scrub(&path)
+            } else {
+                Self::show(&path)
+            };
+            if let Err(e) = result {
+                failures += 1;
+                eprintln!("{}: {:#}", path.display(), e);
+            }
+        }
+        if failures > 0 {
+            anyhow::bail!("{} audio file(s) could not be processed", failures);
+        }
+        Ok(())
+    }
+
+    fn show(path: &Path) -> Result<()> {
+        let tags = audio::read_tags(path)?;
+        println!("{}:", path.display());
+        if tags.is_empty() {
+            println!("  no tags");
+        }
+        for tag in tags {
+            let marker = if tag.sensitive { "!" } else { " " };
+            println!("{} {:<14} {}", marker, tag.key, tag.value);
+        }
+        Ok(())
+    }
+
+    fn scrub(&self, path: &Path) -> Result<()> {
+        let report = audio::scrub_file(path, &self.keep, self.out_dir.as_deref(), self.dry_run)?;
+        println!(
+            "{}: {} removed{}",
+            path.display(),
+            report.removed.len(),
+            report
+                .output
+                .as_ref()
+                .map_or(String::new(), |o| format!(" -> {}", o.display()))
+        );
+        if !report.removed.is_empty() {
+            println!("  {}", report.removed.join(", "));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    /// Images, documents, audio files or directories to audit
+    #[arg(required_unless_present = "stdin_paths")]
+    pub paths: Vec<PathBuf>,
+
+    /// Also read newline separated paths from stdin, e.g. from a git pre-commit hook.
+    /// Paths bresson has no backend for are ignored
+    #[arg(long)]
+    pub stdin_paths: bool,
+
+    /// Audit against a TOML policy file or built-in preset instead of the default
+    /// (no GPS coordinates, no owner, serial number or composite image tags)
+    #[arg(long, value_name = "FILE")]
+    pub policy: Option<PathBuf>,
+
+    /// Don't look for a probable home location among the images
+    #[arg(long)]
+    pub no_home_check: bool,
+}
+
+impl CheckArgs {
+    pub fn run(&self) -> Result<()> {
+        let policy = match &self.policy {
+            Some(path) => Policy::open(path)?,
+            None => Policy::audit_default(),
+        };
+
+        let mut paths = self.paths.clone();
+        if self.stdin_paths {
+            for line in std::io::stdin().lock().lines() {
+                let line = line?;
+                if !line.trim().is_empty() {
+                    paths.push(PathBuf::from(line.trim()));
+                }
+            }
+        }
+
+        let home = detect_home(&paths, self.no_home_check)?;
+
+        let mut failures = 0;
+        for path in batch::collect_supported(&paths)? {
+            let audit = batch::audit_file(&policy, &path).and_then(|mut violations| {
+                if home_distance(home, &path).is_some() {
+                    violations.push((Tag::GPSLatitude, "is near the probable home location"));
+                }
+                Ok((violations, stego::scan_file(&path)?))
+            });
+            match audit {
+                Ok((violations, extra)) if violations.is_empty() && extra.is_empty() => {}
+                Ok((violations, extra)) => {
+                    failures += 1;
+                    println!(
+                        "{}: {} violation(s)",
+                        path.display(),
+                        violations.len() + extra.len()
+                    );
+                    for (tag, reason) in violations {
+                        println!("  {} {}", utils::tag_name(tag), reason);
+                    }
+                    for finding in extra {
+                        println!("  {}, which may hide data", finding);
+                    }
+                }
+                Err(e) => {
+                    failures += 1;
+                    eprintln!("{}: {}", path.display(), e);
+                }
+            }
+        }
+        if failures > 0 {
+            anyhow::bail!(
+                "{} file(s) failed the {} policy check",
+                failures,
+                policy.display_name()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ClustersArgs {
+    /// Images or directories to group
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Images within this many kilometers of each other end up in the same cluster
+    #[arg(long, default_value_t = 5.)]
+    pub radius: f64,
+}
+
+impl ClustersArgs {
+    pub fn run(&self) -> Result<()> {
+        let Clusters {
+            clusters,
+            untagged,
+            path,
+        } = Clusters::load(&self.paths, self.radius)?;
+        if clusters.is_empty() {
+            anyhow::bail!("No images with a location");
+        }
+
+        let mut globe = Globe::new(1., 0., false);
+        globe.font_ratio = tui::font_ratio();
+        let mut table_state = TableState::default().with_selected(0);
+        let mut show_path = true;
+        let chosen = tui::run(|terminal| {
+            terminal.clear()?;
+            loop {
+                let selected = table_state.selected().unwrap_or(0);
+                let cluster = &clusters[selected];
+                globe.look_at(cluster.lat as f32, cluster.lon as f32, 1.5);
+                let path = if show_path { path.as_slice() } else { &[] };
+                globe.markers = ui::cluster_markers(&clusters, selected, path);
+                terminal.draw(|frame| {
+                    ui::render_clusters(&globe, &clusters, untagged, &mut table_state, frame)
+                })?;
+
+                let Event::Key(key) = event::read()? else {
+                    continue;
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        table_state.select(Some(selected.saturating_sub(1)))
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        table_state.select(Some((selected + 1).min(clusters.len() - 1)))
+                    }
+                    KeyCode::Char('p') => show_path = !show_path,
+                    KeyCode::Enter => return Ok(Some(selected)),
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                    _ => {}
+                }
+            }
+        })?;
+
+        // Print the chosen cluster so it can be handed on, like `bresson timeline --print`
+        if let Some(cluster) = chosen.and_then(|i| clusters.get(i)) {
+            for path in &cluster.images {
+                println!("{}", path.display());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Write a config with every setting commented out, to start from
+    Init(ConfigInitArgs),
+}
+
+impl ConfigCommand {
+    pub fn run(&self) -> Result<()> {
+        match self {
+            ConfigCommand::Init(args) => args.run(),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigInitArgs {
+    /// Where to write it, ~/.config/bresson/config.toml by default
+    pub path: Option<PathBuf>,
+
+    /// Replace a config that's already there
+    #[arg(long)]
+    pub force: bool,
+}
+
+impl ConfigInitArgs {
+    pub fn run(&self) -> Result<()> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => Config::default_path()
+                .ok_or_else(|| anyhow::anyhow!("No config directory, HOME isn't set"))?,
+        };
+        if path.exists() && !self.force {
+            anyhow::bail!(
+                "{} already exists, pass --force to replace it",
+                path.display()
+            );
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&path, config::DEFAULT_TOML)?;
+        println!("Wrote {}", path.display());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct DocumentArgs {
+    /// Documents or directories of them
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Remove the metadata instead of showing it
+    #[arg(long)]
+    pub scrub: bool,
+
+    /// Info entry to leave in place when scrubbing, e.g. Title
+    #[arg(long, value_name = "KEY", requires = "scrub")]
+    pub keep: Vec<String>,
+
+    /// Write scrubbed copies into this directory instead of next to the originals
+    #[arg(short, long, requires = "scrub")]
+    pub out_dir: Option<PathBuf>,
+
+    /// Report what would be removed without writing anything
+    #[arg(short = 'n', long, requires = "scrub")]
+    pub dry_run: bool,
+}
+
+impl DocumentArgs {
+    pub fn run(&self) -> Result<()> {
+        let mut failures = 0;
+        for path in document::collect_documents(&self.paths)? {
+            let result = if self.scrub {
+                self.scrub(&path)
+            } else {
+                Self::show(&path)
+            };
+            if let Err(e) = result {
+                failures += 1;
+                eprintln!("{}: {:#}", path.display(), e);
+            }
+        }
+        if failures > 0 {
+            anyhow::bail!("{} document(s) could not be processed", failures);
+        }
+        Ok(())
+    }
+
+    fn show(path: &Path) -> Result<()> {
+        let document = Document::load(path)?;
+        let fields = document.fields();
+        println!("{}:", path.display());
+        if fields.is_empty() && !document.has_xmp() {
+            println!("  no metadata");
+        }
+        for (key, value) in fields {
+            println!("  {:<14} {}", key, value);
+        }
+        if document.has_xmp() {
+            println!("  {:<14} present", "XMP");
+        }
+        Ok(())
+    }
+
+    fn scrub(&self, path: &Path) -> Result<()> {
+        let report = document::scrub_file(path, &self.keep, self.out_dir.as_deref(), self.dry_run)?;
+        println!(
+            "{}: {} removed{}{}",
+            path.display(),
+            report.removed.len(),
+            if report.removed_xmp { " and XMP" } else { "" },
+            report
+                .output
+                .as_ref()
+                .map_or(String::new(), |o| format!(" -> {}", o.display()))
+        );
+        if !report.removed.is_empty() {
+            println!("  {}", report.removed.join(", "));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ElevationArgs {
+    /// Images or directories of one session, e.g. a hike
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+impl ElevationArgs {
+    pub fn run(&self) -> Result<()> {
+        let profile = Profile::load(&self.paths)?;
+        if profile.samples.len() < 2 {
+            anyhow::bail!(
+                "Need at least two photos with an altitude and capture time, found {}",
+                profile.samples.len()
+            );
+        }
+
+        tui::run(|terminal| {
+            terminal.clear()?;
+            loop {
+                terminal.draw(|frame| ui::render_elevation(&profile, frame))?;
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press
+                        && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+                    {
+                        return Ok(());
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct GenFixtureArgs {
+    /// Where to write the JPEG
+    pub output: PathBuf,
+
+    /// Write an image without any Exif data
+    #[arg(long, conflicts_with_all = ["gps", "weird_encoding", "maker_note", "thumbnail"])]
+    pub no_exif: bool,
+
+    /// Add a GPS location
+    #[arg(long)]
+    pub gps: bool,
+
+    /// Use Latin-1 text, junk after NUL terminators and a UCS-2 UserComment
+    #[arg(long)]
+    pub weird_encoding: bool,
+
+    /// Add a MakerNote of this many bytes
+    #[arg(long, value_name = "BYTES")]
+    pub maker_note: Option<usize>,
+
+    /// Embed a thumbnail of an earlier version of the image
+    #[arg(long)]
+    pub thumbnail: bool,
+}
+
+impl GenFixtureArgs {
+    pub fn run(&self) -> Result<()> {
+        let options = fixture::Options {
+            no_exif: self.no_exif,
+            gps: self.gps,
+            weird_encoding: self.weird_encoding,
+            maker_note: self.maker_note,
+            thumbnail: self.thumbnail,
+        };
+        let buf = fixture::generate(&options)?;
+        std::fs::write(&self.output, &buf)?;
+        println!("{}: {} bytes", self.output.display(), buf.len());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct GeotagArgs {
+    /// Images or directories to geotag
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// GPX track recorded while taking the images
+    #[arg(long, value_name = "FILE")]
+    pub gpx: PathBuf,
+
+    /// UTC offset of the camera clock, e.g. +02:00, for images that don't record one
+    #[arg(
+        long,
+        value_name = "OFFSET",
+        default_value = "+00:00",
+        allow_hyphen_values = true
+    )]
+    pub utc_offset: FixedOffset,
+
+    /// Largest distance in time, in seconds, between an image and the track points it is placed by
+    #[arg(long, value_name = "SECONDS", default_value_t = 300)]
+    pub max_gap: i64,
+
+    /// Also geotag images that already have GPS data
+    #[arg(long)]
+    pub overwrite: bool,
+
+    /// Write geotagged images into this directory instead of next to the originals
+    #[arg(short, long)]
+    pub out_dir: Option<PathBuf>,
+
+    /// Only report where the images would be placed
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+impl GeotagArgs {
+    pub fn run(&self) -> Result<()> {
+        let track = Track::load(&self.gpx)?;
+        let max_gap = Duration::seconds(self.max_gap);
+
+        let mut failures = 0;
+        for path in batch::collect_images(&self.paths)? {
+            match batch::geotag_file(
+                &track,
+                &path,
+                self.utc_offset,
+                max_gap,
+                self.overwrite,
+                self.out_dir.as_deref(),
+                self.dry_run,
+            ) {
+                Ok(Geotag::Tagged(point, output)) => println!(
+                    "{}: {:.6}, {:.6}{}",
+                    path.display(),
+                    point.lat,
+                    point.lon,
+                    output.map_or(String::new(), |o| format!(" -> {}", o.display()))
+                ),
+                Ok(Geotag::Skipped(reason)) => println!("{}: skipped, {}", path.display(), reason),
+                Err(e) => {
+                    failures += 1;
+                    eprintln!("{}: {}", path.display(), e);
+                }
+            }
+        }
+        if failures > 0 {
+            anyhow::bail!("{} file(s) could not be geotagged", failures);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct GrepArgs {
+    /// Images or directories to search
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Name of the tag, `*` matches any number of characters, e.g. "GPS*"
+    #[arg(long, required_unless_present = "value")]
+    pub tag: Option<String>,
+
+    /// Regular expression the value has to match, as shown in the table
+    #[arg(long, value_name = "REGEX")]
+    pub value: Option<String>,
+
+    /// Match the value regardless of case
+    #[arg(short, long)]
+    pub ignore_case: bool,
+
+    /// Only print the paths of the matching images
+    #[arg(short = 'l', long)]
+    pub files_with_matches: bool,
+}
+
+impl GrepArgs {
+    pub fn run(&self) -> Result<()> {
+        let query = Query::new(self.tag.as_deref(), self.value.as_deref(), self.ignore_case)?;
+
+        let mut found = 0;
+        for path in batch::collect_images(&self.paths)? {
+            match batch::grep_file(&query, &path) {
+                Ok(matches) if matches.is_empty() => {}
+                Ok(matches) => {
+                    found += 1;
+                    if self.files_with_matches {
+                        println!("{}", path.display());
+                        continue;
+                    }
+                    for (tag, value) in matches {
+                        println!("{}: {} = {}", path.display(), utils::tag_name(tag), value);
+                    }
+                }
+                Err(e) => eprintln!("{}: {}", path.display(), e),
+            }
+        }
+        if found == 0 {
+            anyhow::bail!("No image matched");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct IndexArgs {
+    /// Images or directories to index
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// SQLite database to write, images indexed before are replaced
+    #[arg(long, value_name = "FILE")]
+    pub db: PathBuf,
+}
+
+impl IndexArgs {
+    pub fn run(&self) -> Result<()> {
+        let summary = index::index(&self.paths, &self.db)?;
+        println!(
+            "Indexed {} image(s) ({} without Exif) and {} tag(s) into {}",
+            summary.images,
+            summary.without_exif,
+            summary.tags,
+            self.db.display()
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ReplArgs {
+    /// Images or directories to load
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+impl ReplArgs {
+    pub fn run(&self) -> Result<()> {
+        let library = Library::load(&self.paths)?;
+        println!(
+            "Loaded {} image(s), type `help` for the commands",
+            library.len()
+        );
+        let mut stdout = std::io::stdout();
+        let mut lines = std::io::stdin().lock().lines();
+        loop {
+            print!("bresson> ");
+            stdout.flush()?;
+            let Some(line) = lines.next().transpose()? else {
+                println!();
+                return Ok(());
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match library.run(&line) {
+                Ok(Outcome::Output(output)) => print!("{}", output),
+                Ok(Outcome::Quit) => return Ok(()),
+                Err(e) => println!("error: {}", e),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RestoreArgs {
+    /// Scrubbed image to restore the metadata into
+    pub image: PathBuf,
+
+    /// Vault or plain JSON manifest holding the archived metadata
+    #[arg(long)]
+    pub from: PathBuf,
+
+    /// Where to write the restored image. Defaults to a copy next to the image
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+impl RestoreArgs {
+    pub fn run(&self) -> Result<()> {
+        let manifest = vault::load(&std::fs::read(&self.from)?)?;
+        let restored = vault::restore(&std::fs::read(&self.image)?, &manifest)?;
+
+        let output = self
+            .output
+            .clone()
+            .unwrap_or_else(|| utils::copy_file_name(&self.image));
+        std::fs::write(&output, restored)?;
+        println!(
+            "Restored {} fields from {:?} into {:?}",
+            manifest.fields.len(),
+            self.from,
+            output
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ScrubArgs {
+    /// Images, documents, audio files or directories to scrub
+    #[arg(required_unless_present = "list_processors")]
+    pub paths: Vec<PathBuf>,
+
+    /// Processors to run, in order. Defaults to strip-all unless a policy is given
+    #[arg(short, long = "processor", value_name = "NAME")]
+    pub processors: Vec<String>,
+
+    /// Only remove the GPS tags and keep everything else. Same as `-p strip-gps`
+    #[arg(long, conflicts_with = "processors")]
+    pub gps_only: bool,
+
+    /// Enforce the rules of a TOML policy file or built-in preset before any other
+    /// processor
+    #[arg(long, value_name = "FILE")]
+    pub policy: Option<PathBuf>,
+
+    /// Show the available processors and exit
+    #[arg(long)]
+    pub list_processors: bool,
+
+    /// Write scrubbed images into this directory instead of next to the originals
+    #[arg(short, long)]
+    pub out_dir: Option<PathBuf>,
+
+    /// Only report what would change
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+
+    /// Don't warn about copies that still place an image near the probable
+    /// home location
+    #[arg(long)]
+    pub no_home_check: bool,
+
+    /// Have `randomize` pick camera makes uniformly instead of by market share
+    #[arg(long)]
+    pub uniform_makes: bool,
+
+    /// First day `randomize` makes up capture times on, as YYYY-MM-DD
+    #[arg(long, value_name = "DATE")]
+    pub earliest: Option<NaiveDate>,
+
+    /// Last day `randomize` makes up capture times on, as YYYY-MM-DD
+    #[arg(long, value_name = "DATE")]
+    pub latest: Option<NaiveDate>,
+
+    /// Continue an interrupted scrub with the same options, skipping the files it
+    /// already scrubbed
+    #[arg(long, conflicts_with = "dry_run")]
+    pub resume: bool,
+
+    /// Rest between files, at least MS milliseconds and as long as the last file
+    /// took, so the scrub can run in the background without taking over the machine
+    #[arg(long, value_name = "MS", num_args = 0..=1, default_missing_value = "250")]
+    pub throttle: Option<u64>,
+
+    /// Convert copies of images in other formats, like PNG or TIFF, to JPEG
+    #[arg(long)]
+    pub to_jpeg: bool,
+
+    /// Downscale copies whose width or height is larger than PIXELS, re-encoding
+    /// them as JPEG
+    #[arg(long, value_name = "PIXELS", value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_dimension: Option<u32>,
+
+    /// Quality of the JPEGs written by --to-jpeg and --max-dimension, from 1 to 100
+    #[arg(
+        long,
+        default_value_t = batch::DEFAULT_JPEG_QUALITY,
+        value_parser = clap::value_parser!(u8).range(1..=100)
+    )]
+    pub quality: u8,
+
+    /// Drop the color profile of JPEGs, which can tell what they were edited on, or
+    /// replace it with a generic sRGB one
+    #[arg(
+        long,
+        value_name = "CHANGE",
+        value_parser = clap::builder::PossibleValuesParser::new(icc::Change::ALL.map(|c| c.name()))
+    )]
+    pub icc: Option<String>,
+
+    /// Record in the Software tag of every copy that it was scrubbed by bresson,
+    /// when and how
+    #[arg(long)]
+    pub stamp: bool,
+
+    /// Convert the Exif data of copies to little or big endian, for readers that
+    /// only handle one of them
+    #[arg(
+        long,
+        value_name = "ORDER",
+        value_parser = clap::builder::PossibleValuesParser::new(ByteOrder::ALL.map(|o| o.name()))
+    )]
+    pub byte_order: Option<String>,
+
+    /// Drop the video phones append to motion photos, which keeps its own
+    /// metadata. Live Photo videos are separate files and need scrubbing themselves
+    #[arg(long)]
+    pub strip_video: bool,
+
+    /// What to do when the Exif data of a JPEG outgrows its 64 KB segment: refuse
+    /// to write the copy, drop the MakerNote or move the XMP packet into a segment
+    /// of its own
+    #[arg(
+        long,
+        value_name = "STRATEGY",
+        value_parser = clap::builder::PossibleValuesParser::new(Oversized::ALL.map(|o| o.name()))
+    )]
+    pub oversized_exif: Option<String>,
+
+    /// Write an HTML report of the scrub to FILE, to attach to an audit
+    #[arg(long, value_name = "FILE")]
+    pub report: Option<PathBuf>,
+
+    /// Write the original values of the changed fields to FILE, for
+    /// `bresson undo-batch` to put back into the copies
+    #[arg(long, value_name = "FILE", conflicts_with = "dry_run")]
+    pub undo_journal: Option<PathBuf>,
+
+    /// Encrypt the undo journal. The passphrase is read from
+    /// BRESSON_VAULT_PASSPHRASE or prompted for
+    #[arg(long, requires = "undo_journal")]
+    pub encrypt_undo: bool,
+}
+
+impl ScrubArgs {
+    pub fn run(&self) -> Result<()> {
+        let mut randomizer = RandomMetadata::default();
+        randomizer.weighted = !self.uniform_makes;
+        let dates = self.earliest.unwrap_or(*randomizer.dates.start())
+            ..=self.latest.unwrap_or(*randomizer.dates.end());
+        if dates.is_empty() {
+            anyhow::bail!("No days from {} to {}", dates.start(), dates.end());
+        }
+        randomizer.dates = dates;
+        let registry = Registry::new(randomizer);
+        if self.list_processors {
+            for (name, description) in registry.describe() {
+                println!("{:<16} {}", name, description);
+            }
+            return Ok(());
+        }
+
+        let names = if self.gps_only {
+            vec![String::from("strip-gps")]
+        } else if self.processors.is_empty() && self.policy.is_none() {
+            vec![String::from("strip-all")]
+        } else {
+            self.processors.clone()
+        };
+        let mut processors: Vec<Box<dyn TagProcessor>> = Vec::new();
+        let mut policy_name = None;
+        let mut strip_text = false;
+        if let Some(path) = &self.policy {
+            let policy = Policy::open(path)?;
+            strip_text = policy.strip_text;
+            policy_name = policy.name.clone().or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            });
+            processors.push(Box::new(PolicyProcessor::new(policy)));
+        }
+        processors.extend(registry.select(&names)?);
+        let home = detect_home(&self.paths, self.no_home_check)?;
+
+        let journal_path = Journal::path(self.out_dir.as_deref());
+        let options = journal::Options {
+            paths: self.paths.clone(),
+            processors: names,
+            policy: self.policy.clone(),
+            out_dir: self.out_dir.clone(),
+            to_jpeg: self.to_jpeg,
+            quality: self.quality,
+            max_dimension: self.max_dimension,
+            icc: self.icc.clone(),
+            stamp: self.stamp,
+            byte_order: self.byte_order.clone(),
+            strip_video: self.strip_video,
+            oversized_exif: self.oversized_exif.clone(),
+            // As given rather than resolved, the default range ends today
+            randomizer: vec![
+                format!("uniform-makes={}", self.uniform_makes),
+                format!("earliest={:?}", self.earliest),
+                format!("latest={:?}", self.latest),
+            ],
+        };
+        let mut journal = if self.dry_run {
+            None
+        } else if self.resume {
+            let journal = Journal::resume(&journal_path, &options)?;
+            eprintln!(
+                "Resuming, {} file(s) already scrubbed, {} to try again",
+                journal.done.len(),
+                journal.failed
+            );
+            Some(journal)
+        } else {
+            Some(Journal::start(&journal_path, &options)?)
+        };
+
+        let undo_passphrase = match self.encrypt_undo {
+            true => Some(vault::read_passphrase(true)?),
+            false => None,
+        };
+        let mut undo = match &self.undo_journal {
+            Some(path) if self.resume && path.exists() => {
+                Some(UndoJournal::load(path, undo_passphrase.as_deref())?)
+            }
+            Some(_) => Some(UndoJournal::default()),
+            None => None,
+        };
+
+        let export = Export {
+            to_jpeg: self.to_jpeg,
+            quality: self.quality,
+            max_dimension: self.max_dimension,
+            icc: self.icc.as_deref().and_then(icc::Change::from_name),
+            stamp: self
+                .stamp
+                .then(|| batch::stamp(policy_name.as_deref(), &options.processors)),
+            byte_order: self.byte_order.as_deref().and_then(ByteOrder::from_name),
+            strip_text,
+            strip_video: self.strip_video,
+            oversized_exif: self
+                .oversized_exif
+                .as_deref()
+                .and_then(Oversized::from_name)
+                .unwrap_or_default(),
+        };
+        let mut entries = Vec::new();
+        let mut failures = 0;
+        for path in batch::collect_supported(&self.paths)? {
+            if journal.as_ref().is_some_and(|j| j.done.contains(&path)) {
+                continue;
+            }
+            let started = Instant::now();
+            let result = batch::process_file(
+                &mut processors,
+                &path,
+                self.out_dir.as_deref(),
+                self.dry_run,
+                &export,
+            );
+            if let Some(journal) = journal.as_mut() {
+                let entry = match &result {
+                    Ok(report) => Ok(report.output.as_deref()),
+                    Err(e) => Err(format!("{:#}", e)),
+                };
+                journal.record(&path, entry)?;
+            }
+            match result {
+                Ok(report) => {
+                    if let (Some(undo), Some(output)) = (undo.as_mut(), &report.output) {
+                        let changed = [report.dropped.as_slice(), &report.replaced].concat();
+                        undo.record(&path, output, &changed)?;
+                    }
+                    let mut entry = report::Entry::new(
+                        &report,
+                        cluster::locate(&path).map(|(position, _)| position),
+                    );
+                    println!(
+                        "{}: {} dropped, {} replaced{}",
+                        path.display(),
+                        report.dropped.len(),
+                        report.replaced.len(),
+                        report
+                            .output
+                            .as_ref()
+                            .map_or(String::new(), |o| format!(" -> {}", o.display()))
+                    );
+                    for (tag, reason) in &report.flagged {
+                        println!("  flagged {} ({})", utils::tag_name(*tag), reason);
+                    }
+                    for finding in &report.extra {
+                        println!("  warning: {}, which may hide data", finding);
+                    }
+                    if let Some((width, height)) = report.resized {
+                        println!("  downscaled to {}x{}", width, height);
+                    }
+                    if !report.text.is_empty() {
+                        println!("  dropped text: {}", report.text.join(", "));
+                    }
+                    if let Some(made_room) = &report.made_room {
+                        println!("  {} to fit the Exif data into its segment", made_room);
+                    }
+                    let video = match (&report.motion, report.video_dropped) {
+                        (_, Some(len)) => {
+                            println!("  dropped the motion photo video ({} bytes)", len);
+                            None
+                        }
+                        (Some(motion), None) => Some(match &motion.video {
+                            Video::Embedded { .. } => format!(
+                                "{} video is copied along with its own metadata, \
+                                 --strip-video drops it",
+                                motion.kind
+                            ),
+                            Video::Paired(video) => format!(
+                                "{} video {} keeps its own metadata, scrub it as well",
+                                motion.kind,
+                                video.display()
+                            ),
+                        }),
+                        (None, None) => None,
+                    };
+                    if let Some(warning) = video {
+                        println!("  warning: {}", warning);
+                        entry.flags.push(warning);
+                    }
+                    match (&report.icc, export.icc) {
+                        (Some(name), Some(icc::Change::Drop)) => {
+                            println!("  dropped color profile {:?}", name)
+                        }
+                        (Some(name), Some(icc::Change::Srgb)) => {
+                            println!("  replaced color profile {:?} with sRGB", name)
+                        }
+                        _ => {}
+                    }
+                    // Randomized or dropped coordinates don't give anything away
+                    let kept_gps = ![&report.dropped, &report.replaced]
+                        .iter()
+                        .any(|tags| tags.contains(&Tag::GPSLatitude));
+                    if let Some(distance) = home_distance(home, &path).filter(|_| kept_gps) {
+                        println!(
+                            "  warning: still located {:.1} km from the probable home location",
+                            distance
+                        );
+                        entry.flags.push(format!(
+                            "Still located {:.1} km from the probable home location",
+                            distance
+                        ));
+                    }
+                    entries.push(entry);
+                }
+                Err(e) => {
+                    failures += 1;
+                    eprintln!("{}: {}", path.display(), e);
+                    entries.push(report::Entry::failed(&path, format!("{:#}", e)));
+                }
+            }
+            if let Some(ms) = self.throttle {
+                let rest = started.elapsed().max(std::time::Duration::from_millis(ms));
+                std::thread::sleep(rest);
+            }
+        }
+        if let (Some(undo), Some(path)) = (&undo, &self.undo_journal) {
+            undo.write(path, undo_passphrase.as_deref())?;
+            println!("Wrote undo journal to {}", path.display());
+        }
+        if let Some(path) = &self.report {
+            let report = Report {
+                how: match &policy_name {
+                    Some(policy) => format!("Scrubbed per policy {}", policy),
+                    None => format!("Scrubbed with {}", options.processors.join(", ")),
+                },
+                dry_run: self.dry_run,
+                entries,
+            };
+            report.write(path)?;
+            println!("Wrote report to {}", path.display());
+        }
+        if failures > 0 {
+            anyhow::bail!(
+                "{} file(s) could not be scrubbed, run again with --resume to retry them",
+                failures
+            );
+        }
+        if let Some(journal) = journal {
+            journal.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Looks for a probable home location among `paths`, and says where it is
+fn detect_home(paths: &[PathBuf], skip: bool) -> Result<Option<Home>> {
+    if skip {
+        return Ok(None);
+    }
+    let home = Home::detect(paths)?;
+    if let Some(home) = home {
+        eprintln!(
+            "Probable home location at {:.4}, {:.4} ({} photos taken there at night)",
+            home.lat, home.lon, home.images
+        );
+    }
+    Ok(home)
+}
+
+/// How far the image at `path` was taken from `home`, if that's close to it
+fn home_distance(home: Option<Home>, path: &Path) -> Option<f64> {
+    let (position, _) = cluster::locate(path)?;
+    home?.near(position)
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {}
+
+impl ServeArgs {
+    pub fn run(&self) -> Result<()> {
+        serve::run(std::io::stdin().lock(), std::io::stdout().lock())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct SimulateArgs {
+    /// Images or directories to upload
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Only simulate these platforms instead of all of them
+    #[arg(
+        long = "platform",
+        value_name = "NAME",
+        value_parser = clap::builder::PossibleValuesParser::new(Policy::preset_names())
+    )]
+    pub platforms: Vec<String>,
+}
+
+impl SimulateArgs {
+    pub fn run(&self) -> Result<()> {
+        let presets = if self.platforms.is_empty() {
+            Policy::preset_names().filter_map(Policy::preset).collect()
+        } else {
+            self.platforms
+                .iter()
+                .filter_map(|name| Policy::preset(name))
+                .collect::<Vec<_>>()
+        };
+        // The tags `bresson check` complains about by default
+        let sensitive = Policy::audit_default();
+        let width = presets
+            .iter()
+            .map(|p| p.display_name().chars().count())
+            .max()
+            .unwrap_or(0);
+
+        for path in batch::collect_images(&self.paths)? {
+            let mut simulations = Vec::new();
+            for preset in &presets {
+                simulations.push((preset, batch::simulate_file(preset, &path)?));
+            }
+            let fields = simulations.first().map_or(0, |(_, s)| s.fields);
+            if fields == 0 {
+                println!("{}: no metadata to leak", path.display());
+                continue;
+            }
+            println!("{}: {} fields", path.display(), fields);
+
+            for (preset, simulation) in simulations {
+                let leaked = simulation
+                    .kept
+                    .iter()
+                    .filter(|tag| sensitive.rule_for(**tag) == Some(Rule::Forbid))
+                    .map(|tag| utils::tag_name(*tag))
+                    .collect::<Vec<_>>();
+                let mut outcome = match simulation.kept.len() {
+                    0 => String::from("nothing survives"),
+                    n if n == fields => format!("all {} fields survive", n),
+                    n => format!("{} of {} fields survive", n, fields),
+                };
+                if !simulation.randomized.is_empty() {
+                    outcome += &format!(", {} randomized", simulation.randomized.len());
+                }
+                if !leaked.is_empty() {
+                    outcome += &format!(", including {}", leaked.join(", "));
+                }
+                println!(
+                    "  {:<width$}  {}",
+                    preset.display_name(),
+                    outcome,
+                    width = width
+                );
+            }
+        }
+
+        // Why each platform does what it does
+        println!();
+        for preset in &presets {
+            if let Some(description) = &preset.description {
+                println!(
+                    "{:<width$}  {}",
+                    preset.display_name(),
+                    description,
+                    width = width
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct FlagsArgs {
+    /// Images or directories whose flags to list
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Only list the images with this flag, one path per line to hand on, e.g. to
+    /// `bresson check --stdin-paths`
+    #[arg(
+        long,
+        value_name = "FLAG",
+        value_parser = clap::builder::PossibleValuesParser::new(Flag::ALL.map(|f| f.name()))
+    )]
+    pub flag: Option<String>,
+}
+
+impl FlagsArgs {
+    pub fn run(&self) -> Result<()> {
+        let only = self.flag.as_deref().and_then(Flag::from_name);
+        for path in batch::collect_images(&self.paths)? {
+            let flag = match Sidecar::open(&path) {
+                Ok(sidecar) => sidecar.flag,
+                Err(e) => {
+                    eprintln!("{}: {:#}", path.display(), e);
+                    continue;
+                }
+            };
+            match (flag, only) {
+                (Some(flag), None) => println!("{:<6}  {}", flag.name(), path.display()),
+                (Some(flag), Some(only)) if flag == only => println!("{}", path.display()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct NotesArgs {
+    /// Images or directories whose notes to list
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+}
+
+impl NotesArgs {
+    pub fn run(&self) -> Result<()> {
+        for path in batch::collect_images(&self.paths)? {
+            let sidecar = match Sidecar::open(&path) {
+                Ok(sidecar) => sidecar,
+                Err(e) => {
+                    eprintln!("{}: {:#}", path.display(), e);
+                    continue;
+                }
+            };
+            if sidecar.notes.is_empty() {
+                continue;
+            }
+            println!("{}", path.display());
+            for note in &sidecar.notes {
+                println!("  {}  {}", note.time, note.text);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RecordArgs {
+    /// Geotagged image whose location the globe turns around
+    pub image: PathBuf,
+
+    /// Where to write the recording, a .gif or .cast file
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Length of the turn
+    #[arg(long, default_value_t = 6.)]
+    pub seconds: f64,
+
+    /// Frames per second
+    #[arg(long, default_value_t = 15, value_parser = clap::value_parser!(u32).range(1..=60))]
+    pub fps: u32,
+
+    /// Width in terminal cells, the globe is as high as the recording
+    #[arg(long, default_value_t = 80)]
+    pub columns: usize,
+
+    #[arg(long, default_value_t = 40)]
+    pub rows: usize,
+}
+
+impl RecordArgs {
+    pub fn run(&self) -> Result<()> {
+        let fields = backend::open(&self.image)?.fields();
+        let Some((lat, lon)) = utils::location(|tag| fields.iter().find(|f| f.tag == tag)) else {
+            anyhow::bail!("{:?} has no GPS location", self.image);
+        };
+        if !self.seconds.is_finite() || self.seconds <= 0. || self.columns == 0 || self.rows == 0 {
+            anyhow::bail!(
+                "Nothing to record in {} seconds at {}x{}",
+                self.seconds,
+                self.columns,
+                self.rows
+            );
+        }
+        let recording = Recording {
+            lat,
+            lon,
+            seconds: self.seconds,
+            fps: self.fps,
+            columns: self.columns,
+            rows: self.rows,
+        };
+        recording.write(&self.output)?;
+        println!(
+            "Recorded {:.4}, {:.4} into {}",
+            lat,
+            lon,
+            self.output.display()
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    /// Images or directories to summarize
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Print the summary as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+impl StatsArgs {
+    pub fn run(&self) -> Result<()> {
+        let stats = Stats::collect(&self.paths)?;
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&stats)?);
+            return Ok(());
+        }
+
+        println!(
+            "{:<12} {} ({} without Exif)",
+            "Images", stats.images, stats.without_exif
+        );
+        println!(
+            "{:<12} {} ({:.0}%)",
+            "With GPS",
+            stats.with_gps,
+            stats.gps_percentage()
+        );
+        if let (Some(first), Some(last)) = (&stats.first, &stats.last) {
+            println!("{:<12} {} to {}", "Taken", first, last);
+        }
+        print_counts("Cameras", &stats.cameras);
+        print_counts("Lenses", &stats.lenses);
+        Ok(())
+    }
+}
+
+/// Counts as a horizontal bar chart, scaled to the largest one
+fn print_counts(title: &str, counts: &[Count]) {
+    const BAR_WIDTH: usize = 30;
+    if counts.is_empty() {
+        return;
+    }
+    println!("\n{}", title);
+    let name_width = counts
+        .iter()
+        .map(|c| c.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max = counts.iter().map(|c| c.images).max().unwrap_or(1).max(1);
+    for count in counts {
+        let bar = "█".repeat((count.images * BAR_WIDTH).div_ceil(max));
+        println!(
+            "  {:<width$}  {:>5}  {}",
+            count.name,
+            count.images,
+            bar,
+            width = name_width
+        );
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct TimelineArgs {
+    /// Images or directories to browse
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Processors `s` scrubs the highlighted image or burst with, in order.
+    /// Defaults to strip-all
+    #[arg(short, long = "processor", value_name = "NAME")]
+    pub processors: Vec<String>,
+
+    /// Write scrubbed images into this directory instead of next to the originals
+    #[arg(short, long)]
+    pub out_dir: Option<PathBuf>,
+
+    /// Print the images chosen with Enter instead of opening them
+    #[arg(long)]
+    pub print: bool,
+}
+
+impl TimelineArgs {
+    /// Prints the chosen images so they can be handed on, e.g. to `bresson
+    /// scrub`. `main` opens them in the interface instead, unless `--print`
+    pub fn run(&self) -> Result<()> {
+        for path in self.choose()?.unwrap_or_default() {
+            println!("{}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Shows the timeline until images are chosen with Enter, or it's closed
+    pub fn choose(&self) -> Result<Option<Vec<PathBuf>>> {
+        let mut timeline = Timeline::load(&self.paths)?;
+        if timeline.buckets.is_empty() {
+            anyhow::bail!("No images with a capture time");
+        }
+        let names = match self.processors.is_empty() {
+            true => vec![String::from("strip-all")],
+            false => self.processors.clone(),
+        };
+        // Fail before the terminal is taken over
+        Registry::default().select(&names)?;
+
+        let last = timeline.buckets.len() - 1;
+        let mut view = View::default();
+        tui::run(|terminal| {
+            terminal.clear()?;
+            loop {
+                terminal.draw(|frame| ui::render_timeline(&timeline, &view, frame))?;
+                let Event::Key(key) = event::read()? else {
+                    continue;
+                };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Left | KeyCode::Char('h') => {
+                        view.select_bucket(view.bucket.saturating_sub(1))
+                    }
+                    KeyCode::Right | KeyCode::Char('l') => {
+                        view.select_bucket((view.bucket + 1).min(last))
+                    }
+                    KeyCode::Home => view.select_bucket(0),
+                    KeyCode::End => view.select_bucket(last),
+                    KeyCode::Up | KeyCode::Char('k') => view.move_line(&timeline, -1),
+                    KeyCode::Down | KeyCode::Char('j') => view.move_line(&timeline, 1),
+                    KeyCode::Char(' ') => view.toggle_burst(&timeline),
+                    KeyCode::Char('f') => {
+                        if let Some((bucket, line)) = view.selected(&timeline) {
+                            let paths = bucket.paths(&line);
+                            view.status = timeline.cycle_flags(&paths);
+                        }
+                    }
+                    KeyCode::Char('s') => {
+                        if let Some((bucket, line)) = view.selected(&timeline) {
+                            view.status = self.scrub(&names, &bucket.paths(&line));
+                        }
+                    }
+                    KeyCode::Enter => {
+                        return Ok(Some(match view.selected(&timeline) {
+                            Some((bucket, line)) => bucket.paths(&line),
+                            None => timeline.buckets[view.bucket].paths_all(),
+                        }))
+                    }
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(None),
+                    _ => {}
+                }
+            }
+        })
+    }
+
+    /// Scrubs `paths` with the processors named `names`, all of them the same way
+    fn scrub(&self, names: &[String], paths: &[PathBuf]) -> String {
+        let mut processors = match Registry::default().select(names) {
+            Ok(processors) => processors,
+            Err(e) => return format!("{:#}", e),
+        };
+        let (mut written, mut dropped) = (0, 0);
+        for path in paths {
+            match batch::process_file(
+                &mut processors,
+                path,
+                self.out_dir.as_deref(),
+                false,
+                &Export::default(),
+            ) {
+                Ok(report) => {
+                    written += usize::from(report.output.is_some());
+                    dropped += report.dropped.len();
+                }
+                Err(e) => return format!("{}: {:#}", path.display(), e),
+            }
+        }
+        format!(
+            "Scrubbed {} image(s) with {}, dropped {} field(s) and wrote {} file(s)",
+            paths.len(),
+            names.join(", "),
+            dropped,
+            written
+        )
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct UndoBatchArgs {
+    /// Journal written by `scrub --undo-journal`
+    pub journal: PathBuf,
+
+    /// List the copies and how many fields they'd get back, without changing them
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+}
+
+impl UndoBatchArgs {
+    pub fn run(&self) -> Result<()> {
+        let journal = UndoJournal::load(&self.journal, None)?;
+        let mut failures = 0;
+        for entry in &journal.entries {
+            let fields = entry.manifest.fields.len();
+            if self.dry_run {
+                println!("{}: {} field(s) to put back", entry.copy.display(), fields);
+                continue;
+            }
+            match entry.undo() {
+                Ok(()) => println!("{}: {} field(s) put back", entry.copy.display(), fields),
+                Err(e) => {
+                    failures += 1;
+                    eprintln!("{}: {:#}", entry.copy.display(), e);
+                }
+            }
+        }
+        if failures > 0 {
+            anyhow::bail!("{} copy(ies) could not be restored", failures);
+        }
+        Ok(())
+    }
+}
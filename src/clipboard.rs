@@ -0,0 +1,104 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+// Clipboard
+//
+// `bresson --from-clipboard` opens what was just copied instead of a file named
+// on the command line. Files copied in a file manager arrive as paths and open
+// like any other image, metadata and all. An image copied as pixels, the way
+// most screenshot tools copy, holds no metadata at all - there is nothing in it
+// to inspect or scrub.
+//
+// The last copy saved while the image was open is put back on the clipboard
+// when bresson exits, ready to be pasted wherever it was going.
+//
+// Only built with the `clipboard` feature, without it reading and writing fail
+// saying so.
+
+/// What's on the clipboard
+#[derive(Debug)]
+pub enum Contents {
+    /// Files copied in a file manager
+    Files(Vec<PathBuf>),
+    /// An image copied as pixels, which has no metadata
+    Pixels { width: usize, height: usize },
+}
+
+#[cfg(feature = "clipboard")]
+mod system {
+    use anyhow::{anyhow, Result};
+    use arboard::{Clipboard, Error, ImageData};
+    use std::path::Path;
+
+    use super::Contents;
+
+    /// How long the copy is served after bresson is done, on systems where the
+    /// clipboard is empty again once the program that set it exits. A clipboard
+    /// manager takes it over right away.
+    #[cfg(all(unix, not(target_os = "macos")))]
+    const SERVE_FOR: std::time::Duration = std::time::Duration::from_secs(60);
+
+    pub fn read() -> Result<Contents> {
+        let mut clipboard = Clipboard::new()?;
+        match clipboard.get().file_list() {
+            Ok(files) if !files.is_empty() => return Ok(Contents::Files(files)),
+            Ok(_) | Err(Error::ContentNotAvailable) => {}
+            Err(e) => return Err(e.into()),
+        }
+        match clipboard.get().image() {
+            Ok(image) => Ok(Contents::Pixels {
+                width: image.width,
+                height: image.height,
+            }),
+            Err(Error::ContentNotAvailable) => Err(anyhow!("There's no image on the clipboard")),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn put_image(path: &Path) -> Result<()> {
+        let image = image::open(path)?.into_rgba8();
+        let image = ImageData {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            bytes: image.into_raw().into(),
+        };
+        let mut clipboard = Clipboard::new()?;
+        let set = clipboard.set();
+        #[cfg(all(unix, not(target_os = "macos")))]
+        let set = {
+            use arboard::SetExtLinux;
+            set.wait_until(std::time::Instant::now() + SERVE_FOR)
+        };
+        set.image(image)?;
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "clipboard"))]
+mod system {
+    use anyhow::{bail, Result};
+    use std::path::Path;
+
+    use super::Contents;
+
+    const MISSING: &str =
+        "bresson was built without clipboard support, rebuild it with --features clipboard";
+
+    pub fn read() -> Result<Contents> {
+        bail!(MISSING)
+    }
+
+    pub fn put_image(_path: &Path) -> Result<()> {
+        bail!(MISSING)
+    }
+}
+
+/// Reads what's on the system clipboard
+pub fn read() -> Result<Contents> {
+    system::read()
+}
+
+/// Puts the image at `path` on the system clipboard, as pixels
+pub fn put_image(path: &Path) -> Result<()> {
+    system::put_image(path)
+}
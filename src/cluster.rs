@@ -0,0 +1,174 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use exif::{In, Reader};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::{batch, utils};
+
+// Cluster
+//
+// Groups geotagged images by where they were taken. An image joins a cluster
+// when it's within the given distance of any image already in it, so a walk
+// through a city ends up as one cluster while a trip to the next town starts
+// another one. Images without a location are counted but not placed.
+//
+// Images that also have a capture time make up the path travelled, which is
+// drawn as great circle arcs between consecutive images.
+
+const EARTH_RADIUS_KM: f64 = 6371.;
+
+/// An image and where it was taken
+pub type Located = (PathBuf, (f64, f64));
+
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    /// Center of the cluster in signed degrees
+    pub lat: f64,
+    pub lon: f64,
+    pub images: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default)]
+pub struct Clusters {
+    /// Largest cluster first
+    pub clusters: Vec<Cluster>,
+    /// Images without a location
+    pub untagged: usize,
+    /// Locations of the images with a capture time, in the order they were taken
+    pub path: Vec<(f64, f64)>,
+}
+
+impl Clusters {
+    pub fn load(paths: &[PathBuf], radius_km: f64) -> Result<Self> {
+        let mut located = Vec::new();
+        let mut timed = Vec::new();
+        let mut untagged = 0;
+        for path in batch::collect_images(paths)? {
+            match locate(&path) {
+                Some((position, time)) => {
+                    if let Some(time) = time {
+                        timed.push((time, position));
+                    }
+                    located.push((path, position));
+                }
+                None => untagged += 1,
+            }
+        }
+        timed.sort_by_key(|(time, _)| *time);
+        Ok(Self {
+            clusters: cluster(located, radius_km),
+            untagged,
+            path: timed.into_iter().map(|(_, position)| position).collect(),
+        })
+    }
+}
+
+/// Great circle distance between two points in signed degrees
+pub fn distance_km((lat1, lon1): (f64, f64), (lat2, lon2): (f64, f64)) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.).sin().powi(2);
+    2. * EARTH_RADIUS_KM * a.sqrt().clamp(-1., 1.).asin()
+}
+
+/// Points at most `step` degrees apart along the shortest path from `a` to `b`,
+/// both ends included
+pub fn great_circle(a: (f64, f64), b: (f64, f64), step: f64) -> Vec<(f64, f64)> {
+    let to_vector = |(lat, lon): (f64, f64)| {
+        let (lat, lon) = (lat.to_radians(), lon.to_radians());
+        [lat.cos() * lon.cos(), lat.cos() * lon.sin(), lat.sin()]
+    };
+    let (p, q) = (to_vector(a), to_vector(b));
+    let angle = (p[0] * q[0] + p[1] * q[1] + p[2] * q[2])
+        .clamp(-1., 1.)
+        .acos();
+    let steps = (angle.to_degrees() / step).ceil() as usize;
+    // Antipodes have no single shortest path
+    if steps < 2 || angle.sin().abs() < 1e-9 {
+        return vec![a, b];
+    }
+
+    (0..=steps)
+        .map(|i| {
+            let t = i as f64 / steps as f64;
+            let s = ((1. - t) * angle).sin() / angle.sin();
+            let r = (t * angle).sin() / angle.sin();
+            let v = [0, 1, 2].map(|k| s * p[k] + r * q[k]);
+            (
+                v[2].atan2(v[0].hypot(v[1])).to_degrees(),
+                v[1].atan2(v[0]).to_degrees(),
+            )
+        })
+        .collect()
+}
+
+/// Where and, if known, when the image was taken
+pub fn locate(path: &Path) -> Option<((f64, f64), Option<NaiveDateTime>)> {
+    let img_buf = std::fs::read(path).ok()?;
+    let exif = Reader::new()
+        .read_from_container(&mut Cursor::new(&img_buf))
+        .ok()?;
+    let field = |tag| exif.get_field(tag, In::PRIMARY);
+    let position = utils::location(field)?;
+    Some((position, utils::capture_time(field).map(|(time, _)| time)))
+}
+
+/// Groups images that are within `radius_km` of each other, largest group first
+pub fn cluster(located: Vec<Located>, radius_km: f64) -> Vec<Cluster> {
+    // Union-find over every pair of images closer than the radius
+    let mut parent = (0..located.len()).collect::<Vec<_>>();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    for i in 0..located.len() {
+        for j in i + 1..located.len() {
+            if distance_km(located[i].1, located[j].1) <= radius_km {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a] = b;
+            }
+        }
+    }
+
+    let mut groups: Vec<(usize, Vec<Located>)> = Vec::new();
+    for (i, image) in located.into_iter().enumerate() {
+        let r = root(&mut parent, i);
+        match groups.iter_mut().find(|(g, _)| *g == r) {
+            Some((_, members)) => members.push(image),
+            None => groups.push((r, vec![image])),
+        }
+    }
+
+    let mut clusters = groups
+        .into_iter()
+        .map(|(_, members)| {
+            let (lat, lon) = center(members.iter().map(|(_, p)| *p));
+            Cluster {
+                lat,
+                lon,
+                images: members.into_iter().map(|(path, _)| path).collect(),
+            }
+        })
+        .collect::<Vec<_>>();
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.images.len()));
+    clusters
+}
+
+/// Mean position, averaged on the sphere so clusters across the antimeridian work
+fn center(points: impl Iterator<Item = (f64, f64)>) -> (f64, f64) {
+    let (mut x, mut y, mut z) = (0., 0., 0.);
+    for (lat, lon) in points {
+        let (lat, lon) = (lat.to_radians(), lon.to_radians());
+        x += lat.cos() * lon.cos();
+        y += lat.cos() * lon.sin();
+        z += lat.sin();
+    }
+    (z.atan2(x.hypot(y)).to_degrees(), y.atan2(x).to_degrees())
+}
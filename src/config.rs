@@ -0,0 +1,232 @@
+use anyhow::{bail, Context as _, Result};
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::{
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use crate::{dirs, image::Background, policy, randomize::RandomMetadata, utils};
+
+// Config
+//
+// Preferences that outlive a session are read from a TOML file when the TUI
+// starts, `$XDG_CONFIG_HOME/bresson/config.toml` (or `~/.config/...` when that
+// isn't set) unless `--config` names another one. Every section is optional and
+// a missing default file is the same as an empty one. `bresson config init`
+// writes one with every setting commented out, `DEFAULT_TOML`.
+//
+//     [randomize]
+//     add = ["ImageUniqueID"]
+//     remove = ["*SerialNumber", "ExifVersion"]
+//     weighted = false
+//     earliest = "2022-01-01"
+//     latest = "2022-12-31"
+//
+//     [thumbnail]
+//     background = "#1e1e2e"
+//
+//     [globe]
+//     font_ratio = 2.2
+//     starfield = true
+//     regions = "/srv/maps/regions"
+//
+//     [confirm]
+//     bulk = false
+//
+// `randomize` adjusts which tags `R` touches, whether camera makes are picked
+// by market share and the days random capture times fall on. Added tags are exact Exif tag names, removed ones may
+// contain `*` wildcards like in a policy. Names that aren't tags are reported
+// rather than refused, so a config written for another version doesn't keep
+// the TUI from starting.
+//
+// `thumbnail` sets what transparent images are blended over and the thumbnail
+// is padded with - black by default, a color name, a hex color or "terminal" to
+// let terminals with transparent graphics show their own background.
+//
+// `globe.font_ratio` is the height of a terminal cell over its width. It's
+// taken from the font size terminals report, this is for those that don't or
+// get it wrong, when the globe looks oval rather than round. `starfield` draws
+// stars and an atmosphere around it from the start, `*` toggles them.
+// `regions` is a directory of detailed textures of parts of the earth, see
+// `globe::Region`, drawn once zoomed in close enough. The `regions` directory
+// in the data directory is used when it isn't set, or the one next to the
+// config where earlier versions looked.
+//
+// `confirm.bulk` turns off the question before `C` and `R` change every field
+// at once.
+
+/// The config `bresson config init` writes, every setting commented out
+pub const DEFAULT_TOML: &str = include_str!("../templates/config.toml");
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub randomize: RandomizeConfig,
+    pub thumbnail: ThumbnailConfig,
+    pub globe: GlobeConfig,
+    pub confirm: ConfirmConfig,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RandomizeConfig {
+    /// Tags randomized on top of the built-in ones
+    pub add: Vec<String>,
+    /// Tags left alone even if they're built in, wins over `add`
+    pub remove: Vec<String>,
+    /// Pick camera makes by market share, `false` picks them uniformly
+    pub weighted: Option<bool>,
+    /// First and last day of random capture times, e.g. "2022-01-01"
+    pub earliest: Option<String>,
+    pub latest: Option<String>,
+}
+
+impl RandomizeConfig {
+    /// The days random capture times fall on, if the config narrows them down
+    pub fn dates(&self) -> Result<Option<RangeInclusive<NaiveDate>>> {
+        let parse = |date: &Option<String>| {
+            date.as_deref()
+                .map(|d| {
+                    NaiveDate::parse_from_str(d, "%Y-%m-%d")
+                        .with_context(|| format!("Invalid date {:?}, expected YYYY-MM-DD", d))
+                })
+                .transpose()
+        };
+        let (earliest, latest) = (parse(&self.earliest)?, parse(&self.latest)?);
+        if earliest.is_none() && latest.is_none() {
+            return Ok(None);
+        }
+        let defaults = RandomMetadata::default().dates;
+        let dates = earliest.unwrap_or(*defaults.start())..=latest.unwrap_or(*defaults.end());
+        if dates.is_empty() {
+            bail!("No days from {} to {}", dates.start(), dates.end());
+        }
+        Ok(Some(dates))
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThumbnailConfig {
+    /// "terminal", a color name or "#rrggbb"
+    pub background: Option<String>,
+}
+
+impl ThumbnailConfig {
+    pub fn background(&self) -> Result<Background> {
+        self.background
+            .as_deref()
+            .map_or(Ok(Background::default()), Background::from_name)
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GlobeConfig {
+    /// Height of a terminal cell over its width, instead of the detected one
+    pub font_ratio: Option<f64>,
+    /// Draw stars and an atmosphere around the globe
+    pub starfield: bool,
+    /// Directory of regional textures
+    pub regions: Option<PathBuf>,
+}
+
+impl GlobeConfig {
+    /// Cells from four times as wide as high to four times as high as wide
+    const FONT_RATIOS: RangeInclusive<f64> = 0.25..=4.;
+
+    /// Where regional textures are read from, if anywhere
+    pub fn regions_dir(&self) -> Option<PathBuf> {
+        match &self.regions {
+            Some(dir) => Some(dir.clone()),
+            None => [dirs::data_dir(), dirs::config_dir()]
+                .into_iter()
+                .flatten()
+                .map(|dir| dir.join("regions"))
+                .find(|dir| dir.is_dir()),
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        match self.font_ratio {
+            Some(ratio) if !Self::FONT_RATIOS.contains(&ratio) => bail!(
+                "Invalid font_ratio {}, expected {} to {}",
+                ratio,
+                Self::FONT_RATIOS.start(),
+                Self::FONT_RATIOS.end()
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfirmConfig {
+    /// Ask before clearing or randomizing every field, `true` by default
+    pub bulk: Option<bool>,
+}
+
+impl Config {
+    /// Where the config is read from without `--config`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read config {:?}", path))?;
+        Self::from_toml(&data).with_context(|| format!("Invalid config {:?}", path))
+    }
+
+    /// Loads the file passed to `--config`, or the default one if there is one
+    pub fn open(path: Option<&Path>) -> Result<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None => match Self::default_path().filter(|p| p.is_file()) {
+                Some(path) => Self::load(&path),
+                None => Ok(Self::default()),
+            },
+        }
+    }
+
+    pub fn from_toml(data: &str) -> Result<Self> {
+        let config: Config = toml::from_str(data)?;
+        config.randomize.dates()?;
+        config.thumbnail.background()?;
+        config.globe.check()?;
+        Ok(config)
+    }
+
+    /// Adjusts the tags `randomizer` touches and how, returning the names in the
+    /// config that don't match any tag
+    pub fn apply(&self, randomizer: &mut RandomMetadata) -> Vec<String> {
+        if let Some(weighted) = self.randomize.weighted {
+            randomizer.weighted = weighted;
+        }
+        // Checked when the config was read
+        if let Ok(Some(dates)) = self.randomize.dates() {
+            randomizer.dates = dates;
+        }
+        let mut unknown = Vec::new();
+        for name in &self.randomize.add {
+            match utils::tag_from_name(name) {
+                Some(tag) => {
+                    randomizer.tags_to_randomize.insert(tag);
+                }
+                None => unknown.push(name.clone()),
+            }
+        }
+        for pattern in &self.randomize.remove {
+            if !pattern.contains('*') && utils::tag_from_name(pattern).is_none() {
+                unknown.push(pattern.clone());
+                continue;
+            }
+            randomizer
+                .tags_to_randomize
+                .retain(|tag| !policy::glob_match(pattern, &utils::tag_name(*tag)));
+        }
+        unknown
+    }
+}
@@ -0,0 +1,35 @@
+use std::path::{Path, PathBuf};
+
+// Dirs
+//
+// Where bresson keeps files between runs, after the XDG base directories -
+// preferences in `$XDG_CONFIG_HOME/bresson`, data the user adds or builds up
+// in `$XDG_DATA_HOME/bresson` and whatever can be fetched again in
+// `$XDG_CACHE_HOME/bresson`. Unset or empty variables fall back to
+// `~/.config`, `~/.local/share` and `~/.cache`.
+//
+// Anything new that's kept between runs goes in one of these three, so a user
+// can tell what's safe to delete and back up the rest.
+
+fn base(var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = std::env::var_os(var)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(fallback)))?;
+    Some(base.join("bresson"))
+}
+
+/// Preferences, like `config.toml`
+pub fn config_dir() -> Option<PathBuf> {
+    base("XDG_CONFIG_HOME", ".config")
+}
+
+/// Files the user adds or bresson builds up, like regional textures
+pub fn data_dir() -> Option<PathBuf> {
+    base("XDG_DATA_HOME", ".local/share")
+}
+
+/// Files that can be fetched again, like map tiles
+pub fn cache_dir() -> Option<PathBuf> {
+    base("XDG_CACHE_HOME", ".cache")
+}
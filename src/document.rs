@@ -0,0 +1,371 @@
+use anyhow::{bail, Context as _, Result};
+use exif::{Field, In, Tag, Value};
+use lopdf::{Dictionary, Object, StringFormat};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    backend::MetadataBackend,
+    batch, utils,
+    vault::{Manifest, ManifestValue},
+};
+
+// Document
+//
+// Metadata of PDF documents, the document counterpart of the Exif handling for
+// images. PDFs keep it in two places - the Info dictionary referenced by the
+// trailer, which has the title, author, the creating application and dates, and
+// an XMP packet attached to the document catalog that usually repeats them.
+//
+// Scrubbing removes the Info entries and the XMP packet. Saving rewrites the
+// whole file from its objects, so earlier revisions that an incremental update
+// left behind in the file are dropped along with them.
+//
+// As a backend, the Info entries are shown and edited as the Exif tags closest
+// to them, see `INFO_TAGS`. Copies are written without the XMP packet, which
+// would otherwise contradict the edited entries, and without entries that have
+// no tag, since there's no telling what they hold.
+//
+// https://opensource.adobe.com/dc-acrobat-sdk-docs/pdfstandards/PDF32000_2008.pdf (14.3)
+
+pub const EXTENSIONS: [&str; 1] = ["pdf"];
+
+/// Info entries and the tags they're shown as. The producer is the program that
+/// wrote the file, like the camera for an image.
+const INFO_TAGS: [(&[u8], Tag); 8] = [
+    (b"Title", utils::XP_TAGS[0]),
+    (b"Author", Tag::Artist),
+    (b"Subject", utils::XP_TAGS[4]),
+    (b"Keywords", utils::XP_TAGS[3]),
+    (b"Creator", Tag::Software),
+    (b"Producer", Tag::Make),
+    (b"CreationDate", Tag::DateTimeOriginal),
+    (b"ModDate", Tag::DateTime),
+];
+
+/// Whether `file` is a PDF, by its header
+pub fn is_pdf(file: &[u8]) -> bool {
+    file.starts_with(b"%PDF-")
+}
+
+pub fn is_document(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Documents in `paths`, searching directories recursively
+pub fn collect_documents(paths: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    batch::collect_files(paths, is_document)
+}
+
+#[derive(Debug, Default)]
+pub struct ScrubReport {
+    pub removed: Vec<String>,
+    pub removed_xmp: bool,
+    pub output: Option<PathBuf>,
+}
+
+pub struct Document {
+    pdf: lopdf::Document,
+}
+
+impl Document {
+    pub fn load(path: &Path) -> Result<Self> {
+        let pdf = lopdf::Document::load(path)
+            .with_context(|| format!("Unable to read PDF {:?}", path))?;
+        Self::new(pdf)
+    }
+
+    /// Reads the PDF in `file`, the contents of a file already in memory
+    pub fn parse(file: &[u8]) -> Result<Self> {
+        Self::new(lopdf::Document::load_mem(file).context("Unable to read PDF")?)
+    }
+
+    fn new(pdf: lopdf::Document) -> Result<Self> {
+        if pdf.trailer.has(b"Encrypt") {
+            bail!("Encrypted documents are not supported");
+        }
+        Ok(Self { pdf })
+    }
+
+    fn info(&self) -> Option<&Dictionary> {
+        match self.pdf.trailer.get(b"Info").ok()? {
+            Object::Reference(id) => self.pdf.get_dictionary(*id).ok(),
+            Object::Dictionary(info) => Some(info),
+            _ => None,
+        }
+    }
+
+    fn info_mut(&mut self) -> Option<&mut Dictionary> {
+        match self.pdf.trailer.get(b"Info").ok()? {
+            Object::Reference(id) => {
+                let id = *id;
+                self.pdf.get_dictionary_mut(id).ok()
+            }
+            Object::Dictionary(_) => self.pdf.trailer.get_mut(b"Info").ok()?.as_dict_mut().ok(),
+            _ => None,
+        }
+    }
+
+    /// Entries of the Info dictionary, with text and dates decoded
+    pub fn fields(&self) -> Vec<(String, String)> {
+        self.info().map_or(Vec::new(), |info| {
+            info.iter()
+                .map(|(key, value)| (String::from_utf8_lossy(key).into_owned(), display(value)))
+                .collect()
+        })
+    }
+
+    /// Whether the catalog carries an XMP packet
+    pub fn has_xmp(&self) -> bool {
+        self.pdf
+            .catalog()
+            .is_ok_and(|catalog| catalog.has(b"Metadata"))
+    }
+
+    /// Removes every Info entry but the `keep` ones, and the XMP packet
+    pub fn scrub(&mut self, keep: &[String]) -> ScrubReport {
+        let mut report = ScrubReport::default();
+        if let Some(info) = self.info_mut() {
+            let keys = info
+                .iter()
+                .map(|(key, _)| key.clone())
+                .filter(|key| !keep.iter().any(|k| k.as_bytes() == key.as_slice()))
+                .collect::<Vec<_>>();
+            for key in keys {
+                info.remove(&key);
+                report
+                    .removed
+                    .push(String::from_utf8_lossy(&key).into_owned());
+            }
+        }
+        if let Ok(catalog) = self.pdf.catalog_mut() {
+            report.removed_xmp = catalog.remove(b"Metadata").is_some();
+        }
+        // The XMP stream isn't referenced by anything anymore
+        self.pdf.prune_objects();
+        report
+    }
+
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        self.pdf
+            .save(path)
+            .with_context(|| format!("Unable to write {:?}", path))?;
+        Ok(())
+    }
+}
+
+/// The Info entries of a PDF as fields
+pub struct PdfBackend {
+    fields: Vec<Field>,
+}
+
+impl PdfBackend {
+    pub fn parse(file: &[u8]) -> Result<Self> {
+        let document = Document::parse(file)?;
+        let fields = document.info().map_or(Vec::new(), |info| {
+            INFO_TAGS
+                .iter()
+                .filter_map(|(key, tag)| Some(to_field(*tag, info.get(key).ok()?)))
+                .collect()
+        });
+        Ok(Self { fields })
+    }
+}
+
+impl MetadataBackend for PdfBackend {
+    fn name(&self) -> &'static str {
+        "PDF"
+    }
+
+    fn fields(&self) -> Vec<Field> {
+        self.fields.clone()
+    }
+
+    fn is_editable(&self, tag: Tag) -> bool {
+        INFO_TAGS.iter().any(|(_, t)| *t == tag)
+    }
+
+    fn display(&self, field: &Field) -> String {
+        utils::display_text(field)
+    }
+
+    fn write(&self, file: &[u8], fields: &mut dyn Iterator<Item = &Field>) -> Result<Vec<u8>> {
+        let mut document = Document::parse(file)?;
+        let old = document.info().cloned().unwrap_or_default();
+        let mut info = Dictionary::new();
+        for field in fields {
+            let Some((key, _)) = INFO_TAGS.iter().find(|(_, tag)| *tag == field.tag) else {
+                continue;
+            };
+            // Entries that weren't changed are kept as they were, with the
+            // time zone of a date and the encoding of the text
+            let value = match old.get(key) {
+                Ok(value) if same_value(&to_field(field.tag, value), field) => value.clone(),
+                _ => from_field(field),
+            };
+            info.set(*key, value);
+        }
+        let pdf = &mut document.pdf;
+        pdf.trailer.remove(b"Info");
+        if !info.is_empty() {
+            let id = pdf.add_object(info);
+            pdf.trailer.set("Info", id);
+        }
+        if let Ok(catalog) = pdf.catalog_mut() {
+            catalog.remove(b"Metadata");
+        }
+        pdf.prune_objects();
+        let mut copy = Vec::new();
+        pdf.save_to(&mut copy)?;
+        Ok(copy)
+    }
+
+    fn manifest(&self, source: &Path) -> Manifest {
+        Manifest::from_fields(source, &self.fields, false)
+    }
+}
+
+/// The Info entry `value` as a field for `tag`
+fn to_field(tag: Tag, value: &Object) -> Field {
+    let text = match value {
+        Object::String(bytes, _) => decode_text(bytes),
+        other => display(other),
+    };
+    let value = match tag {
+        _ if utils::XP_TAGS.contains(&tag) => Value::Byte(utils::encode_xp(&text)),
+        Tag::DateTimeOriginal | Tag::DateTime => {
+            let date = decode_date(&text).and_then(|date| date.get(..19).map(str::to_owned));
+            // Exif dates are written with colons between the parts of the day
+            let date = date.map(|d| d.replacen('-', ":", 2));
+            Value::Ascii(vec![date.unwrap_or(text).into_bytes()])
+        }
+        _ => Value::Ascii(vec![text.into_bytes()]),
+    };
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    }
+}
+
+fn same_value(a: &Field, b: &Field) -> bool {
+    ManifestValue::from_value(&a.value) == ManifestValue::from_value(&b.value)
+}
+
+/// `field` as the value of its Info entry, the reverse of `to_field`
+fn from_field(field: &Field) -> Object {
+    let text = match &field.value {
+        Value::Byte(bytes) if utils::XP_TAGS.contains(&field.tag) => utils::decode_xp(bytes),
+        Value::Ascii(strings) => strings
+            .iter()
+            .map(|s| utils::decode_ascii(s))
+            .collect::<Vec<_>>()
+            .join(", "),
+        _ => utils::display_text(field),
+    };
+    let text = match field.tag {
+        Tag::DateTimeOriginal | Tag::DateTime => encode_date(&text).unwrap_or(text),
+        _ => text,
+    };
+    Object::String(encode_text(&text), StringFormat::Literal)
+}
+
+/// Scrubs the document at `path` into a copy, like `batch::process_file` does
+/// for images
+pub fn scrub_file(
+    path: &Path,
+    keep: &[String],
+    out_dir: Option<&Path>,
+    dry_run: bool,
+) -> Result<ScrubReport> {
+    let mut document = Document::load(path)?;
+    let mut report = document.scrub(keep);
+    if dry_run || (report.removed.is_empty() && !report.removed_xmp) {
+        return Ok(report);
+    }
+    let output = batch::output_path(path, out_dir)?;
+    document.save(&output)?;
+    report.output = Some(output);
+    Ok(report)
+}
+
+/// `text` as a text string, UTF-16 unless it's ASCII
+fn encode_text(text: &str) -> Vec<u8> {
+    match text.is_ascii() {
+        true => text.as_bytes().to_vec(),
+        false => [0xFE, 0xFF]
+            .into_iter()
+            .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+            .collect(),
+    }
+}
+
+/// Text strings are either UTF-16 with a byte order mark or PDFDocEncoding,
+/// which matches Latin-1 for everything that matters here
+fn decode_text(bytes: &[u8]) -> String {
+    match bytes {
+        [0xFE, 0xFF, rest @ ..] => {
+            let units = rest
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        }
+        _ => bytes.iter().map(|&b| char::from(b)).collect(),
+    }
+}
+
+/// Dates look like `D:20230614182231+02'00'`, everything after the year is optional
+fn decode_date(text: &str) -> Option<String> {
+    let text = text.strip_prefix("D:")?;
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (digits, zone) = text.split_at(split);
+    if digits.len() < 4 {
+        return None;
+    }
+    let part = |range: std::ops::Range<usize>, default| digits.get(range).unwrap_or(default);
+    let mut date = format!(
+        "{}-{}-{} {}:{}:{}",
+        &digits[..4],
+        part(4..6, "01"),
+        part(6..8, "01"),
+        part(8..10, "00"),
+        part(10..12, "00"),
+        part(12..14, "00")
+    );
+    let zone = zone.replace('\'', "");
+    match zone.as_str() {
+        "Z" => date.push_str(" UTC"),
+        zone if zone.is_ascii() && zone.len() >= 3 => date.push_str(&format!(
+            " {}:{}",
+            &zone[..3],
+            zone.get(3..5).unwrap_or("00")
+        )),
+        _ => {}
+    }
+    Some(date)
+}
+
+/// An Exif date like `2023:06:14 18:22:31` as a PDF date, `None` if it's
+/// anything else
+fn encode_date(text: &str) -> Option<String> {
+    let date = chrono::NaiveDateTime::parse_from_str(text, "%Y:%m:%d %H:%M:%S").ok()?;
+    Some(date.format("D:%Y%m%d%H%M%S").to_string())
+}
+
+fn display(value: &Object) -> String {
+    match value {
+        Object::String(bytes, _) => {
+            let text = decode_text(bytes);
+            decode_date(&text).unwrap_or(text)
+        }
+        Object::Name(name) => String::from_utf8_lossy(name).into_owned(),
+        Object::Boolean(b) => b.to_string(),
+        Object::Integer(i) => i.to_string(),
+        Object::Real(r) => r.to_string(),
+        other => format!("{:?}", other),
+    }
+}
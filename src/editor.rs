@@ -0,0 +1,419 @@
+use anyhow::{anyhow, bail, Context as _, Result};
+use chrono::{NaiveDate, NaiveDateTime};
+use exif::{Field, Rational, SRational, Tag, Value};
+use std::{fmt, str::FromStr};
+
+use crate::utils;
+
+// Editor
+//
+// Changing a field by hand instead of randomizing it. Tags whose values are
+// codes with a fixed meaning, like WhiteBalance, are edited by picking the
+// meaning from a list, so nobody has to look up which number is which. The
+// picked code is stored as a SHORT, the type Exif gives all of them.
+//
+// Exposure settings are typed the way photographers write them, "1/250",
+// "f/2.8" or "35mm", and turned into the rationals cameras store: exposure
+// times below a second as 1/N and everything else with as many decimals as
+// were typed. `parse_rational` does the same for anything else reading
+// values from text.
+//
+// Everything else is typed as text and read back as the type the field had:
+// text for ASCII fields, in the Latin-1 or UTF-8 the field was in when it's
+// more than ASCII, UTF-16 for the XP tags Windows writes, dates as
+// "YYYY:MM:DD HH:MM:SS" the way Exif writes them, whole numbers within the
+// range of their type and fractions for rationals, with commas between the
+// values of fields that hold several. A typo, or a character Latin-1 text has
+// no place for, keeps the editor open rather than storing something cameras
+// never would.
+//
+// The same popup takes notes about the image, which are plain text.
+
+/// Codes a tag can have along with their meaning, as defined by Exif 2.32
+type Choices = &'static [(u16, &'static str)];
+
+const WHITE_BALANCE: Choices = &[(0, "Auto"), (1, "Manual")];
+
+const COLOR_SPACE: Choices = &[(1, "sRGB"), (0xffff, "Uncalibrated")];
+
+const METERING_MODE: Choices = &[
+    (0, "Unknown"),
+    (1, "Average"),
+    (2, "Center-weighted average"),
+    (3, "Spot"),
+    (4, "Multi-spot"),
+    (5, "Pattern"),
+    (6, "Partial"),
+    (255, "Other"),
+];
+
+const EXPOSURE_PROGRAM: Choices = &[
+    (0, "Not defined"),
+    (1, "Manual"),
+    (2, "Normal program"),
+    (3, "Aperture priority"),
+    (4, "Shutter priority"),
+    (5, "Creative program"),
+    (6, "Action program"),
+    (7, "Portrait mode"),
+    (8, "Landscape mode"),
+];
+
+const SCENE_CAPTURE_TYPE: Choices = &[
+    (0, "Standard"),
+    (1, "Landscape"),
+    (2, "Portrait"),
+    (3, "Night scene"),
+];
+
+/// The codes `tag` can have, if it's one edited by picking from a list
+pub fn choices(tag: Tag) -> Option<Choices> {
+    match tag {
+        Tag::WhiteBalance => Some(WHITE_BALANCE),
+        Tag::ColorSpace => Some(COLOR_SPACE),
+        Tag::MeteringMode => Some(METERING_MODE),
+        Tag::ExposureProgram => Some(EXPOSURE_PROGRAM),
+        Tag::SceneCaptureType => Some(SCENE_CAPTURE_TYPE),
+        _ => None,
+    }
+}
+
+/// Rational tags that are typed in, all of them a single RATIONAL
+const RATIONAL_TAGS: [Tag; 3] = [Tag::ExposureTime, Tag::FNumber, Tag::FocalLength];
+
+/// Reads `text` as the value of the rational `tag`, e.g. "1/250" or "0.004" for
+/// an ExposureTime, "f/2.8" for an FNumber and "35mm" for a FocalLength
+pub fn parse_rational(tag: Tag, text: &str) -> Result<Rational> {
+    let text = text.trim();
+    let text = match tag {
+        Tag::FNumber => text
+            .strip_prefix("f/")
+            .or_else(|| text.strip_prefix("F/"))
+            .unwrap_or(text),
+        Tag::ExposureTime => text.strip_suffix('s').unwrap_or(text),
+        Tag::FocalLength => text.strip_suffix("mm").unwrap_or(text),
+        _ => text,
+    }
+    .trim();
+
+    if let Some((num, denom)) = text.split_once('/') {
+        let num: u32 = num
+            .trim()
+            .parse()
+            .context("Expected a whole number over /")?;
+        let denom: u32 = denom
+            .trim()
+            .parse()
+            .context("Expected a whole number under /")?;
+        if denom == 0 {
+            bail!("Cannot divide by zero");
+        }
+        return Ok(Rational::from((num, denom)));
+    }
+
+    let value: f64 = text
+        .parse()
+        .ok()
+        .filter(|v: &f64| v.is_finite() && *v >= 0.)
+        .with_context(|| format!("Expected a number or fraction, not {:?}", text))?;
+    // Cameras write exposure times like 1/250, not 4/1000
+    if tag == Tag::ExposureTime && value > 0. && value < 1. {
+        let denom = (1. / value).round();
+        if ((1. / denom) - value).abs() <= value * 0.01 {
+            return Ok(Rational::from((1, denom as u32)));
+        }
+    }
+    let decimals = text.split_once('.').map_or(0, |(_, d)| d.len()) as u32;
+    let denom = 10u32.checked_pow(decimals).context("Too many decimals")?;
+    let num = (value * f64::from(denom)).round();
+    if num > f64::from(u32::MAX) {
+        bail!("{} is too large", text);
+    }
+    Ok(Rational::from((num as u32, denom)))
+}
+
+/// Tags holding a date and time, written "YYYY:MM:DD HH:MM:SS"
+const DATE_TIME_TAGS: [Tag; 3] = [Tag::DateTime, Tag::DateTimeOriginal, Tag::DateTimeDigitized];
+
+/// Reads `text` as an Exif date and time, also taking dashes between the parts
+/// of the date
+fn parse_date_time(text: &str) -> Result<String> {
+    let text = text.trim().replacen('-', ":", 2);
+    let date_time = NaiveDateTime::parse_from_str(&text, "%Y:%m:%d %H:%M:%S")
+        .with_context(|| format!("Expected a date like 2024:05:31 18:30:00, not {:?}", text))?;
+    Ok(date_time.format("%Y:%m:%d %H:%M:%S").to_string())
+}
+
+/// Reads `text` as an Exif date without a time, like the GPSDateStamp
+fn parse_date(text: &str) -> Result<String> {
+    let text = text.trim().replacen('-', ":", 2);
+    let date = NaiveDate::parse_from_str(&text, "%Y:%m:%d")
+        .with_context(|| format!("Expected a date like 2024:05:31, not {:?}", text))?;
+    Ok(date.format("%Y:%m:%d").to_string())
+}
+
+/// Reads `text` as the value of an ASCII field. Beyond ASCII it's written as
+/// UTF-8, or as Latin-1 if `latin1`, which only takes the characters it has.
+fn parse_ascii(text: &str, latin1: bool) -> Result<Vec<u8>> {
+    if let Some(c) = text.chars().find(|c| c.is_control()) {
+        bail!("Expected text without control characters, not {:?}", c);
+    }
+    if latin1 {
+        return text
+            .chars()
+            .map(|c| {
+                u8::try_from(c)
+                    .map_err(|_| anyhow!("The field is Latin-1 text, which has no {:?}", c))
+            })
+            .collect();
+    }
+    Ok(text.as_bytes().to_vec())
+}
+
+/// Reads `text` as a signed rational, e.g. "-1/3" or "-0.7"
+fn parse_srational(tag: Tag, text: &str) -> Result<SRational> {
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let r = parse_rational(tag, text)?;
+    let num = i32::try_from(r.num).context("Too large")?;
+    let denom = i32::try_from(r.denom).context("Too large")?;
+    Ok(SRational::from((if negative { -num } else { num }, denom)))
+}
+
+/// Reads `text` as a whole number that fits the field's type
+fn parse_int<T: TryFrom<i64>>(text: &str) -> Result<T> {
+    let text = text.trim();
+    let n: i64 = text
+        .parse()
+        .with_context(|| format!("Expected a whole number, not {:?}", text))?;
+    T::try_from(n).map_err(|_| anyhow!("{} is out of range for the field", n))
+}
+
+fn parse_float<T: FromStr>(text: &str) -> Result<T> {
+    let text = text.trim();
+    text.parse()
+        .map_err(|_| anyhow!("Expected a number, not {:?}", text))
+}
+
+/// Reads the comma separated values in `text` with `parse`, as many as `count`
+fn parse_list<T>(text: &str, count: usize, parse: impl Fn(&str) -> Result<T>) -> Result<Vec<T>> {
+    let values = text.split(',').map(parse).collect::<Result<Vec<T>>>()?;
+    if values.len() != count {
+        bail!("Expected {} values separated by commas", count);
+    }
+    Ok(values)
+}
+
+/// `value` the way it's typed into the editor, `None` if it can't be typed
+fn format_value(tag: Tag, value: &Value) -> Option<String> {
+    fn join<T: ToString>(values: impl IntoIterator<Item = T>) -> String {
+        values
+            .into_iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+    let text = match value {
+        Value::Ascii(strings) => strings
+            .first()
+            .map(|s| utils::decode_ascii(s))
+            .unwrap_or_default(),
+        Value::Byte(v) if utils::XP_TAGS.contains(&tag) => utils::decode_xp(v),
+        Value::Byte(v) => join(v),
+        Value::Short(v) => join(v),
+        Value::Long(v) => join(v),
+        Value::SByte(v) => join(v),
+        Value::SShort(v) => join(v),
+        Value::SLong(v) => join(v),
+        Value::Float(v) => join(v),
+        Value::Double(v) => join(v),
+        Value::Rational(v) => join(v.iter().map(|r| format_rational(tag, r))),
+        Value::SRational(v) => join(v.iter().map(|r| format_srational(tag, r))),
+        Value::Undefined(..) | Value::Unknown(..) => return None,
+    };
+    Some(text)
+}
+
+/// `rational` the way it's typed into the editor
+fn format_rational(tag: Tag, rational: &Rational) -> String {
+    if rational.denom == 0 {
+        return String::new();
+    }
+    if rational.num.is_multiple_of(rational.denom) {
+        return (rational.num / rational.denom).to_string();
+    }
+    if tag == Tag::ExposureTime && rational.num == 1 {
+        return format!("1/{}", rational.denom);
+    }
+    rational.to_f64().to_string()
+}
+
+fn format_srational(tag: Tag, rational: &SRational) -> String {
+    let magnitude = Rational::from((rational.num.unsigned_abs(), rational.denom.unsigned_abs()));
+    let negative = rational.num != 0 && (rational.num < 0) != (rational.denom < 0);
+    format!(
+        "{}{}",
+        if negative { "-" } else { "" },
+        format_rational(tag, &magnitude)
+    )
+}
+
+/// What the editor asks for
+#[derive(Debug, Clone)]
+pub enum Input {
+    /// One of the codes the tag can have, `selected` indexing into `choices`
+    Choice { choices: Choices, selected: usize },
+    /// The value as text, read when it's set
+    Text(String),
+}
+
+/// What the editor changes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Field(Tag),
+    /// A new note about the image
+    Note,
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Field(tag) => write!(f, "{}", utils::tag_name(*tag)),
+            Target::Note => write!(f, "Note"),
+        }
+    }
+}
+
+/// A field or note being edited in a popup
+#[derive(Debug, Clone)]
+pub struct Editor {
+    pub target: Target,
+    pub input: Input,
+    /// The value of the field when the editor opened, whose type the text is read as
+    current: Option<Value>,
+}
+
+impl Editor {
+    /// Editor for a new note, starting out empty
+    pub fn note() -> Self {
+        Self {
+            target: Target::Note,
+            input: Input::Text(String::new()),
+            current: None,
+        }
+    }
+
+    /// Editor for `field` starting at its current value, if it can be edited by hand
+    pub fn new(field: &Field) -> Option<Self> {
+        let input = if let Some(choices) = choices(field.tag) {
+            let current = field.value.get_uint(0);
+            let selected = choices
+                .iter()
+                .position(|&(code, _)| Some(u32::from(code)) == current)
+                .unwrap_or(0);
+            Input::Choice { choices, selected }
+        } else {
+            Input::Text(format_value(field.tag, &field.value)?)
+        };
+        Some(Self {
+            target: Target::Field(field.tag),
+            input,
+            current: Some(field.value.clone()),
+        })
+    }
+
+    pub fn next(&mut self) {
+        if let Input::Choice { choices, selected } = &mut self.input {
+            *selected = (*selected + 1) % choices.len();
+        }
+    }
+
+    pub fn previous(&mut self) {
+        if let Input::Choice { choices, selected } = &mut self.input {
+            *selected = (*selected + choices.len() - 1) % choices.len();
+        }
+    }
+
+    /// Whether the editor takes typed characters
+    pub fn is_text(&self) -> bool {
+        matches!(self.input, Input::Text(_))
+    }
+
+    pub fn push(&mut self, c: char) {
+        if let Input::Text(text) = &mut self.input {
+            text.push(c);
+        }
+    }
+
+    pub fn pop(&mut self) {
+        if let Input::Text(text) = &mut self.input {
+            text.pop();
+        }
+    }
+
+    /// The text typed so far, empty for a list of choices
+    pub fn text(&self) -> &str {
+        match &self.input {
+            Input::Text(text) => text,
+            Input::Choice { .. } => "",
+        }
+    }
+
+    /// The value to store in the field, or why the text doesn't make one
+    pub fn value(&self) -> Result<Value> {
+        let Target::Field(tag) = self.target else {
+            bail!("A note isn't stored in a field");
+        };
+        let text = match &self.input {
+            Input::Choice { choices, selected } => {
+                return Ok(Value::Short(vec![choices[*selected].0]))
+            }
+            Input::Text(text) => text.as_str(),
+        };
+        if RATIONAL_TAGS.contains(&tag) {
+            return Ok(Value::Rational(vec![parse_rational(tag, text)?]));
+        }
+        if DATE_TIME_TAGS.contains(&tag) {
+            return Ok(Value::Ascii(vec![parse_date_time(text)?.into_bytes()]));
+        }
+        if tag == Tag::GPSDateStamp {
+            return Ok(Value::Ascii(vec![parse_date(text)?.into_bytes()]));
+        }
+        let Some(current) = &self.current else {
+            bail!("Nothing to read the text as");
+        };
+        let value = match current {
+            Value::Ascii(strings) => {
+                let latin1 = strings.first().is_some_and(|s| utils::is_latin1(s));
+                Value::Ascii(vec![parse_ascii(text, latin1)?])
+            }
+            Value::Byte(_) if utils::XP_TAGS.contains(&tag) => {
+                if let Some(c) = text.chars().find(|c| c.is_control()) {
+                    bail!("Expected text without control characters, not {:?}", c);
+                }
+                Value::Byte(utils::encode_xp(text))
+            }
+            Value::Byte(v) => Value::Byte(parse_list(text, v.len(), parse_int)?),
+            Value::Short(v) => Value::Short(parse_list(text, v.len(), parse_int)?),
+            Value::Long(v) => Value::Long(parse_list(text, v.len(), parse_int)?),
+            Value::SByte(v) => Value::SByte(parse_list(text, v.len(), parse_int)?),
+            Value::SShort(v) => Value::SShort(parse_list(text, v.len(), parse_int)?),
+            Value::SLong(v) => Value::SLong(parse_list(text, v.len(), parse_int)?),
+            Value::Float(v) => Value::Float(parse_list(text, v.len(), parse_float)?),
+            Value::Double(v) => Value::Double(parse_list(text, v.len(), parse_float)?),
+            Value::Rational(v) => {
+                Value::Rational(parse_list(text, v.len(), |t| parse_rational(tag, t))?)
+            }
+            Value::SRational(v) => {
+                Value::SRational(parse_list(text, v.len(), |t| parse_srational(tag, t))?)
+            }
+            Value::Undefined(..) | Value::Unknown(..) => {
+                bail!("{} can't be typed in", utils::tag_name(tag))
+            }
+        };
+        Ok(value)
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::Result;
+use chrono::NaiveDateTime;
+use exif::{In, Reader, Tag, Value};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::{batch, utils};
+
+// Elevation
+//
+// Altitude over time across a set of geotagged photos, e.g. everything shot on
+// one hike. Photos without both an altitude and a capture time are left out.
+// Capture times are compared as recorded, so a session shot in one time zone
+// lines up even when the camera didn't store an offset.
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub path: PathBuf,
+    pub time: NaiveDateTime,
+    /// Meters above sea level
+    pub altitude: f64,
+}
+
+#[derive(Debug, Default)]
+pub struct Profile {
+    /// Ordered by capture time
+    pub samples: Vec<Sample>,
+    /// Images that had no altitude or capture time
+    pub skipped: usize,
+}
+
+impl Profile {
+    pub fn load(paths: &[PathBuf]) -> Result<Self> {
+        let mut profile = Profile::default();
+        for path in batch::collect_images(paths)? {
+            match sample(&path) {
+                Some(sample) => profile.samples.push(sample),
+                None => profile.skipped += 1,
+            }
+        }
+        profile.samples.sort_by_key(|s| s.time);
+        Ok(profile)
+    }
+
+    pub fn start(&self) -> Option<NaiveDateTime> {
+        self.samples.first().map(|s| s.time)
+    }
+
+    pub fn end(&self) -> Option<NaiveDateTime> {
+        self.samples.last().map(|s| s.time)
+    }
+
+    /// (minutes since the first photo, altitude) for every photo
+    pub fn points(&self) -> Vec<(f64, f64)> {
+        let Some(start) = self.start() else {
+            return Vec::new();
+        };
+        self.samples
+            .iter()
+            .map(|s| ((s.time - start).num_seconds() as f64 / 60., s.altitude))
+            .collect()
+    }
+
+    pub fn altitude_range(&self) -> (f64, f64) {
+        self.samples
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), s| {
+                (min.min(s.altitude), max.max(s.altitude))
+            })
+    }
+
+    /// Total meters climbed and descended between consecutive photos
+    pub fn gain_and_loss(&self) -> (f64, f64) {
+        self.samples
+            .windows(2)
+            .map(|w| w[1].altitude - w[0].altitude)
+            .fold((0., 0.), |(gain, loss), d| {
+                if d > 0. {
+                    (gain + d, loss)
+                } else {
+                    (gain, loss - d)
+                }
+            })
+    }
+}
+
+fn sample(path: &Path) -> Option<Sample> {
+    let img_buf = std::fs::read(path).ok()?;
+    let exif = Reader::new()
+        .read_from_container(&mut Cursor::new(&img_buf))
+        .ok()?;
+
+    let altitude = match exif.get_field(Tag::GPSAltitude, In::PRIMARY)?.value {
+        Value::Rational(ref v) if !v.is_empty() && v[0].denom != 0 => v[0].to_f64(),
+        _ => return None,
+    };
+    // 1 means below sea level
+    let below_sea_level = exif
+        .get_field(Tag::GPSAltitudeRef, In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        == Some(1);
+    let (time, _) = utils::capture_time(|tag| exif.get_field(tag, In::PRIMARY))?;
+
+    Some(Sample {
+        path: path.to_path_buf(),
+        time,
+        altitude: if below_sea_level { -altitude } else { altitude },
+    })
+}
@@ -0,0 +1,249 @@
+use anyhow::{anyhow, Result};
+use exif::Field;
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    sync::Arc,
+};
+
+use crate::{
+    backend::{self, MetadataBackend},
+    batch,
+    policy::glob_match,
+    utils,
+};
+
+// FFI
+//
+// A C interface to the scrubbing core, built with the `ffi` feature, so a
+// program not written in Rust - a GTK frontend, say - can use the same engine
+// as the TUI. Images are handed over as buffers, never as paths: the caller
+// opens a file it read itself, lists and clears tags and gets the copy back as
+// another buffer to do with as it likes.
+//
+// include/bresson.h is generated from this module with
+//
+//     cbindgen --config cbindgen.toml --output include/bresson.h
+//
+// Functions that fail return NULL or -1, and `bresson_last_error` tells why.
+// Strings and buffers handed out are owned by the caller, who gives them back
+// to the matching `_free` function. Panics don't cross into C, they are
+// reported like any other error.
+
+/// An image opened from a buffer, with the fields that will be written
+pub struct BressonImage {
+    file: Vec<u8>,
+    backend: Arc<dyn MetadataBackend>,
+    fields: Vec<Field>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, noting why it failed and returning `failed` if it did
+fn guard<T>(failed: T, f: impl FnOnce() -> Result<T>) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => {
+            set_error(format!("{:#}", e));
+            failed
+        }
+        Err(_) => {
+            set_error(String::from("bresson panicked"));
+            failed
+        }
+    }
+}
+
+/// # Safety
+///
+/// `image` must come from `bresson_open`, or be NULL.
+unsafe fn image<'a>(image: *const BressonImage) -> Result<&'a BressonImage> {
+    unsafe { image.as_ref() }.ok_or_else(|| anyhow!("No image given"))
+}
+
+fn field(image: &BressonImage, index: usize) -> Result<&Field> {
+    image
+        .fields
+        .get(index)
+        .ok_or_else(|| anyhow!("No tag at index {}", index))
+}
+
+fn to_c_string(s: String) -> Result<*mut c_char> {
+    Ok(CString::new(s.replace('\0', " "))?.into_raw())
+}
+
+/// Why the last call that failed on this thread failed, or NULL if none did.
+/// The message stays valid until the next call that fails.
+#[no_mangle]
+pub extern "C" fn bresson_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Reads the metadata of the `len` bytes at `data`, the contents of an image.
+/// The buffer is copied, it can be freed once this returns.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn bresson_open(data: *const u8, len: usize) -> *mut BressonImage {
+    guard(ptr::null_mut(), || {
+        if data.is_null() {
+            return Err(anyhow!("No buffer given"));
+        }
+        let file = unsafe { std::slice::from_raw_parts(data, len) }.to_vec();
+        let backend = backend::parse(&file)?;
+        let fields = backend.fields();
+        let image = BressonImage {
+            file,
+            backend,
+            fields,
+        };
+        Ok(Box::into_raw(Box::new(image)))
+    })
+}
+
+/// Frees an image from `bresson_open`
+///
+/// # Safety
+///
+/// `image` must come from `bresson_open` and not have been freed, or be NULL.
+#[no_mangle]
+pub unsafe extern "C" fn bresson_free(image: *mut BressonImage) {
+    if !image.is_null() {
+        drop(unsafe { Box::from_raw(image) });
+    }
+}
+
+/// Number of tags the copy will be written with
+///
+/// # Safety
+///
+/// `image` must come from `bresson_open`.
+#[no_mangle]
+pub unsafe extern "C" fn bresson_tag_count(image: *const BressonImage) -> usize {
+    guard(0, || Ok(unsafe { self::image(image) }?.fields.len()))
+}
+
+/// Name of the tag at `index`, e.g. "GPSLatitude". Free it with
+/// `bresson_string_free`.
+///
+/// # Safety
+///
+/// `image` must come from `bresson_open`.
+#[no_mangle]
+pub unsafe extern "C" fn bresson_tag_name(image: *const BressonImage, index: usize) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        to_c_string(
+            field(unsafe { self::image(image) }?, index)?
+                .tag
+                .to_string(),
+        )
+    })
+}
+
+/// Value of the tag at `index` as bresson shows it, with its unit. Free it
+/// with `bresson_string_free`.
+///
+/// # Safety
+///
+/// `image` must come from `bresson_open`.
+#[no_mangle]
+pub unsafe extern "C" fn bresson_tag_value(
+    image: *const BressonImage,
+    index: usize,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let image = unsafe { self::image(image) }?;
+        to_c_string(image.backend.display(field(image, index)?))
+    })
+}
+
+/// Leaves out every tag whose name matches `pattern` from the copy, `*`
+/// standing for any number of characters like in a policy, e.g. "GPS*".
+/// Returns how many were cleared. Tags that point at other data in the file
+/// can't be cleared and are kept.
+///
+/// # Safety
+///
+/// `image` must come from `bresson_open` and `pattern` be a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn bresson_clear_tags(
+    image: *mut BressonImage,
+    pattern: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        let image = unsafe { image.as_mut() }.ok_or_else(|| anyhow!("No image given"))?;
+        if pattern.is_null() {
+            return Err(anyhow!("No pattern given"));
+        }
+        let pattern = unsafe { CStr::from_ptr(pattern) }.to_str()?;
+        let before = image.fields.len();
+        let backend = &image.backend;
+        image.fields.retain(|f| {
+            !(backend.is_editable(f.tag) && glob_match(pattern, &utils::tag_name(f.tag)))
+        });
+        Ok((before - image.fields.len()) as c_int)
+    })
+}
+
+/// Writes a copy of the image with the remaining tags into a new buffer,
+/// stored in `out` and its length in `out_len`. Free it with
+/// `bresson_buffer_free`. Returns 0, or -1 if it couldn't be written.
+///
+/// # Safety
+///
+/// `image` must come from `bresson_open`, `out` and `out_len` must be writable.
+#[no_mangle]
+pub unsafe extern "C" fn bresson_write(
+    image: *const BressonImage,
+    out: *mut *mut u8,
+    out_len: *mut usize,
+) -> c_int {
+    guard(-1, || {
+        let image = unsafe { self::image(image) }?;
+        if out.is_null() || out_len.is_null() {
+            return Err(anyhow!("Nowhere to put the copy"));
+        }
+        let copy = batch::write_bytes(&*image.backend, &image.file, &image.fields, None)?
+            .into_boxed_slice();
+        unsafe {
+            *out_len = copy.len();
+            *out = Box::into_raw(copy).cast();
+        }
+        Ok(0)
+    })
+}
+
+/// Frees a string from `bresson_tag_name` or `bresson_tag_value`
+///
+/// # Safety
+///
+/// `s` must come from one of them and not have been freed, or be NULL.
+#[no_mangle]
+pub unsafe extern "C" fn bresson_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// Frees a buffer from `bresson_write`, `len` being the length it was given with
+///
+/// # Safety
+///
+/// `buf` must come from `bresson_write` and not have been freed, or be NULL.
+#[no_mangle]
+pub unsafe extern "C" fn bresson_buffer_free(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)) });
+    }
+}
@@ -0,0 +1,171 @@
+use anyhow::Result;
+use exif::{experimental::Writer, Field, In, Rational, Tag, Value};
+use image::{codecs::jpeg::JpegEncoder, Rgb, RgbImage};
+
+use crate::{jpeg, writer};
+
+// Fixture
+//
+// Small synthetic JPEGs with exactly the metadata a test or bug report needs,
+// so nobody has to share their own photos to reproduce an issue. The image is a
+// plain gradient, the interesting part is the Exif data written into it.
+//
+// Weird encodings are the ones cameras and editors actually produce - text in
+// Latin-1 instead of ASCII, strings that aren't NUL terminated or padded with
+// garbage, and a UserComment in UCS-2.
+//
+// The embedded thumbnail is the one of an earlier version, with a white square
+// in the corner that the image no longer has, like one a retouch left behind.
+
+const SIZE: u32 = 16;
+const THUMBNAIL_SIZE: u32 = 8;
+
+/// Location of the GPS fixtures, the Eiffel Tower
+const LOCATION: ((u32, u32, u32), (u32, u32, u32)) = ((48, 51, 2412), (2, 17, 4020));
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Write no Exif data at all, every other option is ignored
+    pub no_exif: bool,
+    pub gps: bool,
+    pub weird_encoding: bool,
+    /// Size of a MakerNote filled with junk, in bytes
+    pub maker_note: Option<usize>,
+    /// Embed a thumbnail that differs from the image
+    pub thumbnail: bool,
+}
+
+/// Encodes a fixture JPEG according to `options`
+pub fn generate(options: &Options) -> Result<Vec<u8>> {
+    let img = RgbImage::from_fn(SIZE, SIZE, |x, y| {
+        Rgb([(x * 255 / SIZE) as u8, (y * 255 / SIZE) as u8, 128])
+    });
+    let mut buf = Vec::new();
+    JpegEncoder::new_with_quality(&mut buf, 90).encode_image(&img)?;
+    if options.no_exif {
+        return Ok(buf);
+    }
+
+    let tiff = if options.thumbnail {
+        let thumbnail = thumbnail()?;
+        let fields = fields(options);
+        let mut exif_writer = Writer::new();
+        for f in &fields {
+            exif_writer.push_field(f);
+        }
+        exif_writer.set_jpeg(&thumbnail, In::THUMBNAIL);
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        exif_writer.write(&mut tiff, false)?;
+        tiff.into_inner()
+    } else {
+        writer::encode(&fields(options), None, false)?
+    };
+    jpeg::replace_exif(&buf, &tiff)
+}
+
+/// The gradient at half the size, with a white square in its top left corner
+fn thumbnail() -> Result<Vec<u8>> {
+    let img = RgbImage::from_fn(THUMBNAIL_SIZE, THUMBNAIL_SIZE, |x, y| {
+        if x < THUMBNAIL_SIZE / 2 && y < THUMBNAIL_SIZE / 2 {
+            Rgb([255, 255, 255])
+        } else {
+            Rgb([
+                (x * 255 / THUMBNAIL_SIZE) as u8,
+                (y * 255 / THUMBNAIL_SIZE) as u8,
+                128,
+            ])
+        }
+    });
+    let mut buf = Vec::new();
+    JpegEncoder::new_with_quality(&mut buf, 90).encode_image(&img)?;
+    Ok(buf)
+}
+
+fn field(tag: Tag, value: Value) -> Field {
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    }
+}
+
+fn ascii(text: &[u8]) -> Value {
+    Value::Ascii(vec![text.to_vec()])
+}
+
+fn dms((degrees, minutes, centiseconds): (u32, u32, u32)) -> Value {
+    Value::Rational(vec![
+        Rational::from((degrees, 1)),
+        Rational::from((minutes, 1)),
+        Rational::from((centiseconds, 100)),
+    ])
+}
+
+fn fields(options: &Options) -> Vec<Field> {
+    let mut fields = vec![
+        field(Tag::Make, ascii(b"Bresson")),
+        field(Tag::Model, ascii(b"Fixture")),
+        field(Tag::DateTime, ascii(b"2023:06:14 18:22:31")),
+        field(Tag::DateTimeOriginal, ascii(b"2023:06:14 18:22:31")),
+        field(Tag::SubSecTimeOriginal, ascii(b"42")),
+        field(Tag::ExifVersion, Value::Undefined(b"0232".to_vec(), 0)),
+        field(Tag::FlashpixVersion, Value::Undefined(b"0100".to_vec(), 0)),
+        field(
+            Tag::ComponentsConfiguration,
+            Value::Undefined(vec![1, 2, 3, 0], 0),
+        ),
+        field(
+            Tag::XResolution,
+            Value::Rational(vec![Rational::from((72, 1))]),
+        ),
+        field(
+            Tag::YResolution,
+            Value::Rational(vec![Rational::from((72, 1))]),
+        ),
+        // 2 = inches
+        field(Tag::ResolutionUnit, Value::Short(vec![2])),
+        field(Tag::PixelXDimension, Value::Short(vec![SIZE as u16])),
+        field(Tag::PixelYDimension, Value::Short(vec![SIZE as u16])),
+    ];
+
+    if options.weird_encoding {
+        // Latin-1 "Zoë Müller", which isn't valid ASCII or UTF-8
+        fields.push(field(Tag::Artist, ascii(b"Zo\xEB M\xFCller")));
+        // NUL in the middle, garbage after it
+        fields.push(field(Tag::Copyright, ascii(b"Bresson\0\xFF\xFE junk")));
+        fields.push(field(Tag::ImageDescription, ascii(b"")));
+        let mut comment = b"UNICODE\0".to_vec();
+        comment.extend(
+            "Caf\u{e9} \u{1F4F7}"
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes),
+        );
+        fields.push(field(Tag::UserComment, Value::Undefined(comment, 0)));
+    } else {
+        fields.push(field(Tag::Artist, ascii(b"Jane Doe")));
+    }
+
+    if options.gps {
+        let (lat, lon) = LOCATION;
+        fields.extend([
+            field(Tag::GPSLatitudeRef, ascii(b"N")),
+            field(Tag::GPSLatitude, dms(lat)),
+            field(Tag::GPSLongitudeRef, ascii(b"E")),
+            field(Tag::GPSLongitude, dms(lon)),
+            field(
+                Tag::GPSProcessingMethod,
+                Value::Undefined(b"ASCII\0\0\0NETWORK".to_vec(), 0),
+            ),
+        ]);
+        // UCS-2 in the byte order of the Exif data, big endian
+        let mut area = b"UNICODE\0".to_vec();
+        area.extend("Champ de Mars".encode_utf16().flat_map(u16::to_be_bytes));
+        fields.push(field(Tag::GPSAreaInformation, Value::Undefined(area, 0)));
+    }
+
+    if let Some(size) = options.maker_note {
+        let junk = (0..size).map(|i| (i * 31 % 251) as u8).collect();
+        fields.push(field(Tag::MakerNote, Value::Undefined(junk, 0)));
+    }
+    fields
+}
@@ -1,15 +1,41 @@
-use std::f32::consts::PI;
+use anyhow::{bail, Context as _, Result};
+use std::{f32::consts::PI, path::Path};
 
 const DW: usize = 4;
 const DH: usize = 8;
-
-static EARTH_DAY: &str = include_str!("../texture/earth.txt");
+/// Height of a terminal cell over its width in most fonts
+pub const DEFAULT_FONT_RATIO: f64 = 2.;
+/// Characters of the globe from darkest to brightest
+pub const PALETTE: [char; 18] = [
+    ' ', '.', ':', ';', '\'', ',', 'w', 'i', 'o', 'g', 'O', 'L', 'X', 'H', 'W', 'Y', 'V', '@',
+];
+/// Position of every ASCII character in `PALETTE`, -1 for those not in it
+const PALETTE_INDEX: [i8; 128] = {
+    let mut index = [-1; 128];
+    let mut i = 0;
+    while i < PALETTE.len() {
+        index[PALETTE[i] as usize] = i as i8;
+        i += 1;
+    }
+    index
+};
+/// Thickness of the atmosphere, relative to the radius of the globe
+const ATMOSPHERE: f32 = 0.08;
+/// Cells of the sky per star, roughly
+const STAR_RARITY: u32 = 48;
+/// Distance of the camera from the middle of the globe, in radii, below which
+/// regional textures replace the whole-earth one
+pub const REGION_DISTANCE: f32 = 1.35;
+
+pub(crate) static EARTH_DAY: &str = include_str!("../texture/earth.txt");
 static EARTH_NIGHT: &str = include_str!("../texture/earth_night.txt");
 
 pub struct Canvas {
     pub matrix: Vec<Vec<char>>,
     pub size: (usize, usize),
     pub char_size: (usize, usize),
+    /// Columns the globe spans, fewer than the canvas has to leave sky around it
+    pub globe_width: usize,
 }
 
 impl Canvas {
@@ -19,9 +45,15 @@ impl Canvas {
             matrix,
             size: (x, y),
             char_size: cs.unwrap_or((DW, DH)),
+            globe_width: x,
         }
     }
 
+    pub fn with_globe_width(mut self, columns: usize) -> Self {
+        self.globe_width = columns.clamp(1, self.size.0);
+        self
+    }
+
     pub fn get_size(&self) -> (usize, usize) {
         self.size
     }
@@ -86,6 +118,104 @@ impl Camera {
     }
 }
 
+/// Spot drawn onto the surface of the globe
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Marker {
+    /// Signed degrees
+    pub lat: f32,
+    pub lon: f32,
+    /// Degrees, markers never get smaller than a texel
+    pub radius: f32,
+    pub symbol: char,
+}
+
+/// A more detailed texture of part of the earth, drawn instead of the
+/// whole-earth one when the camera is close
+///
+/// Read from a text file whose first line has the edges of the region in signed
+/// degrees, `north south west east`, and whose other lines are the texture from
+/// north to south, each from west to east, in the characters of the globe.
+/// Other characters are drawn as they are, without shading them at night.
+#[derive(Debug, Clone)]
+pub struct Region {
+    pub name: String,
+    north: f32,
+    south: f32,
+    west: f32,
+    east: f32,
+    rows: Vec<Vec<char>>,
+}
+
+impl Region {
+    pub fn parse(name: &str, data: &str) -> Result<Self> {
+        let mut lines = data.lines();
+        let edges: Vec<f32> = lines
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .context("Expected `north south west east` on the first line")?;
+        let [north, south, west, east] = edges[..] else {
+            bail!("Expected `north south west east` on the first line");
+        };
+        if !(-90. ..=90.).contains(&south) || !(-90. ..=90.).contains(&north) || south >= north {
+            bail!("Invalid latitudes from {} to {}", south, north);
+        }
+        if !(-180. ..=180.).contains(&west) || !(-180. ..=180.).contains(&east) || west >= east {
+            bail!("Invalid longitudes from {} to {}", west, east);
+        }
+        let rows: Vec<Vec<char>> = lines.map(|l| l.chars().collect()).collect();
+        if rows.is_empty()
+            || rows
+                .iter()
+                .any(|r| r.len() != rows[0].len() || r.is_empty())
+        {
+            bail!("Expected rows of the same length after the first line");
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            north,
+            south,
+            west,
+            east,
+            rows,
+        })
+    }
+
+    /// Every `*.txt` file in `dir`, named after the file
+    pub fn load_dir(dir: &Path) -> Result<Vec<Self>> {
+        let mut regions = Vec::new();
+        let entries =
+            std::fs::read_dir(dir).with_context(|| format!("Unable to read {:?}", dir))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "txt") {
+                continue;
+            }
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            let data = std::fs::read_to_string(&path)?;
+            regions.push(
+                Self::parse(&name, &data)
+                    .with_context(|| format!("Invalid region texture {:?}", path))?,
+            );
+        }
+        regions.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(regions)
+    }
+
+    /// Character of the texture at `lat` / `lon` in signed degrees, if it covers them
+    fn at(&self, lat: f32, lon: f32) -> Option<char> {
+        if !(self.south..=self.north).contains(&lat) || !(self.west..=self.east).contains(&lon) {
+            return None;
+        }
+        let (width, height) = (self.rows[0].len(), self.rows.len());
+        let y = ((self.north - lat) / (self.north - self.south) * height as f32) as usize;
+        let x = ((lon - self.west) / (self.east - self.west) * width as f32) as usize;
+        Some(self.rows[y.min(height - 1)][x.min(width - 1)])
+    }
+}
+
 pub enum TextureType {
     Day,
     Night,
@@ -96,7 +226,17 @@ pub struct Globe {
     pub radius: f32,
     pub angle: f32,
     pub display_night: bool,
-    palette: Vec<char>,
+    /// Drawn over the texture, later ones on top
+    pub markers: Vec<Marker>,
+    /// Height of a terminal cell over its width. The taller cells are, the fewer
+    /// columns the globe takes, so that it looks round rather than oval.
+    pub font_ratio: f64,
+    /// Draws faint stars around the globe and an atmosphere along its rim
+    pub starfield: bool,
+    /// Detailed textures of parts of the earth, drawn when zoomed in on them
+    pub regions: Vec<Region>,
+    /// Position of the light source, far away from the globe
+    light: [f32; 3],
     day_texture: Vec<Vec<char>>,
     night_texture: Vec<Vec<char>>,
 }
@@ -105,17 +245,17 @@ impl Globe {
     pub fn new(radius: f32, angle: f32, display_night: bool) -> Self {
         let day_texture = Globe::load_texture(TextureType::Day);
         let night_texture = Globe::load_texture(TextureType::Night);
-        let palette = vec![
-            ' ', '.', ':', ';', '\'', ',', 'w', 'i', 'o', 'g', 'O', 'L', 'X', 'H', 'W', 'Y', 'V',
-            '@',
-        ];
 
         Self {
             camera: Camera::default(),
             radius,
             angle,
             display_night,
-            palette,
+            markers: Vec::new(),
+            font_ratio: DEFAULT_FONT_RATIO,
+            starfield: false,
+            regions: Vec::new(),
+            light: [0.0, 999999.0, 0.0],
             day_texture,
             night_texture,
         }
@@ -125,6 +265,58 @@ impl Globe {
         self.display_night = !self.display_night;
     }
 
+    pub fn toggle_starfield(&mut self) {
+        self.starfield = !self.starfield;
+    }
+
+    /// Points the camera at `lat` / `lon` in signed degrees from `distance` away.
+    /// The inverse of the texture lookup in `render_sphere`, which wraps the
+    /// texture around the sphere twice, so either matching side is fine.
+    pub fn look_at(&mut self, lat: f32, lon: f32, distance: f32) {
+        let (alfa, beta) = self.angles_to(lat, lon);
+        self.camera.update(distance, alfa, beta);
+    }
+
+    /// Camera angles that put `lat` / `lon` in signed degrees at the center of the view
+    pub fn angles_to(&self, lat: f32, lon: f32) -> (f32, f32) {
+        let alfa = -PI * lon / 360. - self.angle / 2.;
+        let beta = (lat / 90.).clamp(-1., 1.).asin();
+        (alfa, beta)
+    }
+
+    /// Latitude and longitude in signed degrees of the point at the center of the
+    /// view, the inverse of `look_at`. The camera always faces the middle of the
+    /// globe, so that's where the line from the camera to the middle meets the surface.
+    pub fn center(&self) -> (f32, f32) {
+        let Camera { x, y, z, .. } = self.camera;
+        let distance = (x * x + y * y + z * z).sqrt();
+        if distance == 0. {
+            return (0., 0.);
+        }
+        // Same texture lookup as `render_sphere`, from texel back to degrees
+        let lat = 90. * (z / distance).clamp(-1., 1.);
+        let mut theta = (y / x).atan() / PI + 0.5 + self.angle / 2.0 / PI;
+        theta -= theta.floor();
+        let lon = 180. - theta * 360.;
+        (lat, lon)
+    }
+
+    /// Lights the globe like the sun does at the point the camera looks at, given
+    /// the sun's elevation and azimuth (clockwise from north) there, in degrees.
+    /// Blends in the night texture wherever that puts the surface into darkness.
+    pub fn set_sun(&mut self, elevation: f32, azimuth: f32) {
+        let m = self.camera.matrix;
+        let up = [m[8], m[9], m[10]];
+        let east = [m[0], m[1], m[2]];
+        let north = [-m[4], -m[5], -m[6]];
+        let (el, az) = (elevation.to_radians(), azimuth.to_radians());
+        for i in 0..3 {
+            self.light[i] = 999999.0
+                * (el.sin() * up[i] + el.cos() * (az.sin() * east[i] + az.cos() * north[i]));
+        }
+        self.display_night = true;
+    }
+
     fn load_texture(tex: TextureType) -> Vec<Vec<char>> {
         let texture_data = match tex {
             TextureType::Day => EARTH_DAY,
@@ -146,32 +338,76 @@ impl Globe {
         (self.day_texture[0].len(), self.day_texture.len())
     }
 
+    /// Topmost marker covering the texel at `x` / `y`
+    fn marker_at(&self, x: usize, y: usize) -> Option<&Marker> {
+        if self.markers.is_empty() {
+            return None;
+        }
+        // The texture is equirectangular, with its rows flipped east to west
+        let (tex_x, tex_y) = self.texture_size();
+        let lat = 90. - (y as f32 + 0.5) / tex_y as f32 * 180.;
+        let lon = 180. - (x as f32 + 0.5) / tex_x as f32 * 360.;
+        let min_radius = 360. / tex_x as f32;
+        self.markers.iter().rev().find(|m| {
+            let d_lat = lat - m.lat;
+            let d_lon = ((lon - m.lon + 540.).rem_euclid(360.) - 180.) * lat.to_radians().cos();
+            d_lat.hypot(d_lon) <= m.radius.max(min_radius)
+        })
+    }
+
     pub fn render_sphere(&self, canvas: &mut Canvas) {
-        let light = [0.0, 999999.0, 0.0];
+        let light = self.light;
         let (width, height) = canvas.get_size();
         let (c_w, c_h) = canvas.char_size;
-        for yi in 0..height {
-            let yif = yi as isize;
-            for xi in 0..width {
-                let xif = xi as isize;
-                // Origin of the Ray
-                let o = [self.camera.x, self.camera.y, self.camera.z];
-
+        let half_globe = (canvas.globe_width as f32 / c_w as f32 / 2.).max(0.5);
+        let Camera { x, y, z, .. } = self.camera;
+        let zoomed = !self.regions.is_empty()
+            && (x * x + y * y + z * z).sqrt() < REGION_DISTANCE * self.radius;
+        let (tex_x, tex_y) = self.texture_size();
+        // Origin of the rays
+        let o = [x, y, z];
+        let o_squared = dot(&o, &o);
+        let radius_squared = self.radius.powi(2);
+
+        // The ray through a cell goes from the camera to the cell's position on
+        // the screen, `(ux, uy, -1)` moved by the camera matrix. The parts of it
+        // that depend on the column or the row alone are worked out once a frame
+        // instead of once a cell, and always added up in the same order so the
+        // globe looks the same to the last character.
+        let m = self.camera.matrix;
+        let columns: Vec<[f32; 3]> = (0..width)
+            .map(|xi| {
+                let ux = -((xi as isize - (width / c_w / 2) as isize) as f32 + 0.5) / half_globe;
+                [ux * m[0], ux * m[1], ux * m[2]]
+            })
+            .collect();
+        let rows: Vec<[f32; 3]> = (0..height)
+            .map(|yi| {
+                let uy = ((yi as isize - (height / c_h / 2) as isize) as f32 + 0.5)
+                    / (height / c_h / 2) as f32;
+                [uy * m[4], uy * m[5], uy * m[6]]
+            })
+            .collect();
+        let depth = [-m[8], -m[9], -m[10]];
+        let offset = [m[12], m[13], m[14]];
+
+        for (yi, row) in rows.iter().enumerate() {
+            for (xi, column) in columns.iter().enumerate() {
                 // Unit vector. direction of the Ray
-                let mut u = [
-                    -((xif - (width / c_w / 2) as isize) as f32 + 0.5) / (width / c_w / 2) as f32,
-                    ((yif - (height / c_h / 2) as isize) as f32 + 0.5) / (height / c_h / 2) as f32,
-                    -1.0,
-                ];
-                transform_vector(&mut u, self.camera.matrix);
-                u[0] -= self.camera.x;
-                u[1] -= self.camera.y;
-                u[2] -= self.camera.z;
+                let mut u = [0.; 3];
+                for i in 0..3 {
+                    u[i] = column[i] + row[i] + depth[i] + offset[i] - o[i];
+                }
                 normalize(&mut u);
-                let discriminant = dot(&u, &o).powi(2) - dot(&o, &o) + self.radius.powi(2);
+                let discriminant = dot(&u, &o).powi(2) - o_squared + radius_squared;
 
                 // Ray doesn't hit the sphere
                 if discriminant < 0.0 {
+                    if self.starfield {
+                        if let Some(c) = self.sky(&o, &u) {
+                            canvas.draw_at(xi, yi, c);
+                        }
+                    }
                     continue;
                 }
 
@@ -205,47 +441,73 @@ impl Globe {
                 let mut theta = (temp[1] / temp[0]).atan() / PI + 0.5 + self.angle / 2.0 / PI;
                 theta -= theta.floor();
 
-                let (tex_x, tex_y) = self.texture_size();
                 let earth_x = (theta * tex_x as f32) as usize;
                 let earth_y = (phi * tex_y as f32) as usize;
 
-                if self.display_night {
-                    let day = find_index(self.day_texture[earth_y][earth_x], &self.palette);
+                if let Some(marker) = self.marker_at(earth_x, earth_y) {
+                    canvas.draw_at(xi, yi, marker.symbol);
+                    continue;
+                }
 
-                    let night = find_index(self.night_texture[earth_y][earth_x], &self.palette);
+                let regional = if zoomed {
+                    let (lat, lon) = (90. - phi * 180., 180. - theta * 360.);
+                    self.regions.iter().find_map(|r| r.at(lat, lon))
+                } else {
+                    None
+                };
+                let day_texel = regional.unwrap_or(self.day_texture[earth_y][earth_x]);
+
+                let day = find_index(day_texel);
+                // Characters of regional textures outside the palette aren't shaded
+                if self.display_night && (day >= 0 || regional.is_none()) {
+                    let night = find_index(self.night_texture[earth_y][earth_x]);
                     let mut index =
                         ((1.0 - luminance) * night as f32 + luminance * day as f32) as usize;
-                    if index >= self.palette.len() {
+                    if index >= PALETTE.len() {
                         index = 0;
                     }
-                    canvas.draw_at(xi, yi, self.palette[index]);
+                    canvas.draw_at(xi, yi, PALETTE[index]);
                 } else {
-                    canvas.draw_at(xi, yi, self.day_texture[earth_y][earth_x]);
+                    canvas.draw_at(xi, yi, day_texel);
                 }
             }
         }
     }
-}
 
-fn find_index(target: char, palette: &[char]) -> isize {
-    for (i, &ch) in palette.iter().enumerate() {
-        if target == ch {
-            return i as isize;
+    /// What a ray from `o` towards `u` that misses the globe shows - the glow of
+    /// the atmosphere if it passes close to the globe, now and then a star
+    fn sky(&self, o: &[f32; 3], u: &[f32; 3]) -> Option<char> {
+        let along = dot(u, o);
+        let closest = (dot(o, o) - along.powi(2)).max(0.).sqrt();
+        let glow = (closest - self.radius) / (self.radius * ATMOSPHERE);
+        // Only in front of the camera
+        if along < 0. && glow < 1. {
+            return Some(if glow < 0.5 { ':' } else { '.' });
+        }
+
+        // Stars are fixed to directions, so they stay put while the globe turns
+        let cell = u.map(|c| (c * 64.).round() as i32);
+        let hash = cell.iter().fold(0x811c_9dc5_u32, |h, &c| {
+            (h ^ c as u32).wrapping_mul(0x0100_0193)
+        });
+        // One star in eight is a bright one
+        match hash % (STAR_RARITY * 8) {
+            0 => Some('+'),
+            n if n % STAR_RARITY == 0 => Some('.'),
+            _ => None,
         }
     }
+}
 
-    -1
+/// Position of `c` in `PALETTE`, if it's in it
+pub fn palette_index(c: char) -> Option<usize> {
+    usize::try_from(find_index(c)).ok()
 }
 
-fn transform_vector(vec: &mut [f32; 3], m: [f32; 16]) {
-    let (tx, ty, tz) = (
-        vec[0] * m[0] + vec[1] * m[4] + vec[2] * m[8] + m[12],
-        vec[0] * m[1] + vec[1] * m[5] + vec[2] * m[9] + m[13],
-        vec[0] * m[2] + vec[1] * m[6] + vec[2] * m[10] + m[14],
-    );
-    vec[0] = tx;
-    vec[1] = ty;
-    vec[2] = tz;
+fn find_index(target: char) -> isize {
+    PALETTE_INDEX
+        .get(target as usize)
+        .map_or(-1, |&i| isize::from(i))
 }
 
 fn invert(inv: &mut [f32; 16], matrix: [f32; 16]) {
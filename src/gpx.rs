@@ -0,0 +1,151 @@
+use anyhow::{anyhow, bail, Context as _, Result};
+use chrono::{DateTime, Duration, Utc};
+use exif::{Field, In, Rational, Tag, Value};
+use std::path::Path;
+
+use crate::utils;
+
+// GPX
+//
+// GPS Exchange Format tracks, as recorded by phones, watches and GPS loggers.
+// Only track points are used here, since they are the only part of a GPX file
+// that pairs a position with a time. Waypoints and routes are ignored.
+//
+// https://www.topografix.com/GPX/1/1/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackPoint {
+    pub time: DateTime<Utc>,
+    pub lat: f64,
+    pub lon: f64,
+    pub ele: Option<f64>,
+}
+
+/// All timed track points of a GPX file, ordered by time
+#[derive(Debug, Clone, Default)]
+pub struct Track {
+    pub points: Vec<TrackPoint>,
+}
+
+impl Track {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read GPX file {:?}", path))?;
+        Self::parse(&data).with_context(|| format!("Invalid GPX file {:?}", path))
+    }
+
+    pub fn parse(xml: &str) -> Result<Self> {
+        let doc = roxmltree::Document::parse(xml)?;
+        let mut points = Vec::new();
+        for node in doc.descendants().filter(|n| n.has_tag_name("trkpt")) {
+            let coordinate = |name: &str| -> Result<f64> {
+                node.attribute(name)
+                    .ok_or_else(|| anyhow!("Track point without {}", name))?
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid {} in track point", name))
+            };
+            let child_text = |name: &str| {
+                node.children()
+                    .find(|c| c.has_tag_name(name))
+                    .and_then(|c| c.text())
+                    .map(str::trim)
+            };
+            // Points without a time can't be matched against photos
+            let Some(time) = child_text("time") else {
+                continue;
+            };
+            points.push(TrackPoint {
+                time: DateTime::parse_from_rfc3339(time)
+                    .with_context(|| format!("Invalid track point time {:?}", time))?
+                    .with_timezone(&Utc),
+                lat: coordinate("lat")?,
+                lon: coordinate("lon")?,
+                ele: child_text("ele").and_then(|e| e.parse().ok()),
+            });
+        }
+        if points.is_empty() {
+            bail!("No timed track points");
+        }
+        points.sort_by_key(|p| p.time);
+        Ok(Self { points })
+    }
+
+    /// Position at `time`, interpolated between the surrounding track points.
+    /// Where the track has a hole longer than `max_gap`, or `time` lies outside
+    /// of it, only a point at most `max_gap` away is used.
+    pub fn position_at(&self, time: DateTime<Utc>, max_gap: Duration) -> Option<TrackPoint> {
+        let next = self.points.partition_point(|p| p.time < time);
+        let after = self.points.get(next);
+        let before = next.checked_sub(1).and_then(|i| self.points.get(i));
+        match (before, after) {
+            (_, Some(b)) if b.time == time => Some(*b),
+            (Some(a), Some(b)) if b.time - a.time <= max_gap => Some(interpolate(a, b, time)),
+            (Some(a), Some(b)) => [a, b]
+                .into_iter()
+                .filter(|p| (p.time - time).abs() <= max_gap)
+                .min_by_key(|p| (p.time - time).abs())
+                .copied(),
+            (Some(p), None) | (None, Some(p)) if (p.time - time).abs() <= max_gap => Some(*p),
+            _ => None,
+        }
+    }
+}
+
+fn interpolate(a: &TrackPoint, b: &TrackPoint, time: DateTime<Utc>) -> TrackPoint {
+    let span = (b.time - a.time).num_milliseconds() as f64;
+    let t = if span > 0. {
+        (time - a.time).num_milliseconds() as f64 / span
+    } else {
+        0.
+    };
+    let lerp = |x: f64, y: f64| x + (y - x) * t;
+    TrackPoint {
+        time,
+        lat: lerp(a.lat, b.lat),
+        lon: lerp(a.lon, b.lon),
+        ele: match (a.ele, b.ele) {
+            (Some(x), Some(y)) => Some(lerp(x, y)),
+            (x, y) => x.or(y),
+        },
+    }
+}
+
+/// The GPS fields that place an image at `point`
+pub fn gps_fields(point: &TrackPoint) -> Vec<Field> {
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let ascii = |s: &str| Value::Ascii(vec![s.as_bytes().to_vec()]);
+
+    let mut fields = vec![
+        field(Tag::GPSVersionID, Value::Byte(vec![2, 2, 0, 0])),
+        field(
+            Tag::GPSLatitudeRef,
+            ascii(if point.lat < 0. { "S" } else { "N" }),
+        ),
+        field(Tag::GPSLatitude, utils::to_dms(point.lat)),
+        field(
+            Tag::GPSLongitudeRef,
+            ascii(if point.lon < 0. { "W" } else { "E" }),
+        ),
+        field(Tag::GPSLongitude, utils::to_dms(point.lon)),
+    ];
+    if let Some(ele) = point.ele {
+        // 0 = above sea level, 1 = below sea level
+        fields.push(field(
+            Tag::GPSAltitudeRef,
+            Value::Byte(vec![u8::from(ele < 0.)]),
+        ));
+        fields.push(field(
+            Tag::GPSAltitude,
+            Value::Rational(vec![Rational {
+                num: (ele.abs() * 100.).round() as u32,
+                denom: 100,
+            }]),
+        ));
+    }
+    fields
+}
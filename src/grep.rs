@@ -0,0 +1,53 @@
+use anyhow::{bail, Context as _, Result};
+use exif::Tag;
+use regex::{Regex, RegexBuilder};
+
+use crate::{policy, utils};
+
+// Grep
+//
+// Finds the images whose metadata matches a pattern - which of them have an
+// Artist at all, or were taken with a given camera. Tags are matched by name
+// the way policies match them, with `*` standing for any number of characters,
+// and values with a regular expression against the value as shown in the table,
+// units included.
+//
+//     bresson grep --tag Artist --value "Smith" ./photos
+//     bresson grep --tag "GPS*" ./photos
+//     bresson grep --value "^Canon" ./photos
+
+/// A tag and value pattern, either of which may be left out
+#[derive(Debug)]
+pub struct Query {
+    tag: Option<String>,
+    value: Option<Regex>,
+}
+
+impl Query {
+    pub fn new(tag: Option<&str>, value: Option<&str>, ignore_case: bool) -> Result<Self> {
+        if tag.is_none() && value.is_none() {
+            bail!("Nothing to search for, give a tag, a value or both");
+        }
+        let value = value
+            .map(|pattern| {
+                RegexBuilder::new(pattern)
+                    .case_insensitive(ignore_case)
+                    .build()
+                    .with_context(|| format!("Invalid value pattern {:?}", pattern))
+            })
+            .transpose()?;
+        Ok(Self {
+            tag: tag.map(str::to_owned),
+            value,
+        })
+    }
+
+    /// Whether a field with `tag`, showing `value`, matches
+    pub fn matches(&self, tag: Tag, value: &str) -> bool {
+        let tag_matches = match &self.tag {
+            Some(pattern) => policy::glob_match(pattern, &utils::tag_name(tag)),
+            None => true,
+        };
+        tag_matches && self.value.as_ref().is_none_or(|re| re.is_match(value))
+    }
+}
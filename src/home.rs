@@ -0,0 +1,59 @@
+use anyhow::Result;
+use chrono::Timelike;
+use std::path::PathBuf;
+
+use crate::{batch, cluster};
+
+// Home
+//
+// Guesses where the photographer lives from a batch of images - the place where
+// most of the photos taken at night were taken. An image that still carries a
+// location near it gives away a lot more than the place it shows.
+//
+// Night is judged by the capture time as recorded, which is local time for most
+// cameras. It's a heuristic, no home is reported unless several night photos
+// agree on one place.
+
+/// Night photos taken closer than this belong to the same place
+const CLUSTER_RADIUS_KM: f64 = 0.2;
+/// Night photos that have to agree on a place before it's taken for home
+const MIN_IMAGES: usize = 3;
+/// Locations closer than this to home are reported
+pub const WARNING_RADIUS_KM: f64 = 1.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Home {
+    /// Signed degrees
+    pub lat: f64,
+    pub lon: f64,
+    /// Night photos taken there
+    pub images: usize,
+}
+
+impl Home {
+    pub fn detect(paths: &[PathBuf]) -> Result<Option<Self>> {
+        let mut night = Vec::new();
+        for path in batch::collect_images(paths)? {
+            if let Some((position, Some(time))) = cluster::locate(&path) {
+                if time.hour() >= 21 || time.hour() < 6 {
+                    night.push((path, position));
+                }
+            }
+        }
+        Ok(cluster::cluster(night, CLUSTER_RADIUS_KM)
+            .into_iter()
+            .next()
+            .filter(|c| c.images.len() >= MIN_IMAGES)
+            .map(|c| Self {
+                lat: c.lat,
+                lon: c.lon,
+                images: c.images.len(),
+            }))
+    }
+
+    /// Distance to `position` if it's close enough to give home away
+    pub fn near(&self, position: (f64, f64)) -> Option<f64> {
+        let distance = cluster::distance_km((self.lat, self.lon), position);
+        (distance <= WARNING_RADIUS_KM).then_some(distance)
+    }
+}
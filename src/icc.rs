@@ -0,0 +1,158 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+// ICC
+//
+// An embedded ICC profile describes the colors of an image, but it also tells
+// a lot about where the image was edited: profiles of calibrated monitors carry
+// the name of the display or the calibration tool, and print profiles the
+// paper and printer. Scrubbing can drop the profile, leaving viewers to assume
+// sRGB, or replace it with a generic sRGB profile.
+//
+// The sRGB profile is put together here instead of being shipped as a file. It
+// is a minimal ICC v2 display profile - the sRGB primaries adapted to D50 and a
+// 2.2 gamma curve. Pixels are not converted, so an image edited in a wider
+// gamut like Display P3 looks less saturated with it.
+//
+// http://www.color.org/ICC1V42.pdf
+
+/// What to do with the ICC profile of a copy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Drop,
+    Srgb,
+}
+
+impl Change {
+    pub const ALL: [Change; 2] = [Change::Drop, Change::Srgb];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Change::Drop => "drop",
+            Change::Srgb => "srgb",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|c| c.name() == name)
+    }
+
+    /// The profile to embed instead, if any
+    pub fn profile(&self) -> Option<Vec<u8>> {
+        match self {
+            Change::Drop => None,
+            Change::Srgb => Some(srgb()),
+        }
+    }
+}
+
+const SRGB_DESCRIPTION: &str = "sRGB";
+
+/// s15Fixed16 encoding of an XYZ number
+fn xyz(x: f64, y: f64, z: f64) -> Vec<u8> {
+    let mut data = b"XYZ \0\0\0\0".to_vec();
+    for v in [x, y, z] {
+        data.extend_from_slice(&((v * 65536.).round() as i32).to_be_bytes());
+    }
+    data
+}
+
+/// A generic sRGB display profile
+pub fn srgb() -> Vec<u8> {
+    let mut desc = b"desc\0\0\0\0".to_vec();
+    desc.extend_from_slice(&(SRGB_DESCRIPTION.len() as u32 + 1).to_be_bytes());
+    desc.extend_from_slice(SRGB_DESCRIPTION.as_bytes());
+    desc.push(0);
+    // No Unicode or ScriptCode descriptions
+    desc.extend_from_slice(&[0; 8]);
+    desc.extend_from_slice(&[0; 3 + 67]);
+    let mut cprt = b"text\0\0\0\0".to_vec();
+    cprt.extend_from_slice(b"No copyright, use freely\0");
+    // A single gamma of 2.2 as u8Fixed8
+    let trc = b"curv\0\0\0\0\0\0\0\x01\x02\x33".to_vec();
+
+    let tags: [(&[u8; 4], Vec<u8>); 9] = [
+        (b"desc", desc),
+        (b"cprt", cprt),
+        (b"wtpt", xyz(0.9642, 1.0, 0.8249)),
+        (b"rXYZ", xyz(0.4361, 0.2225, 0.0139)),
+        (b"gXYZ", xyz(0.3851, 0.7169, 0.0971)),
+        (b"bXYZ", xyz(0.1431, 0.0606, 0.7141)),
+        (b"rTRC", trc.clone()),
+        (b"gTRC", trc.clone()),
+        (b"bTRC", trc),
+    ];
+
+    let mut table = (tags.len() as u32).to_be_bytes().to_vec();
+    let mut data = Vec::new();
+    let start = 128 + 4 + 12 * tags.len();
+    for (signature, tag) in &tags {
+        // Tag data starts on 4 byte boundaries
+        while data.len() % 4 != 0 {
+            data.push(0);
+        }
+        table.extend_from_slice(*signature);
+        table.extend_from_slice(&((start + data.len()) as u32).to_be_bytes());
+        table.extend_from_slice(&(tag.len() as u32).to_be_bytes());
+        data.extend_from_slice(tag);
+    }
+
+    let size = (start + data.len()) as u32;
+    let mut profile = Vec::with_capacity(size as usize);
+    profile.extend_from_slice(&size.to_be_bytes());
+    profile.extend_from_slice(&[0; 4]); // Preferred CMM
+    profile.extend_from_slice(&[2, 0x10, 0, 0]); // Version 2.1
+    profile.extend_from_slice(b"mntrRGB XYZ ");
+    profile.extend_from_slice(&[0; 12]); // Date and time
+    profile.extend_from_slice(b"acsp");
+    // Platform, flags, device, attributes and rendering intent
+    profile.extend_from_slice(&[0; 4 + 4 + 4 + 4 + 8 + 4]);
+    // Illuminant of the connection space, D50
+    profile.extend_from_slice(&xyz(0.9642, 1.0, 0.8249)[8..]);
+    profile.resize(128, 0);
+    profile.extend(table);
+    profile.extend(data);
+    profile
+}
+
+/// The description of `profile`, e.g. "Display P3", for v2 and v4 profiles
+pub fn description(profile: &[u8]) -> Result<String> {
+    let u32_at = |offset: usize| -> Result<usize> {
+        match profile.get(offset..offset + 4) {
+            Some(b) => Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize),
+            None => bail!("ICC profile is cut short"),
+        }
+    };
+    let count = u32_at(128)?;
+    let entry = (0..count)
+        .map(|i| 132 + 12 * i)
+        .take_while(|&entry| entry + 12 <= profile.len())
+        .find(|&entry| profile.get(entry..entry + 4) == Some(b"desc"))
+        .ok_or_else(|| anyhow::anyhow!("ICC profile has no description"))?;
+    let (offset, len) = (u32_at(entry + 4)?, u32_at(entry + 8)?);
+    let Some(tag) = profile.get(offset..offset + len) else {
+        bail!("ICC profile description is out of bounds");
+    };
+    match tag.get(..4) {
+        // textDescriptionType, ASCII
+        Some(b"desc") => {
+            let ascii = tag.get(12..).unwrap_or_default();
+            let end = ascii.iter().position(|&b| b == 0).unwrap_or(ascii.len());
+            Ok(String::from_utf8_lossy(&ascii[..end]).into_owned())
+        }
+        // multiLocalizedUnicodeType, the first record in UTF-16
+        Some(b"mluc") => {
+            let (len, offset) = (u32_at(offset + 20)?, u32_at(offset + 24)?);
+            let Some(text) = tag.get(offset..offset + len) else {
+                bail!("ICC profile description is out of bounds");
+            };
+            let units: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            Ok(String::from_utf16_lossy(&units))
+        }
+        _ => bail!("ICC profile description has an unknown type"),
+    }
+}
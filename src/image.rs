@@ -1,14 +1,83 @@
-use std::sync::mpsc::Sender;
+use anyhow::{bail, Result};
+use exif::{In, Reader};
+use image::{imageops::FilterType as ImageFilter, DynamicImage, Rgb, RgbImage, Rgba, RgbaImage};
+use std::{
+    io::Cursor,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{SendError, Sender},
+    },
+};
 
-use ratatui::layout::Rect;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    widgets::{Paragraph, StatefulWidget, Widget, Wrap},
+};
 use ratatui_image::{protocol::StatefulProtocol, FilterType, Resize};
 
-/// A widget that uses a custom ThreadProtocol as state to offload resizing and encoding
-/// to a background thread
+use crate::writer;
+
+// Image
+//
+// Pictures shown in the terminal - the thumbnail, the map - and what's done to
+// them on the way. `ThreadImage` draws them without blocking the UI: resizing
+// and encoding for the size of the area happen on a worker thread, which gets a
+// `ResizeRequest` and hands back what `ThreadProtocol::set_protocol` takes.
+//
+//     let (tx, rx) = mpsc::channel::<ResizeRequest>();
+//     thread::spawn(move || {
+//         for request in rx {
+//             tx_main.send(request.resize_encode(None)).unwrap();
+//         }
+//     });
+//     let mut state = ThreadProtocol::new(tx, picker.new_resize_protocol(image));
+//     frame.render_stateful_widget(ThreadImage::default(), area, &mut state);
+
+/// Longest side of the image compared with the embedded thumbnail, more than a
+/// terminal shows anyway
+const COMPARE_SIZE: u32 = 1024;
+
+/// Sent to a worker thread to resize and encode a protocol for `area`
+pub struct ResizeRequest {
+    pub protocol: Box<dyn StatefulProtocol>,
+    pub resize: Resize,
+    pub area: Rect,
+    /// The `ThreadProtocol` that sent it
+    id: u64,
+}
+
+impl ResizeRequest {
+    /// Does the work, on the worker thread, and hands the protocol back to be
+    /// passed to `ThreadProtocol::set_protocol`
+    pub fn resize_encode(mut self, background: Option<Rgb<u8>>) -> Resized {
+        self.protocol
+            .resize_encode(&self.resize, background, self.area);
+        Resized {
+            protocol: self.protocol,
+            id: self.id,
+        }
+    }
+}
+
+/// A protocol resized and encoded by a worker
+pub struct Resized {
+    protocol: Box<dyn StatefulProtocol>,
+    id: u64,
+}
+
+/// Draws an image whose resizing and encoding is done by a worker thread, so
+/// the UI doesn't block on it
 pub struct ThreadImage {
     pub resize: Resize,
 }
 
+impl Default for ThreadImage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ThreadImage {
     pub fn new() -> ThreadImage {
         ThreadImage {
@@ -22,23 +91,184 @@ impl ThreadImage {
     }
 }
 
-/// The state of a ThreadImage.
-///
-/// Has `inner` [ResizeProtocol] that is sent off to the `tx` mspc channel to do the
-/// `resize_encode()` work.
+impl StatefulWidget for ThreadImage {
+    type State = ThreadProtocol;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut ThreadProtocol) {
+        if state.error.is_none() {
+            // Away being resized. Renders for other sizes meanwhile don't queue
+            // more work, the protocol is sent off again for the latest size once
+            // it's back.
+            let Some(mut protocol) = state.inner.take() else {
+                return;
+            };
+            let Some(rect) = protocol.needs_resize(&self.resize, area) else {
+                protocol.render(area, buf);
+                state.inner = Some(protocol);
+                return;
+            };
+            let request = ResizeRequest {
+                protocol,
+                resize: self.resize,
+                area: rect,
+                id: state.id,
+            };
+            let Err(SendError(request)) = state.tx.send(request) else {
+                return;
+            };
+            state.inner = Some(request.protocol);
+            state.error = Some(String::from("Unable to show the image, its worker stopped"));
+        }
+        if let Some(error) = &state.error {
+            Paragraph::new(error.as_str())
+                .wrap(Wrap { trim: true })
+                .render(area, buf);
+        }
+    }
+}
+
+/// The state of a `ThreadImage`, the protocol while it's here and where to send
+/// it to be resized
 pub struct ThreadProtocol {
-    pub inner: Option<Box<dyn StatefulProtocol>>,
-    pub tx: Sender<(Box<dyn StatefulProtocol>, Resize, Rect)>,
+    inner: Option<Box<dyn StatefulProtocol>>,
+    tx: Sender<ResizeRequest>,
+    id: u64,
+    error: Option<String>,
 }
 
 impl ThreadProtocol {
-    pub fn new(
-        tx: Sender<(Box<dyn StatefulProtocol>, Resize, Rect)>,
-        inner: Box<dyn StatefulProtocol>,
-    ) -> ThreadProtocol {
+    pub fn new(tx: Sender<ResizeRequest>, inner: Box<dyn StatefulProtocol>) -> ThreadProtocol {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
         ThreadProtocol {
             inner: Some(inner),
             tx,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            error: None,
+        }
+    }
+
+    /// Takes the protocol back from a worker. Protocols sent by a state that has
+    /// since been replaced, e.g. for the previous image, are dropped.
+    pub fn set_protocol(&mut self, resized: Resized) {
+        if resized.id == self.id {
+            self.inner = Some(resized.protocol);
         }
     }
+
+    /// Whether the protocol is away being resized
+    pub fn is_resizing(&self) -> bool {
+        self.inner.is_none() && self.error.is_none()
+    }
+
+    /// Why the image can't be shown, if it can't
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+}
+
+/// What shows through transparent parts of the thumbnail and around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    /// Transparency is left to the terminal, which shows its own background where
+    /// its graphics protocol supports that
+    Terminal,
+    Color(Rgb<u8>),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color(Rgb([0, 0, 0]))
+    }
+}
+
+impl Background {
+    /// Parses "terminal", a color name like "white" or a hex color like "#1e1e2e"
+    pub fn from_name(name: &str) -> Result<Self> {
+        let color = |r, g, b| Ok(Background::Color(Rgb([r, g, b])));
+        match name.to_lowercase().as_str() {
+            "terminal" => Ok(Background::Terminal),
+            "black" => color(0, 0, 0),
+            "white" => color(255, 255, 255),
+            "gray" | "grey" => color(128, 128, 128),
+            "dark-gray" | "dark-grey" => color(48, 48, 48),
+            "magenta" => color(255, 0, 255),
+            hex => {
+                let digits = hex.strip_prefix('#').unwrap_or(hex);
+                let channel = |i: usize| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok();
+                match (digits.len(), channel(0), channel(2), channel(4)) {
+                    (6, Some(r), Some(g), Some(b)) => color(r, g, b),
+                    _ => bail!(
+                        "Invalid background {:?}, expected \"terminal\", a color name or #rrggbb",
+                        name
+                    ),
+                }
+            }
+        }
+    }
+
+    /// The color to pad the thumbnail with, none to leave it to the terminal
+    pub fn color(&self) -> Option<Rgb<u8>> {
+        match self {
+            Background::Terminal => None,
+            Background::Color(color) => Some(*color),
+        }
+    }
+
+    /// `image` blended over the background, so that protocols without alpha
+    /// don't show whatever color happens to hide behind transparent pixels
+    pub fn composite(&self, image: DynamicImage) -> DynamicImage {
+        let Background::Color(Rgb(bg)) = self else {
+            return image;
+        };
+        if !image.color().has_alpha() {
+            return image;
+        }
+        let rgba = image.to_rgba8();
+        let blended = RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            let [r, g, b, a] = rgba.get_pixel(x, y).0;
+            let blend = |fg: u8, bg: u8| {
+                ((u32::from(fg) * u32::from(a) + u32::from(bg) * (255 - u32::from(a)) + 127) / 255)
+                    as u8
+            };
+            Rgb([blend(r, bg[0]), blend(g, bg[1]), blend(b, bg[2])])
+        });
+        DynamicImage::ImageRgb8(blended)
+    }
+}
+
+/// The thumbnail embedded in the Exif data of `img_buf`, if it has one that decodes
+pub fn embedded_thumbnail(img_buf: &[u8]) -> Option<DynamicImage> {
+    let exif = Reader::new()
+        .read_from_container(&mut Cursor::new(img_buf))
+        .ok()?;
+    let jpeg = writer::get_jpeg(&exif, In::THUMBNAIL).ok()??;
+    image::load_from_memory(jpeg).ok()
+}
+
+/// `image` left of `split` percent of its width and the embedded thumbnail,
+/// stretched to the same size, right of it, with a line where they meet. Crops
+/// and edits made after the thumbnail was written show up as a jump at the line.
+pub fn split_view(image: &DynamicImage, embedded: &DynamicImage, split: u8) -> DynamicImage {
+    let image = if image.width().max(image.height()) > COMPARE_SIZE {
+        image.thumbnail(COMPARE_SIZE, COMPARE_SIZE)
+    } else {
+        image.clone()
+    };
+    let (width, height) = (image.width(), image.height());
+    let embedded = embedded
+        .resize_exact(width, height, ImageFilter::Triangle)
+        .to_rgba8();
+    let image = image.to_rgba8();
+    let line = u32::from(split.min(100)) * width / 100;
+    let line_width = (width / 200).max(1);
+    let view = RgbaImage::from_fn(width, height, |x, y| {
+        if x >= line && x < line + line_width {
+            Rgba([255, 255, 255, 255])
+        } else if x < line {
+            *image.get_pixel(x, y)
+        } else {
+            *embedded.get_pixel(x, y)
+        }
+    });
+    DynamicImage::ImageRgba8(view)
 }
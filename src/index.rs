@@ -0,0 +1,138 @@
+use anyhow::{Context as _, Result};
+use exif::{Exif, In, Reader, Tag};
+use rusqlite::{params, Connection};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    backend::{ExifBackend, MetadataBackend},
+    batch, stats, utils,
+    writer::ByteOrder,
+};
+
+// Index
+//
+// Writes the metadata of a library into an SQLite database for analyses no
+// subcommand covers. `files` has a row per image with the columns most queries
+// need, normalized - signed degrees, ISO 8601 times, numbers as numbers - and
+// `tags` every field of every image as shown in the table.
+//
+//     SELECT model, count(*) FROM files GROUP BY model;
+//     SELECT path FROM files JOIN tags ON tags.file_id = files.id
+//         WHERE tags.name = 'Artist';
+//
+// Indexing into an existing database replaces the rows of the images indexed
+// again and leaves the others, so a library can be indexed a folder at a time.
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    size INTEGER NOT NULL,
+    has_exif INTEGER NOT NULL,
+    byte_order TEXT,
+    make TEXT,
+    model TEXT,
+    lens TEXT,
+    taken TEXT,
+    utc_offset TEXT,
+    latitude REAL,
+    longitude REAL,
+    width INTEGER,
+    height INTEGER,
+    orientation INTEGER
+);
+CREATE TABLE IF NOT EXISTS tags (
+    file_id INTEGER NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    ifd INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    value TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS tags_by_name ON tags(name);
+";
+
+/// How many images went into the database
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub images: usize,
+    pub without_exif: usize,
+    pub tags: usize,
+}
+
+/// Indexes the images in `paths` into the database at `db`, creating it if needed
+pub fn index(paths: &[PathBuf], db: &Path) -> Result<Summary> {
+    let mut conn =
+        Connection::open(db).with_context(|| format!("Unable to open database {:?}", db))?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    conn.execute_batch(SCHEMA)?;
+
+    let tx = conn.transaction()?;
+    let mut summary = Summary::default();
+    for path in batch::collect_images(paths)? {
+        let img_buf = std::fs::read(&path)?;
+        let exif = Reader::new()
+            .read_from_container(&mut Cursor::new(&img_buf))
+            .ok();
+        let path = std::fs::canonicalize(&path).unwrap_or(path);
+        let path = path.to_string_lossy();
+        tx.execute("DELETE FROM files WHERE path = ?1", [&path])?;
+
+        let Some(exif) = exif else {
+            tx.execute(
+                "INSERT INTO files (path, size, has_exif) VALUES (?1, ?2, 0)",
+                params![path, img_buf.len()],
+            )?;
+            summary.images += 1;
+            summary.without_exif += 1;
+            continue;
+        };
+
+        let field = |tag| exif.get_field(tag, In::PRIMARY);
+        let uint = |tags: &[Tag]| tags.iter().find_map(|&t| field(t)?.value.get_uint(0));
+        let taken = utils::capture_time(field);
+        let location = utils::location(field);
+        tx.execute(
+            "INSERT INTO files (path, size, has_exif, byte_order, make, model, lens, taken,
+                utc_offset, latitude, longitude, width, height, orientation)
+             VALUES (?1, ?2, 1, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                path,
+                img_buf.len(),
+                ByteOrder::of(&exif).name(),
+                stats::text(&exif, Tag::Make),
+                stats::text(&exif, Tag::Model),
+                stats::text(&exif, Tag::LensModel),
+                taken.map(|(t, _)| t.format("%Y-%m-%dT%H:%M:%S").to_string()),
+                taken.and_then(|(_, o)| o).map(|o| o.to_string()),
+                location.map(|(lat, _)| lat),
+                location.map(|(_, lon)| lon),
+                uint(&[Tag::PixelXDimension, Tag::ImageWidth]),
+                uint(&[Tag::PixelYDimension, Tag::ImageLength]),
+                uint(&[Tag::Orientation]),
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        summary.images += 1;
+        summary.tags += insert_tags(&tx, id, exif)?;
+    }
+    tx.commit()?;
+    Ok(summary)
+}
+
+fn insert_tags(tx: &rusqlite::Transaction, id: i64, exif: Exif) -> Result<usize> {
+    let backend = ExifBackend::new(exif);
+    let mut insert =
+        tx.prepare("INSERT INTO tags (file_id, ifd, name, value) VALUES (?1, ?2, ?3, ?4)")?;
+    let fields = backend.fields();
+    for field in &fields {
+        insert.execute(params![
+            id,
+            field.ifd_num.index(),
+            utils::tag_name(field.tag),
+            backend.display(field)
+        ])?;
+    }
+    Ok(fields.len())
+}
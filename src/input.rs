@@ -0,0 +1,102 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::time::{Duration, Instant};
+
+// Input
+//
+// Turns key presses into what the app acts on. A held key repeats faster than
+// frames are drawn, so repeats of a key read in the same frame count once,
+// instead of piling up and zooming on after the key is let go.
+//
+// Some keys start a chord, like `g` then `g` to jump to the first row. The
+// first key is held back until the next one shows whether it's a chord, or
+// until `CHORD_TIMEOUT` passes without one and it counts on its own.
+
+/// How long the first key of a chord waits for the second
+pub const CHORD_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chord {
+    FirstRow,
+    LastRow,
+}
+
+/// The keys of each chord, in order
+pub const CHORDS: [(char, char, Chord); 2] =
+    [('g', 'g', Chord::FirstRow), ('g', 'G', Chord::LastRow)];
+
+/// What a key press comes to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Key(KeyEvent),
+    Chord(Chord),
+}
+
+/// Character typed with `key`, if it's a plain one that chords are made of
+fn plain_char(key: &KeyEvent) -> Option<char> {
+    match key.code {
+        KeyCode::Char(c) if (key.modifiers - KeyModifiers::SHIFT).is_empty() => Some(c),
+        _ => None,
+    }
+}
+
+/// Keys read so far that may still turn into a chord
+#[derive(Debug, Default)]
+pub struct InputState {
+    pending: Option<(KeyEvent, Instant)>,
+    /// Last key of the frame being read, to tell repeats from new presses
+    last: Option<KeyEvent>,
+}
+
+impl InputState {
+    /// What to act on after `key` was pressed at `now`
+    pub fn press(&mut self, key: KeyEvent, now: Instant) -> Vec<Input> {
+        // The second key of a chord may well be the first one again
+        if self.pending.is_none() && self.last == Some(key) {
+            return Vec::new();
+        }
+        self.last = Some(key);
+
+        let c = plain_char(&key);
+        if let Some((first, _)) = self.pending.take() {
+            let chord = CHORDS
+                .iter()
+                .find(|(a, b, _)| plain_char(&first) == Some(*a) && c == Some(*b));
+            if let Some((_, _, chord)) = chord {
+                return vec![Input::Chord(*chord)];
+            }
+            // Not a chord after all, both keys count on their own
+            let mut inputs = vec![Input::Key(first)];
+            inputs.extend(self.press_alone(key, now));
+            return inputs;
+        }
+        self.press_alone(key, now).into_iter().collect()
+    }
+
+    fn press_alone(&mut self, key: KeyEvent, now: Instant) -> Option<Input> {
+        let starts_chord = plain_char(&key).is_some_and(|c| CHORDS.iter().any(|(a, ..)| *a == c));
+        if starts_chord {
+            self.pending = Some((key, now));
+            None
+        } else {
+            Some(Input::Key(key))
+        }
+    }
+
+    /// Called once a frame, after its keys were read. Gives back the key held
+    /// for a chord once nothing followed it in time.
+    pub fn tick(&mut self, now: Instant) -> Option<Input> {
+        self.last = None;
+        match self.pending {
+            Some((key, since)) if now.duration_since(since) >= CHORD_TIMEOUT => {
+                self.pending = None;
+                Some(Input::Key(key))
+            }
+            _ => None,
+        }
+    }
+
+    /// First key of a chord waiting for the second
+    pub fn pending(&self) -> Option<char> {
+        self.pending.as_ref().and_then(|(key, _)| plain_char(key))
+    }
+}
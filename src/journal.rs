@@ -0,0 +1,156 @@
+use anyhow::{bail, Context as _, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+// Journal
+//
+// A scrub over a huge library can take long enough to be interrupted. While it
+// runs, every file is recorded in a journal - one JSON line per file with where
+// the copy went or why it failed, after a first line with the options of the
+// job. `--resume` reads it back and skips the files that were already scrubbed,
+// trying the failed ones again.
+//
+// Lines are written as soon as a file is done, so at most the line being
+// written when the job was killed is lost, and that file is simply scrubbed
+// again. The journal is removed once every file was scrubbed.
+
+/// Name of the journal, in the output directory or the current one
+pub const FILE_NAME: &str = "bresson-scrub.journal";
+
+/// What a job was started with. Resuming with other options would mix copies
+/// scrubbed two different ways.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Options {
+    pub paths: Vec<PathBuf>,
+    pub processors: Vec<String>,
+    pub policy: Option<PathBuf>,
+    pub out_dir: Option<PathBuf>,
+    /// How the copies were written, see `batch::Export`
+    #[serde(default)]
+    pub to_jpeg: bool,
+    #[serde(default)]
+    pub quality: u8,
+    #[serde(default)]
+    pub max_dimension: Option<u32>,
+    /// What's done with color profiles, "drop" or "srgb"
+    #[serde(default)]
+    pub icc: Option<String>,
+    #[serde(default)]
+    pub stamp: bool,
+    #[serde(default)]
+    pub byte_order: Option<String>,
+    #[serde(default)]
+    pub strip_video: bool,
+    #[serde(default)]
+    pub oversized_exif: Option<String>,
+    /// Settings of the randomizer, e.g. the range of capture times
+    pub randomizer: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    path: PathBuf,
+    output: Option<PathBuf>,
+    error: Option<String>,
+}
+
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+    /// Files scrubbed before the job was resumed
+    pub done: HashSet<PathBuf>,
+    /// Files that failed before the job was resumed, they're tried again
+    pub failed: usize,
+}
+
+impl Journal {
+    /// Where the journal of a job writing into `out_dir` is kept
+    pub fn path(out_dir: Option<&Path>) -> PathBuf {
+        out_dir.unwrap_or(Path::new(".")).join(FILE_NAME)
+    }
+
+    /// Starts a new journal at `path`, replacing any earlier one
+    pub fn start(path: &Path, options: &Options) -> Result<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file =
+            File::create(path).with_context(|| format!("Unable to create journal {:?}", path))?;
+        writeln!(file, "{}", serde_json::to_string(options)?)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            done: HashSet::new(),
+            failed: 0,
+        })
+    }
+
+    /// Picks the journal at `path` up again, if it was written with `options`
+    pub fn resume(path: &Path, options: &Options) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("No journal to resume at {:?}", path))?;
+        let mut lines = BufReader::new(file).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let started: Options =
+            serde_json::from_str(&header).with_context(|| format!("Invalid journal {:?}", path))?;
+        if started != *options {
+            bail!(
+                "The journal at {:?} was written by a scrub with other options, start over without --resume",
+                path
+            );
+        }
+
+        let mut done = HashSet::new();
+        let mut failed = HashSet::new();
+        // The last line may have been cut short when the job was interrupted
+        for entry in lines
+            .map_while(|l| l.ok())
+            .map_while(|l| serde_json::from_str::<Entry>(&l).ok())
+        {
+            if entry.error.is_some() {
+                failed.insert(entry.path);
+            } else {
+                failed.remove(&entry.path);
+                done.insert(entry.path);
+            }
+        }
+        let file = OpenOptions::new().append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            failed: failed.difference(&done).count(),
+            done,
+        })
+    }
+
+    /// Records that `path` was scrubbed into `output`, or why it wasn't
+    pub fn record(&mut self, path: &Path, result: Result<Option<&Path>, String>) -> Result<()> {
+        let entry = match result {
+            Ok(output) => Entry {
+                path: path.to_path_buf(),
+                output: output.map(Path::to_path_buf),
+                error: None,
+            },
+            Err(error) => Entry {
+                path: path.to_path_buf(),
+                output: None,
+                error: Some(error),
+            },
+        };
+        writeln!(self.file, "{}", serde_json::to_string(&entry)?)?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    /// Removes the journal of a job that's done
+    pub fn finish(self) -> Result<()> {
+        drop(self.file);
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+}
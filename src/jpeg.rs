@@ -0,0 +1,331 @@
+use anyhow::{bail, Result};
+
+// JPEG container helpers
+//
+// A JPEG file is a sequence of marker segments (FFxx + 2 byte big endian length)
+// followed by the entropy coded scan data after the SOS marker. Exif lives in an
+// APP1 segment whose payload starts with "Exif\0\0" followed by the TIFF data.
+
+pub const SOI: u8 = 0xD8;
+pub const EOI: u8 = 0xD9;
+pub const SOS: u8 = 0xDA;
+pub const APP0: u8 = 0xE0;
+pub const APP1: u8 = 0xE1;
+pub const APP2: u8 = 0xE2;
+pub const COM: u8 = 0xFE;
+
+pub const EXIF_HEADER: &[u8; 6] = b"Exif\0\0";
+/// XMP packets are stored in APP1 segments starting with one of these, the
+/// second for the parts of packets too large for a single segment
+pub const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+pub const XMP_EXTENSION_HEADER: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+/// ICC profiles are split over APP2 segments, each starting with this header
+/// followed by its sequence number and the number of segments, both from 1
+pub const ICC_HEADER: &[u8; 12] = b"ICC_PROFILE\0";
+
+/// Largest payload that fits into a single marker segment
+pub const MAX_SEGMENT_PAYLOAD: usize = u16::MAX as usize - 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub marker: u8,
+    /// Offset of the 0xFF byte that starts the segment
+    pub offset: usize,
+    /// Length of the whole segment, including marker and length bytes
+    pub len: usize,
+}
+
+impl Segment {
+    pub fn payload<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        if self.len <= 4 {
+            return &[];
+        }
+        &buf[self.offset + 4..self.offset + self.len]
+    }
+
+    pub fn is_exif(&self, buf: &[u8]) -> bool {
+        self.marker == APP1 && self.payload(buf).starts_with(EXIF_HEADER)
+    }
+
+    pub fn is_icc(&self, buf: &[u8]) -> bool {
+        self.marker == APP2 && self.payload(buf).starts_with(ICC_HEADER)
+    }
+}
+
+pub fn is_jpeg(buf: &[u8]) -> bool {
+    buf.len() >= 2 && buf[0] == 0xFF && buf[1] == SOI
+}
+
+/// Lists the marker segments up to and including SOS
+pub fn segments(buf: &[u8]) -> Result<Vec<Segment>> {
+    if !is_jpeg(buf) {
+        bail!("Not a JPEG file");
+    }
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    loop {
+        if pos + 1 >= buf.len() {
+            bail!("Truncated JPEG: missing SOS marker");
+        }
+        if buf[pos] != 0xFF {
+            bail!("Invalid JPEG marker at offset {}", pos);
+        }
+        // Markers may be preceded by any number of fill bytes
+        let mut marker_pos = pos;
+        while marker_pos + 1 < buf.len() && buf[marker_pos + 1] == 0xFF {
+            marker_pos += 1;
+        }
+        let marker = buf[marker_pos + 1];
+        match marker {
+            // Standalone markers carry no length
+            0x01 | 0xD0..=0xD7 => {
+                pos = marker_pos + 2;
+                continue;
+            }
+            EOI => bail!("Unexpected EOI before SOS"),
+            _ => {}
+        }
+        if marker_pos + 4 > buf.len() {
+            bail!("Truncated JPEG segment at offset {}", marker_pos);
+        }
+        let seg_len = u16::from_be_bytes([buf[marker_pos + 2], buf[marker_pos + 3]]) as usize;
+        if seg_len < 2 || marker_pos + 2 + seg_len > buf.len() {
+            bail!("JPEG segment at offset {} overruns the file", marker_pos);
+        }
+        segments.push(Segment {
+            marker,
+            offset: marker_pos,
+            len: 2 + seg_len,
+        });
+        if marker == SOS {
+            return Ok(segments);
+        }
+        pos = marker_pos + 2 + seg_len;
+    }
+}
+
+/// Offset right after the EOI marker that ends the image, walking through the
+/// scans of baseline and progressive JPEGs alike
+pub fn end_of_image(buf: &[u8]) -> Result<usize> {
+    let sos = segments(buf)?.pop().expect("segments end with SOS");
+    let mut pos = sos.offset + sos.len;
+    loop {
+        // Inside entropy coded data 0xFF is followed by a stuffed 0 or a restart marker
+        while pos + 1 < buf.len()
+            && (buf[pos] != 0xFF || matches!(buf[pos + 1], 0 | 0xD0..=0xD7 | 0xFF))
+        {
+            pos += 1;
+        }
+        if pos + 1 >= buf.len() {
+            bail!("Truncated JPEG: missing EOI marker");
+        }
+        if buf[pos + 1] == EOI {
+            return Ok(pos + 2);
+        }
+        // Tables and the next scan of a progressive JPEG
+        if pos + 4 > buf.len() {
+            bail!("Truncated JPEG segment at offset {}", pos);
+        }
+        pos += 2 + u16::from_be_bytes([buf[pos + 2], buf[pos + 3]]) as usize;
+    }
+}
+
+/// Everything that makes up the pixels - the tables, frame header and the
+/// entropy coded data from SOS on - leaving out the APPn and COM segments that
+/// only hold metadata. Two files with the same image data decode to exactly the
+/// same pixels.
+pub fn image_data(buf: &[u8]) -> Result<Vec<u8>> {
+    let segments = segments(buf)?;
+    let mut data = Vec::with_capacity(buf.len());
+    for segment in &segments {
+        let metadata = matches!(segment.marker, 0xE0..=0xEF | COM);
+        if segment.marker == SOS {
+            data.extend_from_slice(&buf[segment.offset..]);
+        } else if !metadata {
+            data.extend_from_slice(&buf[segment.offset..segment.offset + segment.len]);
+        }
+    }
+    Ok(data)
+}
+
+/// Builds a complete APP1 segment holding the given TIFF encoded Exif data
+pub fn exif_segment(tiff: &[u8]) -> Result<Vec<u8>> {
+    let payload_len = EXIF_HEADER.len() + tiff.len();
+    if payload_len > MAX_SEGMENT_PAYLOAD {
+        bail!(
+            "Exif data is {} bytes, which exceeds the {} byte APP1 limit",
+            payload_len,
+            MAX_SEGMENT_PAYLOAD
+        );
+    }
+    let mut segment = Vec::with_capacity(payload_len + 4);
+    segment.extend_from_slice(&[0xFF, APP1]);
+    segment.extend_from_slice(&((payload_len + 2) as u16).to_be_bytes());
+    segment.extend_from_slice(EXIF_HEADER);
+    segment.extend_from_slice(tiff);
+    Ok(segment)
+}
+
+/// Replaces the Exif APP1 segment with `tiff`, inserting one if the file has none.
+/// Any further Exif segments, left behind by tools that added their own instead
+/// of updating the first, are dropped so they can't contradict the new one.
+pub fn replace_exif(buf: &[u8], tiff: &[u8]) -> Result<Vec<u8>> {
+    let segments = segments(buf)?;
+    let new_segment = exif_segment(tiff)?;
+
+    let mut out = Vec::with_capacity(buf.len() + new_segment.len());
+    let mut exif_segments = segments.iter().filter(|s| s.is_exif(buf)).peekable();
+    if exif_segments.peek().is_none() {
+        // Exif should come right after SOI, but JFIF requires APP0 to be first
+        let insert_at = match segments.first() {
+            Some(s) if s.marker == APP0 => s.offset + s.len,
+            _ => 2,
+        };
+        out.extend_from_slice(&buf[..insert_at]);
+        out.extend(new_segment);
+        out.extend_from_slice(&buf[insert_at..]);
+        return Ok(out);
+    }
+    let mut pos = 0;
+    for (i, exif) in exif_segments.enumerate() {
+        out.extend_from_slice(&buf[pos..exif.offset]);
+        if i == 0 {
+            out.extend_from_slice(&new_segment);
+        }
+        pos = exif.offset + exif.len;
+    }
+    out.extend_from_slice(&buf[pos..]);
+    Ok(out)
+}
+
+/// Adds `packet` as the XMP packet of the image, right after the Exif data.
+/// Fails if the image has one already, or the packet doesn't fit into a segment.
+pub fn insert_xmp(buf: &[u8], packet: &[u8]) -> Result<Vec<u8>> {
+    let segments = segments(buf)?;
+    let has_xmp = segments
+        .iter()
+        .any(|s| s.marker == APP1 && s.payload(buf).starts_with(XMP_HEADER));
+    if has_xmp {
+        bail!("The image has an XMP packet of its own, the one in the Exif data can't join it");
+    }
+    let payload_len = XMP_HEADER.len() + packet.len();
+    if payload_len > MAX_SEGMENT_PAYLOAD {
+        bail!(
+            "The XMP packet is {} bytes, too large for a segment of its own",
+            packet.len()
+        );
+    }
+    let insert_at = segments
+        .iter()
+        .take_while(|s| s.marker == APP0 || s.is_exif(buf))
+        .last()
+        .map_or(2, |s| s.offset + s.len);
+    let mut out = Vec::with_capacity(buf.len() + payload_len + 4);
+    out.extend_from_slice(&buf[..insert_at]);
+    out.extend_from_slice(&[0xFF, APP1]);
+    out.extend_from_slice(&((payload_len + 2) as u16).to_be_bytes());
+    out.extend_from_slice(XMP_HEADER);
+    out.extend_from_slice(packet);
+    out.extend_from_slice(&buf[insert_at..]);
+    Ok(out)
+}
+
+/// Drops every Exif APP1 segment
+pub fn remove_exif(buf: &[u8]) -> Result<Vec<u8>> {
+    let segments = segments(buf)?;
+    let mut out = Vec::with_capacity(buf.len());
+    let mut pos = 0;
+    for exif in segments.iter().filter(|s| s.is_exif(buf)) {
+        out.extend_from_slice(&buf[pos..exif.offset]);
+        pos = exif.offset + exif.len;
+    }
+    out.extend_from_slice(&buf[pos..]);
+    Ok(out)
+}
+
+/// Drops the XMP packets, the extended ones included, and comments. Returns the
+/// copy and what was dropped.
+pub fn remove_xmp(buf: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+    let segments = segments(buf)?;
+    let mut out = Vec::with_capacity(buf.len());
+    let mut removed = Vec::new();
+    let mut pos = 0;
+    for segment in &segments {
+        let payload = segment.payload(buf);
+        let what = match segment.marker {
+            APP1 if payload.starts_with(XMP_HEADER) => "XMP",
+            APP1 if payload.starts_with(XMP_EXTENSION_HEADER) => "Extended XMP",
+            COM => "Comment",
+            _ => continue,
+        };
+        removed.push(what.to_owned());
+        out.extend_from_slice(&buf[pos..segment.offset]);
+        pos = segment.offset + segment.len;
+    }
+    out.extend_from_slice(&buf[pos..]);
+    Ok((out, removed))
+}
+
+/// The embedded ICC profile, put back together from its segments
+pub fn icc_profile(buf: &[u8]) -> Result<Option<Vec<u8>>> {
+    let mut chunks: Vec<(u8, &[u8])> = segments(buf)?
+        .iter()
+        .filter(|s| s.is_icc(buf))
+        .map(|s| s.payload(buf))
+        .filter(|p| p.len() >= ICC_HEADER.len() + 2)
+        .map(|p| (p[ICC_HEADER.len()], &p[ICC_HEADER.len() + 2..]))
+        .collect();
+    if chunks.is_empty() {
+        return Ok(None);
+    }
+    chunks.sort_by_key(|&(sequence, _)| sequence);
+    Ok(Some(
+        chunks.into_iter().flat_map(|(_, c)| c).copied().collect(),
+    ))
+}
+
+/// Replaces the ICC profile with `profile`, split over as many APP2 segments as
+/// it takes, or drops it without one
+pub fn replace_icc(buf: &[u8], profile: Option<&[u8]>) -> Result<Vec<u8>> {
+    let segments = segments(buf)?;
+    let chunk_len = MAX_SEGMENT_PAYLOAD - ICC_HEADER.len() - 2;
+    let chunks: Vec<&[u8]> = profile.map_or(Vec::new(), |p| p.chunks(chunk_len).collect());
+    if chunks.len() > u8::MAX as usize {
+        bail!("ICC profile is too large for a JPEG");
+    }
+    let mut new_segments = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let payload_len = ICC_HEADER.len() + 2 + chunk.len();
+        new_segments.extend_from_slice(&[0xFF, APP2]);
+        new_segments.extend_from_slice(&((payload_len + 2) as u16).to_be_bytes());
+        new_segments.extend_from_slice(ICC_HEADER);
+        new_segments.extend_from_slice(&[i as u8 + 1, chunks.len() as u8]);
+        new_segments.extend_from_slice(chunk);
+    }
+
+    // The profile goes where the old one was, or after the JFIF and Exif segments
+    let insert_at = match segments.iter().find(|s| s.is_icc(buf)) {
+        Some(icc) => icc.offset,
+        None => segments
+            .iter()
+            .take_while(|s| s.marker == APP0 || s.marker == APP1)
+            .last()
+            .map_or(2, |s| s.offset + s.len),
+    };
+    let mut out = Vec::with_capacity(buf.len() + new_segments.len());
+    let mut pos = 0;
+    for icc in segments.iter().filter(|s| s.is_icc(buf)) {
+        out.extend_from_slice(&buf[pos..icc.offset]);
+        if icc.offset == insert_at {
+            out.extend_from_slice(&new_segments);
+        }
+        pos = icc.offset + icc.len;
+    }
+    if pos <= insert_at {
+        out.extend_from_slice(&buf[pos..insert_at]);
+        out.extend_from_slice(&new_segments);
+        pos = insert_at;
+    }
+    out.extend_from_slice(&buf[pos..]);
+    Ok(out)
+}
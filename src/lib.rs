@@ -1,8 +1,57 @@
+pub mod almanac;
+pub mod audio;
+pub mod backend;
+pub mod batch;
+pub mod cli;
+pub mod clipboard;
+pub mod cluster;
+pub mod config;
+pub mod dirs;
+pub mod document;
+pub mod editor;
+pub mod elevation;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fixture;
 pub mod globe;
+pub mod gpx;
+pub mod grep;
+pub mod home;
+pub mod icc;
 pub mod image;
+pub mod index;
+pub mod input;
+pub mod journal;
+pub mod jpeg;
+pub mod motion;
+#[cfg(feature = "async")]
+pub mod nonblocking;
 pub mod order;
+pub mod osm;
+pub mod perf;
+pub mod png;
+pub mod policy;
+pub mod processor;
 pub mod randomize;
+pub mod record;
+pub mod repl;
+pub mod report;
+pub mod save;
+pub mod serial;
+pub mod serve;
+pub mod session;
+pub mod sidecar;
 pub mod state;
+pub mod stats;
+pub mod stego;
+pub mod sun;
+pub mod timeline;
 pub mod tui;
 pub mod ui;
+pub mod undo;
 pub mod utils;
+pub mod vault;
+pub mod version;
+pub mod webp;
+pub mod widget;
+pub mod writer;
@@ -1,33 +1,136 @@
-use bresson::{globe::Globe, state::*, tui, ui::*};
-use ratatui_image::{protocol::StatefulProtocol, Resize};
-use std::{path::Path, sync::mpsc, thread, time::Duration};
+use bresson::{
+    batch,
+    cli::{Cli, Command},
+    clipboard::{self, Contents},
+    config::Config,
+    globe::{Globe, Region},
+    image::{ResizeRequest, Resized},
+    input::{Chord, Input, InputState},
+    osm,
+    policy::Policy,
+    state::*,
+    tui,
+    ui::*,
+    vault, version,
+    writer::Oversized,
+};
+use clap::{CommandFactory, Parser};
+use std::{
+    path::PathBuf,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
 
-use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
-use ratatui::{prelude::*, widgets::TableState};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::widgets::TableState;
 
 enum AppEvent {
     KeyEvent(KeyEvent),
-    Redraw(Box<dyn StatefulProtocol>),
+    Redraw(Resized),
+    RedrawMap(Resized),
+    MapLoaded(anyhow::Result<image::DynamicImage>),
 }
 
 fn main() -> anyhow::Result<()> {
-    if std::env::args().len() < 2 {
+    let cli = Cli::parse();
+
+    match &cli.command {
+        // The images chosen on the timeline are opened like the ones named
+        // on the command line
+        Some(Command::Timeline(timeline)) if !timeline.print => {
+            if let Some(images) = timeline.choose()? {
+                run_tui(&images, &cli)?;
+            }
+            return Ok(());
+        }
+        Some(command) => return command.run(),
+        None => {}
+    }
+    if cli.version {
+        if cli.verbose {
+            print!("{}", version::verbose(version::detect_protocol()));
+        } else {
+            println!("bresson {}", env!("CARGO_PKG_VERSION"));
+        }
+        return Ok(());
+    }
+    let images = match cli.from_clipboard {
+        true => match clipboard::read()? {
+            Contents::Files(files) => {
+                let images: Vec<_> = files.into_iter().filter(|f| batch::is_image(f)).collect();
+                if images.is_empty() {
+                    anyhow::bail!("None of the files on the clipboard is an image");
+                }
+                images
+            }
+            Contents::Pixels { width, height } => {
+                println!(
+                    "The clipboard holds a {}x{} image as pixels only, it has no metadata to scrub",
+                    width, height
+                );
+                return Ok(());
+            }
+        },
+        false => open_paths(&cli.images)?,
+    };
+    if images.is_empty() {
+        Cli::command().print_help()?;
         std::process::exit(1);
     }
-    let image_arg = std::env::args().nth(1).unwrap();
+    if cli.markdown {
+        for image_file in &images {
+            print!("{}", Document::open(image_file)?.markdown());
+        }
+        return Ok(());
+    }
+    let copy = run_tui(&images, &cli)?;
+    if let Some(copy) = copy.filter(|_| cli.from_clipboard) {
+        println!("Putting {:?} on the clipboard", copy);
+        clipboard::put_image(&copy)?;
+    }
+    Ok(())
+}
 
-    let image_file = Path::new(&image_arg);
+/// Files named on the command line, with directories expanded into the files
+/// bresson reads in them
+fn open_paths(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    for path in paths {
+        match path.is_dir() {
+            true => images.extend(batch::collect_supported(std::slice::from_ref(path))?),
+            false => images.push(path.clone()),
+        }
+    }
+    Ok(images)
+}
+
+/// Runs the interface on `images`, starting with the first, returning the last
+/// copy saved
+fn run_tui(images: &[PathBuf], cli: &Cli) -> anyhow::Result<Option<PathBuf>> {
+    let image_file = &images[0];
     if !image_file.is_file() {
         eprintln!("Image not present");
-        return Ok(());
+        return Ok(None);
     }
 
+    // Ask for the passphrase before the terminal switches into raw mode
+    let vault_passphrase = if cli.vault {
+        Some(vault::read_passphrase(true)?)
+    } else {
+        None
+    };
+
+    let policy = cli.policy.as_deref().map(Policy::open).transpose()?;
+    let config = Config::open(cli.config.as_deref())?;
+    let background = config.thumbnail.background()?;
+
     let cam_zoom = 1.5;
     let mut globe = Globe::new(1., 0., false);
     globe.camera.update(cam_zoom, 0., 0.);
 
-    // Send a [ResizeProtocol] to resize and encode it in a separate thread.
-    let (tx_worker, rec_worker) = mpsc::channel::<(Box<dyn StatefulProtocol>, Resize, Rect)>();
+    // Send a [ResizeRequest] to resize and encode it in a separate thread.
+    let (tx_worker, rec_worker) = mpsc::channel::<ResizeRequest>();
 
     // Send UI-events and the [ResizeProtocol] result back to main thread.
     let (tx_main, rec_main) = mpsc::channel();
@@ -35,13 +138,44 @@ fn main() -> anyhow::Result<()> {
     // Resize and encode in background thread.
     let tx_main_render = tx_main.clone();
     thread::spawn(move || loop {
-        if let Ok((mut protocol, resize, area)) = rec_worker.recv() {
-            protocol.resize_encode(&resize, None, area);
-            tx_main_render.send(AppEvent::Redraw(protocol)).unwrap();
+        if let Ok(request) = rec_worker.recv() {
+            let resized = request.resize_encode(background.color());
+            tx_main_render.send(AppEvent::Redraw(resized)).unwrap();
+        }
+    });
+    // The map is resized on its own thread, so that its protocol comes back to it
+    let (tx_map_worker, rec_map_worker) = mpsc::channel::<ResizeRequest>();
+    let tx_main_map = tx_main.clone();
+    thread::spawn(move || loop {
+        if let Ok(request) = rec_map_worker.recv() {
+            let resized = request.resize_encode(None);
+            tx_main_map.send(AppEvent::RedrawMap(resized)).unwrap();
         }
     });
+
     let mut app = Application::new(image_file, globe, tx_worker)?;
-    app.update_gps();
+    app.images = images.to_vec();
+    app.document.vault_passphrase = vault_passphrase;
+    app.document.policy = policy;
+    app.document.backup = cli.backup;
+    if let Some(oversized) = cli.oversized_exif.as_deref().and_then(Oversized::from_name) {
+        app.document.oversized = oversized;
+    }
+    app.ui.online = cli.online;
+    app.ui.hyperlinks = tui::supports_hyperlinks();
+    app.ui.set_background(background);
+    if let Some(ratio) = config.globe.font_ratio {
+        app.ui.globe.font_ratio = ratio;
+    }
+    app.ui.globe.starfield = config.globe.starfield;
+    if let Some(bulk) = config.confirm.bulk {
+        app.ui.confirm_bulk = bulk;
+    }
+    if let Some(dir) = config.globe.regions_dir() {
+        app.ui.globe.regions = Region::load_dir(&dir)?;
+    }
+    let unknown_tags = config.apply(&mut app.document.randomizer);
+    app.document.update_gps();
 
     // Poll events in background thread to demonstrate polling terminal events and redraw events
     // concurrently. It's not required to do it this way - the "redraw event" from the channel
@@ -62,157 +196,266 @@ fn main() -> anyhow::Result<()> {
     let mut terminal = tui::init_terminal()?;
     terminal.clear()?;
 
-    app.show_message(format!("Opened {:?}", app.path_to_image.clone()));
+    let mut status = match &app.document.policy {
+        Some(policy) => format!(
+            "Opened {:?} with policy {}",
+            app.document.path_to_image,
+            policy.display_name()
+        ),
+        None => format!("Opened {:?}", app.document.path_to_image),
+    };
+    if !unknown_tags.is_empty() {
+        status.push_str(&format!(
+            " - unknown tags in config: {}",
+            unknown_tags.join(", ")
+        ));
+    }
+    app.ui.show_message(status);
 
-    loop {
-        app.update_gps();
-        app.transform_coordinates();
+    let mut input_state = InputState::default();
+    'main: loop {
+        app.poll_save();
+        // The camera follows the image location, once it moved or panning stopped
+        let moved = app.document.refresh_gps();
+        if !app.ui.panning && (moved || !app.ui.camera_on_location) {
+            app.transform_coordinates();
+        }
 
+        let drawing = Instant::now();
         terminal.draw(|frame| view(&mut app, frame, &mut table_state))?;
-        if let Ok(ev) = rec_main.try_recv() {
+        tui::write_hyperlinks(&app.ui.links)?;
+        app.ui.perf.frame(drawing, drawing.elapsed());
+        // Everything that came in since the last frame, so that keys don't
+        // queue up behind the drawing
+        let now = Instant::now();
+        let mut inputs: Vec<Input> = input_state.tick(now).into_iter().collect();
+        for ev in rec_main.try_iter() {
+            app.ui.perf.event(now);
             match ev {
                 AppEvent::KeyEvent(key) => {
-                    if key.kind == KeyEventKind::Press && !app.show_keybinds {
-                        match key.code {
-                            KeyCode::Char(c) => match c {
-                                'u' => {
-                                    if let Some(table_index) = app.undo_operation() {
-                                        table_state.select(Some(table_index));
-                                    } else {
-                                        app.show_message("Nothing to Undo!".to_owned());
-                                    }
-                                }
-                                'U' => {
-                                    // Show Original Data
-                                    app.modified_fields = app.original_fields.clone();
-                                    if app.has_gps && !app.should_rotate {
-                                        app.transform_coordinates();
-                                    }
-                                    app.show_message("Restored Original Data".to_owned());
-                                }
-                                'r' => {
-                                    // Only randomize the selected element based on table state
-                                    match table_state.selected() {
-                                        Some(index) => {
-                                            app.randomize(index, false);
-                                        }
-                                        None => {}
-                                    }
-                                }
-                                'R' => {
-                                    // Randomize all fields (generalize over the individual field)
-                                    app.randomize_all();
-                                    app.show_message("Randomized all".to_owned());
-                                }
-                                'c' => match table_state.selected() {
-                                    Some(index) => {
-                                        app.clear_field(index, false);
-                                    }
-                                    None => {}
-                                },
-                                'C' => {
-                                    app.clear_all_fields();
-                                    app.show_message("Cleared All Metadata".to_owned())
-                                }
-                                's' | 'S' => {
-                                    // Save the state into a file copy
-                                    app.show_message("Trying to save copy...".to_owned());
-                                    match app.save_state() {
-                                        Ok(_) => {
-                                            app.show_message("Saved app state to copy".to_owned());
-                                        }
-                                        Err(_) => {
-                                            app.show_message("Unable to save copy :(".to_owned())
-                                        }
-                                    }
-                                }
-                                'g' | 'G' => {
-                                    app.toggle_globe();
-                                    if app.show_mini {
-                                        app.show_message("Showing Globe".to_owned());
-                                    } else {
-                                        app.should_rotate = false;
-                                        app.show_message("Hiding Globe".to_owned());
-                                    }
-                                }
-                                't' | 'T' => app.toggle_render_state(),
-                                '?' => {
-                                    // Display a popup window with keybinds
-                                    // toggle the show_keybinds state
-                                    app.toggle_keybinds();
-                                    if app.show_keybinds {
-                                        app.show_message("Showing Keybinds window".to_owned());
-                                    } else {
-                                        app.show_message("Hid Keybinds window".to_owned());
-                                    }
-                                }
-                                '+' => app.camera_zoom_increase(),
-                                '-' => app.camera_zoom_decrease(),
-                                ',' => app.increase_rotation_speed(),
-                                '.' => app.decrease_rotation_speed(),
-                                ' ' => app.toggle_rotate(),
-                                'q' => break,
-                                _ => {}
-                            },
-                            KeyCode::Esc => {
-                                break;
-                            }
-                            KeyCode::Down | KeyCode::Tab => match table_state.selected() {
-                                Some(i) => {
-                                    if i == app.modified_fields.len() - 1 {
-                                        table_state.select(Some(0))
-                                    } else {
-                                        table_state.select(Some(i + 1))
-                                    }
-                                }
-                                None => table_state.select(Some(0)),
-                            },
-                            KeyCode::Up | KeyCode::BackTab => match table_state.selected() {
-                                Some(i) => {
-                                    if i == 0 {
-                                        table_state.select(Some(app.modified_fields.len() - 1))
-                                    } else {
-                                        table_state.select(Some(i - 1))
-                                    }
-                                }
-                                None => table_state.select(Some(app.modified_fields.len() - 1)),
-                            },
-                            _ => {}
-                        }
+                    if key.kind != KeyEventKind::Press {
+                        continue;
+                    }
+                    // The editor and the search get every key as typed
+                    if app.ui.editor.is_some() || app.ui.is_searching() {
+                        inputs.push(Input::Key(key));
                     } else {
-                        match key.code {
-                            KeyCode::Char(c) => match c {
-                                '?' => {
-                                    // Display a popup window with keybinds
-                                    // toggle the show_keybinds state
-                                    app.toggle_keybinds();
-                                    if app.show_keybinds {
-                                        app.show_message("Showing Keybinds window".to_owned());
-                                    } else {
-                                        app.show_message("Hid Keybinds window".to_owned());
-                                    }
-                                }
-                                _ => {}
-                            },
-                            KeyCode::Esc => {
-                                app.toggle_keybinds();
-                                if app.show_keybinds {
-                                    app.show_message("Showing Keybinds window".to_owned());
-                                } else {
-                                    app.show_message("Hid Keybinds window".to_owned());
-                                }
+                        inputs.extend(input_state.press(key, now));
+                    }
+                }
+                AppEvent::Redraw(resized) => app.ui.async_state.set_protocol(resized),
+                AppEvent::RedrawMap(resized) => {
+                    if let Some(map_state) = app.ui.map_state.as_mut() {
+                        map_state.set_protocol(resized)
+                    }
+                }
+                AppEvent::MapLoaded(Ok(map)) => {
+                    app.ui.set_map(map, tx_map_worker.clone());
+                    app.ui.show_message("Loaded map".to_owned());
+                }
+                AppEvent::MapLoaded(Err(e)) => {
+                    app.ui.show_message(format!("Unable to load map: {}", e));
+                }
+            }
+        }
+
+        for input in inputs {
+            if app.ui.show_keybinds {
+                if let Input::Key(KeyEvent {
+                    code: KeyCode::Char('?') | KeyCode::Esc,
+                    ..
+                }) = input
+                {
+                    app.update(Message::ToggleKeybinds);
+                }
+                continue;
+            }
+            if app.ui.elevation.is_some() {
+                if let Input::Key(KeyEvent {
+                    code: KeyCode::Char('a' | 'q') | KeyCode::Esc,
+                    ..
+                }) = input
+                {
+                    app.update(Message::ToggleElevation);
+                }
+                continue;
+            }
+            let rows = app.row_count();
+            let field = app.field_at(table_state.selected());
+            let message = match input {
+                Input::Chord(Chord::FirstRow) => {
+                    table_state.select(Some(0));
+                    None
+                }
+                Input::Chord(Chord::LastRow) => {
+                    table_state.select(Some(rows.saturating_sub(1)));
+                    None
+                }
+                Input::Key(key) => match key.code {
+                    // The editor popup takes the keys it uses and ignores the others
+                    KeyCode::Esc if app.ui.editor.is_some() => Some(Message::CloseEditor),
+                    KeyCode::Enter if app.ui.editor.is_some() => Some(Message::ConfirmEdit),
+                    KeyCode::Up | KeyCode::BackTab if app.ui.editor.is_some() => {
+                        Some(Message::PreviousChoice)
+                    }
+                    KeyCode::Down | KeyCode::Tab if app.ui.editor.is_some() => {
+                        Some(Message::NextChoice)
+                    }
+                    KeyCode::Char(c) if app.ui.editor.as_ref().is_some_and(|e| e.is_text()) => {
+                        Some(Message::Type(c))
+                    }
+                    KeyCode::Backspace if app.ui.editor.is_some() => Some(Message::DeleteChar),
+                    _ if app.ui.editor.is_some() => None,
+                    // The search takes the keys to type and the arrows still move
+                    // through the rows it finds
+                    KeyCode::Esc if app.ui.is_searching() => Some(Message::ClearSearch {
+                        row: table_state.selected(),
+                    }),
+                    KeyCode::Enter if app.ui.is_searching() => Some(Message::ConfirmSearch),
+                    KeyCode::Char(c) if app.ui.is_searching() => Some(Message::Type(c)),
+                    KeyCode::Backspace if app.ui.is_searching() => Some(Message::DeleteChar),
+                    _ if app.ui.is_searching()
+                        && !matches!(key.code, KeyCode::Up | KeyCode::Down) =>
+                    {
+                        None
+                    }
+                    // So does the question before a bulk operation
+                    KeyCode::Char('y' | 'Y') | KeyCode::Enter if app.ui.confirming.is_some() => {
+                        Some(Message::Answer(true))
+                    }
+                    KeyCode::Char('n' | 'N') | KeyCode::Esc if app.ui.confirming.is_some() => {
+                        Some(Message::Answer(false))
+                    }
+                    _ if app.ui.confirming.is_some() => None,
+                    KeyCode::Char(c) => match c {
+                        'u' => Some(Message::Undo),
+                        'U' => Some(Message::Restore),
+                        // Only randomize the selected element based on table state
+                        'r' => field.map(Message::Randomize),
+                        'R' => Some(Message::RandomizeAll),
+                        'c' => field.map(Message::Clear),
+                        'l' | 'L' => Some(Message::ClearGps),
+                        'h' => Some(Message::SetLocationHere),
+                        'p' => Some(Message::PickLocation),
+                        'C' => Some(Message::ClearAll),
+                        'P' => Some(Message::ApplyPolicy),
+                        'e' => field.map(Message::Edit),
+                        'E' => Some(Message::ApplyScene),
+                        'o' => Some(Message::Rotate),
+                        'O' => Some(Message::Mirror),
+                        'n' => Some(Message::AddNote),
+                        'N' => Some(Message::RemoveNote),
+                        'f' => Some(Message::CycleFlag),
+                        's' | 'S' if cli.in_place => Some(Message::SaveInPlace),
+                        's' | 'S' => Some(Message::Save),
+                        'w' => Some(Message::SaveInPlace),
+                        'g' | 'G' => Some(Message::ToggleGlobe),
+                        '*' => Some(Message::ToggleStarfield),
+                        't' | 'T' => Some(Message::ToggleRenderState),
+                        'i' => Some(Message::ToggleInternal),
+                        'D' => Some(Message::ToggleDebug),
+                        'b' => Some(Message::ToggleByteOrder),
+                        'm' => Some(Message::ExportMarkdown),
+                        '/' => Some(Message::Search),
+                        'v' => Some(Message::Compare),
+                        'a' => Some(Message::ToggleElevation),
+                        '[' if app.ui.compare.is_some() => Some(Message::MoveDivider(-10)),
+                        ']' if app.ui.compare.is_some() => Some(Message::MoveDivider(10)),
+                        '[' | ']' => Some(Message::SwitchImage {
+                            forward: c == ']',
+                            row: table_state.selected(),
+                        }),
+                        '?' => Some(Message::ToggleKeybinds),
+                        '+' => Some(Message::ZoomIn),
+                        '-' => Some(Message::ZoomOut),
+                        ',' => Some(Message::FasterRotation),
+                        '.' => Some(Message::SlowerRotation),
+                        ' ' => Some(Message::ToggleRotate),
+                        'q' => {
+                            if let Some(job) = app.save_job.take() {
+                                job.cancel_and_wait();
                             }
-                            _ => {}
+                            break 'main;
+                        }
+                        _ => None,
+                    },
+                    KeyCode::Esc if app.save_job.is_some() => Some(Message::CancelSave),
+                    KeyCode::Esc if app.ui.picking => Some(Message::PickLocation),
+                    KeyCode::Esc if app.ui.panning => Some(Message::StopPanning),
+                    KeyCode::Esc if app.ui.search.is_some() => Some(Message::ClearSearch {
+                        row: table_state.selected(),
+                    }),
+                    KeyCode::Enter if app.ui.picking => Some(Message::SetLocationHere),
+                    KeyCode::Enter => field.map(Message::Edit),
+                    KeyCode::Esc => {
+                        break 'main;
+                    }
+                    KeyCode::Up
+                        if app.ui.picking || key.modifiers.contains(KeyModifiers::SHIFT) =>
+                    {
+                        Some(Message::Pan(Cardinal::North))
+                    }
+                    KeyCode::Down
+                        if app.ui.picking || key.modifiers.contains(KeyModifiers::SHIFT) =>
+                    {
+                        Some(Message::Pan(Cardinal::South))
+                    }
+                    KeyCode::Left
+                        if app.ui.picking || key.modifiers.contains(KeyModifiers::SHIFT) =>
+                    {
+                        Some(Message::Pan(Cardinal::West))
+                    }
+                    KeyCode::Right
+                        if app.ui.picking || key.modifiers.contains(KeyModifiers::SHIFT) =>
+                    {
+                        Some(Message::Pan(Cardinal::East))
+                    }
+                    KeyCode::Down | KeyCode::Tab => {
+                        match table_state.selected() {
+                            Some(i) if i + 1 < rows => table_state.select(Some(i + 1)),
+                            _ => table_state.select(Some(0)),
+                        }
+                        None
+                    }
+                    KeyCode::Up | KeyCode::BackTab => {
+                        match table_state.selected() {
+                            Some(i) if i > 0 => table_state.select(Some(i - 1)),
+                            _ => table_state.select(Some(rows.saturating_sub(1))),
                         }
+                        None
                     }
+                    _ => None,
+                },
+            };
+            if let Some(message) = message {
+                if let Some(row) = app.update(message) {
+                    table_state.select(Some(row));
+                }
+                // Fetch a map when switching to it, or when the location on it moved
+                let wants_map = matches!(
+                    message,
+                    Message::ToggleRenderState
+                        | Message::SetLocationHere
+                        | Message::ApplyScene
+                        | Message::SwitchImage { .. }
+                ) && matches!(app.ui.render_state, RenderState::Map);
+                if let Some((lat, long)) = app.map_request().filter(|_| wants_map) {
+                    app.ui.show_message("Fetching map...".to_owned());
+                    let tx_main_fetch = tx_main.clone();
+                    thread::spawn(move || {
+                        let map = osm::fetch_map(lat, long, osm::DEFAULT_ZOOM);
+                        tx_main_fetch.send(AppEvent::MapLoaded(map)).unwrap();
+                    });
                 }
-                AppEvent::Redraw(protocol) => app.async_state.set_protocol(protocol),
             }
         }
 
-        if app.should_rotate {
-            app.rotate_globe();
+        if app.ui.should_rotate {
+            app.ui.rotate_globe();
         }
     }
-    tui::restore_terminal()
+    tui::restore_terminal()?;
+    let session = app.finish_session();
+    print!("{}", session);
+    Ok(session.copies.last().map(|(copy, _)| copy.clone()))
 }
@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+
+use crate::jpeg;
+
+// Motion Photos
+//
+// Phones record a few seconds of video around every shot unless told not to.
+// Google and Samsung append it to the JPEG as an MP4 and say where it starts in
+// the XMP packet, either as GCamera:MicroVideoOffset, counted back from the end
+// of the file, or as the Item:Length of the MotionPhoto item in the container
+// directory. Apple keeps it in a .MOV next to the still with the same name,
+// which is how a Live Photo is paired up again after copying.
+//
+// Either way the video has metadata of its own, location included, that
+// scrubbing the still doesn't touch. An embedded video can be dropped from the
+// copy, a paired one has to be dealt with on its own.
+
+/// Extensions of the video half of a Live Photo
+const PAIRED_EXTENSIONS: [&str; 2] = ["mov", "MOV"];
+
+/// Where the video of a motion photo is
+#[derive(Debug, Clone, PartialEq)]
+pub enum Video {
+    /// Appended to the still, from `offset` to the end of the file
+    Embedded { offset: usize, len: usize },
+    /// A file of its own next to the still
+    Paired(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MotionPhoto {
+    /// "Google motion photo" or "Apple Live Photo"
+    pub kind: &'static str,
+    pub video: Video,
+}
+
+/// The video of the image at `path`, whose contents are `buf`, if it's a motion
+/// photo
+pub fn detect(path: &Path, buf: &[u8]) -> Option<MotionPhoto> {
+    if let Some(offset) = embedded_video(buf) {
+        return Some(MotionPhoto {
+            kind: "Google motion photo",
+            video: Video::Embedded {
+                offset,
+                len: buf.len() - offset,
+            },
+        });
+    }
+    PAIRED_EXTENSIONS
+        .iter()
+        .map(|ext| path.with_extension(ext))
+        .find(|video| video.is_file())
+        .map(|video| MotionPhoto {
+            kind: "Apple Live Photo",
+            video: Video::Paired(video),
+        })
+}
+
+/// Offset of the MP4 appended to a JPEG, going by the XMP packet or else by
+/// what follows the end of the image
+fn embedded_video(buf: &[u8]) -> Option<usize> {
+    let is_mp4 = |offset: usize| buf.get(offset + 4..offset + 8) == Some(b"ftyp");
+    let claimed = xmp(buf).and_then(|xmp| {
+        let from_end = attribute(&xmp, "GCamera:MicroVideoOffset")
+            .or_else(|| motion_photo_item(&xmp).and_then(|item| attribute(item, "Item:Length")))?;
+        buf.len().checked_sub(from_end.parse().ok()?)
+    });
+    claimed.filter(|&offset| is_mp4(offset)).or_else(|| {
+        jpeg::end_of_image(buf)
+            .ok()
+            .filter(|&offset| is_mp4(offset))
+    })
+}
+
+/// The first XMP packet of a JPEG
+fn xmp(buf: &[u8]) -> Option<String> {
+    let segments = jpeg::segments(buf).ok()?;
+    segments.iter().find_map(|s| {
+        let payload = s.payload(buf);
+        (s.marker == jpeg::APP1 && payload.starts_with(jpeg::XMP_HEADER))
+            .then(|| String::from_utf8_lossy(&payload[jpeg::XMP_HEADER.len()..]).into_owned())
+    })
+}
+
+/// The value of the attribute `name` in an XMP packet
+fn attribute<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    let start = xmp.find(&format!("{}=\"", name))? + name.len() + 2;
+    let len = xmp[start..].find('"')?;
+    Some(&xmp[start..start + len])
+}
+
+/// The container directory item holding the video, e.g.
+/// `<Container:Item Item:Semantic="MotionPhoto" Item:Length="1234"/>`
+fn motion_photo_item(xmp: &str) -> Option<&str> {
+    let at = xmp.find("Item:Semantic=\"MotionPhoto\"")?;
+    let start = xmp[..at].rfind('<')?;
+    let end = at + xmp[at..].find('>')?;
+    Some(&xmp[start..end])
+}
+
+/// Copy of a JPEG without the video a Google motion photo appends to it, marked
+/// as a plain still so viewers don't look for it. `None` if there's no video.
+pub fn strip_video(buf: &[u8]) -> Option<Vec<u8>> {
+    let offset = embedded_video(buf)?;
+    let mut out = buf[..offset].to_vec();
+    // Overwritten in place, so no segment changes its length
+    for flag in ["GCamera:MicroVideo=\"1\"", "GCamera:MotionPhoto=\"1\""] {
+        let flag = flag.as_bytes();
+        while let Some(at) = out.windows(flag.len()).position(|w| w == flag) {
+            out[at + flag.len() - 2] = b'0';
+        }
+    }
+    Some(out)
+}
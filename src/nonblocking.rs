@@ -0,0 +1,67 @@
+use anyhow::Result;
+use exif::Field;
+use std::{path::Path, sync::Arc};
+use tokio::task;
+
+use crate::{
+    backend::{self, MetadataBackend},
+    batch::{self, FileReport},
+    policy::{Policy, PolicyProcessor},
+    processor::TagProcessor,
+};
+
+// Nonblocking
+//
+// Reading, scrubbing and writing for a program that runs on tokio, like a web
+// service scrubbing uploads, built with the `async` feature. Files are read and
+// written with tokio's file IO, and the parsing and rewriting in between runs
+// on the blocking pool, so none of it holds up the threads serving requests.
+//
+// Everything goes through a metadata backend, the same as the TUI, so whatever
+// formats it reads can be scrubbed here too.
+
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> Result<T> + Send + 'static) -> Result<T> {
+    task::spawn_blocking(f).await?
+}
+
+/// Reads the metadata of `path` with the backend for its format
+pub async fn read(path: &Path) -> Result<Arc<dyn MetadataBackend>> {
+    let file = tokio::fs::read(path).await?;
+    blocking(move || backend::parse(&file)).await
+}
+
+/// Writes a copy of `source` to `output`, with `fields` in place of the ones
+/// `backend` read from it
+pub async fn write(
+    backend: Arc<dyn MetadataBackend>,
+    source: &Path,
+    output: &Path,
+    fields: Vec<Field>,
+) -> Result<()> {
+    let file = tokio::fs::read(source).await?;
+    let copy = blocking(move || batch::write_bytes(&*backend, &file, &fields, None)).await?;
+    tokio::fs::write(output, copy).await?;
+    Ok(())
+}
+
+/// Applies `policy` to `path` and writes the copy next to it, or into `out_dir`,
+/// like `bresson scrub --policy` does. No copy is written if nothing changed.
+pub async fn scrub(path: &Path, out_dir: Option<&Path>, policy: Policy) -> Result<FileReport> {
+    let file = tokio::fs::read(path).await?;
+    let output = batch::output_path(path, out_dir)?;
+    let path = path.to_path_buf();
+    let (report, copy) = blocking(move || {
+        let mut processors: Vec<Box<dyn TagProcessor>> =
+            vec![Box::new(PolicyProcessor::new(policy))];
+        batch::scrub_buffer(&mut processors, &path, &file)
+    })
+    .await?;
+    if !report.changed() {
+        return Ok(report);
+    }
+    tokio::fs::write(&output, copy).await?;
+    Ok(FileReport {
+        output: Some(output),
+        ..report
+    })
+}
@@ -2,7 +2,9 @@ use std::collections::BTreeSet;
 
 use exif::Tag;
 
-pub const EXIF_FIELDS_ORDERED: [Tag; 67] = [
+use crate::{utils, writer};
+
+pub const EXIF_FIELDS_ORDERED: [Tag; 76] = [
     Tag::Make,
     Tag::Model,
     Tag::DateTimeOriginal,
@@ -24,6 +26,13 @@ pub const EXIF_FIELDS_ORDERED: [Tag; 67] = [
     Tag::YResolution,
     Tag::ResolutionUnit,
     Tag::Software,
+    Tag::Artist,
+    Tag::Copyright,
+    utils::XP_TAGS[0],
+    utils::XP_TAGS[1],
+    utils::XP_TAGS[2],
+    utils::XP_TAGS[3],
+    utils::XP_TAGS[4],
     Tag::DateTime,
     Tag::YCbCrPositioning,
     Tag::ExposureProgram,
@@ -64,6 +73,8 @@ pub const EXIF_FIELDS_ORDERED: [Tag; 67] = [
     Tag::GPSDestBearing,
     Tag::GPSDateStamp,
     Tag::GPSHPositioningError,
+    Tag::GPSProcessingMethod,
+    Tag::GPSAreaInformation,
     Tag::Compression,
     Tag::XResolution,
     Tag::YResolution,
@@ -72,6 +83,13 @@ pub const EXIF_FIELDS_ORDERED: [Tag; 67] = [
     Tag::JPEGInterchangeFormatLength,
 ];
 
+/// Tags that only matter to whatever reads the file, like the offset of the
+/// thumbnail. They're listed last, in a section of the table that's collapsed
+/// unless asked for, and randomizing or clearing everything leaves them alone.
+pub fn is_internal(tag: Tag) -> bool {
+    writer::is_structural(tag) || writer::is_required(tag)
+}
+
 #[derive(Debug, Clone)]
 pub struct OrderedTags {
     pub tags: BTreeSet<Tag>,
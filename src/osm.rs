@@ -0,0 +1,170 @@
+use anyhow::{Context as _, Result};
+use image::{DynamicImage, GenericImage, GenericImageView, Rgba, RgbaImage};
+use std::{
+    f64::consts::PI,
+    io::Read,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use crate::dirs;
+
+// OpenStreetMap
+//
+// Builds a small static map around a location out of the standard OSM raster
+// tiles. Only used with `--online`, nothing is requested otherwise.
+//
+// Tiles are 256px squares addressed by zoom/x/y in the Web Mercator projection.
+// The 2x2 block of tiles closest to the location is stitched together and a
+// square centered on the location is cut out of it, which always fits.
+//
+// Fetched tiles are kept in the cache directory for a week, as the tile usage
+// policy asks, so opening the same images again doesn't request them again.
+//
+// https://wiki.openstreetmap.org/wiki/Slippy_map_tilenames
+// https://operations.osmfoundation.org/policies/tiles/
+
+pub const TILE_SERVER: &str = "https://tile.openstreetmap.org";
+pub const ATTRIBUTION: &str = "© OpenStreetMap contributors";
+pub const DEFAULT_ZOOM: u8 = 14;
+
+/// Width and height of tiles and of the maps built from them, in pixels
+pub const TILE_SIZE: u32 = 256;
+/// How long a cached tile is used before it's fetched again
+const TILE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const USER_AGENT: &str = concat!(
+    "bresson/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/itsrainingmani/bresson)"
+);
+
+/// Link to `lat` / `lon` on openstreetmap.org, with a marker on it
+pub fn url(lat: f64, lon: f64) -> String {
+    format!(
+        "https://www.openstreetmap.org/?mlat={lat:.5}&mlon={lon:.5}#map={}/{lat:.5}/{lon:.5}",
+        DEFAULT_ZOOM
+    )
+}
+
+/// Fractional tile coordinates of `lat` / `lon` at `zoom`
+pub fn tile_position(lat: f64, lon: f64, zoom: u8) -> (f64, f64) {
+    let n = f64::from(1u32 << zoom);
+    // Web Mercator can't show the poles
+    let lat = lat.clamp(-85.0511, 85.0511).to_radians();
+    let x = (lon + 180.) / 360. * n;
+    let y = (1. - lat.tan().asinh() / PI) / 2. * n;
+    (x, y)
+}
+
+/// Latitude and longitude in signed degrees at fractional tile coordinates
+/// `x` / `y`, the inverse of `tile_position`
+pub fn location_at(x: f64, y: f64, zoom: u8) -> (f64, f64) {
+    let n = f64::from(1u32 << zoom);
+    let lon = (x / n * 360.).rem_euclid(360.) - 180.;
+    let lat = (PI * (1. - 2. * y / n)).sinh().atan().to_degrees();
+    (lat, lon)
+}
+
+/// Where the tile at `zoom` / `x` / `y` is cached
+fn tile_cache_path(zoom: u8, x: u32, y: u32) -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| {
+        dir.join("tiles")
+            .join(zoom.to_string())
+            .join(x.to_string())
+            .join(format!("{}.png", y))
+    })
+}
+
+/// The cached tile, unless it's missing, too old or broken
+fn cached_tile(path: &PathBuf) -> Option<DynamicImage> {
+    let age = SystemTime::now()
+        .duration_since(std::fs::metadata(path).ok()?.modified().ok()?)
+        .unwrap_or_default();
+    if age > TILE_MAX_AGE {
+        return None;
+    }
+    image::open(path).ok()
+}
+
+fn fetch_tile(zoom: u8, x: u32, y: u32) -> Result<DynamicImage> {
+    let cache = tile_cache_path(zoom, x, y);
+    if let Some(tile) = cache.as_ref().and_then(cached_tile) {
+        return Ok(tile);
+    }
+    let url = format!("{}/{}/{}/{}.png", TILE_SERVER, zoom, x, y);
+    let mut data = Vec::new();
+    ureq::get(&url)
+        .set("User-Agent", USER_AGENT)
+        .call()
+        .with_context(|| format!("Unable to fetch {}", url))?
+        .into_reader()
+        .read_to_end(&mut data)?;
+    let tile = image::load_from_memory(&data)?;
+    // A tile that can't be cached is fetched again next time, nothing more
+    if let Some(path) = cache {
+        if path
+            .parent()
+            .is_some_and(|dir| std::fs::create_dir_all(dir).is_ok())
+        {
+            let _ = std::fs::write(path, &data);
+        }
+    }
+    Ok(tile)
+}
+
+/// A `TILE_SIZE` square map centered on `lat` / `lon`, with the location marked.
+/// The map is stitched from 2x2 tiles, so zoom 0, a single tile of the whole
+/// world, is taken as 1.
+pub fn fetch_map(lat: f64, lon: f64, zoom: u8) -> Result<DynamicImage> {
+    let zoom = zoom.max(1);
+    let n = 1u32 << zoom;
+    let (x, y) = tile_position(lat, lon, zoom);
+
+    // Top left tile of the 2x2 block that has the location closest to its center
+    let left = (x - 0.5).floor() as i64;
+    let top = ((y - 0.5).floor() as i64).clamp(0, i64::from(n) - 2);
+
+    let mut stitched = RgbaImage::new(TILE_SIZE * 2, TILE_SIZE * 2);
+    for dy in 0..2 {
+        for dx in 0..2 {
+            // Tiles wrap around the antimeridian
+            let tile_x = (left + i64::from(dx)).rem_euclid(i64::from(n)) as u32;
+            let tile_y = (top + i64::from(dy)) as u32;
+            let tile = fetch_tile(zoom, tile_x, tile_y)?;
+            stitched.copy_from(&tile.to_rgba8(), dx * TILE_SIZE, dy * TILE_SIZE)?;
+        }
+    }
+
+    // Location in pixels inside of the stitched block
+    let px = ((x - left as f64) * f64::from(TILE_SIZE)) as u32;
+    let py = ((y - top as f64) * f64::from(TILE_SIZE)) as u32;
+    let half = TILE_SIZE / 2;
+    let crop_x = px.saturating_sub(half).min(TILE_SIZE);
+    let crop_y = py.saturating_sub(half).min(TILE_SIZE);
+    let mut map = stitched
+        .view(crop_x, crop_y, TILE_SIZE, TILE_SIZE)
+        .to_image();
+
+    draw_marker(&mut map, px - crop_x, py - crop_y);
+    Ok(DynamicImage::ImageRgba8(map))
+}
+
+/// Red ring with a dot in the middle
+fn draw_marker(map: &mut RgbaImage, cx: u32, cy: u32) {
+    let red = Rgba([220, 30, 30, 255]);
+    let (cx, cy) = (i64::from(cx), i64::from(cy));
+    for y in cy - 9..=cy + 9 {
+        for x in cx - 9..=cx + 9 {
+            let d2 = (x - cx).pow(2) + (y - cy).pow(2);
+            let on_marker = d2 <= 9 || (49..=81).contains(&d2);
+            if on_marker
+                && x >= 0
+                && y >= 0
+                && (x as u32) < map.width()
+                && (y as u32) < map.height()
+            {
+                map.put_pixel(x as u32, y as u32, red);
+            }
+        }
+    }
+}
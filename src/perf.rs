@@ -0,0 +1,69 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// Perf
+//
+// Counters behind the debug overlay `D` shows - how long frames take to draw
+// and how many events come in a second - so a feature that slows the interface
+// down is noticed while using it. Only the last second is kept, the numbers
+// follow what's happening now rather than averaging over the whole session.
+//
+// `cargo bench` measures the same paths, and saving, without a terminal.
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+pub struct PerfCounters {
+    /// When each frame of the last second was drawn and how long it took
+    frames: VecDeque<(Instant, Duration)>,
+    events: VecDeque<Instant>,
+}
+
+impl PerfCounters {
+    /// Notes a frame drawn at `now` in `took`
+    pub fn frame(&mut self, now: Instant, took: Duration) {
+        self.frames.push_back((now, took));
+        self.forget(now);
+    }
+
+    /// Notes an event, a key press or an image coming back from a worker
+    pub fn event(&mut self, now: Instant) {
+        self.events.push_back(now);
+        self.forget(now);
+    }
+
+    fn forget(&mut self, now: Instant) {
+        let recent = |at: &Instant| now.duration_since(*at) <= WINDOW;
+        while self.frames.front().is_some_and(|(at, _)| !recent(at)) {
+            self.frames.pop_front();
+        }
+        while self.events.front().is_some_and(|at| !recent(at)) {
+            self.events.pop_front();
+        }
+    }
+
+    /// Average time to draw a frame over the last second
+    pub fn frame_time(&self) -> Duration {
+        let total: Duration = self.frames.iter().map(|(_, took)| *took).sum();
+        total / self.frames.len().max(1) as u32
+    }
+
+    /// Slowest frame of the last second
+    pub fn slowest_frame(&self) -> Duration {
+        self.frames
+            .iter()
+            .map(|(_, took)| *took)
+            .max()
+            .unwrap_or_default()
+    }
+
+    pub fn frames_per_sec(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn events_per_sec(&self) -> usize {
+        self.events.len()
+    }
+}
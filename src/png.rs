@@ -0,0 +1,147 @@
+use anyhow::{bail, Result};
+
+// PNG container helpers
+//
+// A PNG file is an 8 byte signature followed by chunks, each a 4 byte big
+// endian length, a 4 byte type, the data and a CRC over the type and data.
+// Exif lives in an eXIf chunk holding the TIFF data as it is. Text is kept in
+// tEXt, zTXt and iTXt chunks as a keyword and a value - screenshot tools put
+// the software, the window title and the capture time there, and XMP packets
+// are iTXt chunks with the keyword "XML:com.adobe.xmp". tIME holds the time the
+// image was last changed.
+
+pub const SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+
+const EXIF: &[u8; 4] = b"eXIf";
+const HEADER: &[u8; 4] = b"IHDR";
+/// Chunks holding text, and the time of the last change
+const TEXT: [&[u8; 4]; 4] = [b"tEXt", b"zTXt", b"iTXt", b"tIME"];
+
+/// A chunk in a PNG file. `offset` and `len` cover the whole chunk, from the
+/// length to the CRC.
+#[derive(Debug, Clone, Copy)]
+pub struct Chunk {
+    pub kind: [u8; 4],
+    pub offset: usize,
+    pub len: usize,
+}
+
+impl Chunk {
+    pub fn data<'a>(&self, buf: &'a [u8]) -> &'a [u8] {
+        &buf[self.offset + 8..self.offset + self.len - 4]
+    }
+
+    /// What a text chunk is about, e.g. "Software", or the type of the chunk
+    /// for any other
+    pub fn keyword(&self, buf: &[u8]) -> String {
+        match &self.kind {
+            b"tEXt" | b"zTXt" | b"iTXt" => {
+                let data = self.data(buf);
+                let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                String::from_utf8_lossy(&data[..end]).into_owned()
+            }
+            kind => String::from_utf8_lossy(kind).into_owned(),
+        }
+    }
+}
+
+pub fn is_png(buf: &[u8]) -> bool {
+    buf.starts_with(SIGNATURE)
+}
+
+/// Lists the chunks up to and including IEND
+pub fn chunks(buf: &[u8]) -> Result<Vec<Chunk>> {
+    if !is_png(buf) {
+        bail!("Not a PNG image");
+    }
+    let mut chunks = Vec::new();
+    let mut pos = SIGNATURE.len();
+    loop {
+        let Some(header) = buf.get(pos..pos + 8) else {
+            bail!("PNG ends without an IEND chunk");
+        };
+        let data_len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let len = data_len + 12;
+        if pos + len > buf.len() {
+            bail!("PNG chunk at offset {} runs past the end of the file", pos);
+        }
+        let chunk = Chunk {
+            kind: [header[4], header[5], header[6], header[7]],
+            offset: pos,
+            len,
+        };
+        chunks.push(chunk);
+        pos += len;
+        if &chunk.kind == b"IEND" {
+            return Ok(chunks);
+        }
+    }
+}
+
+/// Builds a complete chunk of type `kind` holding `data`
+pub fn chunk(kind: &[u8; 4], data: &[u8]) -> Result<Vec<u8>> {
+    let Ok(len) = u32::try_from(data.len()) else {
+        bail!("{} bytes don't fit into a PNG chunk", data.len());
+    };
+    let mut out = Vec::with_capacity(data.len() + 12);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+    Ok(out)
+}
+
+/// Copies `buf` leaving out the chunks `drop` picks
+fn without(buf: &[u8], chunks: &[Chunk], drop: impl Fn(&Chunk) -> bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(buf.len());
+    let mut pos = 0;
+    for chunk in chunks.iter().filter(|c| drop(c)) {
+        out.extend_from_slice(&buf[pos..chunk.offset]);
+        pos = chunk.offset + chunk.len;
+    }
+    out.extend_from_slice(&buf[pos..]);
+    out
+}
+
+/// Replaces the eXIf chunk with `tiff`, inserting one after the header if the
+/// file has none
+pub fn replace_exif(buf: &[u8], tiff: &[u8]) -> Result<Vec<u8>> {
+    let chunks = chunks(buf)?;
+    let new = chunk(EXIF, tiff)?;
+    let at = match chunks.iter().find(|c| &c.kind == EXIF) {
+        Some(exif) => exif.offset,
+        None => match chunks.first() {
+            Some(header) if &header.kind == HEADER => header.offset + header.len,
+            _ => bail!("PNG doesn't start with an IHDR chunk"),
+        },
+    };
+    let rest = without(buf, &chunks, |c| &c.kind == EXIF);
+    // Dropping the eXIf chunks only moves what comes after the first of them
+    let mut out = Vec::with_capacity(rest.len() + new.len());
+    out.extend_from_slice(&rest[..at]);
+    out.extend_from_slice(&new);
+    out.extend_from_slice(&rest[at..]);
+    Ok(out)
+}
+
+/// Drops every eXIf chunk
+pub fn remove_exif(buf: &[u8]) -> Result<Vec<u8>> {
+    let chunks = chunks(buf)?;
+    Ok(without(buf, &chunks, |c| &c.kind == EXIF))
+}
+
+/// Drops the text chunks, XMP included, and the time of the last change.
+/// Returns the copy and the keywords of what was dropped.
+pub fn remove_text(buf: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+    let chunks = chunks(buf)?;
+    let is_text = |c: &Chunk| TEXT.contains(&&c.kind);
+    let removed = chunks
+        .iter()
+        .filter(|c| is_text(c))
+        .map(|c| c.keyword(buf))
+        .collect();
+    Ok((without(buf, &chunks, is_text), removed))
+}
@@ -0,0 +1,249 @@
+use anyhow::{bail, Context as _, Result};
+use exif::{Field, Tag};
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::{
+    processor::{Randomize, TagProcessor, Verdict, COMPOSITE_TAGS, OWNER_TAGS},
+    state::MetadataVal,
+    utils,
+};
+
+// Policy
+//
+// A policy is a declarative set of scrubbing rules that an organization can
+// hand out as a TOML file, e.g.
+//
+//     name = "newsroom"
+//     clear = ["GPS*"]
+//     keep = ["DateTimeOriginal", "Orientation"]
+//     randomize = ["*SerialNumber"]
+//     forbid = ["Artist"]
+//     strip_text = true
+//
+// Every entry is an Exif tag name, optionally containing `*` wildcards. A tag
+// matching several lists follows the strongest rule: keep, forbid, clear and
+// finally randomize. `strip_text` drops the XMP packets, PNG text chunks and
+// JPEG comments of copies as well, where screenshot tools keep window titles.
+//
+// Batch mode runs the policy as the first tag processor. The TUI refuses
+// actions that would break it and won't save a copy that violates it.
+// `bresson check` audits files against it without changing anything.
+//
+// Built-in presets emulate what popular platforms do with the metadata of an
+// uploaded image. They are based on observed behavior, which the platforms
+// change without notice, so they are a lesson rather than a guarantee. The
+// screenshot preset is meant for a kind of image instead, it clears what
+// screenshot tools write about the machine and the moment.
+
+/// Names of the built-in presets and their policies
+const PRESETS: &[(&str, &str)] = &[
+    ("email", include_str!("../presets/email.toml")),
+    ("facebook", include_str!("../presets/facebook.toml")),
+    ("flickr", include_str!("../presets/flickr.toml")),
+    ("imessage", include_str!("../presets/imessage.toml")),
+    ("instagram", include_str!("../presets/instagram.toml")),
+    ("signal", include_str!("../presets/signal.toml")),
+    (
+        "telegram-file",
+        include_str!("../presets/telegram-file.toml"),
+    ),
+    ("twitter", include_str!("../presets/twitter.toml")),
+    ("whatsapp", include_str!("../presets/whatsapp.toml")),
+];
+
+/// Built-in presets for kinds of images rather than platforms, which `bresson
+/// simulate` leaves out
+const KIND_PRESETS: &[(&str, &str)] = &[("screenshot", include_str!("../presets/screenshot.toml"))];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// The field must be written unchanged
+    Keep,
+    /// The field must not be written with any value
+    Forbid,
+    /// The original value must not be written
+    Clear,
+    /// The original value must be replaced with a random one
+    Randomize,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub keep: Vec<String>,
+    #[serde(default)]
+    pub forbid: Vec<String>,
+    #[serde(default)]
+    pub clear: Vec<String>,
+    #[serde(default)]
+    pub randomize: Vec<String>,
+    /// Also drop XMP packets and other text kept outside the Exif data, like
+    /// the text chunks of a PNG
+    #[serde(default)]
+    pub strip_text: bool,
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read policy {:?}", path))?;
+        Self::from_toml(&data).with_context(|| format!("Invalid policy {:?}", path))
+    }
+
+    /// Loads the policy file at `path`, or the built-in preset of that name
+    /// when there is no such file
+    pub fn open(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            if let Some(preset) = path.to_str().and_then(Self::preset) {
+                return Ok(preset);
+            }
+        }
+        Self::load(path)
+    }
+
+    /// Names of the presets emulating platforms
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|(name, _)| *name)
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        PRESETS
+            .iter()
+            .chain(KIND_PRESETS)
+            .find(|(preset, _)| *preset == name)
+            .map(|(_, data)| Self::from_toml(data).expect("built-in presets are valid"))
+    }
+
+    pub fn from_toml(data: &str) -> Result<Self> {
+        let policy: Policy = toml::from_str(data)?;
+        let unknown: Vec<&str> = policy
+            .patterns()
+            .filter(|p| !p.contains('*') && utils::tag_from_name(p).is_none())
+            .collect();
+        if !unknown.is_empty() {
+            bail!("Unknown tag(s): {}", unknown.join(", "));
+        }
+        Ok(policy)
+    }
+
+    /// Used by `bresson check` when no policy is given: no coordinates, no owner
+    pub fn audit_default() -> Self {
+        Self {
+            name: Some(String::from("default")),
+            forbid: ["GPS*Latitude", "GPS*Longitude", "GPSAltitude"]
+                .into_iter()
+                .map(String::from)
+                .chain(OWNER_TAGS.iter().map(|t| utils::tag_name(*t)))
+                .collect(),
+            clear: COMPOSITE_TAGS.iter().map(|t| utils::tag_name(*t)).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn display_name(&self) -> &str {
+        self.name.as_deref().unwrap_or("policy")
+    }
+
+    fn patterns(&self) -> impl Iterator<Item = &str> {
+        self.keep
+            .iter()
+            .chain(&self.forbid)
+            .chain(&self.clear)
+            .chain(&self.randomize)
+            .map(String::as_str)
+    }
+
+    /// The rule that applies to `tag`, if any
+    pub fn rule_for(&self, tag: Tag) -> Option<Rule> {
+        let name = utils::tag_name(tag);
+        let matches = |patterns: &[String]| patterns.iter().any(|p| glob_match(p, &name));
+        if matches(&self.keep) {
+            Some(Rule::Keep)
+        } else if matches(&self.forbid) {
+            Some(Rule::Forbid)
+        } else if matches(&self.clear) {
+            Some(Rule::Clear)
+        } else if matches(&self.randomize) {
+            Some(Rule::Randomize)
+        } else {
+            None
+        }
+    }
+
+    /// Why an existing field breaks the policy, if it does. Fields that are
+    /// already cleared are fine, randomization can't be told apart from the real thing.
+    pub fn audit(&self, field: &Field) -> Option<&'static str> {
+        let cleared = || {
+            MetadataVal {
+                field: field.clone(),
+                changed: false,
+            }
+            .is_cleared()
+        };
+        match self.rule_for(field.tag) {
+            Some(Rule::Forbid) if !cleared() => Some("is forbidden"),
+            Some(Rule::Clear) if !cleared() => Some("must be cleared"),
+            _ => None,
+        }
+    }
+}
+
+/// Case insensitive match where `*` stands for any number of characters
+pub(crate) fn glob_match(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[u8], name: &[u8]) -> bool {
+        match (pattern.first(), name.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => {
+                matches(&pattern[1..], name) || (!name.is_empty() && matches(pattern, &name[1..]))
+            }
+            (Some(p), Some(n)) if p.eq_ignore_ascii_case(n) => matches(&pattern[1..], &name[1..]),
+            _ => false,
+        }
+    }
+    matches(pattern.as_bytes(), name.as_bytes())
+}
+
+/// Enforces a policy in batch mode. Forbidden and cleared tags are dropped,
+/// kept tags are protected from the processors that run after it. Tags that
+/// can't be randomized are dropped rather than leaked.
+pub struct PolicyProcessor {
+    policy: Policy,
+    randomize: Randomize,
+}
+
+impl PolicyProcessor {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            randomize: Randomize::default(),
+        }
+    }
+}
+
+impl TagProcessor for PolicyProcessor {
+    fn name(&self) -> &str {
+        self.policy.display_name()
+    }
+
+    fn description(&self) -> &str {
+        "Rules loaded from a policy file"
+    }
+
+    fn process(&mut self, path: &Path, field: &Field) -> Verdict {
+        match self.policy.rule_for(field.tag) {
+            Some(Rule::Keep) => Verdict::Protect,
+            Some(Rule::Forbid) | Some(Rule::Clear) => Verdict::Drop,
+            Some(Rule::Randomize) => match self.randomize.process(path, field) {
+                Verdict::Replace(value) => Verdict::Replace(value),
+                _ => Verdict::Drop,
+            },
+            None => Verdict::Keep,
+        }
+    }
+}
@@ -0,0 +1,317 @@
+use anyhow::{bail, Result};
+use chrono::NaiveDateTime;
+use exif::{Context, Field, Tag, Value};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    randomize::{self, RandomMetadata},
+    state::Cardinal,
+    utils,
+};
+
+// Tag Processors
+//
+// Extension point for batch processing. Every field of every image is handed to
+// the selected processors in order, and each one decides what happens to it.
+// Organizations can encode their own policies by implementing TagProcessor and
+// registering it in the Registry, without touching the batch engine itself.
+
+/// What a processor wants to happen to a field
+#[derive(Debug, Clone)]
+pub enum Verdict {
+    /// Leave the field as it is
+    Keep,
+    /// Write the field with a new value
+    Replace(Value),
+    /// Remove the field from the output
+    Drop,
+    /// Keep the field, but report it
+    Flag(String),
+    /// Keep the field as it is and skip the remaining processors
+    Protect,
+}
+
+pub trait TagProcessor {
+    /// Name used to select the processor on the command line
+    fn name(&self) -> &str;
+
+    /// One line summary shown by `bresson scrub --list-processors`
+    fn description(&self) -> &str;
+
+    fn process(&mut self, path: &Path, field: &Field) -> Verdict;
+}
+
+/// Owner and device identifying tags
+pub const OWNER_TAGS: [Tag; 8] = [
+    Tag::Artist,
+    Tag::Copyright,
+    Tag::CameraOwnerName,
+    Tag::BodySerialNumber,
+    Tag::LensSerialNumber,
+    Tag::ImageUniqueID,
+    Tag::ImageDescription,
+    // XPAuthor
+    utils::XP_TAGS[2],
+];
+
+/// Tags telling that the image was put together from several frames, which
+/// gives away what the device is capable of
+pub const COMPOSITE_TAGS: [Tag; 3] = [
+    Tag::CompositeImage,
+    Tag::SourceImageNumberOfCompositeImage,
+    Tag::SourceExposureTimesOfCompositeImage,
+];
+
+/// Drops everything except the Orientation, so images still display upright
+pub struct StripAll;
+
+impl TagProcessor for StripAll {
+    fn name(&self) -> &str {
+        "strip-all"
+    }
+
+    fn description(&self) -> &str {
+        "Remove all metadata except Orientation"
+    }
+
+    fn process(&mut self, _path: &Path, field: &Field) -> Verdict {
+        match field.tag {
+            Tag::Orientation => Verdict::Keep,
+            _ => Verdict::Drop,
+        }
+    }
+}
+
+pub struct StripGps;
+
+impl TagProcessor for StripGps {
+    fn name(&self) -> &str {
+        "strip-gps"
+    }
+
+    fn description(&self) -> &str {
+        "Remove all GPS tags"
+    }
+
+    fn process(&mut self, _path: &Path, field: &Field) -> Verdict {
+        match field.tag.context() {
+            Context::Gps => Verdict::Drop,
+            _ => Verdict::Keep,
+        }
+    }
+}
+
+pub struct StripOwner;
+
+impl TagProcessor for StripOwner {
+    fn name(&self) -> &str {
+        "strip-owner"
+    }
+
+    fn description(&self) -> &str {
+        "Remove owner names, copyright and serial numbers"
+    }
+
+    fn process(&mut self, _path: &Path, field: &Field) -> Verdict {
+        if OWNER_TAGS.contains(&field.tag) {
+            Verdict::Drop
+        } else {
+            Verdict::Keep
+        }
+    }
+}
+
+/// Reports identifying tags without changing anything
+pub struct FlagSensitive;
+
+impl TagProcessor for FlagSensitive {
+    fn name(&self) -> &str {
+        "flag-sensitive"
+    }
+
+    fn description(&self) -> &str {
+        "Report GPS, owner, serial number and composite image tags without changing them"
+    }
+
+    fn process(&mut self, _path: &Path, field: &Field) -> Verdict {
+        if field.tag.context() == Context::Gps {
+            Verdict::Flag(String::from("location"))
+        } else if OWNER_TAGS.contains(&field.tag) {
+            Verdict::Flag(String::from("identity"))
+        } else if COMPOSITE_TAGS.contains(&field.tag) {
+            Verdict::Flag(String::from("device capabilities"))
+        } else {
+            Verdict::Keep
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Randomize {
+    randomizer: RandomMetadata,
+    sample: Option<Sample>,
+}
+
+/// Random values shared by the fields of one image, so that its DateTime tags
+/// agree with each other, its GPS fields describe one place and time and its
+/// make, model, ISO speed, resolution and dimensions are those of a single camera
+struct Sample {
+    path: PathBuf,
+    datetime: String,
+    /// Digits of the fraction of a second, shared by the SubSecTime tags
+    subsec_digits: String,
+    latitude: (Value, String),
+    longitude: (Value, String),
+    /// Fields are processed one at a time, so whether the image is upright
+    /// isn't known and the dimensions are always landscape
+    together: Vec<(Tag, Value)>,
+}
+
+impl Randomize {
+    pub fn new(randomizer: RandomMetadata) -> Self {
+        Self {
+            randomizer,
+            sample: None,
+        }
+    }
+
+    fn sample(&mut self, path: &Path) -> &Sample {
+        if self.sample.as_ref().is_none_or(|s| s.path != path) {
+            let camera = self.randomizer.random_camera();
+            let iso = self.randomizer.random_iso(Some(&camera[1].1));
+            self.sample = Some(Sample {
+                path: path.to_path_buf(),
+                datetime: self.randomizer.randomize_datetime(),
+                subsec_digits: self.randomizer.random_subsec_digits(),
+                latitude: self.randomizer.random_latlong(Cardinal::North),
+                longitude: self.randomizer.random_latlong(Cardinal::East),
+                together: camera
+                    .into_iter()
+                    .chain([(Tag::PhotographicSensitivity, iso)])
+                    .chain(self.randomizer.random_resolution())
+                    .chain(self.randomizer.random_dimensions(false))
+                    .collect(),
+            });
+        }
+        self.sample.as_ref().unwrap()
+    }
+}
+
+impl TagProcessor for Randomize {
+    fn name(&self) -> &str {
+        "randomize"
+    }
+
+    fn description(&self) -> &str {
+        "Replace randomizable tags with plausible random values"
+    }
+
+    fn process(&mut self, path: &Path, field: &Field) -> Verdict {
+        let ascii = |s: &str| Value::Ascii(vec![s.as_bytes().to_vec()]);
+        match field.tag {
+            Tag::DateTime | Tag::DateTimeOriginal | Tag::DateTimeDigitized => {
+                Verdict::Replace(ascii(&self.sample(path).datetime))
+            }
+            Tag::SubSecTime | Tag::SubSecTimeOriginal | Tag::SubSecTimeDigitized => {
+                let digits = &self.sample(path).subsec_digits;
+                Verdict::Replace(randomize::subsec(digits, Some(&field.value)))
+            }
+            Tag::GPSLatitude => Verdict::Replace(self.sample(path).latitude.0.clone()),
+            Tag::GPSLatitudeRef => Verdict::Replace(ascii(&self.sample(path).latitude.1)),
+            Tag::GPSLongitude => Verdict::Replace(self.sample(path).longitude.0.clone()),
+            Tag::GPSLongitudeRef => Verdict::Replace(ascii(&self.sample(path).longitude.1)),
+            // The random capture time stands in for UTC, it's random either way
+            Tag::GPSDateStamp | Tag::GPSTimeStamp => {
+                let datetime = &self.sample(path).datetime;
+                match NaiveDateTime::parse_from_str(datetime, "%Y:%m:%d %H:%M:%S") {
+                    Ok(utc) => randomize::gps_time_stamps(utc)
+                        .into_iter()
+                        .find(|(tag, _)| *tag == field.tag)
+                        .map_or(Verdict::Drop, |(_, value)| Verdict::Replace(value)),
+                    Err(_) => Verdict::Drop,
+                }
+            }
+            Tag::Make
+            | Tag::Model
+            | Tag::PhotographicSensitivity
+            | Tag::XResolution
+            | Tag::YResolution
+            | Tag::ResolutionUnit
+            | Tag::PixelXDimension
+            | Tag::PixelYDimension => {
+                match self
+                    .sample(path)
+                    .together
+                    .iter()
+                    .find(|(t, _)| *t == field.tag)
+                {
+                    Some((_, value)) => Verdict::Replace(value.clone()),
+                    None => Verdict::Keep,
+                }
+            }
+            _ => match self.randomizer.randomize_tag(field.tag) {
+                Some(v) => Verdict::Replace(v),
+                // GPS fields left as they were would give the location away
+                None if field.tag.context() == Context::Gps => Verdict::Drop,
+                None => Verdict::Keep,
+            },
+        }
+    }
+}
+
+/// Compiled-in set of processors that can be selected by name
+pub struct Registry {
+    processors: Vec<Box<dyn TagProcessor>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self::new(RandomMetadata::default())
+    }
+}
+
+impl Registry {
+    /// The built-in processors, `randomize` drawing its values from `randomizer`
+    pub fn new(randomizer: RandomMetadata) -> Self {
+        let mut registry = Self {
+            processors: Vec::new(),
+        };
+        registry.register(Box::new(StripAll));
+        registry.register(Box::new(StripGps));
+        registry.register(Box::new(StripOwner));
+        registry.register(Box::new(FlagSensitive));
+        registry.register(Box::new(Randomize::new(randomizer)));
+        registry
+    }
+
+    pub fn register(&mut self, processor: Box<dyn TagProcessor>) {
+        self.processors.push(processor);
+    }
+
+    pub fn describe(&self) -> Vec<(&str, &str)> {
+        self.processors
+            .iter()
+            .map(|p| (p.name(), p.description()))
+            .collect()
+    }
+
+    /// Takes the named processors out of the registry, in the given order
+    pub fn select(mut self, names: &[String]) -> Result<Vec<Box<dyn TagProcessor>>> {
+        let mut selected = Vec::with_capacity(names.len());
+        for name in names {
+            match self.processors.iter().position(|p| p.name() == name) {
+                Some(i) => selected.push(self.processors.remove(i)),
+                None => bail!(
+                    "Unknown processor {:?}. Available: {}",
+                    name,
+                    self.describe()
+                        .iter()
+                        .map(|(n, _)| *n)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            }
+        }
+        Ok(selected)
+    }
+}
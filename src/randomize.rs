@@ -1,73 +1,333 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, ops::RangeInclusive};
 
-use chrono::{Datelike, Timelike, Utc};
-use exif::{Tag, Value};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+use exif::{Rational, Tag, Value};
 use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
 
-use crate::state::Cardinal;
-
-const MANUFACTURERS: [&str; 48] = [
-    "Acer",
-    "Apple",
-    "BenQ",
-    "BlackBerry",
-    "Canon",
-    "Casio",
-    "Concord",
-    "DJI",
-    "DoCoMo",
-    "Epson",
-    "Fujifilm",
-    "GoPro",
-    "Google",
-    "HP",
-    "HTC",
-    "Hasselblad",
-    "Helio",
-    "Huawei",
-    "JVC",
-    "KDDI",
-    "Kodak",
-    "Konica Minolta",
-    "Kyocera",
-    "LG",
-    "Leaf",
-    "Leica",
-    "Mamiya",
-    "Motorola",
-    "Nikon",
-    "Nintendo",
-    "Nokia",
-    "Olympus",
-    "OnePlus",
-    "Palm",
-    "Panasonic",
-    "Pentax",
-    "Phase One",
-    "Polaroid",
-    "Ricoh",
-    "Samsung",
-    "Sanyo",
-    "Sharp",
-    "Sigma",
-    "Sony",
-    "Sony Ericsson",
-    "Toshiba",
-    "Vivitar",
-    "Xiaomi",
+use crate::{state::Cardinal, utils};
+
+/// Model names with the highest ISO speed each of them goes up to
+type Models = &'static [(&'static str, u16)];
+
+/// Manufacturers with their rough share of the photos taken today, going by what
+/// photo sharing sites see, and a few of their models as they write them into
+/// Model along with the highest ISO speed they're likely to use. Picking them
+/// uniformly would make a "Phase One" as likely as an "Apple", which stands out
+/// in a large set of scrubbed images.
+const MANUFACTURERS: [(&str, u32, Models); 48] = [
+    ("Acer", 1, &[("Liquid Z630", 1600), ("Liquid Jade", 1600)]),
+    (
+        "Apple",
+        320,
+        &[
+            ("iPhone 12", 2500),
+            ("iPhone 13", 3200),
+            ("iPhone 14 Pro", 3200),
+            ("iPhone 15", 3200),
+            ("iPhone 15 Pro Max", 3200),
+            ("iPhone SE (3rd generation)", 2500),
+        ],
+    ),
+    ("BenQ", 1, &[("DC E1050", 1600)]),
+    ("BlackBerry", 1, &[("BBB100-1", 1600)]),
+    (
+        "Canon",
+        60,
+        &[
+            ("Canon EOS R5", 12800),
+            ("Canon EOS R6", 12800),
+            ("Canon EOS 5D Mark IV", 12800),
+            ("Canon EOS 90D", 12800),
+            ("Canon PowerShot G7 X Mark III", 12800),
+        ],
+    ),
+    ("Casio", 1, &[("EX-ZR1000", 3200)]),
+    ("Concord", 1, &[("Eye-Q Duo 2MP", 400)]),
+    (
+        "DJI",
+        8,
+        &[("FC3582", 6400), ("FC7303", 3200), ("L2D-20c", 6400)],
+    ),
+    ("DoCoMo", 1, &[("SH-01F", 1600)]),
+    ("Epson", 1, &[("R-D1", 1600)]),
+    (
+        "Fujifilm",
+        15,
+        &[
+            ("X-T4", 12800),
+            ("X-T5", 12800),
+            ("X100V", 12800),
+            ("X-S10", 12800),
+        ],
+    ),
+    (
+        "GoPro",
+        6,
+        &[
+            ("HERO9 Black", 6400),
+            ("HERO11 Black", 6400),
+            ("HERO12 Black", 6400),
+        ],
+    ),
+    (
+        "Google",
+        40,
+        &[
+            ("Pixel 6", 6400),
+            ("Pixel 7", 6400),
+            ("Pixel 7a", 6400),
+            ("Pixel 8 Pro", 6400),
+        ],
+    ),
+    ("HP", 1, &[("PhotoSmart R967", 800)]),
+    ("HTC", 3, &[("HTC U11", 3200), ("HTC One M8", 1600)]),
+    ("Hasselblad", 1, &[("X2D 100C", 12800), ("907X", 12800)]),
+    ("Helio", 1, &[("Ocean", 400)]),
+    (
+        "Huawei",
+        35,
+        &[("ELS-NX9", 6400), ("VOG-L29", 6400), ("ANA-NX9", 6400)],
+    ),
+    ("JVC", 1, &[("GC-XA2", 1600)]),
+    ("KDDI", 1, &[("W51CA", 400)]),
+    (
+        "Kodak",
+        2,
+        &[("PIXPRO AZ421", 3200), ("EasyShare Z981", 6400)],
+    ),
+    (
+        "Konica Minolta",
+        1,
+        &[("DiMAGE A2", 800), ("DYNAX 7D", 3200)],
+    ),
+    ("Kyocera", 1, &[("E6910", 1600)]),
+    ("LG", 8, &[("LG-H870", 3200), ("LM-G900N", 3200)]),
+    ("Leaf", 1, &[("Aptus 75", 800)]),
+    ("Leica", 2, &[("LEICA Q2", 12800), ("LEICA M11", 12800)]),
+    ("Mamiya", 1, &[("Mamiya ZD", 400)]),
+    (
+        "Motorola",
+        10,
+        &[("moto g(60)", 3200), ("motorola edge 30", 3200)],
+    ),
+    (
+        "Nikon",
+        30,
+        &[
+            ("NIKON Z 6_2", 12800),
+            ("NIKON Z 8", 12800),
+            ("NIKON D750", 12800),
+            ("NIKON D850", 12800),
+        ],
+    ),
+    ("Nintendo", 1, &[("Nintendo 3DS", 400)]),
+    ("Nokia", 4, &[("Nokia 8.3 5G", 3200), ("Lumia 1020", 4000)]),
+    (
+        "Olympus",
+        5,
+        &[
+            ("E-M10MarkIV", 6400),
+            ("E-M1MarkIII", 6400),
+            ("TG-6", 12800),
+        ],
+    ),
+    ("OnePlus", 10, &[("ONEPLUS A6003", 3200), ("LE2123", 6400)]),
+    ("Palm", 1, &[("Treo 650", 100)]),
+    (
+        "Panasonic",
+        8,
+        &[("DC-S5", 12800), ("DC-GH5", 12800), ("DMC-FZ1000", 12800)],
+    ),
+    (
+        "Pentax",
+        2,
+        &[("PENTAX K-3 Mark III", 12800), ("PENTAX K-1", 12800)],
+    ),
+    ("Phase One", 1, &[("IQ4 150MP", 12800)]),
+    ("Polaroid", 1, &[("iS2132", 1600)]),
+    (
+        "Ricoh",
+        2,
+        &[("RICOH GR III", 12800), ("RICOH THETA SC2", 3200)],
+    ),
+    (
+        "Samsung",
+        180,
+        &[
+            ("SM-G991B", 3200),
+            ("SM-S911B", 3200),
+            ("SM-S918B", 3200),
+            ("SM-A536B", 3200),
+        ],
+    ),
+    ("Sanyo", 1, &[("VPC-E1", 1600)]),
+    ("Sharp", 2, &[("SH-53C", 3200)]),
+    ("Sigma", 1, &[("fp", 12800), ("sd Quattro", 6400)]),
+    (
+        "Sony",
+        45,
+        &[
+            ("ILCE-7M3", 12800),
+            ("ILCE-7M4", 12800),
+            ("ILCE-6400", 12800),
+            ("XQ-CT54", 3200),
+        ],
+    ),
+    ("Sony Ericsson", 1, &[("K800i", 400)]),
+    ("Toshiba", 1, &[("TS921", 400)]),
+    ("Vivitar", 1, &[("ViviCam 8025", 1600)]),
+    (
+        "Xiaomi",
+        60,
+        &[("M2101K6G", 3200), ("2201123G", 6400), ("23049PCD8G", 3200)],
+    ),
 ];
 
 const F_NUMBERS: [f32; 13] = [
     1.0, 1.4, 2.0, 2.8, 4.0, 5.6, 8.0, 11.0, 16.0, 22.0, 32.0, 45.0, 64.0,
 ];
 
-const ISO_SPEEDS: [u32; 31] = [
-    12, 16, 20, 25, 32, 40, 50, 64, 80, 100, 125, 160, 200, 250, 320, 400, 500, 640, 800, 1000,
-    1250, 1600, 2000, 2500, 3200, 4000, 5000, 6400, 8000, 10_000, 12_500,
+/// Standard ISO speeds in third stops
+const ISO_SPEEDS: [u16; 22] = [
+    100, 125, 160, 200, 250, 320, 400, 500, 640, 800, 1000, 1250, 1600, 2000, 2500, 3200, 4000,
+    5000, 6400, 8000, 10_000, 12_800,
+];
+
+/// Resolutions in dots per inch written by cameras, most of them write 72 or 300
+const RESOLUTIONS: [u32; 3] = [72, 240, 300];
+
+/// Landscape image sizes of popular phones and cameras
+const DIMENSIONS: [(u32, u32); 9] = [
+    (4032, 3024),
+    (4000, 3000),
+    (4608, 3456),
+    (5184, 3456),
+    (5472, 3648),
+    (6000, 4000),
+    (6048, 4024),
+    (6240, 4160),
+    (8256, 5504),
 ];
 
+/// Flash values cameras write: no flash, off, auto without and with firing,
+/// forced on and auto with red-eye reduction
+const FLASH_VALUES: [u16; 6] = [0x00, 0x10, 0x18, 0x19, 0x09, 0x59];
+
+/// Exif versions in use, from the oldest cameras still around to current phones
+const EXIF_VERSIONS: [&[u8; 4]; 5] = [b"0220", b"0221", b"0230", b"0231", b"0232"];
+
+/// A kind of photo whose settings and location `random_scene` makes up together
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scene {
+    SunnyLandscape,
+    IndoorParty,
+    NightStreet,
+}
+
+/// Latitude and longitude ranges in signed degrees
+type Region = ((f64, f64), (f64, f64));
+
+/// Camera settings that go together for a scene, each picked from its list
+struct Settings {
+    /// Denominators of the exposure time in seconds
+    exposure: &'static [u32],
+    /// In tenths
+    f_number: &'static [u32],
+    /// Range of ISO speeds, within what the camera can do
+    iso: (u16, u16),
+    flash: &'static [u16],
+    metering: u16,
+    capture_type: u16,
+    program: &'static [u16],
+}
+
+impl Scene {
+    pub const ALL: [Scene; 3] = [
+        Scene::SunnyLandscape,
+        Scene::IndoorParty,
+        Scene::NightStreet,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scene::SunnyLandscape => "sunny landscape",
+            Scene::IndoorParty => "indoor party",
+            Scene::NightStreet => "night street",
+        }
+    }
+
+    fn settings(self) -> Settings {
+        match self {
+            // Fast and stopped down in bright light, matrix metering, no flash
+            Scene::SunnyLandscape => Settings {
+                exposure: &[250, 500, 1000, 2000],
+                f_number: &[80, 110, 160],
+                iso: (100, 200),
+                flash: &[0x10, 0x18],
+                metering: 5,
+                capture_type: 1,
+                program: &[3, 8],
+            },
+            // Slow enough for the ambient light, the flash does the rest
+            Scene::IndoorParty => Settings {
+                exposure: &[30, 60, 125],
+                f_number: &[18, 20, 28],
+                iso: (800, 3200),
+                flash: &[0x19, 0x59],
+                metering: 2,
+                capture_type: 0,
+                program: &[2],
+            },
+            // Wide open and handheld, street lights instead of a flash
+            Scene::NightStreet => Settings {
+                exposure: &[15, 30, 60],
+                f_number: &[14, 18, 20],
+                iso: (1600, 6400),
+                flash: &[0x10, 0x18],
+                metering: 5,
+                capture_type: 3,
+                program: &[1, 2],
+            },
+        }
+    }
+
+    /// Places where photos like that are taken a lot
+    fn regions(self) -> &'static [Region] {
+        match self {
+            // Swiss Alps, Yosemite, Patagonia, Scottish Highlands
+            Scene::SunnyLandscape => &[
+                ((45.9, 46.9), (7.0, 10.3)),
+                ((37.5, 38.1), (-119.9, -119.3)),
+                ((-51.4, -50.0), (-73.4, -72.1)),
+                ((56.6, 57.9), (-5.9, -3.6)),
+            ],
+            // Berlin, London, Sao Paulo, Brooklyn
+            Scene::IndoorParty => &[
+                ((52.46, 52.56), (13.31, 13.49)),
+                ((51.49, 51.55), (-0.19, -0.01)),
+                ((-23.6, -23.52), (-46.7, -46.61)),
+                ((40.65, 40.72), (-73.98, -73.93)),
+            ],
+            // Shinjuku, Mong Kok, Midtown Manhattan, central Paris
+            Scene::NightStreet => &[
+                ((35.68, 35.7), (139.69, 139.71)),
+                ((22.31, 22.33), (114.16, 114.18)),
+                ((40.75, 40.77), (-73.99, -73.97)),
+                ((48.85, 48.88), (2.33, 2.36)),
+            ],
+        }
+    }
+}
+
+/// Earliest day random capture times fall on unless told otherwise
+const EARLIEST_DATE: NaiveDate = NaiveDate::from_ymd_opt(2001, 1, 1).unwrap();
+
 pub struct RandomMetadata {
     pub tags_to_randomize: HashSet<Tag>,
+    /// Days random capture times fall on, both included
+    pub dates: RangeInclusive<NaiveDate>,
+    /// Pick manufacturers by their market share rather than uniformly
+    pub weighted: bool,
     thread_rng: ThreadRng,
 }
 
@@ -81,42 +341,101 @@ impl Default for RandomMetadata {
                 Tag::ExposureTime,
                 Tag::FNumber,
                 Tag::PhotographicSensitivity,
+                Tag::Flash,
                 Tag::MeteringMode,
                 Tag::ColorSpace,
                 Tag::GPSLatitude,
                 Tag::GPSLatitudeRef,
                 Tag::GPSLongitude,
                 Tag::GPSLongitudeRef,
+                Tag::GPSAltitude,
+                Tag::GPSAltitudeRef,
+                Tag::GPSTimeStamp,
+                Tag::GPSDateStamp,
+                Tag::GPSSpeed,
+                Tag::GPSSpeedRef,
+                Tag::GPSImgDirection,
+                Tag::GPSImgDirectionRef,
+                Tag::GPSDestBearing,
+                Tag::GPSDestBearingRef,
+                Tag::GPSHPositioningError,
+                Tag::XResolution,
+                Tag::YResolution,
+                Tag::ResolutionUnit,
+                Tag::PixelXDimension,
+                Tag::PixelYDimension,
+                Tag::ExifVersion,
+                Tag::FlashpixVersion,
+                Tag::ComponentsConfiguration,
                 Tag::DateTime,
                 Tag::DateTimeDigitized,
+                Tag::SubSecTime,
+                Tag::SubSecTimeOriginal,
+                Tag::SubSecTimeDigitized,
+                Tag::BodySerialNumber,
+                Tag::LensSerialNumber,
+                utils::XP_TAGS[0],
+                utils::XP_TAGS[1],
+                utils::XP_TAGS[2],
+                utils::XP_TAGS[3],
+                utils::XP_TAGS[4],
             ]),
+            dates: EARLIEST_DATE..=Utc::now().date_naive(),
+            weighted: true,
             thread_rng: rand::thread_rng(),
         }
     }
 }
 
 impl RandomMetadata {
+    /// A capture time within `dates` that isn't in the future, as written into
+    /// the DateTime tags
     pub fn randomize_datetime(&mut self) -> String {
-        let now_utc = Utc::now();
-        let date_utc = now_utc.date_naive();
-        format!(
-            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
-            self.thread_rng.gen_range(2001..=date_utc.year_ce().1),
-            self.thread_rng.gen_range(1..=(date_utc.month0() + 1)),
-            self.thread_rng.gen_range(1..=(date_utc.day0() + 1)),
-            self.thread_rng.gen_range(0..=now_utc.hour()),
-            self.thread_rng.gen_range(0..=now_utc.minute()),
-            self.thread_rng.gen_range(0..=now_utc.second())
-        )
+        let start = self.dates.start().and_time(NaiveTime::MIN);
+        let end = self
+            .dates
+            .end()
+            .and_hms_opt(23, 59, 59)
+            .unwrap()
+            .min(Utc::now().naive_utc())
+            .max(start);
+        let seconds = self.thread_rng.gen_range(0..=(end - start).num_seconds());
+        (start + Duration::seconds(seconds))
+            .format("%Y:%m:%d %H:%M:%S")
+            .to_string()
+    }
+
+    /// Nine random digits of a fraction of a second, for the SubSecTime tags
+    /// of one capture time to share
+    pub fn random_subsec_digits(&mut self) -> String {
+        (0..9)
+            .map(|_| char::from(self.thread_rng.gen_range(b'0'..=b'9')))
+            .collect()
+    }
+
+    /// A capitalized word of made up letters
+    pub fn random_word(&mut self) -> String {
+        let len = self.thread_rng.gen_range(4..=10);
+        (0..len)
+            .map(|i| {
+                let letter = char::from(self.thread_rng.gen_range(b'a'..=b'z'));
+                match i {
+                    0 => letter.to_ascii_uppercase(),
+                    _ => letter,
+                }
+            })
+            .collect()
     }
 
     pub fn randomize_tag(&mut self, tag_to_modify: Tag) -> Option<Value> {
         // let mut random_data: ExifTags = Vec::new();
         if self.tags_to_randomize.contains(&tag_to_modify) {
             match tag_to_modify {
-                Tag::Make => Some(Value::Ascii(vec![Vec::from(
-                    *MANUFACTURERS.choose(&mut self.thread_rng).unwrap(),
-                )])),
+                Tag::Make | Tag::Model => self
+                    .random_camera()
+                    .into_iter()
+                    .find(|(tag, _)| *tag == tag_to_modify)
+                    .map(|(_, value)| value),
                 Tag::ExposureTime => Some(Value::Rational(vec![exif::Rational {
                     num: 1,
                     denom: rand::random::<u8>() as u32,
@@ -124,10 +443,45 @@ impl RandomMetadata {
                 Tag::FNumber => Some(Value::Float(vec![*F_NUMBERS
                     .choose(&mut self.thread_rng)
                     .unwrap()])),
-                Tag::PhotographicSensitivity => Some(Value::Long(vec![*ISO_SPEEDS
-                    .choose(&mut self.thread_rng)
-                    .unwrap()])),
+                Tag::PhotographicSensitivity => Some(self.random_iso(None)),
+                Tag::Flash => Some(self.random_flash(false)),
+                Tag::SubSecTime | Tag::SubSecTimeOriginal | Tag::SubSecTimeDigitized => {
+                    let digits = self.random_subsec_digits();
+                    Some(subsec(&digits, None))
+                }
                 Tag::MeteringMode => Some(Value::Short(vec![self.thread_rng.gen_range(1..=6)])),
+                Tag::BodySerialNumber | Tag::LensSerialNumber => Some(Value::Ascii(vec![(0..10)
+                    .map(|_| self.thread_rng.gen_range(b'0'..=b'9'))
+                    .collect()])),
+                Tag::ExifVersion => Some(Value::Undefined(
+                    EXIF_VERSIONS.choose(&mut self.thread_rng).unwrap().to_vec(),
+                    0,
+                )),
+                // Every camera writes 1.0, anything else stands out
+                Tag::FlashpixVersion => Some(Value::Undefined(b"0100".to_vec(), 0)),
+                // Y, Cb, Cr, as in every JPEG straight out of a camera
+                Tag::ComponentsConfiguration => Some(Value::Undefined(vec![1, 2, 3, 0], 0)),
+                // Up to 3000m above sea level, in centimeters
+                Tag::GPSAltitude => Some(rational(self.thread_rng.gen_range(0..=300_000), 100)),
+                Tag::GPSAltitudeRef => Some(Value::Byte(vec![0])),
+                // Up to 120 km/h
+                Tag::GPSSpeed => Some(rational(self.thread_rng.gen_range(0..=1200), 10)),
+                Tag::GPSSpeedRef => Some(Value::Ascii(vec![b"K".to_vec()])),
+                // Degrees from true north
+                Tag::GPSImgDirection | Tag::GPSDestBearing => {
+                    Some(rational(self.thread_rng.gen_range(0..36_000), 100))
+                }
+                Tag::GPSImgDirectionRef | Tag::GPSDestBearingRef => {
+                    Some(Value::Ascii(vec![b"T".to_vec()]))
+                }
+                // Between 1m and 50m, what phones usually report
+                Tag::GPSHPositioningError => {
+                    Some(rational(self.thread_rng.gen_range(100..=5000), 100))
+                }
+                // Windows text, a made up word in place of a title or a name
+                tag if utils::XP_TAGS.contains(&tag) => {
+                    Some(Value::Byte(utils::encode_xp(&self.random_word())))
+                }
                 _ => None,
             }
         } else {
@@ -135,6 +489,118 @@ impl RandomMetadata {
         }
     }
 
+    /// Make and Model of one camera
+    pub fn random_camera(&mut self) -> [(Tag, Value); 2] {
+        let (make, _, models) = if self.weighted {
+            MANUFACTURERS
+                .choose_weighted(&mut self.thread_rng, |(_, share, _)| *share)
+                .unwrap()
+        } else {
+            MANUFACTURERS.choose(&mut self.thread_rng).unwrap()
+        };
+        let (model, _) = models.choose(&mut self.thread_rng).unwrap();
+        [
+            (Tag::Make, Value::Ascii(vec![make.as_bytes().to_vec()])),
+            (Tag::Model, Value::Ascii(vec![model.as_bytes().to_vec()])),
+        ]
+    }
+
+    /// PhotographicSensitivity within what the camera with the Model `model`
+    /// goes up to, any standard speed for a model that isn't known
+    pub fn random_iso(&mut self, model: Option<&Value>) -> Value {
+        self.random_iso_between(100, max_iso(model))
+    }
+
+    /// A standard ISO speed from `min` to `max`, or the lowest one if there's
+    /// none in between
+    fn random_iso_between(&mut self, min: u16, max: u16) -> Value {
+        let speeds: Vec<u16> = ISO_SPEEDS
+            .into_iter()
+            .filter(|iso| (min..=max).contains(iso))
+            .collect();
+        let iso = speeds
+            .choose(&mut self.thread_rng)
+            .unwrap_or(&ISO_SPEEDS[0]);
+        Value::Short(vec![*iso])
+    }
+
+    /// Exposure, white balance, flash, metering and location of a typical photo
+    /// of `scene`, taken with the camera with the Model `model`
+    pub fn random_scene(&mut self, scene: Scene, model: Option<&Value>) -> Vec<(Tag, Value)> {
+        let settings = scene.settings();
+        let (low, high) = settings.iso;
+        let iso = self.random_iso_between(low, high.min(max_iso(model)));
+        let ((lat_min, lat_max), (lon_min, lon_max)) =
+            *scene.regions().choose(&mut self.thread_rng).unwrap();
+        let lat = self.thread_rng.gen_range(lat_min..lat_max);
+        let lon = self.thread_rng.gen_range(lon_min..lon_max);
+        let ascii = |s: &str| Value::Ascii(vec![s.as_bytes().to_vec()]);
+        let short = |v: u16| Value::Short(vec![v]);
+        let rng = &mut self.thread_rng;
+        vec![
+            (
+                Tag::ExposureTime,
+                rational(1, *settings.exposure.choose(rng).unwrap()),
+            ),
+            (
+                Tag::FNumber,
+                rational(*settings.f_number.choose(rng).unwrap(), 10),
+            ),
+            (Tag::PhotographicSensitivity, iso),
+            (
+                Tag::ExposureProgram,
+                short(*settings.program.choose(rng).unwrap()),
+            ),
+            // Auto white balance, like nearly every photo
+            (Tag::WhiteBalance, short(0)),
+            (Tag::Flash, short(*settings.flash.choose(rng).unwrap())),
+            (Tag::MeteringMode, short(settings.metering)),
+            (Tag::SceneCaptureType, short(settings.capture_type)),
+            (Tag::GPSLatitudeRef, ascii(if lat < 0. { "S" } else { "N" })),
+            (Tag::GPSLatitude, utils::to_dms(lat)),
+            (
+                Tag::GPSLongitudeRef,
+                ascii(if lon < 0. { "W" } else { "E" }),
+            ),
+            (Tag::GPSLongitude, utils::to_dms(lon)),
+        ]
+    }
+
+    /// Flash as a camera would set it, never fired for `landscape` shots where
+    /// it can't light anything up
+    pub fn random_flash(&mut self, landscape: bool) -> Value {
+        let flash = FLASH_VALUES
+            .into_iter()
+            .filter(|flash| !landscape || flash & 0x01 == 0)
+            .collect::<Vec<_>>();
+        Value::Short(vec![*flash.choose(&mut self.thread_rng).unwrap()])
+    }
+
+    /// XResolution, YResolution and ResolutionUnit of a typical camera, the same
+    /// resolution both ways in dots per inch
+    pub fn random_resolution(&mut self) -> [(Tag, Value); 3] {
+        let dpi = *RESOLUTIONS.choose(&mut self.thread_rng).unwrap();
+        [
+            (Tag::XResolution, rational(dpi, 1)),
+            (Tag::YResolution, rational(dpi, 1)),
+            // 2 = inches
+            (Tag::ResolutionUnit, Value::Short(vec![2])),
+        ]
+    }
+
+    /// PixelXDimension and PixelYDimension of a typical camera, turned upright
+    /// for `portrait` images
+    pub fn random_dimensions(&mut self, portrait: bool) -> [(Tag, Value); 2] {
+        let (mut width, mut height) = *DIMENSIONS.choose(&mut self.thread_rng).unwrap();
+        if portrait {
+            (width, height) = (height, width);
+        }
+        [
+            (Tag::PixelXDimension, Value::Long(vec![width])),
+            (Tag::PixelYDimension, Value::Long(vec![height])),
+        ]
+    }
+
     pub fn random_latlong(&mut self, direction: Cardinal) -> (Value, String) {
         let latlong_range = match direction {
             Cardinal::East | Cardinal::West => 180,
@@ -171,3 +637,53 @@ impl RandomMetadata {
         (new_lat, dir)
     }
 }
+
+/// The highest ISO speed the camera with the Model `model` goes up to
+fn max_iso(model: Option<&Value>) -> u16 {
+    let model = match model {
+        Some(Value::Ascii(v)) => v.first().map(|m| m.trim_ascii_end()),
+        _ => None,
+    };
+    MANUFACTURERS
+        .iter()
+        .flat_map(|(_, _, models)| models.iter())
+        .find(|(name, _)| Some(name.as_bytes()) == model)
+        .map_or(u16::MAX, |(_, max_iso)| *max_iso)
+}
+
+fn rational(num: u32, denom: u32) -> Value {
+    Value::Rational(vec![Rational { num, denom }])
+}
+
+/// A SubSecTime value made of the first of `digits`, as many as the camera
+/// wrote into `original` or three if that's not known
+pub fn subsec(digits: &str, original: Option<&Value>) -> Value {
+    let length = match original {
+        Some(Value::Ascii(v)) => v.first().map_or(0, |s| s.trim_ascii().len()),
+        _ => 0,
+    };
+    let length = if (1..=digits.len()).contains(&length) {
+        length
+    } else {
+        3
+    };
+    Value::Ascii(vec![digits.as_bytes()[..length].to_vec()])
+}
+
+/// GPSDateStamp and GPSTimeStamp for the UTC time `utc`
+pub fn gps_time_stamps(utc: NaiveDateTime) -> [(Tag, Value); 2] {
+    [
+        (
+            Tag::GPSDateStamp,
+            Value::Ascii(vec![utc.format("%Y:%m:%d").to_string().into_bytes()]),
+        ),
+        (
+            Tag::GPSTimeStamp,
+            Value::Rational(vec![
+                (utc.hour(), 1).into(),
+                (utc.minute(), 1).into(),
+                (utc.second(), 1).into(),
+            ]),
+        ),
+    ]
+}
@@ -0,0 +1,162 @@
+use anyhow::{bail, Context as _, Result};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, Rgba, RgbaImage,
+};
+use serde_json::json;
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use crate::globe::{self, Canvas, Globe, Marker, PALETTE};
+
+// Record
+//
+// The globe turning once around the place a photo was taken, rendered off-screen
+// for a blog post about it. The turn starts and ends on the location, which is
+// marked in red. It's written as a GIF, every cell a block shaded like its
+// character, or as an asciicast for asciinema players, going by the extension
+// of the output.
+
+/// Marks the location, a character the textures don't use
+const LOCATION_MARKER: char = '#';
+/// Pixels of a cell in the GIF, twice as high as wide like a terminal cell
+const CELL_WIDTH: u32 = 4;
+const CELL_HEIGHT: u32 = 8;
+/// Distance of the camera from the middle of the globe, in radii
+const DISTANCE: f32 = 1.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gif,
+    Asciicast,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("gif") => Ok(Format::Gif),
+            Some(e) if e.eq_ignore_ascii_case("cast") => Ok(Format::Asciicast),
+            _ => bail!(
+                "Unknown format of {:?}, expected a .gif or .cast file",
+                path
+            ),
+        }
+    }
+}
+
+/// A turn of the globe around a location
+#[derive(Debug, Clone)]
+pub struct Recording {
+    /// Signed degrees
+    pub lat: f64,
+    pub lon: f64,
+    pub seconds: f64,
+    pub fps: u32,
+    /// Size in terminal cells, the globe is as high as the recording
+    pub columns: usize,
+    pub rows: usize,
+}
+
+impl Recording {
+    fn frame_count(&self) -> usize {
+        ((self.seconds * f64::from(self.fps)).round() as usize).max(1)
+    }
+
+    /// The globe of every frame, as characters
+    pub fn frames(&self) -> Vec<Vec<Vec<char>>> {
+        let mut globe = Globe::new(1., 0., false);
+        globe.markers.push(Marker {
+            lat: self.lat as f32,
+            lon: self.lon as f32,
+            radius: 2.,
+            symbol: LOCATION_MARKER,
+        });
+        // As high as the recording, and round in cells twice as high as wide
+        let globe_width = (self.rows * 2).min(self.columns);
+        let count = self.frame_count();
+        (0..count)
+            .map(|i| {
+                let turned = 360. * i as f64 / count as f64;
+                globe.look_at(self.lat as f32, (self.lon + turned) as f32, DISTANCE);
+                let mut canvas = Canvas::new(self.columns, self.rows, Some((1, 1)))
+                    .with_globe_width(globe_width);
+                globe.render_sphere(&mut canvas);
+                canvas.matrix
+            })
+            .collect()
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let format = Format::from_path(path)?;
+        let file = File::create(path).with_context(|| format!("Unable to create {:?}", path))?;
+        let frames = self.frames();
+        match format {
+            Format::Gif => self.write_gif(&frames, file),
+            Format::Asciicast => self.write_cast(&frames, BufWriter::new(file)),
+        }
+    }
+
+    fn write_gif(&self, frames: &[Vec<Vec<char>>], file: File) -> Result<()> {
+        let mut encoder = GifEncoder::new_with_speed(file, 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+        let delay = Delay::from_numer_denom_ms(1000, self.fps.max(1));
+        for cells in frames {
+            let width = self.columns as u32 * CELL_WIDTH;
+            let height = self.rows as u32 * CELL_HEIGHT;
+            let pixels = RgbaImage::from_fn(width, height, |x, y| {
+                shade(cells[(y / CELL_HEIGHT) as usize][(x / CELL_WIDTH) as usize])
+            });
+            encoder.encode_frame(Frame::from_parts(pixels, 0, 0, delay))?;
+        }
+        Ok(())
+    }
+
+    /// asciicast v2, a JSON header and a JSON line per frame
+    fn write_cast(&self, frames: &[Vec<Vec<char>>], mut out: impl Write) -> Result<()> {
+        let header = json!({
+            "version": 2,
+            "width": self.columns,
+            "height": self.rows,
+            "title": format!("{:.4}, {:.4}", self.lat, self.lon),
+        });
+        writeln!(out, "{}", header)?;
+        for (i, cells) in frames.iter().enumerate() {
+            // Home instead of clearing, so players don't flicker
+            let mut screen = String::from(if i == 0 { "\x1B[2J\x1B[H" } else { "\x1B[H" });
+            for (y, row) in cells.iter().enumerate() {
+                if y > 0 {
+                    screen.push_str("\r\n");
+                }
+                for &c in row {
+                    if c == LOCATION_MARKER {
+                        let _ = write!(screen, "\x1B[1;31m{}\x1B[0m", c);
+                    } else {
+                        screen.push(c);
+                    }
+                }
+            }
+            let time = i as f64 / f64::from(self.fps.max(1));
+            writeln!(out, "{}", json!([time, "o", screen]))?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Color of a cell in the GIF, brighter the further up the palette its
+/// character is
+fn shade(c: char) -> Rgba<u8> {
+    if c == LOCATION_MARKER {
+        return Rgba([230, 40, 40, 255]);
+    }
+    let top = PALETTE.len() as u32 - 1;
+    // Characters outside the palette, like stars, halfway up
+    let level = globe::palette_index(c).map_or(top / 2, |i| i as u32);
+    let v = level * 255 / top;
+    // Tinted blue rather than plain gray
+    Rgba([(v / 3) as u8, (v * 3 / 4) as u8, v as u8, 255])
+}
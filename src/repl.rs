@@ -0,0 +1,370 @@
+use anyhow::{bail, Context as _, Result};
+use exif::{Context, Field, Reader, Tag};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    backend::{ExifBackend, MetadataBackend},
+    batch::{self, Export},
+    policy,
+    processor::{TagProcessor, Verdict},
+    utils,
+};
+
+// REPL
+//
+// Queries and operations typed against a set of images loaded once, to find
+// out what's in a library before deciding how to scrub it.
+//
+//     bresson> count by Model where Make="Apple"
+//     bresson> show DateTimeOriginal where Model~"iPhone 1[45]"
+//     bresson> clear GPS where Make="Apple"
+//
+// Tags are matched by name with `*` wildcards, like in policies, and `GPS`
+// stands for every GPS tag. Values are compared with what bresson shows for
+// them. Operations write scrubbed copies through the batch engine, the loaded
+// images themselves are never changed.
+
+pub const HELP: &str = "\
+count [by TAG] [where ...]   Number of images, or of images per value of a tag
+list [where ...]             Paths of the images
+show TAG [where ...]         Values of a tag
+clear TAG [where ...]        Write copies without the tag
+help                         This help
+quit                         Leave
+
+Conditions are TAG=VALUE, TAG!=VALUE or TAG~REGEX, joined with `and`.
+Quote values with spaces, e.g. Model=\"Pixel 8\"";
+
+/// Whether `pattern` names `tag`, `GPS` standing for all GPS tags
+fn names(pattern: &str, tag: Tag) -> bool {
+    policy::glob_match(pattern, &utils::tag_name(tag))
+        || (pattern.eq_ignore_ascii_case("GPS") && tag.0 == Context::Gps)
+}
+
+#[derive(Debug)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Equals,
+    NotEquals,
+    Matches,
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '=' => {
+                chars.next();
+                tokens.push(Token::Equals);
+            }
+            '~' => {
+                chars.next();
+                tokens.push(Token::Matches);
+            }
+            '!' => {
+                chars.next();
+                if chars.next() != Some('=') {
+                    bail!("Expected `!=`");
+                }
+                tokens.push(Token::NotEquals);
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => text.extend(chars.next()),
+                        Some(c) => text.push(c),
+                        None => bail!("Missing closing quote"),
+                    }
+                }
+                tokens.push(Token::Quoted(text));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || matches!(c, '=' | '~' | '!' | '"') {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Test {
+    Equals(String),
+    NotEquals(String),
+    Matches(Regex),
+}
+
+#[derive(Debug)]
+struct Condition {
+    tag: String,
+    test: Test,
+}
+
+impl Condition {
+    fn holds(&self, image: &Image) -> bool {
+        let mut values = image.values(&self.tag);
+        match &self.test {
+            Test::Equals(expected) => values.any(|v| v == expected),
+            Test::NotEquals(expected) => values.all(|v| v != expected),
+            Test::Matches(re) => values.any(|v| re.is_match(v)),
+        }
+    }
+}
+
+/// What was typed at the prompt
+#[derive(Debug)]
+enum Statement {
+    Count {
+        by: Option<String>,
+        conditions: Vec<Condition>,
+    },
+    List(Vec<Condition>),
+    Show(String, Vec<Condition>),
+    Clear(String, Vec<Condition>),
+    Help,
+    Quit,
+}
+
+fn word(token: Option<Token>, what: &str) -> Result<String> {
+    match token {
+        Some(Token::Word(word) | Token::Quoted(word)) => Ok(word),
+        _ => bail!("Expected {}", what),
+    }
+}
+
+/// The conditions of a `where` clause, if there is one
+fn conditions(tokens: &mut impl Iterator<Item = Token>) -> Result<Vec<Condition>> {
+    let mut conditions = Vec::new();
+    match tokens.next() {
+        None => return Ok(conditions),
+        Some(Token::Word(w)) if w.eq_ignore_ascii_case("where") => {}
+        _ => bail!("Expected `where` before conditions"),
+    }
+    loop {
+        let tag = word(tokens.next(), "a tag in the condition")?;
+        let op = tokens.next();
+        let value = word(tokens.next(), "a value in the condition")?;
+        let test = match op {
+            Some(Token::Equals) => Test::Equals(value),
+            Some(Token::NotEquals) => Test::NotEquals(value),
+            Some(Token::Matches) => Test::Matches(
+                Regex::new(&value).with_context(|| format!("Invalid pattern {:?}", value))?,
+            ),
+            _ => bail!("Expected =, != or ~ after {}", tag),
+        };
+        conditions.push(Condition { tag, test });
+        match tokens.next() {
+            None => return Ok(conditions),
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case("and") => {}
+            _ => bail!("Expected `and` between conditions"),
+        }
+    }
+}
+
+fn parse(line: &str) -> Result<Statement> {
+    let mut tokens = tokenize(line)?.into_iter().peekable();
+    let Some(Token::Word(command)) = tokens.next() else {
+        bail!("Expected a command, try `help`");
+    };
+    let statement = match command.to_lowercase().as_str() {
+        "count" => {
+            let by = match tokens.peek() {
+                Some(Token::Word(w)) if w.eq_ignore_ascii_case("by") => {
+                    tokens.next();
+                    Some(word(tokens.next(), "a tag after `by`")?)
+                }
+                _ => None,
+            };
+            Statement::Count {
+                by,
+                conditions: conditions(&mut tokens)?,
+            }
+        }
+        "list" => Statement::List(conditions(&mut tokens)?),
+        "show" => Statement::Show(
+            word(tokens.next(), "a tag to show")?,
+            conditions(&mut tokens)?,
+        ),
+        "clear" => Statement::Clear(
+            word(tokens.next(), "a tag to clear")?,
+            conditions(&mut tokens)?,
+        ),
+        "help" => Statement::Help,
+        "quit" | "exit" => Statement::Quit,
+        _ => bail!("Unknown command {:?}, try `help`", command),
+    };
+    if tokens.next().is_some() {
+        bail!("Unexpected input after `{}`", command);
+    }
+    Ok(statement)
+}
+
+/// An image and its fields, with their values as shown in the table
+struct Image {
+    path: PathBuf,
+    fields: Vec<(Tag, String)>,
+}
+
+impl Image {
+    fn values<'a>(&'a self, pattern: &'a str) -> impl Iterator<Item = &'a str> {
+        self.fields
+            .iter()
+            .filter(move |(tag, _)| names(pattern, *tag))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Drops the fields named by a pattern
+struct Clear(String);
+
+impl TagProcessor for Clear {
+    fn name(&self) -> &str {
+        "clear"
+    }
+
+    fn description(&self) -> &str {
+        "Remove the tags typed in the REPL"
+    }
+
+    fn process(&mut self, _path: &Path, field: &Field) -> Verdict {
+        if names(&self.0, field.tag) {
+            Verdict::Drop
+        } else {
+            Verdict::Keep
+        }
+    }
+}
+
+/// What a statement asks for after it ran
+pub enum Outcome {
+    Output(String),
+    Quit,
+}
+
+/// Images loaded for the REPL
+pub struct Library {
+    images: Vec<Image>,
+}
+
+impl Library {
+    /// Reads the metadata of the images in `paths`, images without any count too
+    pub fn load(paths: &[PathBuf]) -> Result<Self> {
+        let mut images = Vec::new();
+        for path in batch::collect_images(paths)? {
+            let img_buf = std::fs::read(&path)?;
+            let fields = match Reader::new().read_from_container(&mut Cursor::new(&img_buf)) {
+                Ok(exif) => {
+                    let backend = ExifBackend::new(exif);
+                    backend
+                        .fields()
+                        .iter()
+                        .map(|f| (f.tag, backend.display(f)))
+                        .collect()
+                }
+                Err(_) => Vec::new(),
+            };
+            images.push(Image { path, fields });
+        }
+        Ok(Self { images })
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.images.is_empty()
+    }
+
+    fn matching<'a>(&'a self, conditions: &'a [Condition]) -> impl Iterator<Item = &'a Image> {
+        self.images
+            .iter()
+            .filter(|image| conditions.iter().all(|c| c.holds(image)))
+    }
+
+    /// Runs a line typed at the prompt
+    pub fn run(&self, line: &str) -> Result<Outcome> {
+        let mut out = String::new();
+        match parse(line)? {
+            Statement::Count {
+                by: None,
+                conditions,
+            } => writeln!(out, "{}", self.matching(&conditions).count())?,
+            Statement::Count {
+                by: Some(tag),
+                conditions,
+            } => {
+                let mut counts: HashMap<&str, usize> = HashMap::new();
+                for image in self.matching(&conditions) {
+                    let value = image.values(&tag).next().unwrap_or("(none)");
+                    *counts.entry(value).or_default() += 1;
+                }
+                let mut counts: Vec<_> = counts.into_iter().collect();
+                counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+                for (value, count) in counts {
+                    writeln!(out, "{:>5}  {}", count, value)?;
+                }
+            }
+            Statement::List(conditions) => {
+                for image in self.matching(&conditions) {
+                    writeln!(out, "{}", image.path.display())?;
+                }
+            }
+            Statement::Show(tag, conditions) => {
+                for image in self.matching(&conditions) {
+                    for (field, value) in image.fields.iter().filter(|(t, _)| names(&tag, *t)) {
+                        writeln!(out, "{}: {} = {}", image.path.display(), field, value)?;
+                    }
+                }
+            }
+            Statement::Clear(tag, conditions) => {
+                let mut processors: Vec<Box<dyn TagProcessor>> = vec![Box::new(Clear(tag))];
+                for image in self.matching(&conditions) {
+                    match batch::process_file(
+                        &mut processors,
+                        &image.path,
+                        None,
+                        false,
+                        &Export::default(),
+                    ) {
+                        Ok(report) => match report.output {
+                            Some(output) => writeln!(
+                                out,
+                                "{}: {} field(s) cleared -> {}",
+                                image.path.display(),
+                                report.dropped.len(),
+                                output.display()
+                            )?,
+                            None => writeln!(out, "{}: nothing to clear", image.path.display())?,
+                        },
+                        Err(e) => writeln!(out, "{}: {}", image.path.display(), e)?,
+                    }
+                }
+            }
+            Statement::Help => writeln!(out, "{}", HELP)?,
+            Statement::Quit => return Ok(Outcome::Quit),
+        }
+        Ok(Outcome::Output(out))
+    }
+}
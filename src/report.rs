@@ -0,0 +1,266 @@
+use anyhow::{Context as _, Result};
+use chrono::Local;
+use exif::Tag;
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use crate::{batch::FileReport, globe, utils};
+
+// Report
+//
+// A standalone HTML page summing up a scrub, to attach to an audit - every
+// file with the copy it got, the fields dropped and replaced, and whatever is
+// still worth a look, like flagged fields, extra data or a location near home.
+// A small map shows where the images were taken, red where the copy still
+// gives the location away.
+//
+// The page is filled in from `templates/report.html`, where `{{name}}` stands
+// for a piece of HTML put together here. Styles and the map are inline, so the
+// file can be mailed or archived on its own.
+
+static TEMPLATE: &str = include_str!("../templates/report.html");
+
+/// Size of the map, a pixel per degree
+const MAP_WIDTH: f64 = 360.;
+const MAP_HEIGHT: f64 = 180.;
+
+/// What happened to one file
+#[derive(Debug, Default)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub output: Option<PathBuf>,
+    pub dropped: Vec<Tag>,
+    pub replaced: Vec<Tag>,
+    /// Flagged fields and warnings
+    pub flags: Vec<String>,
+    /// Where the original was taken
+    pub location: Option<(f64, f64)>,
+    /// Whether the copy still has the location
+    pub kept_location: bool,
+    pub error: Option<String>,
+}
+
+impl Entry {
+    pub fn new(report: &FileReport, location: Option<(f64, f64)>) -> Self {
+        let kept_location = location.is_some()
+            && ![&report.dropped, &report.replaced]
+                .iter()
+                .any(|tags| tags.contains(&Tag::GPSLatitude));
+        let mut flags: Vec<String> = report
+            .flagged
+            .iter()
+            .map(|(tag, reason)| format!("{} ({})", utils::tag_name(*tag), reason))
+            .collect();
+        flags.extend(
+            report
+                .extra
+                .iter()
+                .map(|finding| format!("{}, which may hide data", finding)),
+        );
+        Self {
+            path: report.path.clone(),
+            output: report.output.clone(),
+            dropped: report.dropped.clone(),
+            replaced: report.replaced.clone(),
+            flags,
+            location,
+            kept_location,
+            error: None,
+        }
+    }
+
+    pub fn failed(path: &Path, error: String) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    fn status(&self) -> (&'static str, &'static str) {
+        if self.error.is_some() {
+            ("failed", "Failed")
+        } else if !self.flags.is_empty() || self.kept_location {
+            ("flagged", "Check")
+        } else {
+            ("clean", "Clean")
+        }
+    }
+}
+
+/// Everything a scrub did, to be rendered as HTML
+#[derive(Debug, Default)]
+pub struct Report {
+    /// How the images were scrubbed, e.g. "with strip-gps"
+    pub how: String,
+    pub dry_run: bool,
+    pub entries: Vec<Entry>,
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Replaces every `{{name}}` in `template` with its piece of HTML
+pub fn fill(template: &str, values: &[(&str, String)]) -> String {
+    values
+        .iter()
+        .fold(template.to_owned(), |page, (name, html)| {
+            page.replace(&format!("{{{{{}}}}}", name), html)
+        })
+}
+
+fn list(items: impl IntoIterator<Item = String>) -> String {
+    let items: Vec<String> = items
+        .into_iter()
+        .map(|item| format!("<li>{}</li>", escape(&item)))
+        .collect();
+    if items.is_empty() {
+        String::new()
+    } else {
+        format!("<ul>{}</ul>", items.concat())
+    }
+}
+
+impl Report {
+    pub fn render(&self) -> String {
+        let count = |status| {
+            self.entries
+                .iter()
+                .filter(|e| e.status().0 == status)
+                .count()
+        };
+        let summary = format!(
+            "<span>{} file(s)</span><span class=\"clean\">{} clean</span>\
+             <span class=\"flagged\">{} to check</span><span class=\"failed\">{} failed</span>",
+            self.entries.len(),
+            count("clean"),
+            count("flagged"),
+            count("failed")
+        );
+        let meta = format!(
+            "{} on {} by bresson {}{}",
+            escape(&self.how),
+            Local::now().format("%Y-%m-%d %H:%M"),
+            env!("CARGO_PKG_VERSION"),
+            if self.dry_run {
+                ", dry run - no copies were written"
+            } else {
+                ""
+            }
+        );
+
+        let mut rows = String::new();
+        for entry in &self.entries {
+            let (class, status) = entry.status();
+            let mut flags = entry.flags.clone();
+            if entry.kept_location {
+                flags.push(String::from("Copy still has the location"));
+            }
+            flags.extend(entry.error.clone());
+            // Writing to a String can't fail
+            let _ = writeln!(
+                rows,
+                "<tr><td class=\"status {}\">{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                class,
+                status,
+                escape(&entry.path.display().to_string()),
+                entry
+                    .output
+                    .as_ref()
+                    .map_or(String::new(), |o| escape(&o.display().to_string())),
+                list(entry.dropped.iter().map(|t| utils::tag_name(*t))),
+                list(entry.replaced.iter().map(|t| utils::tag_name(*t))),
+                list(flags),
+            );
+        }
+
+        fill(
+            TEMPLATE,
+            &[
+                ("title", String::from("bresson scrub report")),
+                ("meta", meta),
+                ("summary", summary),
+                ("map", self.map()),
+                ("rows", rows),
+            ],
+        )
+    }
+
+    /// Equirectangular map of the locations, empty if none of the images had one
+    fn map(&self) -> String {
+        let located: Vec<_> = self
+            .entries
+            .iter()
+            .filter_map(|e| Some((e.location?, e.kept_location)))
+            .collect();
+        if located.is_empty() {
+            return String::new();
+        }
+
+        let mut svg = format!(
+            "<svg viewBox=\"0 0 {} {}\" xmlns=\"http://www.w3.org/2000/svg\">",
+            MAP_WIDTH, MAP_HEIGHT
+        );
+        // Land from the texture of the globe, a rectangle per run of land on a row
+        let rows: Vec<&str> = globe::EARTH_DAY.lines().collect();
+        let cell_h = MAP_HEIGHT / rows.len() as f64;
+        for (y, row) in rows.iter().enumerate() {
+            let cells: Vec<bool> = row.chars().map(is_land).collect();
+            let cell_w = MAP_WIDTH / cells.len() as f64;
+            let mut x = 0;
+            while x < cells.len() {
+                if !cells[x] {
+                    x += 1;
+                    continue;
+                }
+                let start = x;
+                while x < cells.len() && cells[x] {
+                    x += 1;
+                }
+                let _ = write!(
+                    svg,
+                    "<rect class=\"land\" x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\"/>",
+                    start as f64 * cell_w,
+                    y as f64 * cell_h,
+                    (x - start) as f64 * cell_w,
+                    cell_h
+                );
+            }
+        }
+        for ((lat, lon), kept) in located {
+            let _ = write!(
+                svg,
+                "<circle class=\"{}\" cx=\"{:.2}\" cy=\"{:.2}\" r=\"2\"/>",
+                if kept { "kept" } else { "removed" },
+                lon + 180.,
+                90. - lat
+            );
+        }
+        svg.push_str("</svg>");
+        svg
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.render())
+            .with_context(|| format!("Unable to write report {:?}", path))
+    }
+}
+
+/// Whether a character of the globe texture is land rather than sea
+fn is_land(c: char) -> bool {
+    c.is_ascii_alphabetic() || matches!(c, '@' | '*' | '"')
+}
@@ -0,0 +1,258 @@
+use anyhow::{bail, Result};
+use exif::Field;
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    backend::MetadataBackend,
+    batch, vault,
+    writer::{self, ByteOrder, Oversized},
+};
+
+// Save
+//
+// Writing a copy of a large image takes long enough to freeze the interface, so
+// the TUI saves on a worker thread. Everything the worker needs is cloned out of
+// the document up front, the document itself stays with the UI.
+//
+// The copy is written in chunks, reporting progress after each one and checking
+// whether the save was cancelled. A cancelled or failed save removes whatever
+// part of the copy was already written.
+//
+// Saving in place writes the same way, into a temporary file next to the
+// original that is renamed over it once complete, so the original is either
+// untouched or entirely replaced. The original can be kept as `<name>.bak`.
+
+/// Bytes written between progress updates
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// Everything needed to write a copy of an image with edited metadata
+pub struct SaveRequest {
+    pub path: PathBuf,
+    /// Where the copy is written, next to `path`, or `path` itself when saving
+    /// in place
+    pub copy: PathBuf,
+    pub backend: Arc<dyn MetadataBackend>,
+    pub fields: Vec<Field>,
+    /// Passphrase to seal the original metadata with, into a vault next to the copy
+    pub vault_passphrase: Option<String>,
+    /// Byte order to convert the metadata to, instead of keeping the one it has
+    pub byte_order: Option<ByteOrder>,
+    pub target: Destination,
+    /// What to do when the Exif data outgrows its JPEG segment
+    pub oversized: Oversized,
+}
+
+/// Where a save ends up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Destination {
+    /// A new copy next to the original
+    #[default]
+    Copy,
+    /// Over the original, keeping it as `<name>.bak` first if `backup`
+    InPlace { backup: bool },
+}
+
+/// Where the original is kept before it's saved over, i.e. `<name>.bak`
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+/// Where a file saved over the original is written until it's complete
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".bresson-tmp");
+    path.with_file_name(name)
+}
+
+impl SaveRequest {
+    /// Writes the copy, calling `progress` with the bytes written so far and the
+    /// total after every chunk. Returning false from it cancels the save.
+    pub fn run(&self, mut progress: impl FnMut(u64, u64) -> bool) -> Result<String> {
+        let img_buf = std::fs::read(&self.path)?;
+
+        // Modified fields will always have the latest modifications to the state of the
+        // Exif Metadata (including randomization and clearing)
+        let (new_img_buf, taken) = batch::write_fitting(
+            &*self.backend,
+            &img_buf,
+            &self.fields,
+            self.byte_order,
+            self.oversized,
+        )?;
+        drop(img_buf);
+        let mut fields = self.fields.clone();
+        if let Some(taken) = &taken {
+            fields.retain(|f| (f.tag, f.ifd_num) != (taken.tag, taken.ifd_num));
+        }
+        let roundtrip = self.verify(&new_img_buf, fields);
+
+        let copy_file_name = &self.copy;
+        let written = match self.target {
+            Destination::Copy => copy_file_name.clone(),
+            Destination::InPlace { .. } => temporary_path(&self.path),
+        };
+        if let Err(e) = write_chunked(&written, &new_img_buf, &mut progress) {
+            let _ = std::fs::remove_file(&written);
+            return Err(e);
+        }
+        let mut saved = match self.target {
+            Destination::Copy => format!("Saved a copy - {:?}", copy_file_name),
+            Destination::InPlace { backup } => {
+                let replaced = self.replace_original(&written, backup);
+                if replaced.is_err() {
+                    let _ = std::fs::remove_file(&written);
+                }
+                match replaced? {
+                    Some(backup) => format!("Saved over {:?}, backup {:?}", self.path, backup),
+                    None => format!("Saved over {:?}", self.path),
+                }
+            }
+        };
+
+        if let Some(taken) = &taken {
+            saved.push_str(&format!(", {}", self.oversized.describe(taken)));
+        }
+
+        // Archive the original metadata so the owner can restore it later
+        if let Some(passphrase) = &self.vault_passphrase {
+            let manifest = self.backend.manifest(&self.path);
+            let vault_file_name = vault::vault_path_for(copy_file_name);
+            std::fs::write(&vault_file_name, manifest.seal(passphrase)?)?;
+            return Ok(format!(
+                "{} with vault {:?}, {}",
+                saved, vault_file_name, roundtrip
+            ));
+        }
+
+        Ok(format!("{}, {}", saved, roundtrip))
+    }
+
+    /// Renames `written` over the original, copying the original to its backup
+    /// first if asked to. Returns where the backup is.
+    fn replace_original(&self, written: &Path, backup: bool) -> Result<Option<PathBuf>> {
+        // The new file is only as readable as the one it replaces
+        std::fs::set_permissions(written, std::fs::metadata(&self.path)?.permissions())?;
+        let backup = match backup {
+            true => {
+                let backup = backup_path(&self.path);
+                std::fs::copy(&self.path, &backup)?;
+                Some(backup)
+            }
+            false => None,
+        };
+        std::fs::rename(written, &self.path)?;
+        Ok(backup)
+    }
+
+    /// Whether `copy` reads back with `fields`, the ones it was written with,
+    /// for the status message
+    fn verify(&self, copy: &[u8], mut fields: Vec<Field>) -> String {
+        let read = self.backend.as_exif().map(|exif| exif.byte_order());
+        if let (Some(read), Some(order)) = (read, self.byte_order) {
+            writer::convert_byte_order(&mut fields, read, order);
+        }
+        match writer::verify_roundtrip(copy, &fields) {
+            Ok(roundtrip) => roundtrip.to_string(),
+            Err(e) => format!("but it couldn't be read back: {}", e),
+        }
+    }
+}
+
+fn write_chunked(
+    path: &Path,
+    buf: &[u8],
+    progress: &mut impl FnMut(u64, u64) -> bool,
+) -> Result<()> {
+    let total = buf.len() as u64;
+    let mut file = File::create(path)?;
+    let mut written = 0;
+    for chunk in buf.chunks(CHUNK_SIZE) {
+        file.write_all(chunk)?;
+        written += chunk.len() as u64;
+        if !progress(written, total) {
+            bail!("Save cancelled");
+        }
+    }
+    file.sync_all()?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum SaveEvent {
+    Progress {
+        written: u64,
+        total: u64,
+    },
+    /// The status message, or why the save failed
+    Done(Result<String>),
+}
+
+/// A save running on a worker thread
+pub struct SaveJob {
+    /// Where the copy is being written
+    pub copy: PathBuf,
+    pub target: Destination,
+    events: Receiver<SaveEvent>,
+    cancelled: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl SaveJob {
+    pub fn spawn(request: SaveRequest) -> Self {
+        let copy = request.copy.clone();
+        let target = request.target;
+        let (tx, events) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = cancelled.clone();
+        let handle = thread::spawn(move || {
+            let result = request.run(|written, total| {
+                let _ = tx.send(SaveEvent::Progress { written, total });
+                !flag.load(Ordering::Relaxed)
+            });
+            let _ = tx.send(SaveEvent::Done(result));
+        });
+        Self {
+            copy,
+            target,
+            events,
+            cancelled,
+            handle,
+        }
+    }
+
+    /// Asks the worker to stop after the chunk it's writing
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// The next event, if there is one yet. The job is over once it returned `Done`.
+    pub fn poll(&mut self) -> Option<SaveEvent> {
+        match self.events.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) => None,
+            // The worker is gone without a word, which only happens if it panicked
+            Err(TryRecvError::Disconnected) => {
+                Some(SaveEvent::Done(Err(anyhow::anyhow!("Save failed"))))
+            }
+        }
+    }
+
+    /// Cancels the save and waits for the worker to clean up
+    pub fn cancel_and_wait(self) {
+        self.cancel();
+        let _ = self.handle.join();
+    }
+}
@@ -0,0 +1,175 @@
+use exif::{Field, Tag};
+use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    state::{MetadataVal, Operation},
+    vault::{ManifestField, ManifestTag},
+};
+
+// Serial
+//
+// How the core types are written out with serde - for the JSON export, logs of
+// what was done to an image and anything else kept on disk. Fields are written
+// like in a vault manifest, by tag name, IFD context and number with a typed
+// value, so there's one representation of a field wherever it ends up.
+//
+// Fields whose values are of a type kamadak-exif doesn't know can't be written,
+// there's nothing to read them back into.
+//
+// `Operation` is written as a log record tagged with the kind of edit, with
+// the fields as they were before it:
+//
+//     {"op":"clear","from":{"name":"Make",...},"to":{"name":"Make",...}}
+//     {"op":"set_fields","what":"location","fields":[{"tag":{...},"field":null}]}
+
+fn encode<E: ser::Error>(f: &Field) -> Result<ManifestField, E> {
+    ManifestField::encode(f)
+        .ok_or_else(|| E::custom(format!("{} has a value of an unknown type", f.tag)))
+}
+
+fn decode<E: de::Error>(f: &ManifestField) -> Result<Field, E> {
+    f.to_field()
+        .ok_or_else(|| E::custom(format!("unknown IFD context {:?}", f.context)))
+}
+
+#[derive(Serialize, Deserialize)]
+struct MetadataRecord {
+    #[serde(flatten)]
+    field: ManifestField,
+    changed: bool,
+}
+
+impl Serialize for MetadataVal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        MetadataRecord {
+            field: encode(&self.field)?,
+            changed: self.changed,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MetadataVal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let record = MetadataRecord::deserialize(deserializer)?;
+        Ok(MetadataVal {
+            field: decode(&record.field)?,
+            changed: record.changed,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SetFieldRecord {
+    tag: ManifestTag,
+    /// The field before, `None` if there wasn't one
+    field: Option<ManifestField>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum OperationRecord {
+    Randomize {
+        from: ManifestField,
+        to: ManifestField,
+    },
+    Clear {
+        from: ManifestField,
+        to: ManifestField,
+    },
+    Edit {
+        from: ManifestField,
+        to: ManifestField,
+    },
+    RandomizeAll,
+    ClearAll,
+    ClearGps {
+        fields: Vec<ManifestField>,
+    },
+    RandomizeTogether {
+        fields: Vec<ManifestField>,
+    },
+    SetFields {
+        what: String,
+        fields: Vec<SetFieldRecord>,
+    },
+    ApplyPolicy,
+}
+
+impl Serialize for Operation {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let pair = |(from, to): &(Field, Field)| -> Result<_, S::Error> {
+            Ok((encode(from)?, encode(to)?))
+        };
+        let all = |fields: &[Field]| fields.iter().map(encode).collect::<Result<Vec<_>, _>>();
+        let record = match self {
+            Operation::Randomize(fields) => {
+                let (from, to) = pair(fields)?;
+                OperationRecord::Randomize { from, to }
+            }
+            Operation::Clear(fields) => {
+                let (from, to) = pair(fields)?;
+                OperationRecord::Clear { from, to }
+            }
+            Operation::Edit(fields) => {
+                let (from, to) = pair(fields)?;
+                OperationRecord::Edit { from, to }
+            }
+            Operation::RandomizeAll => OperationRecord::RandomizeAll,
+            Operation::ClearAll => OperationRecord::ClearAll,
+            Operation::ClearGps(fields) => OperationRecord::ClearGps {
+                fields: all(fields)?,
+            },
+            Operation::RandomizeTogether(fields) => OperationRecord::RandomizeTogether {
+                fields: all(fields)?,
+            },
+            Operation::SetFields(what, fields) => OperationRecord::SetFields {
+                what: what.clone(),
+                fields: fields
+                    .iter()
+                    .map(|(tag, field)| {
+                        Ok(SetFieldRecord {
+                            tag: ManifestTag::from_tag(*tag),
+                            field: field.as_ref().map(encode).transpose()?,
+                        })
+                    })
+                    .collect::<Result<_, S::Error>>()?,
+            },
+            Operation::ApplyPolicy => OperationRecord::ApplyPolicy,
+        };
+        record.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Operation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pair = |from: &ManifestField, to: &ManifestField| -> Result<_, D::Error> {
+            Ok((decode(from)?, decode(to)?))
+        };
+        let all = |fields: &[ManifestField]| fields.iter().map(decode).collect::<Result<_, _>>();
+        Ok(match OperationRecord::deserialize(deserializer)? {
+            OperationRecord::Randomize { from, to } => Operation::Randomize(pair(&from, &to)?),
+            OperationRecord::Clear { from, to } => Operation::Clear(pair(&from, &to)?),
+            OperationRecord::Edit { from, to } => Operation::Edit(pair(&from, &to)?),
+            OperationRecord::RandomizeAll => Operation::RandomizeAll,
+            OperationRecord::ClearAll => Operation::ClearAll,
+            OperationRecord::ClearGps { fields } => Operation::ClearGps(all(&fields)?),
+            OperationRecord::RandomizeTogether { fields } => {
+                Operation::RandomizeTogether(all(&fields)?)
+            }
+            OperationRecord::SetFields { what, fields } => Operation::SetFields(
+                what,
+                fields
+                    .iter()
+                    .map(|f| {
+                        let tag: Tag = f.tag.to_tag().ok_or_else(|| {
+                            de::Error::custom(format!("unknown IFD context {:?}", f.tag.context))
+                        })?;
+                        Ok((tag, f.field.as_ref().map(decode).transpose()?))
+                    })
+                    .collect::<Result<_, D::Error>>()?,
+            ),
+            OperationRecord::ApplyPolicy => Operation::ApplyPolicy,
+        })
+    }
+}
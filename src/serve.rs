@@ -0,0 +1,250 @@
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    backend,
+    batch::{self, Export},
+    policy::{Policy, PolicyProcessor},
+    processor::{Registry, TagProcessor},
+    stats::Stats,
+    vault::{Manifest, ManifestTag},
+};
+
+// Serve
+//
+// `bresson serve` answers JSON-RPC 2.0 requests on stdin, one per line, with a
+// line on stdout each, so editors and agents can inspect and scrub images
+// without scraping what the other subcommands print. Answers use the same JSON
+// as the rest of bresson - fields as in a vault manifest, summaries as from
+// `stats --json` and scrub options as in `batch::Export`.
+//
+//     {"jsonrpc":"2.0","id":1,"method":"inspect","params":{"path":"photo.jpg"}}
+//     {"jsonrpc":"2.0","id":1,"result":{"version":1,"source":"photo.jpg",...}}
+//
+// Methods:
+//
+//     inspect {path}                          the fields of an image
+//     check   {path, policy?}                 fields that break a policy
+//     scrub   {path, processors?, policy?,    writes a scrubbed copy, like
+//              out_dir?, dry_run?, export?}   `bresson scrub`
+//     stats   {paths}                         like `bresson stats --json`
+//
+// Requests without an id are notifications and get no answer. Failures are
+// answered with an error instead of ending the server.
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// Anything that went wrong carrying out a valid request
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct Error {
+    code: i64,
+    message: String,
+}
+
+impl Error {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        Self::new(SERVER_ERROR, format!("{:#}", e))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PathParams {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CheckParams {
+    path: PathBuf,
+    /// Policy file or preset, the default audit without one
+    policy: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScrubParams {
+    path: PathBuf,
+    #[serde(default)]
+    processors: Vec<String>,
+    policy: Option<PathBuf>,
+    out_dir: Option<PathBuf>,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    export: Export,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StatsParams {
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct Violation {
+    tag: ManifestTag,
+    reason: String,
+}
+
+#[derive(Serialize)]
+struct Scrubbed {
+    path: PathBuf,
+    output: Option<PathBuf>,
+    dropped: Vec<ManifestTag>,
+    replaced: Vec<ManifestTag>,
+    flagged: Vec<Violation>,
+}
+
+fn params<T: DeserializeOwned>(params: Value) -> Result<T, Error> {
+    // Methods whose parameters are all optional can be called without any
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| Error::new(INVALID_PARAMS, e))
+}
+
+fn inspect(params: PathParams) -> Result<Manifest> {
+    Ok(backend::open(&params.path)?.manifest(&params.path))
+}
+
+fn check(params: CheckParams) -> Result<Vec<Violation>> {
+    let policy = match &params.policy {
+        Some(path) => Policy::open(path)?,
+        None => Policy::audit_default(),
+    };
+    Ok(batch::audit_file(&policy, &params.path)?
+        .into_iter()
+        .map(|(tag, reason)| Violation {
+            tag: ManifestTag::from_tag(tag),
+            reason: reason.to_owned(),
+        })
+        .collect())
+}
+
+fn scrub(params: ScrubParams) -> Result<Scrubbed> {
+    let mut processors: Vec<Box<dyn TagProcessor>> = Vec::new();
+    let mut export = params.export;
+    if let Some(path) = &params.policy {
+        let policy = Policy::open(path)?;
+        export.strip_text |= policy.strip_text;
+        processors.push(Box::new(PolicyProcessor::new(policy)));
+    }
+    let names = match params.processors.is_empty() && params.policy.is_none() {
+        true => vec![String::from("strip-all")],
+        false => params.processors,
+    };
+    processors.extend(Registry::default().select(&names)?);
+    let report = batch::process_file(
+        &mut processors,
+        &params.path,
+        params.out_dir.as_deref(),
+        params.dry_run,
+        &export,
+    )?;
+    let tags = |tags: &[exif::Tag]| tags.iter().map(|t| ManifestTag::from_tag(*t)).collect();
+    Ok(Scrubbed {
+        dropped: tags(&report.dropped),
+        replaced: tags(&report.replaced),
+        flagged: report
+            .flagged
+            .into_iter()
+            .map(|(tag, reason)| Violation {
+                tag: ManifestTag::from_tag(tag),
+                reason,
+            })
+            .collect(),
+        path: report.path,
+        output: report.output,
+    })
+}
+
+fn stats(params: StatsParams) -> Result<Stats> {
+    Stats::collect(&params.paths)
+}
+
+fn call(method: &str, p: Value) -> Result<Value, Error> {
+    let result = match method {
+        "inspect" => serde_json::to_value(inspect(params(p)?)?),
+        "check" => serde_json::to_value(check(params(p)?)?),
+        "scrub" => serde_json::to_value(scrub(params(p)?)?),
+        "stats" => serde_json::to_value(stats(params(p)?)?),
+        _ => {
+            return Err(Error::new(
+                METHOD_NOT_FOUND,
+                format!("Unknown method {}", method),
+            ))
+        }
+    };
+    result.map_err(|e| Error::new(SERVER_ERROR, e))
+}
+
+/// The answer to one line of input, `None` for a notification
+pub fn handle(line: &str) -> Option<String> {
+    let answer = |id: Value, outcome: Result<Value, Error>| {
+        let answer = match outcome {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": e.code, "message": e.message},
+            }),
+        };
+        Some(answer.to_string())
+    };
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => return answer(Value::Null, Err(Error::new(PARSE_ERROR, e))),
+    };
+    let id = value.get("id").cloned().unwrap_or(Value::Null);
+    let request = match serde_json::from_value::<Request>(value) {
+        Ok(request) if request.jsonrpc == "2.0" => request,
+        Ok(_) => return answer(id, Err(Error::new(INVALID_REQUEST, "Not JSON-RPC 2.0"))),
+        Err(e) => return answer(id, Err(Error::new(INVALID_REQUEST, e))),
+    };
+    let outcome = call(&request.method, request.params);
+    match request.id {
+        Some(id) => answer(id, outcome),
+        None => None,
+    }
+}
+
+/// Answers requests from `input` on `output` until the input ends
+pub fn run(input: impl BufRead, mut output: impl Write) -> Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(answer) = handle(&line) {
+            writeln!(output, "{}", answer)?;
+            output.flush()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use std::{fmt, path::PathBuf};
+
+// Session
+//
+// What was done while the TUI was open - the images looked at and the copies
+// written - so the work doesn't vanish along with the interface. The summary is
+// printed to the terminal once it's back to normal.
+
+#[derive(Debug, Default)]
+pub struct Session {
+    /// Images opened, in order
+    pub inspected: Vec<PathBuf>,
+    /// Copies written, with the number of fields changed in each
+    pub copies: Vec<(PathBuf, usize)>,
+    /// Changed fields that weren't in any copy when the session ended
+    pub unsaved: usize,
+}
+
+fn plural(count: usize, one: &str, many: &str) -> String {
+    format!("{} {}", count, if count == 1 { one } else { many })
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Inspected {}",
+            plural(self.inspected.len(), "image", "images")
+        )?;
+        if self.copies.is_empty() {
+            writeln!(f, "No copies written")?;
+        } else {
+            writeln!(f, "Wrote {}", plural(self.copies.len(), "copy", "copies"))?;
+            for (copy, changed) in &self.copies {
+                writeln!(
+                    f,
+                    "  {} ({} changed)",
+                    copy.display(),
+                    plural(*changed, "field", "fields")
+                )?;
+            }
+        }
+        if self.unsaved > 0 {
+            writeln!(
+                f,
+                "{} not saved",
+                plural(self.unsaved, "changed field was", "changed fields were")
+            )?;
+        }
+        Ok(())
+    }
+}
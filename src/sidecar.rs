@@ -0,0 +1,117 @@
+use anyhow::{Context as _, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// Sidecar
+//
+// Things bresson remembers about an image that aren't metadata of the image,
+// like notes taken while auditing a set of photos and whether it was flagged
+// to be kept, scrubbed or looked at again. They're kept in a TOML file
+// next to it, `<image>.bresson.toml`, so the image itself is never touched and
+// the notes travel with it when the folder is copied.
+//
+//     flag = "review"
+//
+//     [[notes]]
+//     time = "2024-03-02 14:05"
+//     text = "Street sign in the background gives the location away"
+//
+// A sidecar is only written once there is something in it, and removed again
+// when there's nothing left.
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sidecar {
+    pub flag: Option<Flag>,
+    pub notes: Vec<Note>,
+}
+
+/// What to do with an image, decided while going through a set of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Flag {
+    Keep,
+    Scrub,
+    Review,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 3] = [Flag::Keep, Flag::Scrub, Flag::Review];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Flag::Keep => "keep",
+            Flag::Scrub => "scrub",
+            Flag::Review => "review",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    /// The flag after `flag` when cycling through them, ending up unflagged
+    pub fn cycle(flag: Option<Flag>) -> Option<Flag> {
+        match flag {
+            None => Some(Flag::Keep),
+            Some(Flag::Keep) => Some(Flag::Scrub),
+            Some(Flag::Scrub) => Some(Flag::Review),
+            Some(Flag::Review) => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Note {
+    /// Local time the note was taken, e.g. "2024-03-02 14:05"
+    pub time: String,
+    pub text: String,
+}
+
+impl Note {
+    pub fn new(text: &str) -> Self {
+        Self {
+            time: Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            text: text.trim().to_owned(),
+        }
+    }
+}
+
+impl Sidecar {
+    /// Where the sidecar of `image` is kept
+    pub fn path(image: &Path) -> PathBuf {
+        let mut name = image.file_name().unwrap_or_default().to_os_string();
+        name.push(".bresson.toml");
+        image.with_file_name(name)
+    }
+
+    /// Reads the sidecar of `image`, which is empty if there isn't one
+    pub fn open(image: &Path) -> Result<Self> {
+        let path = Self::path(image);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let data = std::fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read sidecar {:?}", path))?;
+        toml::from_str(&data).with_context(|| format!("Invalid sidecar {:?}", path))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.flag.is_none() && self.notes.is_empty()
+    }
+
+    /// Writes the sidecar of `image`, or removes it if there's nothing to keep
+    pub fn save(&self, image: &Path) -> Result<()> {
+        let path = Self::path(image);
+        if self.is_empty() {
+            if path.is_file() {
+                std::fs::remove_file(&path)?;
+            }
+            return Ok(());
+        }
+        std::fs::write(&path, toml::to_string(self)?)
+            .with_context(|| format!("Unable to write sidecar {:?}", path))
+    }
+}
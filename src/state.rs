@@ -1,30 +1,64 @@
-use anyhow::Result;
-use chrono::{DateTime, Utc};
+use anyhow::{bail, Result};
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
 use core::f32;
-use exif::{experimental::Writer, Exif, Field, In, Rational, Reader, SRational, Tag, Value};
+use exif::{Context, Field, In, Rational, SRational, Tag, Value};
 use ratatui::{
     layout::Rect,
     style::{Style, Stylize},
+    text::{Line, Span},
     widgets::{Cell, Row},
 };
-use ratatui_image::{picker::Picker, protocol::StatefulProtocol, thread::ThreadProtocol, Resize};
+use ratatui_image::picker::Picker;
+use serde::{Deserialize, Serialize};
 use std::{
+    cell::RefCell,
     collections::{HashMap, VecDeque},
     fmt::Display,
-    io::{self, Read, Write},
+    ops::{Deref, DerefMut},
     path::{Path, PathBuf},
-    sync::mpsc::Sender,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
 };
 
 use crate::{
+    almanac,
+    backend::{self, ExifBackend, MetadataBackend},
+    editor::{Editor, Target},
+    elevation::Profile,
     globe::*,
+    image::{embedded_thumbnail, split_view, Background, ResizeRequest, ThreadProtocol},
+    jpeg,
     order::{self, OrderedTags},
-    randomize::RandomMetadata,
+    osm,
+    perf::PerfCounters,
+    policy::{Policy, Rule},
+    randomize::{self, RandomMetadata, Scene},
+    save::{Destination, SaveEvent, SaveJob, SaveRequest},
+    session::Session,
+    sidecar::{Flag, Note, Sidecar},
+    stego,
+    sun::SolarPosition,
+    tui::Hyperlink,
     utils,
+    widget::GlobeState,
+    writer::{self, ByteOrder, Oversized},
 };
 
 pub type ExifTags = Vec<Field>;
 
+/// Font size in pixels assumed when the terminal doesn't tell
+const FALLBACK_FONT_SIZE: (u16, u16) = (8, 16);
+
+/// How far the camera turns with every step of panning, in radians
+const PAN_STEP: f32 = 0.02;
+/// Keeps the camera just short of the poles, where its orientation flips
+const MAX_PAN_BETA: f32 = 1.55;
+/// How far the cursor on the map moves with every step, in map pixels
+const MAP_CURSOR_STEP: i32 = 8;
+
 // Metadata
 //
 // Structure for defining how the metadata should be represented by Bresson
@@ -72,10 +106,25 @@ impl MetadataVal {
             Value::SRational(x) => Value::SRational(vec![SRational { num: 0, denom: 0 }; x.len()]),
             Value::Float(x) => Value::Float(vec![0.; x.len()]),
             Value::Double(x) => Value::Double(vec![0.; x.len()]),
+            // Free text behind a character code, nothing else undefined is safe to zero
+            Value::Undefined(x, offset)
+                if matches!(
+                    self.field.tag,
+                    Tag::GPSProcessingMethod | Tag::GPSAreaInformation
+                ) =>
+            {
+                Value::Undefined(vec![0; x.len()], offset)
+            }
             _ => self.field.value.clone(),
         };
     }
 
+    pub fn is_cleared(&self) -> bool {
+        let mut cleared = self.clone();
+        cleared.clear();
+        *self == cleared
+    }
+
     pub fn display_val(&self) -> String {
         self.field.value.display_as(self.field.tag).to_string()
     }
@@ -91,11 +140,107 @@ impl PartialEq for MetadataVal {
     }
 }
 
+/// Versions handed out to field maps, unique across all of them
+static NEXT_VERSION: AtomicU64 = AtomicU64::new(0);
+
+/// Fields by tag, with a version that changes whenever they may have, so that
+/// what's built from them can be kept until then. Reads go through `Deref` to
+/// the map, every mutable borrow takes a new version.
+#[derive(Debug, Clone, Default)]
+pub struct FieldMap {
+    fields: HashMap<Tag, MetadataVal>,
+    version: u64,
+}
+
+impl FieldMap {
+    pub fn new(fields: HashMap<Tag, MetadataVal>) -> Self {
+        Self {
+            fields,
+            version: NEXT_VERSION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// The same for maps holding the same fields, a clone keeps it until either
+    /// one changes
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl Deref for FieldMap {
+    type Target = HashMap<Tag, MetadataVal>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.fields
+    }
+}
+
+impl DerefMut for FieldMap {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.version = NEXT_VERSION.fetch_add(1, Ordering::Relaxed);
+        &mut self.fields
+    }
+}
+
+/// Rows of the table built from the fields, and what they were built from
+struct RowCache {
+    modified: u64,
+    original: u64,
+    show_internal: bool,
+    query: String,
+    rows: Vec<FieldRow>,
+    hidden: usize,
+}
+
+/// The text of a field's row, which the table borrows every frame
+struct FieldRow {
+    name: String,
+    value: String,
+    /// The original value of a changed field, in parentheses
+    original: Option<String>,
+    len: String,
+    changed: bool,
+    internal: bool,
+}
+
+impl FieldRow {
+    fn row(&self) -> Row<'_> {
+        let style = match self.changed {
+            true => Style::new().red().italic(),
+            false => Style::default(),
+        };
+        let value = match &self.original {
+            Some(original) => Line::from(vec![
+                Span::styled(self.value.as_str(), style),
+                Span::styled(original.as_str(), Style::new().dim()),
+            ]),
+            None => Line::styled(self.value.as_str(), style),
+        };
+        let cells = [
+            Cell::from(self.name.as_str()),
+            Cell::from(value),
+            Cell::from(Line::from(self.len.as_str()).right_aligned()),
+        ];
+        match self.internal {
+            true => Row::new(cells.into_iter().map(|c| c.dim())),
+            false => Row::new(cells),
+        }
+    }
+}
+
 pub enum Operation {
     Randomize((Field, Field)),
     Clear((Field, Field)),
+    /// A field set by hand, from and to
+    Edit((Field, Field)),
     RandomizeAll,
     ClearAll,
+    ClearGps(Vec<Field>),
+    /// Fields that were randomized together, e.g. every GPS field, as they were
+    RandomizeTogether(Vec<Field>),
+    /// Fields before a location or scene was set, `None` for ones that didn't exist
+    SetFields(String, Vec<(Tag, Option<Field>)>),
+    ApplyPolicy,
 }
 
 // Step one is taking a given image file and read out some of the super basic metadata about it
@@ -104,9 +249,11 @@ pub enum Operation {
 pub enum RenderState {
     Thumbnail,
     Globe,
+    /// OpenStreetMap of the image location, only with `--online`
+    Map,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum Cardinal {
     North,
     East,
@@ -129,7 +276,8 @@ impl Display for Cardinal {
     }
 }
 
-#[derive(Debug)]
+/// Location of an image, as degrees north or south and east or west
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct GPSInfo {
     latitude: f32,
     lat_direction: Cardinal,
@@ -137,6 +285,60 @@ pub struct GPSInfo {
     long_direction: Cardinal,
 }
 
+impl GPSInfo {
+    /// From latitude and longitude in signed degrees, north and east positive.
+    /// Latitudes past the poles are taken as the pole, longitudes wrap around.
+    pub fn from_decimal(lat: f64, lon: f64) -> Self {
+        let lat = lat.clamp(-90., 90.);
+        let lon = (lon + 180.).rem_euclid(360.) - 180.;
+        Self {
+            latitude: lat.abs() as f32,
+            lat_direction: if lat < 0. {
+                Cardinal::South
+            } else {
+                Cardinal::North
+            },
+            longitude: lon.abs() as f32,
+            long_direction: if lon < 0. {
+                Cardinal::West
+            } else {
+                Cardinal::East
+            },
+        }
+    }
+
+    /// Latitude and longitude in signed degrees, north and east are positive
+    pub fn as_decimal(&self) -> (f64, f64) {
+        let lat = match self.lat_direction {
+            Cardinal::South => -self.latitude,
+            _ => self.latitude,
+        };
+        let lon = match self.long_direction {
+            Cardinal::West => -self.longitude,
+            _ => self.longitude,
+        };
+        (lat as f64, lon as f64)
+    }
+
+    /// Degrees from the equator, north or south as `lat_direction` says
+    pub fn latitude(&self) -> f32 {
+        self.latitude
+    }
+
+    pub fn lat_direction(&self) -> Cardinal {
+        self.lat_direction
+    }
+
+    /// Degrees from the prime meridian, east or west as `long_direction` says
+    pub fn longitude(&self) -> f32 {
+        self.longitude
+    }
+
+    pub fn long_direction(&self) -> Cardinal {
+        self.long_direction
+    }
+}
+
 impl Default for GPSInfo {
     fn default() -> Self {
         Self {
@@ -179,67 +381,165 @@ pub struct CameraSettings {
     alpha: f32, // Rotation along xy-axis
     beta: f32,  // Rotation along z-axis
     pub globe_rot_speed: f32,
-    pub cam_rot_speed: f32,
 }
 
 impl Default for CameraSettings {
     fn default() -> Self {
         Self {
-            zoom: 1.5,
+            zoom: 1.45,
             alpha: 0.,
             beta: 0.,
             globe_rot_speed: 0.0005,
-            cam_rot_speed: 0.0005,
         }
     }
 }
 
-pub struct Application {
+/// Changes to the application, one per user action
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Message {
+    Randomize(usize),
+    RandomizeAll,
+    Clear(usize),
+    ClearAll,
+    ClearGps,
+    /// Moves the image to the point under the crosshair of the globe, or under
+    /// the cursor on the map while picking a location
+    SetLocationHere,
+    /// Starts or stops picking a location with the cursor
+    PickLocation,
+    /// Opens an editor for the field in the row, if it can be edited by hand
+    Edit(usize),
+    /// Highlights the choice above or below in the editor
+    PreviousChoice,
+    NextChoice,
+    /// Types a character into the editor or the search, or deletes the last one
+    Type(char),
+    DeleteChar,
+    /// Sets the field to the highlighted choice or typed value, or adds the
+    /// typed note, and closes the editor
+    ConfirmEdit,
+    /// Opens the editor for a new note about the image
+    AddNote,
+    /// Flags the image to keep, scrub or review, or unflags it
+    CycleFlag,
+    RemoveNote,
+    CloseEditor,
+    /// Applies the next scene preset
+    ApplyScene,
+    /// Turns the image a quarter clockwise
+    Rotate,
+    /// Mirrors the image
+    Mirror,
+    ApplyPolicy,
+    Undo,
+    /// Undo every change at once
+    Restore,
+    Save,
+    /// Saves over the original instead of writing a copy
+    SaveInPlace,
+    /// Stop a save running in the background, removing the partial copy
+    CancelSave,
+    ToggleGlobe,
+    /// Shows or hides the stars and atmosphere around the globe
+    ToggleStarfield,
+    ToggleRenderState,
+    ToggleRotate,
+    ToggleKeybinds,
+    /// Charts the altitude of the open images over time, or closes the chart
+    ToggleElevation,
+    /// Expands or collapses the internal fields at the end of the table
+    ToggleInternal,
+    /// Shows or hides frame times and events per second
+    ToggleDebug,
+    /// Converts saved copies to the other byte order, or stops converting them
+    ToggleByteOrder,
+    /// Writes the metadata table as Markdown next to the image
+    ExportMarkdown,
+    /// Starts or stops comparing the image with its embedded thumbnail
+    Compare,
+    /// Moves the line between the image and the embedded thumbnail, in percent
+    /// of the width
+    MoveDivider(i8),
+    /// Switches to the next image, or the previous one, remembering `row` as
+    /// the one selected in the image switched away from
+    SwitchImage {
+        forward: bool,
+        row: Option<usize>,
+    },
+    /// Starts typing a search that filters the table, or changing the last one
+    Search,
+    /// Stops typing the search, the table stays filtered
+    ConfirmSearch,
+    /// Lists every field again, keeping the field in `row` of the filtered
+    /// table selected
+    ClearSearch {
+        row: Option<usize>,
+    },
+    /// Answers the question before a bulk operation, going ahead with it or not
+    Answer(bool),
+    ZoomIn,
+    ZoomOut,
+    /// Turns the globe by hand, so a different spot is under the crosshair. Moves
+    /// the cursor instead while picking a location on the map.
+    Pan(Cardinal),
+    /// Points the camera back at the image location
+    StopPanning,
+    FasterRotation,
+    SlowerRotation,
+}
+
+// Document
+//
+// The metadata of one image and everything done to it, free of any terminal
+// state so it can be edited headlessly, e.g. by the CLI. Every edit returns the
+// message to show in the status bar instead of showing it itself.
+
+pub struct Document {
     pub path_to_image: PathBuf,
-    pub exif: Exif,
-    pub original_fields: HashMap<Tag, MetadataVal>,
-    pub modified_fields: HashMap<Tag, MetadataVal>,
+    pub backend: Arc<dyn MetadataBackend>,
+    pub original_fields: FieldMap,
+    pub modified_fields: FieldMap,
     pub randomizer: RandomMetadata,
     pub ordered_tags: OrderedTags,
     ring_buffer: VecDeque<Operation>,
 
-    pub async_state: ThreadProtocol,
-    pub render_state: RenderState,
-
-    pub status_msg: String,
-
-    pub globe: Globe,
     pub has_gps: bool,
+    /// Version of the fields the location was last worked out from
+    gps_version: Option<u64>,
     pub gps_info: GPSInfo,
-
-    pub camera_settings: CameraSettings,
-    pub show_keybinds: bool,
-    pub should_rotate: bool,
-    pub show_mini: bool,
+    pub captured: Option<DateTime<FixedOffset>>,
+    pub solar: Option<SolarPosition>,
+
+    pub vault_passphrase: Option<String>,
+    pub policy: Option<Policy>,
+    /// Notes about the image, kept next to it rather than in it
+    pub sidecar: Sidecar,
+    /// Places in the file data may hide in, outside the metadata
+    pub extra_data: Vec<stego::Finding>,
+    /// Byte order copies are converted to, instead of the one the file has
+    pub byte_order: Option<ByteOrder>,
+    /// Keep the original as `<name>.bak` when saving over it
+    pub backup: bool,
+    /// What saving does when the Exif data outgrows its JPEG segment
+    pub oversized: Oversized,
+    /// JPEGs keep the Exif data in a single segment, which can't hold more
+    is_jpeg: bool,
+    /// Size of the Exif segment the modified fields make, and the version of
+    /// the fields it was worked out for
+    segment_cache: RefCell<Option<(u64, Option<usize>)>>,
+    /// Rows of the fields as last drawn, built again once they change
+    row_cache: Option<RowCache>,
 }
 
-impl Application {
-    pub fn new(
-        path_to_image: &Path,
-        g: Globe,
-        tx_worker: Sender<(Box<dyn StatefulProtocol>, Resize, Rect)>,
-    ) -> Result<Self> {
-        let file = std::fs::File::open(path_to_image)?;
-
-        let mut bufreader = std::io::BufReader::new(&file);
-        let exifreader = Reader::new();
-        let exif = exifreader.read_from_container(&mut bufreader)?;
+impl Document {
+    pub fn open(path_to_image: &Path) -> Result<Self> {
+        let file = std::fs::read(path_to_image)?;
+        let backend = backend::parse(&file)?;
         let mut has_gps = false;
-        let dyn_img = image::DynamicImage::from(image::open(path_to_image)?);
-
-        // If the picker doesn't work, we should do something to fail over safely
-        let mut picker = Picker::from_termios().unwrap();
-        picker.guess_protocol();
-        picker.background_color = Some(image::Rgb::<u8>([255, 0, 255]));
 
         let mut exif_data_map = HashMap::new();
         let ordered_tags = OrderedTags::new();
-        for f in exif.fields() {
+        for f in backend.fields() {
             if f.tag == Tag::GPSLatitude || f.tag == Tag::GPSLongitude {
                 has_gps = true;
             }
@@ -247,387 +547,998 @@ impl Application {
                 exif_data_map.insert(
                     f.tag,
                     MetadataVal {
-                        field: f.clone(),
+                        field: f,
                         changed: false,
                     },
                 );
             }
         }
 
-        let gps_info = GPSInfo::default();
-
         Ok(Self {
             path_to_image: path_to_image.to_path_buf(),
-            exif,
-            original_fields: exif_data_map.clone(),
-            modified_fields: exif_data_map.clone(),
+            backend,
+            original_fields: FieldMap::new(exif_data_map.clone()),
+            modified_fields: FieldMap::new(exif_data_map),
             ordered_tags,
             ring_buffer: VecDeque::with_capacity(50),
             randomizer: RandomMetadata::default(),
-            async_state: ThreadProtocol::new(tx_worker, picker.new_resize_protocol(dyn_img)),
-            render_state: RenderState::Globe,
-            status_msg: String::new(),
-            globe: g,
             has_gps,
-            gps_info,
-            camera_settings: CameraSettings::default(),
-            show_keybinds: false,
-            should_rotate: false || !has_gps,
-            show_mini: true,
+            gps_info: GPSInfo::default(),
+            gps_version: None,
+            captured: None,
+            solar: None,
+            vault_passphrase: None,
+            policy: None,
+            sidecar: Sidecar::open(path_to_image)?,
+            extra_data: stego::scan(&file),
+            byte_order: None,
+            backup: false,
+            oversized: Oversized::Refuse,
+            is_jpeg: jpeg::is_jpeg(&file),
+            segment_cache: RefCell::new(None),
+            row_cache: None,
         })
     }
 
-    pub fn keybind_rows(&self) -> Vec<Row> {
-        Vec::from([
-            Row::new(vec!["r", "Randomize selected Metadata"]),
-            Row::new(vec!["R", "Randomize all Metadata"]),
-            Row::new(vec!["c", "Clear selected Metadata"]),
-            Row::new(vec!["C", "Clear all Metadata"]),
-            Row::new(vec!["u", "Undo change"]),
-            Row::new(vec!["U", "Undo all changes \\ Restore"]),
-            Row::new(vec!["s | S", "Save a Copy"]),
-            Row::new(vec!["t | T", "Toggle Thumbnail or Globe"]),
-            Row::new(vec!["g | G", "Toggle Globe Visibility"]),
-            Row::new(vec!["<Spc>", "Toggle Globe Rotation"]),
-            Row::new(vec!["?", "Show/Dismiss Keybind Info"]),
-            Row::new(vec!["q | <Esc>", "Quit"]),
-        ])
+    /// Edits that can still be undone, oldest first
+    pub fn operations(&self) -> impl Iterator<Item = &Operation> {
+        self.ring_buffer.iter()
     }
 
     fn tag_desc(&self, f: &Field) -> String {
         f.tag
             .description()
-            .unwrap_or(&f.tag.to_string())
-            .to_string()
+            .map_or_else(|| utils::tag_name(f.tag), str::to_owned)
     }
 
-    pub fn process_rows(&self, _term_width: u16) -> Vec<Row> {
-        let mut exif_data_rows = Vec::new();
-        for (_idx, t) in order::EXIF_FIELDS_ORDERED.iter().enumerate() {
-            if let Some(m) = self.modified_fields.get(t) {
-                let f = &m.field;
-                let f_val = f.tag.to_string();
-                if f_val.len() > 0 {
-                    let data_row = vec![
-                        Cell::from(self.tag_desc(f)),
-                        Cell::from(match &f.value {
-                            Value::Ascii(x) => {
-                                if x.iter().all(|x| x.len() > 0) {
-                                    utils::clean_disp(
-                                        &f.display_value().with_unit(&self.exif).to_string(),
-                                    )
-                                } else {
-                                    String::from("")
-                                }
-                            }
-                            _ => match *t {
-                                Tag::GPSLatitude => {
-                                    format!(
-                                        "{} {}",
-                                        utils::clean_disp(&f.display_value().to_string()),
-                                        &f.display_value()
-                                    )
-                                }
-                                Tag::GPSLongitude => {
-                                    format!(
-                                        "{} {}",
-                                        utils::clean_disp(&f.display_value().to_string()),
-                                        &f.display_value()
-                                    )
-                                }
-                                _ => utils::clean_disp(
-                                    &f.display_value().with_unit(&self.exif).to_string(),
-                                ),
-                            },
-                        })
-                        .style(match m.changed {
-                            true => Style::new().red().italic(),
-                            false => Style::default(),
-                        }),
-                    ];
-                    exif_data_rows.push(data_row);
+    /// Value of `f` as shown in the table
+    fn display(&self, f: &Field) -> String {
+        match &f.value {
+            Value::Ascii(x) => {
+                if x.iter().all(|x| !x.is_empty()) {
+                    self.backend.display(f)
+                } else {
+                    String::from("")
                 }
             }
+            _ => match f.tag {
+                Tag::GPSLatitude | Tag::GPSLongitude => {
+                    format!(
+                        "{} {}",
+                        utils::clean_disp(&f.display_value().to_string()),
+                        &f.display_value()
+                    )
+                }
+                _ => self.backend.display(f),
+            },
         }
-
-        exif_data_rows
-            .iter()
-            .map(|data| {
-                // let mut height = 1;
-                // let total_length: usize = data.iter().map(|d| d..len()).sum();
-                // if total_length as u16 >= term_width {
-                //     height += 1
-                // };
-                Row::new(data.clone())
-                // let tag = data.get(0).unwrap().clone();
-                // let mut val = data.get(1).unwrap().chars();
-                // let sub_string = (0..)
-                //     .map(|_| val.by_ref().take(term_width as usize).collect::<String>())
-                //     .take_while(|s| !s.is_empty())
-                //     .collect::<Vec<_>>()
-                //     .join("\n");
-                // let height = sub_string.chars().filter(|x| *x == '\n').count();
-                // Row::new(vec![tag, sub_string]).height(if height == 0 { 1 } else { height as u16 })
-            })
-            .collect::<Vec<Row>>()
     }
 
-    pub fn rotate_globe(&mut self) {
-        self.globe.angle += self.camera_settings.globe_rot_speed;
+    /// The metadata table as a Markdown table of the original and modified
+    /// values, for pasting into issues and documentation
+    pub fn markdown(&self) -> String {
+        let cell = |text: String| text.replace('|', "\\|").replace('\n', " ");
+        let mut table = String::from("| Tag | Original | Modified |\n|---|---|---|\n");
+        for t in order::EXIF_FIELDS_ORDERED.iter() {
+            let (Some(original), Some(modified)) =
+                (self.original_fields.get(t), self.modified_fields.get(t))
+            else {
+                continue;
+            };
+            let name = cell(self.tag_desc(&original.field));
+            let original = cell(self.display(&original.field));
+            let modified = cell(self.display(&modified.field));
+            // Changed values stand out when the table is rendered
+            let modified = if original == modified {
+                modified
+            } else if modified.is_empty() {
+                String::from("*cleared*")
+            } else {
+                format!("**{}**", modified)
+            };
+            table.push_str(&format!("| {} | {} | {} |\n", name, original, modified));
+        }
+        table
+    }
 
-        self.camera_settings.alpha +=
-            self.camera_settings.cam_rot_speed + (self.camera_settings.globe_rot_speed / 2.);
+    /// Writes the Markdown table next to the image, returning the status message
+    pub fn export_markdown(&self) -> String {
+        let mut name = self
+            .path_to_image
+            .file_name()
+            .unwrap_or_default()
+            .to_os_string();
+        name.push(".md");
+        let path = self.path_to_image.with_file_name(name);
+        match std::fs::write(&path, self.markdown()) {
+            Ok(()) => format!("Wrote the metadata table to {}", path.display()),
+            Err(e) => format!("Unable to write {}: {}", path.display(), e),
+        }
     }
 
-    pub fn toggle_globe(&mut self) {
-        self.show_mini = !self.show_mini
+    /// Bytes of the Exif segment a copy with the modified fields would have, and
+    /// the most a segment holds. `None` for images other than JPEGs, which
+    /// have no such limit.
+    pub fn segment_budget(&self) -> Option<(usize, usize)> {
+        if !self.is_jpeg {
+            return None;
+        }
+        let version = self.modified_fields.version();
+        let mut cache = self.segment_cache.borrow_mut();
+        let len = match *cache {
+            Some((cached, len)) if cached == version => len,
+            _ => {
+                let mut fields = self.modified_fields.values().map(|m| &m.field);
+                let len = self
+                    .backend
+                    .as_exif()
+                    .and_then(|exif| exif.encoded_len(&mut fields))
+                    .map(|tiff| jpeg::EXIF_HEADER.len() + tiff);
+                *cache = Some((version, len));
+                len
+            }
+        };
+        len.map(|len| (len, jpeg::MAX_SEGMENT_PAYLOAD))
     }
 
-    pub fn camera_zoom_increase(&mut self) {
-        self.camera_settings.zoom -= 0.01;
-        self.globe.camera.update(
-            self.camera_settings.zoom,
-            self.camera_settings.alpha,
-            self.camera_settings.beta,
-        );
+    /// Whether the name, description or value of `m` contains `query`, ignoring
+    /// case
+    fn matches(&self, m: &MetadataVal, query: &str) -> bool {
+        let query = query.to_lowercase();
+        [
+            utils::tag_name(m.field.tag),
+            self.tag_desc(&m.field),
+            self.display(&m.field),
+        ]
+        .iter()
+        .any(|text| text.to_lowercase().contains(&query))
     }
 
-    pub fn camera_zoom_decrease(&mut self) {
-        self.camera_settings.zoom += 0.01;
-        self.globe.camera.update(
-            self.camera_settings.zoom,
-            self.camera_settings.alpha,
-            self.camera_settings.beta,
-        );
+    /// Tags of the fields in the table, in its order, that match `query`. The
+    /// internal ones are left out unless `show_internal`.
+    pub fn matching_tags(&self, show_internal: bool, query: &str) -> Vec<Tag> {
+        order::EXIF_FIELDS_ORDERED
+            .iter()
+            .filter(|t| show_internal || !order::is_internal(**t))
+            .filter(|t| {
+                self.modified_fields
+                    .get(t)
+                    .is_some_and(|m| query.is_empty() || self.matches(m, query))
+            })
+            .copied()
+            .collect()
     }
 
-    pub fn update_gps(&mut self) {
-        let lat: f32 = match self.modified_fields.get(&Tag::GPSLatitude) {
-            Some(l) => match l.field.value {
-                Value::Rational(ref v) if !v.is_empty() => {
-                    let lat_internals = vec![
-                        (v[0].num as f32 / v[0].denom as f32),
-                        (v[1].num as f32 / v[1].denom as f32) / 60.,
-                        (v[2].num as f32 / v[2].denom as f32) / (60. * 100.),
-                    ];
-                    lat_internals
-                        .iter()
-                        .fold(0., |sum: f32, x| if x.is_nan() { sum } else { sum + x })
-                }
-                _ => 0.,
-            },
-            None => 0.,
-        };
-        let long: f32 = match self.modified_fields.get(&Tag::GPSLongitude) {
-            Some(l) => match l.field.value {
-                Value::Rational(ref v) if !v.is_empty() => {
-                    let long_internals = vec![
-                        (v[0].num as f32 / v[0].denom as f32),
-                        (v[1].num as f32 / v[1].denom as f32) / 60.,
-                        (v[2].num as f32 / v[2].denom as f32) / (60. * 100.),
-                    ];
-                    long_internals
-                        .iter()
-                        .fold(0., |sum: f32, x| if x.is_nan() { sum } else { sum + x })
-                }
-                _ => 0.,
-            },
-            None => 0.,
-        };
-        let lat_dir = match self.modified_fields.get(&Tag::GPSLatitudeRef) {
-            Some(l) => {
-                let display_value = &l.field.display_value().to_string();
-                let str_val = display_value.as_str();
-                match str_val {
-                    "N" => Cardinal::North,
-                    "S" => Cardinal::South,
-                    _ => Cardinal::North,
-                }
+    /// Rows of the fields matching `query`, leaving out the internal ones unless
+    /// `show_internal`, and how many were left out
+    fn field_rows(&self, show_internal: bool, query: &str) -> (Vec<FieldRow>, usize) {
+        let mut rows = Vec::new();
+        let mut hidden = 0;
+        for t in order::EXIF_FIELDS_ORDERED.iter() {
+            if !show_internal && order::is_internal(*t) && self.modified_fields.contains_key(t) {
+                hidden += 1;
+                continue;
             }
-            None => Cardinal::North,
-        };
-        let long_dir = match self.modified_fields.get(&Tag::GPSLongitudeRef) {
-            Some(l) => {
-                let display_value = &l.field.display_value().to_string();
-                let str_val = display_value.as_str();
-                match str_val {
-                    "E" => Cardinal::East,
-                    "W" => Cardinal::West,
-                    _ => Cardinal::North,
-                }
+            if let Some(m) = self
+                .modified_fields
+                .get(t)
+                .filter(|m| query.is_empty() || self.matches(m, query))
+            {
+                let f = &m.field;
+                // The original value dimmed after a new or cleared one, for a
+                // quick look without the diff
+                let original = self.original_fields.get(t);
+                let original = (original != Some(m)).then(|| match original {
+                    Some(o) => format!(" ({})", self.display(&o.field)),
+                    None => String::from(" (added)"),
+                });
+                rows.push(FieldRow {
+                    name: self.tag_desc(f),
+                    value: self.display(f),
+                    original,
+                    len: writer::field_len(f).to_string(),
+                    changed: m.changed,
+                    internal: order::is_internal(*t),
+                });
             }
-            None => Cardinal::East,
-        };
+        }
+        (rows, hidden)
+    }
+
+    /// Rows of the metadata table, with the internal fields collapsed into one
+    /// unless `show_internal`. The text of the fields is kept from the last call
+    /// until a field changes and the rows borrow it, only the ones below them are
+    /// built every time. With a `query` only the fields matching it are listed.
+    pub fn process_rows(
+        &mut self,
+        _term_width: u16,
+        show_internal: bool,
+        query: &str,
+    ) -> Vec<Row<'_>> {
+        let fresh = self.row_cache.as_ref().is_some_and(|c| {
+            c.modified == self.modified_fields.version()
+                && c.original == self.original_fields.version()
+                && c.show_internal == show_internal
+                && c.query == query
+        });
+        if !fresh {
+            let (rows, hidden) = self.field_rows(show_internal, query);
+            self.row_cache = Some(RowCache {
+                modified: self.modified_fields.version(),
+                original: self.original_fields.version(),
+                show_internal,
+                query: query.to_owned(),
+                rows,
+                hidden,
+            });
+        }
+        let RowCache { rows, hidden, .. } = self.row_cache.as_ref().expect("rows were just built");
+        let mut rows: Vec<_> = rows.iter().map(FieldRow::row).collect();
+        // Nothing but the fields that were searched for
+        if !query.is_empty() {
+            return rows;
+        }
+
+        if *hidden > 0 {
+            let style = Style::new().dim().italic();
+            rows.push(Row::new([
+                Cell::from("Internal").style(style),
+                Cell::from(format!("{} fields, press i to show them", hidden)).style(style),
+            ]));
+        }
+
+        for finding in &self.extra_data {
+            rows.push(Row::new([
+                Cell::from("Extra data").style(Style::new().red()),
+                Cell::from(finding.to_string()).style(Style::new().red()),
+            ]));
+        }
+
+        // Derived rows can't be edited and are never saved
+        for (name, value) in self.derived_rows() {
+            let style = Style::new().dim().italic();
+            rows.push(Row::new([
+                Cell::from(format!("{} (derived)", name)).style(style),
+                Cell::from(value).style(style),
+            ]));
+        }
+        rows
+    }
 
+    pub fn update_gps(&mut self) {
+        let (lat, long) = utils::location(|tag| self.modified_fields.get(&tag).map(|m| &m.field))
+            .unwrap_or((0., 0.));
         if lat == 0. && long == 0. {
             self.has_gps = false
         }
+        self.gps_info = GPSInfo::from_decimal(lat, long);
+        self.update_capture();
+        self.gps_version = Some(self.modified_fields.version());
+    }
 
-        self.gps_info = GPSInfo {
-            latitude: lat,
-            lat_direction: lat_dir,
-            longitude: long,
-            long_direction: long_dir,
+    /// Works out the location and capture time again if the fields changed since
+    /// the last time, returning whether they did
+    pub fn refresh_gps(&mut self) -> bool {
+        if self.gps_version == Some(self.modified_fields.version()) {
+            return false;
         }
+        self.update_gps();
+        true
     }
 
-    pub fn transform_coordinates(&mut self) {
-        // Latitude is 0 at the equator and increases to 90N for the north pole
-        // and 90S for the South Pole
-        // Longitude is 0 at the Prime Meridian (Greenwich) and increases to 180E at the
-        // 180th Meridian
-        // Latitude is a -90 -> 90 spread
-        // Longitude is a -180 -> 180 spread
+    /// Latitude and longitude in signed degrees, north and east are positive
+    pub fn location(&self) -> (f64, f64) {
+        self.gps_info.as_decimal()
+    }
 
-        let new_longitude = match self.gps_info.long_direction {
-            Cardinal::East => self.gps_info.longitude,
-            Cardinal::West => 360. - self.gps_info.longitude, // Convert into Long East
-            _ => 0.0,
-        } / 360.;
-        let new_latitude = match self.gps_info.lat_direction {
-            Cardinal::North => self.gps_info.latitude / 90.,
-            Cardinal::South => -self.gps_info.latitude / 90.,
-            _ => 0.,
+    /// When the photo was taken, and where the sun stood at the time
+    fn update_capture(&mut self) {
+        let (lat, long) = self.location();
+        let has_gps = self.has_gps;
+        self.captured = utils::capture_time(|tag| self.modified_fields.get(&tag).map(|m| &m.field))
+            .and_then(|(local, offset)| {
+                // Without a recorded offset, guess the time zone from the longitude
+                let offset = match offset {
+                    Some(offset) => offset,
+                    None if has_gps => FixedOffset::east_opt((long / 15.).round() as i32 * 3600)?,
+                    None => FixedOffset::east_opt(0)?,
+                };
+                offset.from_local_datetime(&local).single()
+            });
+        self.solar = match self.captured {
+            Some(time) if self.has_gps => {
+                Some(SolarPosition::at(time.with_timezone(&Utc), lat, long))
+            }
+            _ => None,
         };
-        self.camera_settings = CameraSettings {
-            zoom: 1.45,
-            alpha: new_longitude,
-            beta: new_latitude,
-            globe_rot_speed: 0.0005,
-            cam_rot_speed: 0.0005,
+    }
+
+    /// Facts computed from the capture time and location, shown below the metadata
+    pub fn derived_rows(&self) -> Vec<(&'static str, String)> {
+        let mut rows = Vec::new();
+        let Some(captured) = self.captured else {
+            return rows;
         };
+        rows.push(("Day of Week", captured.format("%A").to_string()));
+        let (phase, illumination) = almanac::moon_phase(captured.with_timezone(&Utc));
+        rows.push((
+            "Moon Phase",
+            format!("{} ({:.0}% lit)", phase, illumination * 100.),
+        ));
+        if self.has_gps {
+            let southern = matches!(self.gps_info.lat_direction, Cardinal::South);
+            rows.push((
+                "Season",
+                format!(
+                    "{} ({} hemisphere)",
+                    almanac::season(captured.date_naive(), southern),
+                    if southern { "southern" } else { "northern" }
+                ),
+            ));
+        }
+        rows
+    }
 
-        self.globe.camera.update(1.45, new_longitude, new_latitude);
+    /// Positions in the field order of the fields the image has, leaving out
+    /// the internal ones, for the edits that go over all of them
+    fn bulk_indexes(&self) -> Vec<usize> {
+        order::EXIF_FIELDS_ORDERED
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| !order::is_internal(**t) && self.modified_fields.contains_key(*t))
+            .map(|(i, _)| i)
+            .collect()
     }
 
-    pub fn randomize_all(&mut self) {
-        for i in 0..self.modified_fields.len() {
+    pub fn randomize_all(&mut self) -> String {
+        for i in self.bulk_indexes() {
             self.randomize(i, true);
         }
         self.ring_buffer.push_back(Operation::RandomizeAll);
+        String::from("Randomized all")
     }
 
-    pub fn randomize(&mut self, index: usize, all: bool) {
-        let tag_at_index = order::EXIF_FIELDS_ORDERED.get(index).unwrap();
-        if let Some(field_in_map) = self.modified_fields.get_mut(&tag_at_index) {
-            field_in_map.changed = true;
-            match *tag_at_index {
-                Tag::DateTimeOriginal | Tag::DateTime | Tag::DateTimeDigitized => {
-                    let new_dt = self.randomizer.randomize_datetime();
-                    self.sync_date_fields(new_dt);
-                    self.show_message(String::from("Randomized DateTime"));
-                }
-                Tag::GPSLatitude | Tag::GPSLatitudeRef => self.sync_latitude(),
-                Tag::GPSLongitude | Tag::GPSLongitudeRef => self.sync_longitude(),
-                _ => {
-                    if let Some(v) = self.randomizer.randomize_tag(*tag_at_index) {
-                        let old_field = field_in_map.field.clone();
-                        field_in_map.field.value = v.clone();
-                        if !all {
-                            self.ring_buffer.push_back(Operation::Randomize((
-                                old_field,
-                                field_in_map.field.clone(),
-                            )))
-                        };
-                        self.show_message(format!("Randomized {}", tag_at_index.to_string()));
-                    } else {
-                        field_in_map.changed = false;
-                        self.show_message(format!("Cannot randomize {}", tag_at_index.to_string()));
-                    }
+    pub fn randomize(&mut self, index: usize, all: bool) -> String {
+        let Some(tag_at_index) = order::EXIF_FIELDS_ORDERED.get(index) else {
+            return String::from("Nothing to edit");
+        };
+        if !self.backend.is_editable(*tag_at_index) {
+            return format!("Cannot edit {}", utils::tag_name(*tag_at_index));
+        }
+        match self.policy_rule(*tag_at_index) {
+            Some(Rule::Keep) => return format!("Policy keeps {}", utils::tag_name(*tag_at_index)),
+            Some(Rule::Forbid) => {
+                return format!(
+                    "Policy forbids {}, clear it",
+                    utils::tag_name(*tag_at_index)
+                )
+            }
+            _ => {}
+        }
+        // Any GPS field randomizes the whole location, so it takes the coordinates
+        let randomized = |tag| self.randomizer.tags_to_randomize.contains(&tag);
+        let allowed = if tag_at_index.context() == Context::Gps {
+            randomized(Tag::GPSLatitude) && randomized(Tag::GPSLongitude)
+        } else {
+            randomized(*tag_at_index)
+        };
+        if !allowed {
+            return format!("Cannot randomize {}", utils::tag_name(*tag_at_index));
+        }
+        let Some(field_in_map) = self.modified_fields.get_mut(tag_at_index) else {
+            return format!("No {} to randomize", utils::tag_name(*tag_at_index));
+        };
+        field_in_map.changed = true;
+        match *tag_at_index {
+            Tag::DateTimeOriginal
+            | Tag::DateTime
+            | Tag::DateTimeDigitized
+            | Tag::SubSecTime
+            | Tag::SubSecTimeOriginal
+            | Tag::SubSecTimeDigitized => {
+                let new_dt = self.randomizer.randomize_datetime();
+                self.sync_date_fields(new_dt);
+                String::from("Randomized DateTime")
+            }
+            tag if tag.context() == Context::Gps => self.randomize_gps(all),
+            Tag::Make | Tag::Model => {
+                let values = self.randomizer.random_camera();
+                self.randomize_together(&values, all)
+            }
+            // Landscapes are shot without flash, whether the camera was set to them
+            // or they were recognized afterwards
+            Tag::Flash => {
+                let is = |tag, value| {
+                    self.modified_fields
+                        .get(&tag)
+                        .and_then(|m| m.field.value.get_uint(0))
+                        == Some(value)
+                };
+                let landscape = is(Tag::ExposureProgram, 8) || is(Tag::SceneCaptureType, 1);
+                let value = self.randomizer.random_flash(landscape);
+                self.randomize_together(&[(Tag::Flash, value)], all)
+            }
+            // The speeds a camera can use depend on the model, randomized or not
+            Tag::PhotographicSensitivity => {
+                let model = self
+                    .modified_fields
+                    .get(&Tag::Model)
+                    .map(|m| &m.field.value);
+                let value = self.randomizer.random_iso(model);
+                self.randomize_together(&[(Tag::PhotographicSensitivity, value)], all)
+            }
+            Tag::XResolution | Tag::YResolution | Tag::ResolutionUnit => {
+                let values = self.randomizer.random_resolution();
+                self.randomize_together(&values, all)
+            }
+            Tag::PixelXDimension | Tag::PixelYDimension => {
+                let dimension = |tag| {
+                    self.modified_fields
+                        .get(&tag)
+                        .and_then(|m| m.field.value.get_uint(0))
+                };
+                let portrait = matches!(
+                    (dimension(Tag::PixelXDimension), dimension(Tag::PixelYDimension)),
+                    (Some(x), Some(y)) if x < y
+                );
+                let values = self.randomizer.random_dimensions(portrait);
+                self.randomize_together(&values, all)
+            }
+            _ => {
+                if let Some(v) = self.randomizer.randomize_tag(*tag_at_index) {
+                    let old_field = field_in_map.field.clone();
+                    field_in_map.field.value = v.clone();
+                    if !all {
+                        self.ring_buffer.push_back(Operation::Randomize((
+                            old_field,
+                            field_in_map.field.clone(),
+                        )))
+                    };
+                    format!("Randomized {}", utils::tag_name(*tag_at_index))
+                } else {
+                    field_in_map.changed = false;
+                    format!("Cannot randomize {}", utils::tag_name(*tag_at_index))
                 }
             }
         }
     }
 
-    pub fn clear_all_fields(&mut self) {
-        for i in 0..self.modified_fields.len() {
+    pub fn clear_all_fields(&mut self) -> String {
+        for i in self.bulk_indexes() {
             self.clear_field(i, true);
         }
         self.ring_buffer.push_back(Operation::ClearAll);
+        String::from("Cleared All Metadata")
     }
 
-    pub fn clear_field(&mut self, index: usize, all: bool) {
-        let tag_at_index = order::EXIF_FIELDS_ORDERED.get(index).unwrap();
-        if let Some(field_in_map) = self.modified_fields.get_mut(&tag_at_index) {
-            let old_field = field_in_map.field.clone();
-            field_in_map.clear();
-            if !all {
-                self.ring_buffer
-                    .push_back(Operation::Clear((old_field, field_in_map.field.clone())))
-            };
-            self.show_message(format!("Cleared {}", tag_at_index.to_string()));
+    pub fn clear_field(&mut self, index: usize, all: bool) -> String {
+        let Some(tag_at_index) = order::EXIF_FIELDS_ORDERED.get(index) else {
+            return String::from("Nothing to edit");
+        };
+        if !self.backend.is_editable(*tag_at_index) {
+            return format!("Cannot edit {}", utils::tag_name(*tag_at_index));
         }
+        if self.policy_rule(*tag_at_index) == Some(Rule::Keep) {
+            return format!("Policy keeps {}", utils::tag_name(*tag_at_index));
+        }
+        let Some(field_in_map) = self.modified_fields.get_mut(tag_at_index) else {
+            return format!("No {} to clear", utils::tag_name(*tag_at_index));
+        };
+        let old_field = field_in_map.field.clone();
+        field_in_map.clear();
+        if !all {
+            self.ring_buffer
+                .push_back(Operation::Clear((old_field, field_in_map.field.clone())))
+        };
+        format!("Cleared {}", utils::tag_name(*tag_at_index))
     }
 
-    fn find_index(&self, tag_to_find: &Tag) -> Option<usize> {
-        for (i, t) in order::EXIF_FIELDS_ORDERED.iter().enumerate() {
-            if t == tag_to_find {
-                return Some(i);
+    /// Sets every field in `values` the image has, for fields that only make
+    /// sense together like the horizontal and vertical resolution
+    fn randomize_together(&mut self, values: &[(Tag, Value)], all: bool) -> String {
+        let policy = self.policy.as_ref();
+        let mut old_fields = Vec::new();
+        for (tag, value) in values {
+            let Some(m) = self.modified_fields.get_mut(tag) else {
+                continue;
+            };
+            if policy.and_then(|p| p.rule_for(*tag)) == Some(Rule::Keep)
+                || !self.randomizer.tags_to_randomize.contains(tag)
+            {
+                continue;
             }
+            old_fields.push(m.field.clone());
+            // Dimensions may be either SHORT or LONG, keep whichever the image uses
+            m.field.value = match (&m.field.value, value) {
+                (Value::Short(_), Value::Long(v)) if v.iter().all(|&x| x <= u16::MAX.into()) => {
+                    Value::Short(v.iter().map(|&x| x as u16).collect())
+                }
+                _ => value.clone(),
+            };
+            m.changed = true;
         }
-        None
+        let message = format!(
+            "Randomized {}",
+            old_fields
+                .iter()
+                .map(|f| utils::tag_name(f.tag))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if !all {
+            self.ring_buffer
+                .push_back(Operation::RandomizeTogether(old_fields));
+        }
+        message
     }
 
-    pub fn undo_operation(&mut self) -> Option<usize> {
-        if let Some(op) = self.ring_buffer.pop_back() {
-            match op {
-                Operation::Randomize((old_f, new_f)) | Operation::Clear((old_f, new_f)) => {
-                    if let Some(metadata_to_modify) = self.modified_fields.get_mut(&new_f.tag) {
-                        metadata_to_modify.field = old_f.clone();
-                        let original_metadata = self.original_fields.get(&new_f.tag).unwrap();
-                        if metadata_to_modify == original_metadata {
-                            metadata_to_modify.changed = false;
-                        }
-                        let mut og_val = old_f.display_value().to_string();
-                        let new_val = new_f.display_value().to_string();
-                        if !metadata_to_modify.changed {
-                            og_val += " (original)";
-                        }
-                        self.show_message(format!(
-                            "Changed {} from {} to {}",
-                            &new_f.tag.to_string(),
-                            new_val,
-                            og_val
-                        ));
-                        self.find_index(&new_f.tag)
-                    } else {
-                        None
-                    }
-                }
-                Operation::RandomizeAll | Operation::ClearAll => {
-                    self.modified_fields = self.original_fields.clone();
-                    None
-                }
+    /// Randomizes the location along with every other GPS field, since any of
+    /// them left as it was, like the altitude or the time of the fix, narrows
+    /// down where the image was taken. Fields without a random value, or taken
+    /// out of the randomized tags, are cleared.
+    fn randomize_gps(&mut self, all: bool) -> String {
+        let old_fields = self
+            .modified_fields
+            .values()
+            .filter(|m| m.field.tag.context() == Context::Gps)
+            .map(|m| m.field.clone())
+            .collect::<Vec<_>>();
+        self.sync_latitude();
+        self.sync_longitude();
+
+        let policy = self.policy.as_ref();
+        for (&t, m) in self.modified_fields.iter_mut() {
+            let synced = matches!(
+                t,
+                Tag::GPSLatitude
+                    | Tag::GPSLatitudeRef
+                    | Tag::GPSLongitude
+                    | Tag::GPSLongitudeRef
+                    | Tag::GPSDateStamp
+                    | Tag::GPSTimeStamp
+            );
+            if t.context() != Context::Gps
+                || synced
+                || policy.and_then(|p| p.rule_for(t)) == Some(Rule::Keep)
+            {
+                continue;
             }
-        } else {
-            None
+            match self.randomizer.randomize_tag(t) {
+                Some(v) => m.field.value = v,
+                None => m.clear(),
+            }
+            m.changed = true;
+        }
+        self.sync_gps_time();
+
+        if !all {
+            self.ring_buffer
+                .push_back(Operation::RandomizeTogether(old_fields));
         }
+        String::from("Randomized GPS location")
     }
 
-    fn sync_latitude(&mut self) {
-        let (new_lat, lat_dir) = self.randomizer.random_latlong(Cardinal::North);
+    /// Clears every GPS field and leaves everything else untouched
+    pub fn clear_gps(&mut self) -> String {
+        let policy = self.policy.as_ref();
+        let mut old_fields = Vec::new();
         for (&t, m) in self.modified_fields.iter_mut() {
-            match t {
-                Tag::GPSLatitudeRef => {
-                    m.changed = true;
-                    m.field.value = Value::Ascii(vec![lat_dir.bytes().collect()])
-                }
-                Tag::GPSLatitude => {
-                    m.changed = true;
-                    m.field.value = new_lat.clone()
-                }
-                _ => {}
+            if t.context() != Context::Gps || policy.and_then(|p| p.rule_for(t)) == Some(Rule::Keep)
+            {
+                continue;
             }
+            old_fields.push(m.field.clone());
+            m.clear();
+            m.changed = true;
         }
+        if old_fields.is_empty() {
+            return String::from("No GPS data to clear");
+        }
+        let message = format!("Cleared {} GPS fields", old_fields.len());
+        self.ring_buffer.push_back(Operation::ClearGps(old_fields));
+        message
     }
 
-    fn sync_longitude(&mut self) {
-        let (new_long, long_dir) = self.randomizer.random_latlong(Cardinal::East);
-        for (&t, m) in self.modified_fields.iter_mut() {
-            match t {
-                Tag::GPSLongitudeRef => {
-                    m.changed = true;
-                    m.field.value = Value::Ascii(vec![long_dir.bytes().collect()])
+    /// Places the image at `lat` / `lon` in signed degrees, adding the GPS fields
+    /// if it didn't have any
+    pub fn set_location(&mut self, lat: f64, lon: f64) -> String {
+        let ascii = |s: &str| Value::Ascii(vec![s.as_bytes().to_vec()]);
+        let values = vec![
+            (Tag::GPSLatitudeRef, ascii(if lat < 0. { "S" } else { "N" })),
+            (Tag::GPSLatitude, utils::to_dms(lat)),
+            (
+                Tag::GPSLongitudeRef,
+                ascii(if lon < 0. { "W" } else { "E" }),
+            ),
+            (Tag::GPSLongitude, utils::to_dms(lon)),
+        ];
+        match self.set_fields("location", values) {
+            Ok(()) => format!("Set location to {:.5}, {:.5}", lat, lon),
+            Err(e) => e,
+        }
+    }
+
+    /// Makes the image look like a typical photo of `scene`, with settings and
+    /// a location that fit together
+    pub fn apply_scene(&mut self, scene: Scene) -> String {
+        let model = self
+            .modified_fields
+            .get(&Tag::Model)
+            .map(|m| &m.field.value);
+        let values = self.randomizer.random_scene(scene, model);
+        match self.set_fields("scene", values) {
+            Ok(()) => format!("Applied the {} scene", scene.name()),
+            Err(e) => e,
+        }
+    }
+
+    /// Sets `tag` to `value` picked or typed by hand
+    pub fn set_value(&mut self, tag: Tag, value: Value) -> String {
+        if !self.backend.is_editable(tag) {
+            return format!("Cannot edit {}", utils::tag_name(tag));
+        }
+        match self.policy_rule(tag) {
+            Some(Rule::Keep) => return format!("Policy keeps {}", utils::tag_name(tag)),
+            Some(Rule::Forbid) => {
+                return format!("Policy forbids {}, clear it", utils::tag_name(tag))
+            }
+            _ => {}
+        }
+        let Some(m) = self.modified_fields.get_mut(&tag) else {
+            return format!("No {} to edit", utils::tag_name(tag));
+        };
+        let old_field = m.field.clone();
+        m.field.value = value;
+        m.changed = self.original_fields.get(&tag) != Some(m);
+        let new_field = m.field.clone();
+        let message = format!(
+            "Set {} to {}",
+            utils::tag_name(tag),
+            self.backend.display(&new_field)
+        );
+        self.ring_buffer
+            .push_back(Operation::Edit((old_field, new_field)));
+        message
+    }
+
+    /// Changed fields that are needed to read the image, which may leave a
+    /// copy that doesn't open
+    pub fn changed_required(&self) -> Vec<Tag> {
+        let mut tags: Vec<Tag> = self
+            .modified_fields
+            .iter()
+            .filter(|(tag, m)| {
+                writer::is_required(**tag) && self.original_fields.get(tag) != Some(*m)
+            })
+            .map(|(tag, _)| *tag)
+            .collect();
+        tags.sort_by_key(|tag| utils::tag_name(*tag));
+        tags
+    }
+
+    /// Attaches a note to the image, written to its sidecar right away
+    pub fn add_note(&mut self, text: &str) -> String {
+        if text.trim().is_empty() {
+            return String::from("Empty note, nothing added");
+        }
+        self.sidecar.notes.push(Note::new(text));
+        match self.sidecar.save(&self.path_to_image) {
+            Ok(()) => format!("Added note {}", self.sidecar.notes.len()),
+            Err(e) => {
+                self.sidecar.notes.pop();
+                format!("{:#}", e)
+            }
+        }
+    }
+
+    /// Flags the image to be kept, scrubbed or reviewed, in turn, and then
+    /// unflags it
+    pub fn cycle_flag(&mut self) -> String {
+        let previous = self.sidecar.flag;
+        self.sidecar.flag = Flag::cycle(previous);
+        if let Err(e) = self.sidecar.save(&self.path_to_image) {
+            self.sidecar.flag = previous;
+            return format!("{:#}", e);
+        }
+        match self.sidecar.flag {
+            Some(flag) => format!("Flagged to {}", flag.name()),
+            None => String::from("Removed the flag"),
+        }
+    }
+
+    /// Removes the last note attached to the image
+    pub fn remove_last_note(&mut self) -> String {
+        let Some(note) = self.sidecar.notes.pop() else {
+            return String::from("No notes to remove");
+        };
+        match self.sidecar.save(&self.path_to_image) {
+            Ok(()) => format!("Removed note {:?}", note.text),
+            Err(e) => {
+                self.sidecar.notes.push(note);
+                format!("{:#}", e)
+            }
+        }
+    }
+
+    /// Orientation of the image as edited, 1 (upright) if it doesn't say
+    pub fn orientation(&self) -> u32 {
+        self.modified_fields
+            .get(&Tag::Orientation)
+            .and_then(|m| m.field.value.get_uint(0))
+            .filter(|o| (1..=8).contains(o))
+            .unwrap_or(1)
+    }
+
+    /// Turns the image a quarter clockwise, or mirrors it, by changing its
+    /// Orientation. The pixels stay as they are.
+    pub fn reorient(&mut self, mirror: bool) -> String {
+        let next = match (mirror, self.orientation()) {
+            (false, 1) => 6,
+            (false, 6) => 3,
+            (false, 3) => 8,
+            (false, 8) => 1,
+            (false, 2) => 7,
+            (false, 7) => 4,
+            (false, 4) => 5,
+            (false, _) => 2,
+            (true, 1) => 2,
+            (true, 2) => 1,
+            (true, 3) => 4,
+            (true, 4) => 3,
+            (true, 5) => 8,
+            (true, 8) => 5,
+            (true, 6) => 7,
+            (true, _) => 6,
+        };
+        let value = Value::Short(vec![next as u16]);
+        match self.set_fields("orientation", vec![(Tag::Orientation, value)]) {
+            Ok(()) => format!(
+                "Orientation {}, {}",
+                next,
+                utils::describe_orientation(next)
+            ),
+            Err(e) => e,
+        }
+    }
+
+    /// Sets every field in `values` as one change, adding the ones the image
+    /// doesn't have. Nothing is set if any of them can't be.
+    fn set_fields(&mut self, what: &'static str, values: Vec<(Tag, Value)>) -> Result<(), String> {
+        for (tag, _) in &values {
+            if !self.backend.is_editable(*tag) {
+                return Err(format!("Cannot edit {}", utils::tag_name(*tag)));
+            }
+            match self.policy_rule(*tag) {
+                Some(Rule::Keep) => return Err(format!("Policy keeps {}", utils::tag_name(*tag))),
+                Some(Rule::Forbid) => {
+                    return Err(format!("Policy forbids {}", utils::tag_name(*tag)))
+                }
+                _ => {}
+            }
+        }
+
+        let mut old_fields = Vec::new();
+        for (tag, value) in values {
+            let field = Field {
+                tag,
+                ifd_num: In::PRIMARY,
+                value,
+            };
+            let old = self.modified_fields.insert(
+                tag,
+                MetadataVal {
+                    field,
+                    changed: true,
+                },
+            );
+            old_fields.push((tag, old.map(|m| m.field)));
+        }
+        self.update_has_gps();
+        self.ring_buffer
+            .push_back(Operation::SetFields(what.to_owned(), old_fields));
+        Ok(())
+    }
+
+    fn policy_rule(&self, tag: Tag) -> Option<Rule> {
+        self.policy.as_ref().and_then(|p| p.rule_for(tag))
+    }
+
+    /// Clears and randomizes every field the policy asks for
+    pub fn apply_policy(&mut self) -> String {
+        if self.policy.is_none() {
+            return String::from("No policy loaded");
+        }
+        for (i, tag) in order::EXIF_FIELDS_ORDERED.iter().enumerate() {
+            let (Some(original), Some(modified)) =
+                (self.original_fields.get(tag), self.modified_fields.get(tag))
+            else {
+                continue;
+            };
+            match self.policy_rule(*tag) {
+                Some(Rule::Forbid) if !modified.is_cleared() => {
+                    self.clear_field(i, true);
+                }
+                Some(Rule::Clear) if modified == original && !modified.is_cleared() => {
+                    self.clear_field(i, true);
+                }
+                Some(Rule::Randomize) if modified == original => {
+                    self.randomize(i, true);
+                    // Fall back to clearing tags the randomizer doesn't know
+                    if self.modified_fields.get(tag) == self.original_fields.get(tag) {
+                        self.clear_field(i, true);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.ring_buffer.push_back(Operation::ApplyPolicy);
+        format!(
+            "Applied policy {}",
+            self.policy.as_ref().map_or("", |p| p.display_name())
+        )
+    }
+
+    /// Fields that would break the policy if saved as they are
+    pub fn policy_violations(&self) -> Vec<String> {
+        let mut violations = Vec::new();
+        for tag in order::EXIF_FIELDS_ORDERED.iter() {
+            let (Some(original), Some(modified)) =
+                (self.original_fields.get(tag), self.modified_fields.get(tag))
+            else {
+                continue;
+            };
+            match self.policy_rule(*tag) {
+                Some(Rule::Keep) if modified != original => {
+                    violations.push(format!("{} must be kept", utils::tag_name(*tag)))
+                }
+                Some(Rule::Forbid) if !modified.is_cleared() => {
+                    violations.push(format!("{} is forbidden", utils::tag_name(*tag)))
+                }
+                Some(Rule::Clear) if modified == original && !modified.is_cleared() => {
+                    violations.push(format!("{} must be cleared", utils::tag_name(*tag)))
+                }
+                Some(Rule::Randomize) if modified == original => {
+                    violations.push(format!("{} must be randomized", utils::tag_name(*tag)))
+                }
+                _ => {}
+            }
+        }
+        violations
+    }
+
+    fn find_index(&self, tag_to_find: &Tag) -> Option<usize> {
+        for (i, t) in order::EXIF_FIELDS_ORDERED.iter().enumerate() {
+            if t == tag_to_find {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    /// Reverts the last edit, returning the status message and the row it changed
+    pub fn undo_operation(&mut self) -> (String, Option<usize>) {
+        if let Some(op) = self.ring_buffer.pop_back() {
+            match op {
+                Operation::Randomize((old_f, new_f))
+                | Operation::Clear((old_f, new_f))
+                | Operation::Edit((old_f, new_f)) => {
+                    if let Some(metadata_to_modify) = self.modified_fields.get_mut(&new_f.tag) {
+                        metadata_to_modify.field = old_f.clone();
+                        if self.original_fields.get(&new_f.tag) == Some(metadata_to_modify) {
+                            metadata_to_modify.changed = false;
+                        }
+                        let mut og_val = utils::display_text(&old_f);
+                        let new_val = utils::display_text(&new_f);
+                        if !metadata_to_modify.changed {
+                            og_val += " (original)";
+                        }
+                        let message = format!(
+                            "Changed {} from {} to {}",
+                            utils::tag_name(new_f.tag),
+                            new_val,
+                            og_val
+                        );
+                        (message, self.find_index(&new_f.tag))
+                    } else {
+                        (String::from("Nothing to Undo!"), None)
+                    }
+                }
+                Operation::ClearGps(old_fields) => {
+                    self.restore_fields(old_fields);
+                    (String::from("Restored GPS fields"), None)
+                }
+                Operation::RandomizeTogether(old_fields) => {
+                    let message = format!(
+                        "Restored {}",
+                        old_fields
+                            .iter()
+                            .map(|f| utils::tag_name(f.tag))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                    self.restore_fields(old_fields);
+                    (message, None)
+                }
+                Operation::SetFields(what, old_fields) => {
+                    for (tag, old_f) in old_fields {
+                        match old_f {
+                            Some(old_f) => {
+                                if let Some(m) = self.modified_fields.get_mut(&tag) {
+                                    m.field = old_f;
+                                    m.changed = self.original_fields.get(&tag) != Some(m);
+                                }
+                            }
+                            None => {
+                                self.modified_fields.remove(&tag);
+                            }
+                        }
+                    }
+                    self.update_has_gps();
+                    (format!("Restored previous {}", what), None)
+                }
+                Operation::RandomizeAll | Operation::ClearAll | Operation::ApplyPolicy => {
+                    (self.restore(), None)
+                }
+            }
+        } else {
+            (String::from("Nothing to Undo!"), None)
+        }
+    }
+
+    fn restore_fields(&mut self, old_fields: Vec<Field>) {
+        for old_f in old_fields {
+            if let Some(m) = self.modified_fields.get_mut(&old_f.tag) {
+                m.field = old_f;
+                m.changed = self.original_fields.get(&m.field.tag) != Some(m);
+            }
+        }
+    }
+
+    /// Undoes every change at once
+    pub fn restore(&mut self) -> String {
+        self.modified_fields.clone_from(&self.original_fields);
+        self.update_has_gps();
+        String::from("Restored Original Data")
+    }
+
+    /// Whether there are GPS fields at all, `update_gps` rules out the ones at 0, 0
+    fn update_has_gps(&mut self) {
+        self.has_gps = self.modified_fields.contains_key(&Tag::GPSLatitude)
+            || self.modified_fields.contains_key(&Tag::GPSLongitude);
+    }
+
+    fn sync_latitude(&mut self) {
+        let (new_lat, lat_dir) = self.randomizer.random_latlong(Cardinal::North);
+        let policy = self.policy.as_ref();
+        for (&t, m) in self.modified_fields.iter_mut() {
+            if policy.and_then(|p| p.rule_for(t)) == Some(Rule::Keep) {
+                continue;
+            }
+            match t {
+                Tag::GPSLatitudeRef => {
+                    m.changed = true;
+                    m.field.value = Value::Ascii(vec![lat_dir.bytes().collect()])
+                }
+                Tag::GPSLatitude => {
+                    m.changed = true;
+                    m.field.value = new_lat.clone()
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn sync_longitude(&mut self) {
+        let (new_long, long_dir) = self.randomizer.random_latlong(Cardinal::East);
+        let policy = self.policy.as_ref();
+        for (&t, m) in self.modified_fields.iter_mut() {
+            if policy.and_then(|p| p.rule_for(t)) == Some(Rule::Keep) {
+                continue;
+            }
+            match t {
+                Tag::GPSLongitudeRef => {
+                    m.changed = true;
+                    m.field.value = Value::Ascii(vec![long_dir.bytes().collect()])
                 }
                 Tag::GPSLongitude => {
                     m.changed = true;
@@ -639,170 +1550,323 @@ impl Application {
     }
 
     fn sync_date_fields(&mut self, new_dt: String) {
+        // The old fractions of a second would stand out next to a new time
+        let subsec_digits = self.randomizer.random_subsec_digits();
+        let policy = self.policy.as_ref();
         for (&t, m) in self.modified_fields.iter_mut() {
+            if policy.and_then(|p| p.rule_for(t)) == Some(Rule::Keep) {
+                continue;
+            }
             match t {
-                Tag::DateTime | Tag::DateTimeOriginal | Tag::DateTimeDigitized => {
+                Tag::DateTime | Tag::DateTimeOriginal | Tag::DateTimeDigitized
+                    if self.randomizer.tags_to_randomize.contains(&t) =>
+                {
                     m.changed = true;
                     m.field.value = Value::Ascii(vec![Vec::from(new_dt.clone())]);
                 }
+                Tag::SubSecTime | Tag::SubSecTimeOriginal | Tag::SubSecTimeDigitized
+                    if self.randomizer.tags_to_randomize.contains(&t) =>
+                {
+                    m.changed = true;
+                    m.field.value = randomize::subsec(&subsec_digits, Some(&m.field.value));
+                }
                 _ => {}
             }
         }
+        self.sync_gps_time();
     }
 
-    fn create_copy_file_name(&self) -> Result<PathBuf> {
-        let mut copy_file_path = self.path_to_image.clone();
-        let file_stem = copy_file_path
-            .file_stem()
-            .expect("Valid File Name")
-            .to_str()
-            .unwrap();
-        let extension = copy_file_path
-            .extension()
-            .map_or("", |ext| ext.to_str().unwrap());
-        let now: DateTime<Utc> = Utc::now();
-        let formatted_timestamp = now.format("%Y%m%d%H%M%S").to_string();
+    /// Sets GPSDateStamp and GPSTimeStamp, which are in UTC, to the capture time
+    /// at the current location, so they don't give away the original time or
+    /// time zone. They're cleared if there's no capture time to go by, or if
+    /// they aren't among the randomized tags.
+    fn sync_gps_time(&mut self) {
+        self.update_gps();
+        let stamps = self
+            .captured
+            .map(|captured| randomize::gps_time_stamps(captured.naive_utc()));
+        let policy = self.policy.as_ref();
+        for (&t, m) in self.modified_fields.iter_mut() {
+            if !matches!(t, Tag::GPSDateStamp | Tag::GPSTimeStamp)
+                || policy.and_then(|p| p.rule_for(t)) == Some(Rule::Keep)
+            {
+                continue;
+            }
+            match stamps
+                .as_ref()
+                .filter(|_| self.randomizer.tags_to_randomize.contains(&t))
+                .and_then(|s| s.iter().find(|(tag, _)| *tag == t))
+            {
+                Some((_, value)) => m.field.value = value.clone(),
+                None => m.clear(),
+            }
+            m.changed = true;
+        }
+    }
 
-        let copy_file_name = format!("copy-{}-{}.{}", file_stem, formatted_timestamp, extension);
-        copy_file_path.set_file_name(copy_file_name);
+    /// What saving a copy with the modified fields takes, unless the policy
+    /// forbids saving
+    pub fn save_request(&self) -> Result<SaveRequest> {
+        self.save_request_to(Destination::Copy)
+    }
 
-        Ok(copy_file_path)
+    /// Like `save_request`, writing to `target`
+    pub fn save_request_to(&self, target: Destination) -> Result<SaveRequest> {
+        let violations = self.policy_violations();
+        if !violations.is_empty() {
+            bail!("Policy violation: {}", violations.join(", "));
+        }
+        Ok(SaveRequest {
+            path: self.path_to_image.clone(),
+            copy: match target {
+                // Named after the original, with the time it was saved
+                Destination::Copy => utils::copy_file_name(&self.path_to_image),
+                Destination::InPlace { .. } => self.path_to_image.clone(),
+            },
+            target,
+            backend: self.backend.clone(),
+            fields: self
+                .modified_fields
+                .values()
+                .map(|m| m.field.clone())
+                .collect(),
+            vault_passphrase: self.vault_passphrase.clone(),
+            byte_order: self.byte_order,
+            oversized: self.oversized,
+        })
     }
 
-    pub fn save_state(&mut self) -> Result<()> {
-        // Zero out all available tags
-        // Internals of Exif read_from_container
-        // reader.by_ref().take(4096).read_to_end(&mut buf)?;
-        // take -> creates an adapter which will read at most "limit" bytes from it
-        let exif_buf = self.exif.buf();
-        let size_of_exif_buf = exif_buf.len();
-        // eprintln!("Size of og exif buf: {}", size_of_exif_buf);
+    /// Switches the byte order copies are written in, between the one the file has
+    /// and the other one
+    pub fn toggle_byte_order(&mut self) -> String {
+        let Some(read) = self.backend.as_exif().map(ExifBackend::byte_order) else {
+            return format!("{} has no byte order", self.backend.name());
+        };
+        self.byte_order = match self.byte_order {
+            Some(_) => None,
+            None => Some(read.other()),
+        };
+        match self.byte_order {
+            Some(order) => format!("Copies will be converted to {}", order),
+            None => format!("Copies will be written {}, like the original", read),
+        }
+    }
 
-        // Write exif version to a new exif data buffer
-        let mut exif_writer = Writer::new();
-        let mut new_exif_buf = io::Cursor::new(Vec::new());
+    /// Writes a copy of the image with the modified fields, returning the status message
+    pub fn save_state(&self) -> Result<String> {
+        self.save_request()?.run(|_, _| true)
+    }
+}
 
-        // Modified fields will always have the latest modifications to the state of the
-        // Exif Metadata (including randomization and clearing)
-        for (_, m) in &self.modified_fields {
-            exif_writer.push_field(&m.field);
-        }
+// UiState
+//
+// Everything about how the document is shown - the thumbnail and map, the globe
+// and its camera, popups and the status bar.
+
+/// A search filtering the metadata table
+#[derive(Debug, Clone, Default)]
+pub struct Search {
+    pub query: String,
+    /// Typed keys go into the query rather than working on the table
+    pub typing: bool,
+}
 
-        // https://github.com/kamadak/exif-rs/blob/a8883a6597f2ba9eb8c9b1cb38bfa61a5cc67837/tests/rwrcmp.rs#L90
-        let strips = self.get_strips(In::PRIMARY);
-        let tn_strips = self.get_strips(In::THUMBNAIL);
-        let tiles = self.get_tiles(In::PRIMARY);
-        let tn_jpeg = self.get_jpeg(In::THUMBNAIL);
+pub struct UiState {
+    pub async_state: ThreadProtocol,
+    pub render_state: RenderState,
+    picker: Picker,
 
-        if let Some(ref strips) = strips {
-            exif_writer.set_strips(strips, In::PRIMARY);
-        }
-        if let Some(ref tn_strips) = tn_strips {
-            exif_writer.set_strips(tn_strips, In::THUMBNAIL);
-        }
-        if let Some(ref tiles) = tiles {
-            exif_writer.set_tiles(tiles, In::PRIMARY);
+    pub online: bool,
+    pub map_state: Option<ThreadProtocol>,
+    map_location: Option<(f64, f64)>,
+
+    pub status_msg: String,
+
+    pub globe: Globe,
+    /// Where the globe was drawn in the last frame
+    pub globe_state: GlobeState,
+    pub camera_settings: CameraSettings,
+    /// The camera points where `transform_coordinates` pointed it, it's turned
+    /// back there once panning stops
+    pub camera_on_location: bool,
+    pub show_keybinds: bool,
+    /// Altitude over time of the open images, charted over the interface
+    pub elevation: Option<Profile>,
+    pub should_rotate: bool,
+    pub show_mini: bool,
+    /// The globe was turned by hand, the camera stays put instead of following
+    /// the image location
+    pub panning: bool,
+    /// The arrow keys move the cursor, the crosshair on the globe, and `Enter`
+    /// moves the image there
+    pub picking: bool,
+    /// Whether the internal fields are listed or collapsed into one row
+    pub show_internal: bool,
+    /// Field being edited in a popup, which takes the arrow keys and `Enter`
+    pub editor: Option<Editor>,
+    /// Search the table is filtered by
+    pub search: Option<Search>,
+    /// Ask before clearing or randomizing every field
+    pub confirm_bulk: bool,
+    /// Bulk operation waiting for a yes in a popup
+    pub confirming: Option<Message>,
+    /// Whether the file name and location are drawn as OSC 8 links
+    pub hyperlinks: bool,
+    /// Links in the last frame, drawn over it by `tui::write_hyperlinks`
+    pub links: Vec<Hyperlink>,
+    /// Frame times and events per second are drawn over the top right corner
+    pub show_debug: bool,
+    pub perf: PerfCounters,
+    /// Saving was asked for once despite a warning, asking again saves
+    confirm_save: bool,
+    /// Offset of the cursor on the map from its center, in map pixels
+    map_cursor: (i32, i32),
+    /// Index into `Scene::ALL` of the preset applied next
+    next_scene: usize,
+    /// The image as read, the thumbnail shows it turned by `thumbnail_orientation`
+    thumbnail: image::DynamicImage,
+    thumbnail_orientation: u32,
+    /// What transparent parts of the thumbnail are blended over
+    background: Background,
+    /// Thumbnail embedded in the Exif data, to compare the image with
+    embedded: Option<image::DynamicImage>,
+    /// Percent of the width showing the image while it's compared with the
+    /// embedded thumbnail, which shows in the rest
+    pub compare: Option<u8>,
+    tx_worker: Sender<ResizeRequest>,
+}
+
+impl UiState {
+    pub fn new(
+        mut picker: Picker,
+        thumbnail: image::DynamicImage,
+        mut g: Globe,
+        tx_worker: Sender<ResizeRequest>,
+    ) -> Self {
+        let (font_w, font_h) = picker.font_size;
+        if font_w > 0 && font_h > 0 {
+            g.font_ratio = f64::from(font_h) / f64::from(font_w);
         }
-        if let Some(ref tn_jpeg) = tn_jpeg {
-            exif_writer.set_jpeg(tn_jpeg, In::THUMBNAIL);
+        let background = Background::default();
+        picker.background_color = background.color();
+        Self {
+            async_state: ThreadProtocol::new(
+                tx_worker.clone(),
+                picker.new_resize_protocol(background.composite(thumbnail.clone())),
+            ),
+            render_state: RenderState::Globe,
+            picker,
+            online: false,
+            map_state: None,
+            map_location: None,
+            status_msg: String::new(),
+            globe: g,
+            globe_state: GlobeState::default(),
+            camera_settings: CameraSettings::default(),
+            camera_on_location: false,
+            show_keybinds: false,
+            elevation: None,
+            should_rotate: true,
+            show_mini: true,
+            panning: false,
+            picking: false,
+            map_cursor: (0, 0),
+            show_internal: false,
+            editor: None,
+            search: None,
+            confirm_bulk: true,
+            confirming: None,
+            hyperlinks: false,
+            links: Vec::new(),
+            show_debug: false,
+            perf: PerfCounters::default(),
+            confirm_save: false,
+            next_scene: 0,
+            thumbnail,
+            thumbnail_orientation: 1,
+            background,
+            embedded: None,
+            compare: None,
+            tx_worker,
         }
-        exif_writer.write(&mut new_exif_buf, self.exif.little_endian())?;
-        let new_exif_buf = new_exif_buf.clone().into_inner();
-        // eprintln!("Size of new exif buf: {}", new_exif_buf.len());
+    }
 
-        // Open the Image File and read into a buffer
-        let file = std::fs::File::open(&self.path_to_image)?;
-        let mut bufreader = std::io::BufReader::new(&file);
-        let mut img_buf = Vec::new();
-        _ = bufreader.read_to_end(&mut img_buf);
+    pub fn keybind_rows(&self) -> Vec<Row<'_>> {
+        Vec::from([
+            Row::new(vec!["r", "Randomize selected Metadata"]),
+            Row::new(vec!["R", "Randomize all Metadata"]),
+            Row::new(vec!["c", "Clear selected Metadata"]),
+            Row::new(vec!["C", "Clear all Metadata"]),
+            Row::new(vec!["l | L", "Clear GPS Location only"]),
+            Row::new(vec!["e | <Enter>", "Edit selected Value"]),
+            Row::new(vec!["u", "Undo change"]),
+            Row::new(vec!["U", "Undo all changes \\ Restore"]),
+            Row::new(vec!["P", "Apply Policy"]),
+            Row::new(vec!["E", "Apply the next Scene Preset"]),
+            Row::new(vec!["o | O", "Rotate \\ Mirror the Image"]),
+            Row::new(vec!["n | N", "Add a Note \\ Remove the last one"]),
+            Row::new(vec!["f", "Flag to Keep, Scrub or Review"]),
+            Row::new(vec!["s | S", "Save a Copy"]),
+            Row::new(vec!["w", "Save over the Original"]),
+            Row::new(vec!["t | T", "Toggle Thumbnail, Globe or Map"]),
+            Row::new(vec!["v", "Compare with the Embedded Thumbnail"]),
+            Row::new(vec!["a", "Chart the Elevation of the Open Images"]),
+            Row::new(vec![
+                "[ | ]",
+                "Previous \\ Next Image, or move the Comparison Line",
+            ]),
+            Row::new(vec!["/", "Search the Fields"]),
+            Row::new(vec!["i", "Show/Hide Internal Fields"]),
+            Row::new(vec!["D", "Show/Hide Frame Times"]),
+            Row::new(vec!["b", "Convert Copies to the other Byte Order"]),
+            Row::new(vec!["m", "Export the Table as Markdown"]),
+            Row::new(vec!["g | G", "Toggle Globe Visibility"]),
+            Row::new(vec!["gg | gG", "Jump to the First \\ Last Field"]),
+            Row::new(vec!["<Spc>", "Toggle Globe Rotation"]),
+            Row::new(vec!["*", "Toggle Stars and Atmosphere"]),
+            Row::new(vec!["<Shift>+Arrows", "Pan the Globe"]),
+            Row::new(vec!["h", "Set Location to the Crosshair"]),
+            Row::new(vec!["p", "Pick Location with the Arrows"]),
+            Row::new(vec!["?", "Show/Dismiss Keybind Info"]),
+            Row::new(vec!["q | <Esc>", "Quit"]),
+        ])
+    }
 
-        // Replace the exif buffer slice in the original image with the one we create
-        let position_of_exif = img_buf
-            .windows(2)
-            .position(|x| x == &new_exif_buf[0..2])
-            .unwrap();
+    /// What the table is filtered by, empty when it isn't
+    pub fn query(&self) -> &str {
+        self.search.as_ref().map_or("", |s| s.query.as_str())
+    }
 
-        let mut exif_header = Vec::new();
-        exif_header.extend_from_slice(&img_buf[0..position_of_exif]);
-        exif_header.extend(new_exif_buf.clone());
-        // exif_header.extend(exif_buf);
-        let img_data = &img_buf[position_of_exif + size_of_exif_buf..];
-        exif_header.extend_from_slice(&img_data);
-        // eprintln!("Position of start of exif: {}", position_of_exif);
-        // eprintln!("{}", exif_header.len());
+    /// Whether typed keys go into the search
+    pub fn is_searching(&self) -> bool {
+        self.search.as_ref().is_some_and(|s| s.typing)
+    }
 
-        // Create a file copy using the original name of the file
-        let copy_file_name = self.create_copy_file_name()?;
-        let mut copy_file = std::fs::File::create(copy_file_name.clone())?;
-        copy_file.write_all(&exif_header.as_slice())?;
+    /// Turns the globe under the camera, which stays where it points
+    pub fn rotate_globe(&mut self) {
+        self.globe.angle += self.camera_settings.globe_rot_speed;
+    }
 
-        self.show_message(format!("Saved a copy - {:?}", copy_file_name).to_owned());
+    pub fn toggle_globe(&mut self) {
+        self.show_mini = !self.show_mini
+    }
 
-        Ok(())
+    pub fn camera_zoom_increase(&mut self) {
+        self.camera_settings.zoom -= 0.01;
+        self.globe.camera.update(
+            self.camera_settings.zoom,
+            self.camera_settings.alpha,
+            self.camera_settings.beta,
+        );
     }
 
-    fn get_strips(&self, ifd_num: In) -> Option<Vec<&[u8]>> {
-        let offsets = self
-            .exif
-            .get_field(Tag::StripOffsets, ifd_num)
-            .and_then(|f| f.value.iter_uint());
-        let counts = self
-            .exif
-            .get_field(Tag::StripByteCounts, ifd_num)
-            .and_then(|f| f.value.iter_uint());
-        let (offsets, counts) = match (offsets, counts) {
-            (Some(offsets), Some(counts)) => (offsets, counts),
-            (None, None) => return None,
-            _ => panic!("inconsistent strip offsets and byte counts"),
-        };
-        let buf = self.exif.buf();
-        assert_eq!(offsets.len(), counts.len());
-        let strips = offsets
-            .zip(counts)
-            .map(|(ofs, cnt)| &buf[ofs as usize..(ofs + cnt) as usize])
-            .collect();
-        Some(strips)
-    }
-
-    fn get_tiles(&self, ifd_num: In) -> Option<Vec<&[u8]>> {
-        let offsets = self
-            .exif
-            .get_field(Tag::TileOffsets, ifd_num)
-            .and_then(|f| f.value.iter_uint());
-        let counts = self
-            .exif
-            .get_field(Tag::TileByteCounts, ifd_num)
-            .and_then(|f| f.value.iter_uint());
-        let (offsets, counts) = match (offsets, counts) {
-            (Some(offsets), Some(counts)) => (offsets, counts),
-            (None, None) => return None,
-            _ => panic!("inconsistent tile offsets and byte counts"),
-        };
-        assert_eq!(offsets.len(), counts.len());
-        let buf = self.exif.buf();
-        let strips = offsets
-            .zip(counts)
-            .map(|(ofs, cnt)| &buf[ofs as usize..(ofs + cnt) as usize])
-            .collect();
-        Some(strips)
-    }
-
-    pub fn get_jpeg(&self, ifd_num: In) -> Option<&[u8]> {
-        let offset = self
-            .exif
-            .get_field(Tag::JPEGInterchangeFormat, ifd_num)
-            .and_then(|f| f.value.get_uint(0));
-        let len = self
-            .exif
-            .get_field(Tag::JPEGInterchangeFormatLength, ifd_num)
-            .and_then(|f| f.value.get_uint(0));
-        let (offset, len) = match (offset, len) {
-            (Some(offset), Some(len)) => (offset as usize, len as usize),
-            (None, None) => return None,
-            _ => panic!("inconsistent JPEG offset and length"),
-        };
-        let buf = self.exif.buf();
-        Some(&buf[offset..offset + len])
+    pub fn camera_zoom_decrease(&mut self) {
+        self.camera_settings.zoom += 0.01;
+        self.globe.camera.update(
+            self.camera_settings.zoom,
+            self.camera_settings.alpha,
+            self.camera_settings.beta,
+        );
     }
 
     pub fn show_message(&mut self, msg: String) {
@@ -811,6 +1875,99 @@ impl Application {
 
     pub fn toggle_rotate(&mut self) {
         self.should_rotate = !self.should_rotate;
+        if self.should_rotate {
+            self.panning = false;
+            self.picking = false;
+        }
+    }
+
+    /// Points the camera at `lat` / `lon` in signed degrees
+    pub fn look_at(&mut self, lat: f32, lon: f32) {
+        let (alpha, beta) = self.globe.angles_to(lat, lon);
+        self.camera_settings.alpha = alpha;
+        self.camera_settings.beta = beta;
+        self.camera_on_location = false;
+        self.globe
+            .camera
+            .update(self.camera_settings.zoom, alpha, beta);
+    }
+
+    /// Turns the camera a step towards `towards`, which stops the rotation
+    pub fn pan(&mut self, towards: Cardinal) {
+        let (d_alpha, d_beta) = match towards {
+            Cardinal::North => (0., PAN_STEP),
+            Cardinal::South => (0., -PAN_STEP),
+            Cardinal::East => (-PAN_STEP, 0.),
+            Cardinal::West => (PAN_STEP, 0.),
+        };
+        self.panning = true;
+        self.should_rotate = false;
+        self.camera_on_location = false;
+        let settings = &mut self.camera_settings;
+        settings.alpha += d_alpha;
+        settings.beta = (settings.beta + d_beta).clamp(-MAX_PAN_BETA, MAX_PAN_BETA);
+        self.globe
+            .camera
+            .update(settings.zoom, settings.alpha, settings.beta);
+    }
+
+    pub fn move_map_cursor(&mut self, towards: Cardinal) {
+        let (dx, dy) = match towards {
+            Cardinal::North => (0, -MAP_CURSOR_STEP),
+            Cardinal::South => (0, MAP_CURSOR_STEP),
+            Cardinal::East => (MAP_CURSOR_STEP, 0),
+            Cardinal::West => (-MAP_CURSOR_STEP, 0),
+        };
+        let half = osm::TILE_SIZE as i32 / 2;
+        self.map_cursor = (
+            (self.map_cursor.0 + dx).clamp(-half, half - 1),
+            (self.map_cursor.1 + dy).clamp(-half, half - 1),
+        );
+    }
+
+    /// Whether the cursor is on the map rather than the globe
+    fn picking_on_map(&self) -> bool {
+        self.picking && matches!(self.render_state, RenderState::Map) && self.map_state.is_some()
+    }
+
+    /// Latitude and longitude in signed degrees under the cursor while picking a
+    /// location on the map, or under the crosshair of the globe while panning it
+    pub fn crosshair(&self) -> Option<(f32, f32)> {
+        if self.picking_on_map() {
+            // The map is centered on the location it was fetched for
+            let (lat, lon) = self.map_location?;
+            let (x, y) = osm::tile_position(lat, lon, osm::DEFAULT_ZOOM);
+            let size = f64::from(osm::TILE_SIZE);
+            let (lat, lon) = osm::location_at(
+                x + f64::from(self.map_cursor.0) / size,
+                y + f64::from(self.map_cursor.1) / size,
+                osm::DEFAULT_ZOOM,
+            );
+            return Some((lat as f32, lon as f32));
+        }
+        self.panning.then(|| self.globe.center())
+    }
+
+    /// Cell within `area` to draw the map cursor in, if the map is drawn into
+    /// `area`. Maps are fit into the area at its top left, by whole cells.
+    pub fn map_cursor_cell(&self, area: Rect) -> Option<(u16, u16)> {
+        if !self.picking_on_map() || area.width == 0 || area.height == 0 {
+            return None;
+        }
+        let size = f64::from(osm::TILE_SIZE);
+        let (font_w, font_h) = self.picker.font_size;
+        let (cells_w, cells_h) = (size / f64::from(font_w), size / f64::from(font_h));
+        let scale = (f64::from(area.width) / cells_w)
+            .min(f64::from(area.height) / cells_h)
+            .min(1.);
+        let cell = |offset: i32, cells: f64| {
+            let cells = (cells * scale).round().max(1.);
+            ((0.5 + f64::from(offset) / size) * cells).clamp(0., cells - 1.) as u16
+        };
+        Some((
+            cell(self.map_cursor.0, cells_w),
+            cell(self.map_cursor.1, cells_h),
+        ))
     }
 
     pub fn toggle_keybinds(&mut self) {
@@ -820,10 +1977,111 @@ impl Application {
     pub fn toggle_render_state(&mut self) {
         match self.render_state {
             RenderState::Globe => self.render_state = RenderState::Thumbnail,
-            RenderState::Thumbnail => self.render_state = RenderState::Globe,
+            RenderState::Thumbnail if self.online => self.render_state = RenderState::Map,
+            RenderState::Thumbnail | RenderState::Map => self.render_state = RenderState::Globe,
         }
     }
 
+    pub fn set_map(&mut self, map: image::DynamicImage, tx_worker: Sender<ResizeRequest>) {
+        self.map_state = Some(ThreadProtocol::new(
+            tx_worker,
+            self.picker.new_resize_protocol(map),
+        ));
+    }
+
+    /// Shows the thumbnail turned the way the Orientation `orientation` says,
+    /// unless it already is
+    pub fn orient_thumbnail(&mut self, orientation: u32) {
+        if orientation == self.thumbnail_orientation {
+            return;
+        }
+        self.thumbnail_orientation = orientation;
+        self.show_thumbnail();
+    }
+
+    /// Shows another image, with its own embedded thumbnail, and leaves whatever
+    /// was going on with the last one
+    pub fn set_image(
+        &mut self,
+        thumbnail: image::DynamicImage,
+        embedded: Option<image::DynamicImage>,
+        orientation: u32,
+    ) {
+        self.thumbnail = thumbnail;
+        self.embedded = embedded;
+        self.thumbnail_orientation = orientation;
+        self.compare = None;
+        self.picking = false;
+        self.editor = None;
+        self.confirming = None;
+        self.show_thumbnail();
+    }
+
+    /// Blends transparent parts of the thumbnail over `background` from now on
+    pub fn set_background(&mut self, background: Background) {
+        if background == self.background {
+            return;
+        }
+        self.background = background;
+        self.picker.background_color = background.color();
+        self.show_thumbnail();
+    }
+
+    pub fn background(&self) -> Background {
+        self.background
+    }
+
+    /// Starts comparing the image with its embedded thumbnail, split down the
+    /// middle, or stops comparing them
+    pub fn toggle_compare(&mut self) -> String {
+        let Some(embedded) = &self.embedded else {
+            return String::from("The image has no embedded thumbnail to compare with");
+        };
+        let status = if self.compare.take().is_some() {
+            String::from("Stopped comparing with the embedded thumbnail")
+        } else {
+            self.compare = Some(50);
+            self.render_state = RenderState::Thumbnail;
+            let ratio = |image: &image::DynamicImage| {
+                f64::from(image.width()) / f64::from(image.height().max(1))
+            };
+            format!(
+                "Embedded thumbnail ({}x{}) on the right, [ and ] move the line{}",
+                embedded.width(),
+                embedded.height(),
+                if (ratio(&self.thumbnail) / ratio(embedded) - 1.).abs() > 0.02 {
+                    " - its aspect ratio differs, the image was likely cropped"
+                } else {
+                    ""
+                }
+            )
+        };
+        self.show_thumbnail();
+        status
+    }
+
+    /// Moves the line between the image and the embedded thumbnail while they're
+    /// compared
+    pub fn move_divider(&mut self, by: i8) {
+        if let Some(split) = self.compare {
+            self.compare = Some(split.saturating_add_signed(by).min(100));
+            self.show_thumbnail();
+        }
+    }
+
+    fn show_thumbnail(&mut self) {
+        let thumbnail = match (&self.embedded, self.compare) {
+            (Some(embedded), Some(split)) => split_view(&self.thumbnail, embedded, split),
+            _ => self.thumbnail.clone(),
+        };
+        let thumbnail = utils::orient(thumbnail, self.thumbnail_orientation);
+        self.async_state = ThreadProtocol::new(
+            self.tx_worker.clone(),
+            self.picker
+                .new_resize_protocol(self.background.composite(thumbnail)),
+        );
+    }
+
     pub fn increase_rotation_speed(&mut self) {
         self.camera_settings.globe_rot_speed += 0.0005;
     }
@@ -832,3 +2090,703 @@ impl Application {
         self.camera_settings.globe_rot_speed -= 0.0005;
     }
 }
+
+/// The image at `path` to show as the thumbnail. Files that aren't images, like
+/// PDFs or MP3s, get a blank one.
+fn thumbnail_of(path: &Path) -> Result<image::DynamicImage> {
+    match backend::Format::of(&std::fs::read(path)?) {
+        backend::Format::Exif => Ok(image::open(path)?),
+        _ => Ok(image::DynamicImage::new_rgba8(1, 1)),
+    }
+}
+
+/// Fields that differ between `before` and `after`, or are only in one of them
+fn changed_between(before: &HashMap<Tag, MetadataVal>, after: &HashMap<Tag, MetadataVal>) -> usize {
+    let changed = after
+        .iter()
+        .filter(|(tag, m)| before.get(tag) != Some(*m))
+        .count();
+    changed + before.keys().filter(|tag| !after.contains_key(tag)).count()
+}
+
+pub struct Application {
+    pub document: Document,
+    pub ui: UiState,
+    /// Copy being written in the background
+    pub save_job: Option<SaveJob>,
+    pub session: Session,
+    /// Fields as written to the last copy, or as read if there is none, and as
+    /// they're being written to the copy in the background
+    saved_fields: HashMap<Tag, MetadataVal>,
+    saving_fields: Option<HashMap<Tag, MetadataVal>>,
+    /// Images to switch between, the one open is `images[current]`
+    pub images: Vec<PathBuf>,
+    pub current: usize,
+    /// Images switched away from, by their index in `images`. Others are only
+    /// read once switched to.
+    parked: HashMap<usize, Parked>,
+}
+
+/// An image switched away from, with its edits and the row that was selected
+struct Parked {
+    document: Document,
+    saved_fields: HashMap<Tag, MetadataVal>,
+    row: Option<usize>,
+}
+
+impl Application {
+    pub fn new(path_to_image: &Path, g: Globe, tx_worker: Sender<ResizeRequest>) -> Result<Self> {
+        // Terminals that don't report their size in pixels get a typical font
+        // size, which only affects how the thumbnail is scaled and how wide the
+        // globe is drawn
+        let mut picker = Picker::from_termios().unwrap_or_else(|_| Picker::new(FALLBACK_FONT_SIZE));
+        picker.guess_protocol();
+        Self::with_picker(path_to_image, g, tx_worker, picker)
+    }
+
+    /// Like `new`, with a picker that doesn't have to query the terminal, e.g. for
+    /// rendering into a `TestBackend`
+    pub fn with_picker(
+        path_to_image: &Path,
+        g: Globe,
+        tx_worker: Sender<ResizeRequest>,
+        picker: Picker,
+    ) -> Result<Self> {
+        let document = Document::open(path_to_image)?;
+        let dyn_img = thumbnail_of(path_to_image)?;
+        let mut ui = UiState::new(picker, dyn_img, g, tx_worker);
+        ui.embedded = embedded_thumbnail(&std::fs::read(path_to_image)?);
+        ui.should_rotate = !document.has_gps;
+        ui.orient_thumbnail(document.orientation());
+        let session = Session {
+            inspected: vec![path_to_image.to_path_buf()],
+            ..Default::default()
+        };
+        Ok(Self {
+            saved_fields: HashMap::clone(&document.original_fields),
+            document,
+            ui,
+            save_job: None,
+            session,
+            saving_fields: None,
+            images: vec![path_to_image.to_path_buf()],
+            current: 0,
+            parked: HashMap::new(),
+        })
+    }
+
+    /// Opens `path` with the settings the open image has, like the policy and
+    /// what the randomizer changes
+    fn open_like_current(&self, path: &Path) -> Result<Document> {
+        let mut document = Document::open(path)?;
+        document.vault_passphrase = self.document.vault_passphrase.clone();
+        document.policy = self.document.policy.clone();
+        document.backup = self.document.backup;
+        document.oversized = self.document.oversized;
+        let randomizer = &self.document.randomizer;
+        document.randomizer.tags_to_randomize = randomizer.tags_to_randomize.clone();
+        document.randomizer.dates = randomizer.dates.clone();
+        document.randomizer.weighted = randomizer.weighted;
+        document.update_gps();
+        Ok(document)
+    }
+
+    /// Shows the next or previous image, skipping any that can't be opened.
+    /// Returns the status message and the row to select.
+    fn switch_image(&mut self, forward: bool, row: Option<usize>) -> (String, Option<usize>) {
+        let count = self.images.len();
+        if count < 2 {
+            return (String::from("No other image is open"), None);
+        }
+        if self.save_job.is_some() {
+            return (String::from("Wait for the copy to be saved first"), None);
+        }
+        let mut skipped = 0;
+        let mut next = self.current;
+        let opened = loop {
+            next = match forward {
+                true => (next + 1) % count,
+                false => (next + count - 1) % count,
+            };
+            if next == self.current {
+                break None;
+            }
+            let path = &self.images[next];
+            let thumbnail = match thumbnail_of(path) {
+                Ok(thumbnail) => thumbnail,
+                Err(_) => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            let parked = match self.parked.remove(&next) {
+                Some(parked) => parked,
+                None => match self.open_like_current(path) {
+                    Ok(document) => Parked {
+                        saved_fields: HashMap::clone(&document.original_fields),
+                        document,
+                        row: Some(0),
+                    },
+                    Err(_) => {
+                        skipped += 1;
+                        continue;
+                    }
+                },
+            };
+            break Some((thumbnail, parked));
+        };
+        let Some((thumbnail, parked)) = opened else {
+            return (String::from("None of the other images can be opened"), None);
+        };
+
+        let document = std::mem::replace(&mut self.document, parked.document);
+        let saved_fields = std::mem::replace(&mut self.saved_fields, parked.saved_fields);
+        self.parked.insert(
+            self.current,
+            Parked {
+                document,
+                saved_fields,
+                row,
+            },
+        );
+        self.current = next;
+        let path = self.document.path_to_image.clone();
+        let embedded = std::fs::read(&path)
+            .ok()
+            .and_then(|file| embedded_thumbnail(&file));
+        self.ui
+            .set_image(thumbnail, embedded, self.document.orientation());
+        self.ui.should_rotate = !self.document.has_gps;
+        self.ui.panning = false;
+        self.ui.camera_on_location = false;
+        if !self.session.inspected.contains(&path) {
+            self.session.inspected.push(path.clone());
+        }
+        let mut status = format!("Opened {:?} ({} of {})", path, next + 1, count);
+        if skipped > 0 {
+            status.push_str(&format!(", skipped {} that couldn't be opened", skipped));
+        }
+        (status, parked.row)
+    }
+
+    /// Charts the elevation of the open images as they are on disk, or closes
+    /// the chart. Returns the status message
+    fn toggle_elevation(&mut self) -> String {
+        if self.ui.elevation.take().is_some() {
+            return String::from("Closed the elevation chart");
+        }
+        match Profile::load(&self.images) {
+            Ok(profile) if profile.samples.len() >= 2 => {
+                let status = format!("Charting the elevation of {} images", profile.samples.len());
+                self.ui.elevation = Some(profile);
+                status
+            }
+            Ok(profile) => format!(
+                "Need at least two open images with an altitude and capture time, found {}",
+                profile.samples.len()
+            ),
+            Err(e) => format!("Unable to chart the elevation: {}", e),
+        }
+    }
+
+    /// Applies `message`, returning the table row to select if it should change
+    pub fn update(&mut self, message: Message) -> Option<usize> {
+        let mut select = None;
+        // A warning before saving only holds for the next key
+        let save_confirmed = std::mem::take(&mut self.ui.confirm_save);
+        let fitted = self.fits_segment();
+        let status = match message {
+            Message::Randomize(index) => self.document.randomize(index, false),
+            Message::RandomizeAll | Message::ClearAll if self.ui.confirm_bulk => {
+                self.ui.confirming = Some(message);
+                String::from("Press y to go ahead, n to cancel")
+            }
+            Message::Answer(yes) => match self.ui.confirming.take() {
+                Some(Message::RandomizeAll) if yes => self.document.randomize_all(),
+                Some(Message::ClearAll) if yes => self.document.clear_all_fields(),
+                _ => String::from("Cancelled, nothing changed"),
+            },
+            Message::RandomizeAll => self.document.randomize_all(),
+            Message::Clear(index) => self.document.clear_field(index, false),
+            Message::ClearAll => self.document.clear_all_fields(),
+            Message::ClearGps => self.document.clear_gps(),
+            Message::SetLocationHere => match self.ui.crosshair() {
+                Some((lat, lon)) => {
+                    self.ui.picking = false;
+                    self.document.set_location(lat as f64, lon as f64)
+                }
+                None => String::from("Pan the globe to pick a location first"),
+            },
+            Message::PickLocation if self.ui.picking => {
+                self.ui.picking = false;
+                self.ui.panning = false;
+                String::from("Stopped picking a location")
+            }
+            Message::PickLocation => {
+                self.ui.show_mini = true;
+                match self.ui.render_state {
+                    RenderState::Map if self.ui.map_state.is_some() => self.ui.map_cursor = (0, 0),
+                    _ => {
+                        self.ui.render_state = RenderState::Globe;
+                        self.start_panning();
+                    }
+                }
+                self.ui.picking = true;
+                String::from("Move the cursor with the arrow keys, <Enter> sets the location")
+            }
+            Message::Edit(index) => {
+                let m = order::EXIF_FIELDS_ORDERED
+                    .get(index)
+                    .and_then(|tag| self.document.modified_fields.get(tag))?;
+                match Editor::new(&m.field) {
+                    _ if !self.document.backend.is_editable(m.field.tag) => {
+                        format!("Cannot edit {}", utils::tag_name(m.field.tag))
+                    }
+                    Some(editor) => {
+                        let status = if editor.is_text() {
+                            format!("Type a value for {}, <Enter> sets it", editor.target)
+                        } else {
+                            format!(
+                                "Pick a value for {} with the arrow keys, <Enter> sets it",
+                                editor.target
+                            )
+                        };
+                        self.ui.editor = Some(editor);
+                        status
+                    }
+                    None => format!(
+                        "{} holds raw bytes and can't be typed in",
+                        utils::tag_name(m.field.tag)
+                    ),
+                }
+            }
+            Message::PreviousChoice | Message::NextChoice => {
+                if let Some(editor) = self.ui.editor.as_mut() {
+                    if message == Message::NextChoice {
+                        editor.next()
+                    } else {
+                        editor.previous()
+                    }
+                }
+                return None;
+            }
+            Message::Type(_) | Message::DeleteChar if self.ui.editor.is_none() => {
+                match self.ui.search.as_mut() {
+                    Some(search) if search.typing => match message {
+                        Message::Type(c) => search.query.push(c),
+                        _ => _ = search.query.pop(),
+                    },
+                    _ => return None,
+                }
+                select = Some(0);
+                self.search_status()
+            }
+            Message::Type(c) => {
+                if let Some(editor) = self.ui.editor.as_mut() {
+                    editor.push(c)
+                }
+                return None;
+            }
+            Message::DeleteChar => {
+                if let Some(editor) = self.ui.editor.as_mut() {
+                    editor.pop()
+                }
+                return None;
+            }
+            Message::Search => {
+                self.ui.search.get_or_insert_with(Search::default).typing = true;
+                self.search_status()
+            }
+            Message::ConfirmSearch => {
+                let search = self.ui.search.as_mut()?;
+                search.typing = false;
+                if search.query.is_empty() {
+                    self.ui.search = None;
+                    return None;
+                }
+                format!(
+                    "Showing the {} fields matching {:?}, <Esc> shows every field",
+                    self.row_count(),
+                    self.ui.query()
+                )
+            }
+            Message::ClearSearch { row } => {
+                // The field selected in the filtered table stays selected
+                let index = self.field_at(row);
+                self.ui.search = None;
+                select = index.and_then(|i| self.row_of(i)).or(Some(0));
+                String::from("Showing every field")
+            }
+            // Typos keep the editor open to fix them
+            Message::ConfirmEdit => match self.ui.editor.take() {
+                Some(editor) => match editor.target {
+                    Target::Note => self.document.add_note(editor.text()),
+                    Target::Field(tag) => match editor.value() {
+                        Ok(value) => self.document.set_value(tag, value),
+                        Err(e) => {
+                            self.ui.editor = Some(editor);
+                            format!("Invalid {}: {}", utils::tag_name(tag), e)
+                        }
+                    },
+                },
+                None => return None,
+            },
+            Message::AddNote => {
+                self.ui.editor = Some(Editor::note());
+                String::from("Type a note about the image, <Enter> adds it")
+            }
+            Message::RemoveNote => self.document.remove_last_note(),
+            Message::CycleFlag => self.document.cycle_flag(),
+            Message::CloseEditor => {
+                self.ui.editor = None;
+                String::from("Closed the editor")
+            }
+            Message::ApplyScene => {
+                let scene = Scene::ALL[self.ui.next_scene % Scene::ALL.len()];
+                self.ui.next_scene += 1;
+                self.document.apply_scene(scene)
+            }
+            // Show the thumbnail to see the change right away
+            Message::Rotate | Message::Mirror => {
+                self.ui.show_mini = true;
+                self.ui.render_state = RenderState::Thumbnail;
+                self.document.reorient(message == Message::Mirror)
+            }
+            Message::ApplyPolicy => self.document.apply_policy(),
+            Message::Undo => {
+                let (status, index) = self.document.undo_operation();
+                select = index.and_then(|i| self.row_of(i));
+                status
+            }
+            Message::Restore => {
+                let status = self.document.restore();
+                if self.document.has_gps && !self.ui.should_rotate {
+                    self.transform_coordinates();
+                }
+                status
+            }
+            Message::Save | Message::SaveInPlace if self.save_job.is_some() => {
+                String::from("Already saving a copy")
+            }
+            Message::Save | Message::SaveInPlace
+                if !save_confirmed && !self.document.changed_required().is_empty() =>
+            {
+                self.ui.confirm_save = true;
+                let tags = self.document.changed_required();
+                let (saved, key) = match message {
+                    Message::SaveInPlace => ("image", 'w'),
+                    _ => ("copy", 's'),
+                };
+                format!(
+                    "Changed {} may leave the {} unreadable, press {} again to save anyway",
+                    tags.iter()
+                        .map(|t| t.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    saved,
+                    key
+                )
+            }
+            Message::Save | Message::SaveInPlace => {
+                let target = match message {
+                    Message::SaveInPlace => Destination::InPlace {
+                        backup: self.document.backup,
+                    },
+                    _ => Destination::Copy,
+                };
+                match self.document.save_request_to(target) {
+                    Ok(request) => {
+                        self.saving_fields = Some(HashMap::clone(&self.document.modified_fields));
+                        self.save_job = Some(SaveJob::spawn(request));
+                        match target {
+                            Destination::Copy => String::from("Saving a copy..."),
+                            Destination::InPlace { .. } => {
+                                String::from("Saving over the original...")
+                            }
+                        }
+                    }
+                    Err(e) => format!("Unable to save: {}", e),
+                }
+            }
+            Message::CancelSave => match &self.save_job {
+                Some(job) => {
+                    job.cancel();
+                    String::from("Cancelling save...")
+                }
+                None => String::from("Nothing to cancel"),
+            },
+            Message::ToggleStarfield => {
+                self.ui.globe.toggle_starfield();
+                if self.ui.globe.starfield {
+                    String::from("Showing stars and atmosphere")
+                } else {
+                    String::from("Hiding stars and atmosphere")
+                }
+            }
+            Message::ToggleGlobe => {
+                self.ui.toggle_globe();
+                if self.ui.show_mini {
+                    String::from("Showing Globe")
+                } else {
+                    self.ui.should_rotate = false;
+                    String::from("Hiding Globe")
+                }
+            }
+            Message::ToggleRenderState => {
+                self.ui.toggle_render_state();
+                return None;
+            }
+            Message::ToggleInternal => {
+                self.ui.show_internal = !self.ui.show_internal;
+                if self.ui.show_internal {
+                    String::from("Showing internal fields, changing them can break the file")
+                } else {
+                    String::from("Hiding internal fields")
+                }
+            }
+            Message::ToggleDebug => {
+                self.ui.show_debug = !self.ui.show_debug;
+                if self.ui.show_debug {
+                    String::from("Showing frame times")
+                } else {
+                    String::from("Hiding frame times")
+                }
+            }
+            Message::ToggleByteOrder => self.document.toggle_byte_order(),
+            Message::ExportMarkdown => self.document.export_markdown(),
+            Message::Compare => self.ui.toggle_compare(),
+            Message::MoveDivider(by) => {
+                self.ui.move_divider(by);
+                return None;
+            }
+            Message::SwitchImage { forward, row } => {
+                let (status, row) = self.switch_image(forward, row);
+                select = row;
+                status
+            }
+            Message::ToggleKeybinds => {
+                self.ui.toggle_keybinds();
+                if self.ui.show_keybinds {
+                    String::from("Showing Keybinds window")
+                } else {
+                    String::from("Hid Keybinds window")
+                }
+            }
+            Message::ToggleElevation => self.toggle_elevation(),
+            Message::ToggleRotate => {
+                self.ui.toggle_rotate();
+                return None;
+            }
+            Message::ZoomIn => {
+                self.ui.camera_zoom_increase();
+                return None;
+            }
+            Message::ZoomOut => {
+                self.ui.camera_zoom_decrease();
+                return None;
+            }
+            Message::Pan(towards) if self.ui.picking_on_map() => {
+                self.ui.move_map_cursor(towards);
+                return None;
+            }
+            Message::Pan(towards) => {
+                self.start_panning();
+                self.ui.pan(towards);
+                return None;
+            }
+            Message::StopPanning => {
+                self.ui.panning = false;
+                String::from("Back to the image location")
+            }
+            Message::FasterRotation => {
+                self.ui.increase_rotation_speed();
+                return None;
+            }
+            Message::SlowerRotation => {
+                self.ui.decrease_rotation_speed();
+                return None;
+            }
+        };
+        // Undoing or restoring may have turned the image as well
+        self.ui.orient_thumbnail(self.document.orientation());
+        let status = match self.document.segment_budget() {
+            Some((len, limit)) if fitted && !self.fits_segment() => format!(
+                "{} - the Exif data is now {} bytes, more than the {} a JPEG segment holds, {}",
+                status,
+                len,
+                limit,
+                match self.document.oversized {
+                    Oversized::Refuse => "shorten or clear fields to save",
+                    Oversized::DropMakerNote => "saving drops the MakerNote",
+                    Oversized::MoveXmp => "saving moves the XMP packet out of it",
+                }
+            ),
+            _ => status,
+        };
+        self.ui.show_message(status);
+        select
+    }
+
+    /// Index into `order::EXIF_FIELDS_ORDERED` of the field in table `row`, which
+    /// is what messages about a field take. The table leaves out the fields the
+    /// image doesn't have, hidden ones and the ones a search doesn't find.
+    pub fn field_at(&self, row: Option<usize>) -> Option<usize> {
+        let tags = self
+            .document
+            .matching_tags(self.ui.show_internal, self.ui.query());
+        let tag = tags.get(row?)?;
+        order::EXIF_FIELDS_ORDERED.iter().position(|t| t == tag)
+    }
+
+    /// The table row showing the field at `index`, the reverse of `field_at`
+    fn row_of(&self, index: usize) -> Option<usize> {
+        let tag = order::EXIF_FIELDS_ORDERED.get(index)?;
+        self.document
+            .matching_tags(self.ui.show_internal, self.ui.query())
+            .iter()
+            .position(|t| t == tag)
+    }
+
+    /// Rows the arrow keys move through
+    pub fn row_count(&self) -> usize {
+        match self.ui.query() {
+            "" => self.document.modified_fields.len(),
+            query => self
+                .document
+                .matching_tags(self.ui.show_internal, query)
+                .len(),
+        }
+    }
+
+    /// The search as typed so far and how many fields it finds
+    fn search_status(&self) -> String {
+        let query = self.ui.query();
+        format!(
+            "/{} - {} of {} fields match, <Enter> keeps the filter, <Esc> clears it",
+            query,
+            self.row_count(),
+            self.document.modified_fields.len()
+        )
+    }
+
+    /// Whether the Exif data of a copy fits into the segment a JPEG keeps it in
+    fn fits_segment(&self) -> bool {
+        self.document
+            .segment_budget()
+            .is_none_or(|(len, limit)| len <= limit)
+    }
+
+    /// Stops the rotation to turn the globe by hand, starting from the image
+    /// location rather than wherever the camera was
+    fn start_panning(&mut self) {
+        if !self.ui.panning && self.document.has_gps {
+            let (lat, lon) = self.document.location();
+            self.ui.look_at(lat as f32, lon as f32);
+        }
+        self.ui.panning = true;
+        self.ui.should_rotate = false;
+    }
+
+    /// What was done in the session, counting the changes not saved yet
+    pub fn finish_session(&mut self) -> &Session {
+        self.session.unsaved = changed_between(&self.saved_fields, &self.document.modified_fields)
+            + self
+                .parked
+                .values()
+                .map(|p| changed_between(&p.saved_fields, &p.document.modified_fields))
+                .sum::<usize>();
+        &self.session
+    }
+
+    /// Shows the progress of a save running in the background, and its result
+    /// once it's done
+    pub fn poll_save(&mut self) {
+        while let Some(event) = self.save_job.as_mut().and_then(|job| job.poll()) {
+            match event {
+                SaveEvent::Progress { written, total } => {
+                    let saving = match self.save_job.as_ref().map(|job| job.target) {
+                        Some(Destination::InPlace { .. }) => "Saving over the original",
+                        _ => "Saving a copy",
+                    };
+                    self.ui.show_message(format!(
+                        "{}... {}% (<Esc> to cancel)",
+                        saving,
+                        written * 100 / total.max(1)
+                    ));
+                }
+                SaveEvent::Done(result) => {
+                    let job = self.save_job.take();
+                    let fields = self.saving_fields.take();
+                    if let (Ok(_), Some(job), Some(fields)) = (&result, job, fields) {
+                        let changed = changed_between(&self.document.original_fields, &fields);
+                        // The file no longer holds the metadata it was opened with,
+                        // and the next save starts from what it holds now
+                        if job.target != Destination::Copy {
+                            if let Ok(backend) = backend::open(&self.document.path_to_image) {
+                                self.document.backend = backend;
+                            }
+                        }
+                        self.session.copies.push((job.copy, changed));
+                        self.saved_fields = fields;
+                    }
+                    self.ui.show_message(match result {
+                        Ok(status) => status,
+                        Err(e) => format!("Unable to save: {}", e),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Points the globe camera at the location of the image
+    pub fn transform_coordinates(&mut self) {
+        // Latitude is 0 at the equator and increases to 90N for the north pole
+        // and 90S for the South Pole
+        // Longitude is 0 at the Prime Meridian (Greenwich) and increases to 180E at the
+        // 180th Meridian
+        // Latitude is a -90 -> 90 spread
+        // Longitude is a -180 -> 180 spread
+        let gps_info = &self.document.gps_info;
+
+        let new_longitude = match gps_info.long_direction {
+            Cardinal::East => gps_info.longitude,
+            Cardinal::West => 360. - gps_info.longitude, // Convert into Long East
+            _ => 0.0,
+        } / 360.;
+        let new_latitude = match gps_info.lat_direction {
+            Cardinal::North => gps_info.latitude / 90.,
+            Cardinal::South => -gps_info.latitude / 90.,
+            _ => 0.,
+        };
+        // Zoom and rotation speeds stay as the user set them
+        let settings = &mut self.ui.camera_settings;
+        settings.alpha = new_longitude;
+        settings.beta = new_latitude;
+        self.ui
+            .globe
+            .camera
+            .update(settings.zoom, new_longitude, new_latitude);
+        self.ui.camera_on_location = true;
+        match self.document.solar {
+            Some(sun) => self
+                .ui
+                .globe
+                .set_sun(sun.elevation as f32, sun.azimuth as f32),
+            None => self.ui.globe.display_night = false,
+        }
+    }
+
+    /// Location to fetch a map for, if the current map doesn't show it yet
+    pub fn map_request(&mut self) -> Option<(f64, f64)> {
+        if !self.ui.online || !self.document.has_gps {
+            return None;
+        }
+        let location = self.document.location();
+        if self.ui.map_location == Some(location) {
+            return None;
+        }
+        self.ui.map_location = Some(location);
+        self.ui.map_state = None;
+        Some(location)
+    }
+}
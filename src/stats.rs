@@ -0,0 +1,110 @@
+use anyhow::Result;
+use exif::{Exif, In, Reader, Tag};
+use serde::Serialize;
+use std::{collections::HashMap, io::Cursor, path::PathBuf};
+
+use crate::{batch, utils};
+
+// Stats
+//
+// Inventory of a set of images - which cameras and lenses took them, how many
+// carry a location and when they were taken. Only reads metadata, nothing is
+// written.
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Count {
+    pub name: String,
+    pub images: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    pub images: usize,
+    /// Images without any Exif data
+    pub without_exif: usize,
+    pub with_gps: usize,
+    /// Earliest capture time, as recorded by the camera
+    pub first: Option<String>,
+    /// Latest capture time, as recorded by the camera
+    pub last: Option<String>,
+    /// Most common first
+    pub cameras: Vec<Count>,
+    /// Most common first
+    pub lenses: Vec<Count>,
+}
+
+impl Stats {
+    pub fn collect(paths: &[PathBuf]) -> Result<Self> {
+        let mut stats = Stats::default();
+        let mut cameras = HashMap::new();
+        let mut lenses = HashMap::new();
+        let mut times = Vec::new();
+
+        for path in batch::collect_images(paths)? {
+            stats.images += 1;
+            let exif = std::fs::read(&path).ok().and_then(|img_buf| {
+                Reader::new()
+                    .read_from_container(&mut Cursor::new(&img_buf))
+                    .ok()
+            });
+            let Some(exif) = exif else {
+                stats.without_exif += 1;
+                continue;
+            };
+
+            if exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_some() {
+                stats.with_gps += 1;
+            }
+            if let Some((time, _)) = utils::capture_time(|tag| exif.get_field(tag, In::PRIMARY)) {
+                times.push(time);
+            }
+            *cameras.entry(camera(&exif)).or_insert(0) += 1;
+            let lens = text(&exif, Tag::LensModel).unwrap_or_else(|| String::from("Unknown"));
+            *lenses.entry(lens).or_insert(0) += 1;
+        }
+
+        let format = |t: &chrono::NaiveDateTime| t.format("%Y-%m-%d %H:%M:%S").to_string();
+        stats.first = times.iter().min().map(format);
+        stats.last = times.iter().max().map(format);
+        stats.cameras = ranked(cameras);
+        stats.lenses = ranked(lenses);
+        Ok(stats)
+    }
+
+    /// Share of the readable images that have a location, in percent
+    pub fn gps_percentage(&self) -> f64 {
+        let readable = self.images - self.without_exif;
+        if readable == 0 {
+            return 0.;
+        }
+        self.with_gps as f64 * 100. / readable as f64
+    }
+}
+
+/// Value of a primary field as text, if it isn't empty
+pub(crate) fn text(exif: &Exif, tag: Tag) -> Option<String> {
+    let field = exif.get_field(tag, In::PRIMARY)?;
+    let value = utils::display_text(field);
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Make and model, without repeating the make when the model already has it
+fn camera(exif: &Exif) -> String {
+    match (text(exif, Tag::Make), text(exif, Tag::Model)) {
+        (Some(make), Some(model)) if model.to_lowercase().starts_with(&make.to_lowercase()) => {
+            model
+        }
+        (Some(make), Some(model)) => format!("{} {}", make, model),
+        (make, model) => make.or(model).unwrap_or_else(|| String::from("Unknown")),
+    }
+}
+
+fn ranked(counts: HashMap<String, usize>) -> Vec<Count> {
+    let mut counts = counts
+        .into_iter()
+        .map(|(name, images)| Count { name, images })
+        .collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.images.cmp(&a.images).then_with(|| a.name.cmp(&b.name)));
+    counts
+}
@@ -0,0 +1,155 @@
+use anyhow::Result;
+use std::{fmt, path::Path};
+
+use crate::jpeg;
+
+// Stego
+//
+// Places in an image file where data can hide without showing up in the pixels
+// or the Exif data - bytes after the end of the image, APP segments no viewer
+// reads and archives tucked in anywhere. A steganography tool appending a ZIP
+// to a JPEG is the classic case, but it's as often a phone storing a second
+// image or a video after the first one. Either way it survives scrubbing the
+// metadata, so it's pointed out.
+//
+// A second Exif segment is one of these places too. Some tools add their own
+// instead of updating the one that's there, and as bresson only reads the first
+// one, whatever is in the others wouldn't show up anywhere. They're removed
+// whenever a copy is written.
+//
+// Only the container is looked at. Data hidden in the pixels themselves can't
+// be told from noise this way.
+
+/// Unrecognized APP segments up to this size are left alone, cameras write
+/// small ones of their own
+const UNKNOWN_SEGMENT_LIMIT: usize = 4096;
+
+/// Comments longer than this are pointed out
+const COMMENT_LIMIT: usize = 256;
+
+/// Identifiers of the APP segments viewers and editors know about
+const KNOWN_SEGMENTS: &[(u8, &[u8])] = &[
+    (jpeg::APP0, b"JFIF\0"),
+    (jpeg::APP0, b"JFXX\0"),
+    (jpeg::APP1, jpeg::EXIF_HEADER),
+    (jpeg::APP1, jpeg::XMP_HEADER),
+    (jpeg::APP1, jpeg::XMP_EXTENSION_HEADER),
+    (jpeg::APP2, jpeg::ICC_HEADER),
+    (jpeg::APP2, b"MPF\0"),
+    (jpeg::APP2, b"FPXR\0"),
+    (0xED, b"Photoshop 3.0\0"),
+    (0xEE, b"Adobe"),
+];
+
+/// Signatures of archives, which have no business inside an image
+const ARCHIVES: &[(&str, &[u8])] = &[
+    ("ZIP archive", b"PK\x03\x04"),
+    ("RAR archive", b"Rar!\x1a\x07"),
+    ("7z archive", b"7z\xbc\xaf\x27\x1c"),
+];
+
+/// Something in an image that may hold hidden data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub offset: usize,
+    pub len: usize,
+    pub what: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at offset {} ({} bytes)",
+            self.what, self.offset, self.len
+        )
+    }
+}
+
+/// Looks for extra data in the image at `path`
+pub fn scan_file(path: &Path) -> Result<Vec<Finding>> {
+    Ok(scan(&std::fs::read(path)?))
+}
+
+/// Looks for extra data in an image
+pub fn scan(buf: &[u8]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut end = None;
+    if jpeg::is_jpeg(buf) {
+        if let Ok(segments) = jpeg::segments(buf) {
+            findings.extend(segments.iter().filter_map(|s| unusual_segment(buf, s)));
+            // Only the first Exif segment is read, and written
+            findings.extend(
+                segments
+                    .iter()
+                    .filter(|s| s.is_exif(buf))
+                    .skip(1)
+                    .map(|s| Finding {
+                        offset: s.offset,
+                        len: s.len,
+                        what: String::from("Another Exif segment, removed when saving"),
+                    }),
+            );
+        }
+        end = jpeg::end_of_image(buf).ok();
+    } else if buf.starts_with(b"\x89PNG\r\n\x1a\n") {
+        // The IEND chunk is empty, followed by its CRC
+        end = buf
+            .windows(4)
+            .rposition(|w| w == b"IEND")
+            .map(|i| i + 8)
+            .filter(|&end| end <= buf.len());
+    }
+
+    if let Some(end) = end {
+        let trailer = &buf[end..];
+        // Some cameras pad their files, which hides nothing
+        if trailer.iter().any(|&b| b != 0 && b != 0xFF) {
+            let what = if jpeg::is_jpeg(trailer) {
+                "Another JPEG after the end of the image"
+            } else if trailer.get(4..8) == Some(b"ftyp") {
+                "A video after the end of the image"
+            } else {
+                "Data after the end of the image"
+            };
+            findings.push(Finding {
+                offset: end,
+                len: trailer.len(),
+                what: what.to_owned(),
+            });
+        }
+    }
+
+    for (name, signature) in ARCHIVES {
+        if let Some(offset) = buf.windows(signature.len()).position(|w| w == *signature) {
+            findings.push(Finding {
+                offset,
+                len: buf.len() - offset,
+                what: format!("Start of a {}", name),
+            });
+        }
+    }
+    findings
+}
+
+fn unusual_segment(buf: &[u8], segment: &jpeg::Segment) -> Option<Finding> {
+    let payload = segment.payload(buf);
+    let what = match segment.marker {
+        jpeg::COM if payload.len() > COMMENT_LIMIT => String::from("Long comment"),
+        marker @ 0xE0..=0xEF if payload.len() > UNKNOWN_SEGMENT_LIMIT => {
+            let known = KNOWN_SEGMENTS
+                .iter()
+                .any(|&(m, id)| m == marker && payload.starts_with(id));
+            if known {
+                return None;
+            }
+            format!("Unrecognized APP{} segment", marker - 0xE0)
+        }
+        _ => return None,
+    };
+    Some(Finding {
+        offset: segment.offset,
+        len: segment.len,
+        what,
+    })
+}
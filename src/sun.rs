@@ -0,0 +1,107 @@
+use chrono::{DateTime, NaiveTime, Timelike, Utc};
+use std::fmt::Display;
+
+// Sun
+//
+// Position of the sun for a place and time, precise to a fraction of a degree
+// which is plenty to tell golden hour from broad daylight.
+//
+// https://aa.usno.navy.mil/faq/sun_approx
+// https://gml.noaa.gov/grad/solcalc/solareqns.PDF
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Daylight {
+    Night,
+    /// Civil twilight, the sun is up to 6° below the horizon
+    Twilight,
+    /// The sun is up to 6° above the horizon
+    GoldenHour,
+    Day,
+}
+
+impl Display for Daylight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Daylight::Night => write!(f, "Night"),
+            Daylight::Twilight => write!(f, "Twilight"),
+            Daylight::GoldenHour => write!(f, "Golden hour"),
+            Daylight::Day => write!(f, "Day"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolarPosition {
+    /// Degrees above the horizon, negative when the sun has set
+    pub elevation: f64,
+    /// Degrees clockwise from north
+    pub azimuth: f64,
+    /// Local apparent solar time, i.e. 12:00 is when the sun is highest
+    pub solar_time: NaiveTime,
+}
+
+impl SolarPosition {
+    /// Where the sun is at `time`, seen from `lat` / `lon` in signed degrees
+    pub fn at(time: DateTime<Utc>, lat: f64, lon: f64) -> Self {
+        // Days since J2000.0
+        let days = (time.timestamp_millis() - 946_728_000_000) as f64 / 86_400_000.;
+
+        let mean_anomaly = (357.529 + 0.985_600_28 * days).to_radians();
+        let mean_longitude = (280.459 + 0.985_647_36 * days).rem_euclid(360.);
+        let ecliptic_longitude =
+            (mean_longitude + 1.915 * mean_anomaly.sin() + 0.020 * (2. * mean_anomaly).sin())
+                .to_radians();
+        let obliquity = (23.439 - 0.000_000_36 * days).to_radians();
+
+        let right_ascension = (obliquity.cos() * ecliptic_longitude.sin())
+            .atan2(ecliptic_longitude.cos())
+            .to_degrees();
+        let declination = (obliquity.sin() * ecliptic_longitude.sin()).asin();
+
+        // Equation of time in minutes, wrapped into -12h..12h
+        let equation_of_time =
+            ((mean_longitude - right_ascension + 180.).rem_euclid(360.) - 180.) * 4.;
+        let utc_minutes = f64::from(time.num_seconds_from_midnight()) / 60.;
+        let solar_minutes = (utc_minutes + 4. * lon + equation_of_time).rem_euclid(1440.);
+
+        let hour_angle = (solar_minutes / 4. - 180.).to_radians();
+        let lat = lat.to_radians();
+        let elevation = (lat.sin() * declination.sin()
+            + lat.cos() * declination.cos() * hour_angle.cos())
+        .asin();
+        let azimuth = (-hour_angle.sin() * declination.cos()).atan2(
+            declination.sin() * lat.cos() - declination.cos() * lat.sin() * hour_angle.cos(),
+        );
+
+        Self {
+            elevation: elevation.to_degrees(),
+            azimuth: azimuth.to_degrees().rem_euclid(360.),
+            solar_time: NaiveTime::from_num_seconds_from_midnight_opt(
+                (solar_minutes * 60.) as u32 % 86_400,
+                0,
+            )
+            .unwrap_or_default(),
+        }
+    }
+
+    pub fn daylight(&self) -> Daylight {
+        match self.elevation {
+            e if e < -6. => Daylight::Night,
+            e if e < 0. => Daylight::Twilight,
+            e if e < 6. => Daylight::GoldenHour,
+            _ => Daylight::Day,
+        }
+    }
+}
+
+impl Display for SolarPosition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}, sun at {:.1}°, solar time {}",
+            self.daylight(),
+            self.elevation,
+            self.solar_time.format("%H:%M")
+        )
+    }
+}
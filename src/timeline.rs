@@ -0,0 +1,319 @@
+use anyhow::Result;
+use chrono::{NaiveDateTime, TimeDelta, Timelike};
+use exif::{In, Reader, Tag};
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    batch,
+    sidecar::{Flag, Sidecar},
+    utils,
+};
+
+// Timeline
+//
+// Groups a set of images by when they were taken, so a shoot can be browsed in
+// order. Images are bucketed per day, or per hour when they were all taken on
+// the same day. Images without a capture time are counted but not placed.
+//
+// Bursts - frames one camera took in quick succession - are told apart by the
+// SubSecTime tags, which keep the fraction of a second the capture time leaves
+// out. Frames at most `BURST_GAP_MS` apart end up in one burst, listed as a
+// single line that can be expanded, and flagged or scrubbed all at once.
+// Images without a fraction of a second are never part of one, as two shots in
+// the same second can't be told from two shots a second apart.
+
+/// Longest time between two frames of a burst, in milliseconds
+const BURST_GAP_MS: i64 = 1000;
+
+#[derive(Debug, Clone)]
+pub struct Bucket {
+    /// Start of the day or hour covered by the bucket
+    pub start: NaiveDateTime,
+    /// Ordered by capture time
+    pub images: Vec<(NaiveDateTime, PathBuf)>,
+    /// Ranges of `images` taken in a burst, in order
+    pub bursts: Vec<Range<usize>>,
+}
+
+/// A line in the list of a bucket
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    /// The image at this index
+    Image(usize),
+    /// A burst, followed by a line for each of its frames when it's expanded
+    Burst(Range<usize>),
+}
+
+impl Bucket {
+    /// The lines listing the bucket. Bursts are collapsed into one line unless
+    /// their first frame is in `expanded`.
+    pub fn lines(&self, expanded: &HashSet<PathBuf>) -> Vec<Entry> {
+        let mut lines = Vec::new();
+        let mut i = 0;
+        while i < self.images.len() {
+            match self.bursts.iter().find(|burst| burst.start == i) {
+                Some(burst) => {
+                    lines.push(Entry::Burst(burst.clone()));
+                    if expanded.contains(&self.images[i].1) {
+                        lines.extend(burst.clone().map(Entry::Image));
+                    }
+                    i = burst.end;
+                }
+                None => {
+                    lines.push(Entry::Image(i));
+                    i += 1;
+                }
+            }
+        }
+        lines
+    }
+
+    /// The paths of all the images in the bucket
+    pub fn paths_all(&self) -> Vec<PathBuf> {
+        self.images.iter().map(|(_, path)| path.clone()).collect()
+    }
+
+    /// The paths of the images on `line`, every frame for a burst
+    pub fn paths(&self, line: &Entry) -> Vec<PathBuf> {
+        let range = match line {
+            Entry::Image(i) => *i..*i + 1,
+            Entry::Burst(range) => range.clone(),
+        };
+        self.images[range]
+            .iter()
+            .map(|(_, path)| path.clone())
+            .collect()
+    }
+}
+
+/// Where `bresson timeline` is in a timeline
+#[derive(Debug, Default)]
+pub struct View {
+    /// Index of the bucket whose images are listed
+    pub bucket: usize,
+    /// Index of the highlighted line in the list, if any
+    pub line: Option<usize>,
+    /// First frames of the bursts that are expanded
+    pub expanded: HashSet<PathBuf>,
+    /// What the last operation did
+    pub status: String,
+}
+
+impl View {
+    /// Shows the images of the bucket at `index`, without a line highlighted
+    pub fn select_bucket(&mut self, index: usize) {
+        if index != self.bucket {
+            self.bucket = index;
+            self.line = None;
+        }
+    }
+
+    /// Highlights the line `offset` lines away from the highlighted one, the
+    /// first one if none is
+    pub fn move_line(&mut self, timeline: &Timeline, offset: isize) {
+        let count = timeline
+            .buckets
+            .get(self.bucket)
+            .map_or(0, |b| b.lines(&self.expanded).len());
+        if count == 0 {
+            return;
+        }
+        self.line = Some(match self.line {
+            Some(line) => line.saturating_add_signed(offset).min(count - 1),
+            None => 0,
+        });
+    }
+
+    /// The highlighted line
+    pub fn selected<'a>(&self, timeline: &'a Timeline) -> Option<(&'a Bucket, Entry)> {
+        let bucket = timeline.buckets.get(self.bucket)?;
+        let line = bucket.lines(&self.expanded).get(self.line?)?.clone();
+        Some((bucket, line))
+    }
+
+    /// Expands the highlighted burst, or collapses the one it's in
+    pub fn toggle_burst(&mut self, timeline: &Timeline) {
+        let Some((bucket, line)) = self.selected(timeline) else {
+            return;
+        };
+        let burst = match line {
+            Entry::Burst(range) => range,
+            Entry::Image(i) => match bucket.bursts.iter().find(|b| b.contains(&i)) {
+                Some(burst) => burst.clone(),
+                None => return,
+            },
+        };
+        let first = bucket.images[burst.start].1.clone();
+        if !self.expanded.remove(&first) {
+            self.expanded.insert(first);
+        }
+        // Stay on the burst rather than on whatever moved into its place
+        let lines = bucket.lines(&self.expanded);
+        self.line = lines.iter().position(|l| *l == Entry::Burst(burst.clone()));
+    }
+}
+
+/// When an image was taken, and by which camera
+struct Shot {
+    time: NaiveDateTime,
+    /// Whether the time has a fraction of a second
+    precise: bool,
+    camera: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Timeline {
+    pub buckets: Vec<Bucket>,
+    pub hourly: bool,
+    /// Images without a capture time
+    pub undated: usize,
+    /// Flags given to the images, from their sidecars
+    pub flags: HashMap<PathBuf, Flag>,
+}
+
+impl Timeline {
+    pub fn load(paths: &[PathBuf]) -> Result<Self> {
+        let mut images = Vec::new();
+        let mut undated = 0;
+        let mut flags = HashMap::new();
+        for path in batch::collect_images(paths)? {
+            if let Some(flag) = Sidecar::open(&path).ok().and_then(|s| s.flag) {
+                flags.insert(path.clone(), flag);
+            }
+            match shot(&path) {
+                Some(shot) => images.push((shot.time, path, shot)),
+                None => undated += 1,
+            }
+        }
+        images.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+        let hourly = match (images.first(), images.last()) {
+            (Some(first), Some(last)) => first.0.date() == last.0.date(),
+            _ => false,
+        };
+        let mut buckets: Vec<Bucket> = Vec::new();
+        let mut previous: Option<Shot> = None;
+        for (time, path, shot) in images {
+            let start = if hourly {
+                time.date().and_hms_opt(time.hour(), 0, 0).unwrap_or(time)
+            } else {
+                time.date().and_time(Default::default())
+            };
+            let bucket = match buckets.last_mut() {
+                Some(bucket) if bucket.start == start => bucket,
+                _ => {
+                    previous = None;
+                    buckets.push(Bucket {
+                        start,
+                        images: Vec::new(),
+                        bursts: Vec::new(),
+                    });
+                    buckets.last_mut().expect("just pushed")
+                }
+            };
+            let i = bucket.images.len();
+            if previous.is_some_and(|p| is_next_frame(&p, &shot)) {
+                match bucket.bursts.last_mut() {
+                    Some(burst) if burst.end == i => burst.end += 1,
+                    _ => bucket.bursts.push(i - 1..i + 1),
+                }
+            }
+            bucket.images.push((time, path));
+            previous = Some(shot);
+        }
+
+        Ok(Self {
+            buckets,
+            hourly,
+            undated,
+            flags,
+        })
+    }
+
+    /// Moves the flags of `paths` on together, to the one after the flag of
+    /// the first of them, and saves it in their sidecars
+    pub fn cycle_flags(&mut self, paths: &[PathBuf]) -> String {
+        let Some(first) = paths.first() else {
+            return String::from("Nothing to flag");
+        };
+        let flag = Flag::cycle(self.flags.get(first).copied());
+        for path in paths {
+            let saved = Sidecar::open(path).and_then(|mut sidecar| {
+                sidecar.flag = flag;
+                sidecar.save(path)
+            });
+            if let Err(e) = saved {
+                return format!("{}: {:#}", path.display(), e);
+            }
+            match flag {
+                Some(flag) => self.flags.insert(path.clone(), flag),
+                None => self.flags.remove(path),
+            };
+        }
+        match flag {
+            Some(flag) => format!("Flagged {} image(s) to {}", paths.len(), flag.name()),
+            None => format!("Removed the flag of {} image(s)", paths.len()),
+        }
+    }
+
+    pub fn label(&self, bucket: &Bucket) -> String {
+        if self.hourly {
+            bucket.start.format("%H:00").to_string()
+        } else {
+            bucket.start.format("%m-%d").to_string()
+        }
+    }
+}
+
+/// Whether `next` was taken by the same camera as `previous`, soon enough after
+/// it to be the next frame of a burst
+fn is_next_frame(previous: &Shot, next: &Shot) -> bool {
+    previous.precise
+        && next.precise
+        && previous.camera == next.camera
+        && next.time - previous.time <= TimeDelta::milliseconds(BURST_GAP_MS)
+}
+
+fn shot(path: &Path) -> Option<Shot> {
+    let img_buf = std::fs::read(path).ok()?;
+    let exif = Reader::new()
+        .read_from_container(&mut Cursor::new(&img_buf))
+        .ok()?;
+    let field = |tag| exif.get_field(tag, In::PRIMARY);
+    let (time, _) = utils::capture_time(field)?;
+    let ascii = |tag| field(tag).map(|f| utils::display_text(f).trim().to_owned());
+    // The fraction of a second belonging to the time that was found
+    let subsec = [
+        (Tag::DateTimeOriginal, Tag::SubSecTimeOriginal),
+        (Tag::DateTimeDigitized, Tag::SubSecTimeDigitized),
+        (Tag::DateTime, Tag::SubSecTime),
+    ]
+    .into_iter()
+    .find(|(dt, _)| field(*dt).is_some())
+    .and_then(|(_, subsec)| ascii(subsec))
+    .filter(|s| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()));
+    let (time, precise) = match subsec {
+        Some(digits) => {
+            // "5" is half a second, "05" a twentieth
+            let nanos: String = digits.chars().chain("000000000".chars()).take(9).collect();
+            let nanos = nanos.parse().unwrap_or(0);
+            (time.with_nanosecond(nanos).unwrap_or(time), true)
+        }
+        None => (time, false),
+    };
+    let camera = [Tag::Make, Tag::Model, Tag::BodySerialNumber]
+        .into_iter()
+        .map(|tag| ascii(tag).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(Shot {
+        time,
+        precise,
+        camera: Some(camera).filter(|c| !c.trim().is_empty()),
+    })
+}
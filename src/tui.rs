@@ -1,16 +1,21 @@
 use anyhow::Result;
 use crossterm::{
+    cursor::MoveTo,
+    style::Print,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-    ExecutableCommand,
+    ExecutableCommand, QueueableCommand,
 };
-use ratatui::{
-    backend::Backend,
-    prelude::{CrosstermBackend, Terminal},
+use ratatui::prelude::{CrosstermBackend, Terminal};
+use ratatui_image::picker::Picker;
+use std::{
+    io::{stdout, Stdout, Write},
+    panic,
 };
-use std::{io::stdout, panic};
+
+use crate::globe;
 
 // Have the terminal be generic over a backend
-pub fn init_terminal() -> Result<Terminal<impl Backend>> {
+pub fn init_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>> {
     enable_raw_mode()?;
     stdout().execute(EnterAlternateScreen)?;
     let terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
@@ -32,3 +37,96 @@ pub fn restore_terminal() -> Result<()> {
 
     Ok(())
 }
+
+/// Runs `session` on the terminal taken over by `init_terminal`, restoring it
+/// however the session ends. An error from the session wins over one from
+/// restoring the terminal
+pub fn run<T>(
+    session: impl FnOnce(&mut Terminal<CrosstermBackend<Stdout>>) -> Result<T>,
+) -> Result<T> {
+    install_panic_hook();
+    let mut terminal = init_terminal()?;
+    let result = session(&mut terminal);
+    let restored = restore_terminal();
+    let value = result?;
+    restored?;
+    Ok(value)
+}
+
+// Hyperlinks
+//
+// Terminals that know OSC 8 turn text between two escape sequences into a
+// link. ratatui counts the escape sequences as wide text, so links aren't
+// rendered with the rest of the frame - the text is drawn as usual, and then
+// drawn again over itself wrapped in the sequences once the frame is out.
+
+/// Text drawn at `x`, `y` that links to `url`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hyperlink {
+    pub x: u16,
+    pub y: u16,
+    pub text: String,
+    pub url: String,
+}
+
+/// Height of a cell over its width, from the font size the terminal reports
+pub fn font_ratio() -> f64 {
+    match Picker::from_termios() {
+        Ok(picker) if picker.font_size.0 > 0 && picker.font_size.1 > 0 => {
+            f64::from(picker.font_size.1) / f64::from(picker.font_size.0)
+        }
+        _ => globe::DEFAULT_FONT_RATIO,
+    }
+}
+
+/// Whether the terminal is known to support OSC 8 links. BRESSON_HYPERLINKS=1
+/// or 0 overrides the guess
+pub fn supports_hyperlinks() -> bool {
+    let var = |name| std::env::var(name).ok();
+    match var("BRESSON_HYPERLINKS").as_deref() {
+        Some("1") => return true,
+        Some("0") => return false,
+        _ => {}
+    }
+    let known_program = var("TERM_PROGRAM").is_some_and(|p| {
+        ["iTerm.app", "WezTerm", "vscode", "ghostty", "Hyper"].contains(&p.as_str())
+    });
+    let known_term =
+        var("TERM").is_some_and(|t| ["kitty", "alacritty", "foot"].iter().any(|k| t.contains(k)));
+    // GNOME Terminal and the other VTE based ones since 0.50
+    let vte = var("VTE_VERSION")
+        .and_then(|v| v.parse::<u32>().ok())
+        .is_some_and(|v| v >= 5000);
+    known_program || known_term || vte || var("WT_SESSION").is_some()
+}
+
+/// Draws `links` over the text of the last frame
+pub fn write_hyperlinks(links: &[Hyperlink]) -> Result<()> {
+    if links.is_empty() {
+        return Ok(());
+    }
+    let mut out = stdout();
+    for link in links {
+        out.queue(MoveTo(link.x, link.y))?.queue(Print(format!(
+            "\x1B]8;;{}\x1B\\{}\x1B]8;;\x1B\\",
+            link.url, link.text
+        )))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// `file://` URL of `path`
+pub fn file_url(path: &std::path::Path) -> String {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut url = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
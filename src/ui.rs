@@ -1,17 +1,35 @@
-use crate::{globe, state::*};
+use crate::{
+    cluster::{self, Cluster},
+    editor::{Editor, Input},
+    elevation::Profile,
+    globe::{Globe, Marker},
+    image::ThreadImage,
+    osm,
+    sidecar::Flag,
+    state::*,
+    timeline::{Entry, Timeline, View},
+    tui::{self, Hyperlink},
+    widget::GlobeWidget,
+};
+use anyhow::Result;
 use ratatui::{
+    backend::TestBackend,
+    buffer::Buffer,
     layout::{Constraint, Direction, Layout},
     prelude::*,
     style::{Color, Modifier, Style},
     symbols,
-    widgets::{canvas::*, Block, Borders, Clear, Paragraph, Row, Table, TableState},
+    widgets::{
+        Axis, Bar, BarChart, BarGroup, Block, Borders, Chart, Clear, Dataset, GraphType, Paragraph,
+        Row, Table, TableState,
+    },
     Frame,
 };
-use ratatui_image::{thread::ThreadImage, Resize};
+use ratatui_image::Resize;
 
 fn _render_filename(app: &mut Application, frame: &mut Frame, area: Rect) {
     frame.render_widget(
-        Paragraph::new(app.path_to_image.display().to_string())
+        Paragraph::new(app.document.path_to_image.display().to_string())
             .style(Style::new().italic().bold().green())
             .block(
                 Block::new()
@@ -31,19 +49,66 @@ fn render_metadata_table(
     area: Rect,
 ) {
     // let widths = [Constraint::Ratio(1, 3), Constraint::Ratio(2, 3)];
-    let widths = Constraint::from_mins([100, 100]);
-    let exif_table = Table::new(app.process_rows(frame.area().width), widths).column_spacing(1);
+    let widths = [
+        Constraint::Fill(1),
+        Constraint::Fill(1),
+        Constraint::Length(5),
+    ];
+    let mut title = String::from("Image Metadata");
+    if app.images.len() > 1 {
+        title.push_str(&format!(" ({}/{})", app.current + 1, app.images.len()));
+    }
+    if let Some(flag) = app.document.sidecar.flag {
+        title.push_str(&format!(" [{}]", flag.name()));
+    }
+    if !app.ui.query().is_empty() {
+        title.push_str(&format!(" /{}", app.ui.query()));
+    }
+    // Which byte order the metadata is in, and what copies are converted to
+    let read = app.document.backend.as_exif().map(|exif| exif.byte_order());
+    let byte_order = match (read, app.document.byte_order) {
+        (Some(read), Some(order)) => {
+            format!(" {}, {} → {}", app.document.backend.name(), read, order)
+        }
+        (Some(read), None) => format!(" {}, {}", app.document.backend.name(), read),
+        (None, _) => String::new(),
+    };
+    // How much of the segment a JPEG keeps the Exif data in copies would fill
+    let mut info = vec![Span::raw(byte_order)];
+    if let Some((len, limit)) = app.document.segment_budget() {
+        let style = match len > limit {
+            true => Style::new().red(),
+            false => Style::default(),
+        };
+        let budget = format!(
+            "APP1 {:.1}/{:.0} KB",
+            len as f64 / 1024.,
+            limit as f64 / 1024.
+        );
+        info.push(Span::raw(", "));
+        info.push(Span::styled(budget, style));
+    }
+    if info.iter().any(|span| !span.content.is_empty()) {
+        info.push(Span::raw(" "));
+    }
+    let exif_table = Table::new(
+        app.document
+            .process_rows(frame.area().width, app.ui.show_internal, app.ui.query()),
+        widths,
+    )
+    .column_spacing(1);
 
     frame.render_stateful_widget(
         exif_table
             .block(
                 Block::new()
-                    .title("Image Metadata")
+                    .title(title)
+                    .title(text::Line::from(info).right_aligned())
                     .title_style(Style::new().bold())
                     .border_set(symbols::border::ROUNDED)
                     .borders(Borders::TOP | Borders::RIGHT | Borders::LEFT), // .padding(Padding::uniform(1)),
             )
-            .header(Row::new(vec!["Tag", "Data"]).bold().underlined())
+            .header(Row::new(vec!["Tag", "Data", "Bytes"]).bold().underlined())
             .highlight_style(
                 Style::new()
                     .add_modifier(Modifier::BOLD)
@@ -57,71 +122,63 @@ fn render_metadata_table(
 }
 
 fn render_globe(app: &mut Application, frame: &mut Frame, area: Rect) {
+    let location = app.document.gps_info.to_string();
+    if app.ui.hyperlinks && app.document.has_gps && area.height > 1 {
+        let (lat, lon) = app.document.location();
+        // The bottom row inside the left border, where it is printed over the globe
+        app.ui.links.push(Hyperlink {
+            x: area.x + 1,
+            y: area.bottom() - 1,
+            text: location
+                .chars()
+                .take(area.width.saturating_sub(2) as usize)
+                .collect(),
+            url: osm::url(lat, lon),
+        });
+    }
     let collapsed_top_border_set = symbols::border::Set {
         top_left: symbols::line::ROUNDED.vertical_right,
         top_right: symbols::line::ROUNDED.vertical_left,
         ..symbols::border::ROUNDED
     };
 
-    frame.render_widget(
-        Canvas::default()
-            .block(
-                Block::default()
-                    .title(if app.has_gps {
-                        "Image Location"
-                    } else {
-                        "Globe"
-                    })
-                    .title_style(Style::new().bold())
-                    .border_set(collapsed_top_border_set)
-                    .borders(Borders::RIGHT | Borders::LEFT | Borders::TOP),
-            )
-            .x_bounds([0., 100.])
-            .y_bounds([0., 50.])
-            .paint(|ctx| {
-                // Globe Width should be 3/4 of the width of the frame to look spherical
-                // let adjusted_width = (layout[1].width as f64 * 0.75) as u16;
-                // println!("{:?}", adjusted_width);
-                ctx.layer();
-                let mut globe_canvas = globe::Canvas::new(75, 50, Some((1, 1)));
-                globe_canvas.clear();
-                app.globe.render_sphere(&mut globe_canvas);
-                let (size_x, size_y) = globe_canvas.get_size();
-
-                // Print GPS Coordinates in Bottom-Left Corner
-                ctx.print(0 as f64, 0 as f64, app.gps_info.to_string());
-
-                // default character size is 4 by 8
-                for i in 0..size_y {
-                    for j in 0..size_x {
-                        let translated_i = 50 - i;
-                        let translated_j = j as f64 + 12.5;
-                        match globe_canvas.matrix[i][j] {
-                            ' ' => ctx.print(translated_j as f64, translated_i as f64, " "),
-                            '.' | ':' | ';' => {
-                                let x = globe_canvas.matrix[i][j].to_string().dim();
-                                ctx.print(translated_j as f64, translated_i as f64, x);
-                            }
-                            x => {
-                                // Only useful when there is no z-axis panning going on
-                                let long_lat_color = if app.has_gps
-                                    && !app.should_rotate
-                                    && i == (size_y / 2) - 1
-                                    && j == (size_x / 2) - 1
-                                {
-                                    x.to_string().red().bold().rapid_blink()
-                                } else {
-                                    x.to_string().into()
-                                };
-                                ctx.print(translated_j as f64, translated_i as f64, long_lat_color)
-                                // ctx.print(translated_j as f64, translated_i as f64, x.to_string())
-                            }
-                        }
-                    }
-                }
-            }),
-        area, // centered_rect(layout[1], 80, 80),
+    let block = Block::default()
+        .title(if app.document.has_gps {
+            "Image Location"
+        } else {
+            "Globe"
+        })
+        .title_style(Style::new().bold())
+        .border_set(collapsed_top_border_set)
+        .borders(Borders::RIGHT | Borders::LEFT | Borders::TOP);
+    let highlight = app.ui.panning || app.document.has_gps && !app.ui.should_rotate;
+    frame.render_stateful_widget(
+        GlobeWidget::new(&app.ui.globe)
+            .block(block)
+            .highlight_center(highlight),
+        area,
+        &mut app.ui.globe_state,
     );
+
+    // GPS coordinates in the bottom-left corner, and the daylight at that
+    // place and time on the line above
+    let inner = app.ui.globe_state.area;
+    let lines = [
+        app.document.solar.map(|sun| sun.to_string()),
+        Some(location),
+    ];
+    for (i, line) in lines.into_iter().flatten().rev().enumerate() {
+        if i as u16 >= inner.height {
+            break;
+        }
+        frame.buffer_mut().set_stringn(
+            inner.x,
+            inner.bottom() - 1 - i as u16,
+            line,
+            inner.width as usize,
+            Style::new(),
+        );
+    }
 }
 
 fn render_image(app: &mut Application, frame: &mut Frame, area: Rect) {
@@ -131,8 +188,12 @@ fn render_image(app: &mut Application, frame: &mut Frame, area: Rect) {
         ..symbols::border::ROUNDED
     };
 
+    let title = match app.ui.compare {
+        Some(split) => format!("Thumbnail {}% | Embedded Thumbnail", split),
+        None => String::from("Thumbnail"),
+    };
     let block = Block::default()
-        .title("Thumbnail")
+        .title(title)
         .title_style(Style::new().bold())
         .border_set(collapsed_top_border_set)
         .borders(Borders::RIGHT | Borders::LEFT | Borders::TOP);
@@ -140,10 +201,47 @@ fn render_image(app: &mut Application, frame: &mut Frame, area: Rect) {
     let rect = centered_rect(block.inner(area), 50, 100);
     let image = ThreadImage::default().resize(Resize::Fit(None));
 
-    frame.render_stateful_widget(image, rect, &mut app.async_state);
+    frame.render_stateful_widget(image, rect, &mut app.ui.async_state);
     frame.render_widget(block.clone(), area);
 }
 
+fn render_map(app: &mut Application, frame: &mut Frame, area: Rect) {
+    let collapsed_top_border_set = symbols::border::Set {
+        top_left: symbols::line::NORMAL.vertical_right,
+        top_right: symbols::line::NORMAL.vertical_left,
+        ..symbols::border::ROUNDED
+    };
+
+    let block = Block::default()
+        .title("Map")
+        .title_bottom(text::Line::from(osm::ATTRIBUTION).right_aligned())
+        .title_style(Style::new().bold())
+        .border_set(collapsed_top_border_set)
+        .borders(Borders::RIGHT | Borders::LEFT | Borders::TOP);
+
+    let inner = block.inner(area);
+    match app.ui.map_state.as_mut() {
+        Some(map_state) => {
+            let rect = centered_rect(inner, 50, 100);
+            let image = ThreadImage::default().resize(Resize::Fit(None));
+            frame.render_stateful_widget(image, rect, map_state);
+            if let Some((col, row)) = app.ui.map_cursor_cell(rect) {
+                let cursor = Rect::new(rect.x + col, rect.y + row, 1, 1);
+                frame.render_widget(Paragraph::new("+").red().bold(), cursor);
+            }
+        }
+        None => {
+            let msg = if app.document.has_gps {
+                "Loading map..."
+            } else {
+                "No location to show"
+            };
+            frame.render_widget(Paragraph::new(msg).italic().centered(), inner);
+        }
+    }
+    frame.render_widget(block, area);
+}
+
 fn render_status_msg(app: &mut Application, frame: &mut Frame, area: Rect) {
     let collapsed_top_border_set = symbols::border::Set {
         top_left: symbols::line::ROUNDED.vertical_right,
@@ -151,22 +249,93 @@ fn render_status_msg(app: &mut Application, frame: &mut Frame, area: Rect) {
         // bottom_left: symbols::line::NORMAL.horizontal_up,
         ..symbols::border::ROUNDED
     };
+    let mut block = Block::new()
+        .title("Status")
+        .title_style(Style::new().bold())
+        .borders(Borders::ALL)
+        .border_set(collapsed_top_border_set);
+    let path = &app.document.path_to_image;
+    let file_name = path
+        .file_name()
+        .map_or(String::new(), |n| n.to_string_lossy().into_owned());
+    if !file_name.is_empty() {
+        block = block.title_bottom(format!(" {} ", file_name));
+        if app.ui.hyperlinks {
+            // After the corner and the space before the name
+            app.ui.links.push(Hyperlink {
+                x: area.x + 2,
+                y: area.bottom() - 1,
+                text: file_name
+                    .chars()
+                    .take(area.width.saturating_sub(4) as usize)
+                    .collect(),
+                url: tui::file_url(path),
+            });
+        }
+    }
+    // What's under the crosshair while the globe is turned by hand, or under the
+    // cursor while picking a location
+    if let Some((lat, lon)) = app.ui.crosshair() {
+        let (name, hint) = if app.ui.picking {
+            ("Cursor", "<Enter>")
+        } else {
+            ("Crosshair", "<h>")
+        };
+        block = block.title(
+            text::Line::from(format!(
+                " {} {:.4}°{} {:.4}°{} ({} to set) ",
+                name,
+                lat.abs(),
+                if lat < 0. { 'S' } else { 'N' },
+                lon.abs(),
+                if lon < 0. { 'W' } else { 'E' },
+                hint
+            ))
+            .right_aligned(),
+        );
+    }
+    frame.render_widget(Paragraph::new(app.ui.status_msg.clone()).block(block), area);
+}
+
+/// Most notes shown at once, the latest ones
+const MAX_NOTES_SHOWN: usize = 5;
+
+fn render_notes(app: &mut Application, frame: &mut Frame, area: Rect) {
+    let notes = &app.document.sidecar.notes;
+    let lines = notes
+        .iter()
+        .skip(notes.len().saturating_sub(MAX_NOTES_SHOWN))
+        .map(|note| {
+            text::Line::from(vec![
+                Span::styled(format!("{} ", note.time), Style::new().dim()),
+                Span::raw(note.text.as_str()),
+            ])
+        })
+        .collect::<Vec<_>>();
     frame.render_widget(
-        Paragraph::new(app.status_msg.clone()).block(
+        Paragraph::new(lines).block(
             Block::new()
-                .title("Status")
+                .title(format!("Notes ({})", notes.len()))
                 .title_style(Style::new().bold())
                 .borders(Borders::ALL)
-                .border_set(collapsed_top_border_set),
+                .border_set(symbols::border::ROUNDED),
         ),
         area,
     );
 }
 
 fn render_keybind_popup(app: &mut Application, frame: &mut Frame) {
-    let pop_area = centered_rect(frame.area(), 50, 50);
+    let rows = app.ui.keybind_rows();
+    // Tall enough for every row, as far as the terminal allows
+    let height = rows.len() as u16 + 2;
+    let pop_area = centered_rect(frame.area(), 50, 100);
+    let pop_area = Rect {
+        y: pop_area.y + pop_area.height.saturating_sub(height) / 2,
+        height: height.min(pop_area.height),
+        ..pop_area
+    };
     let widths = [Constraint::Ratio(1, 3), Constraint::Ratio(2, 3)];
-    let keybind_table = Table::new(app.keybind_rows(), widths).column_spacing(1);
+    let keybind_table = Table::new(rows, widths).column_spacing(1);
     frame.render_widget(Clear, pop_area);
     frame.render_widget(
         keybind_table.block(
@@ -180,8 +349,135 @@ fn render_keybind_popup(app: &mut Application, frame: &mut Frame) {
     )
 }
 
+fn render_editor_popup(editor: &Editor, frame: &mut Frame) {
+    let height = match &editor.input {
+        Input::Choice { choices, .. } => choices.len() as u16 + 2,
+        Input::Text(_) => 3,
+    };
+    let pop_area = centered_rect(frame.area(), 40, 100);
+    let pop_area = Rect {
+        y: pop_area.y + pop_area.height.saturating_sub(height) / 2,
+        height: height.min(pop_area.height),
+        ..pop_area
+    };
+    let block = Block::new()
+        .title(editor.target.to_string())
+        .title_style(Style::new().bold())
+        .title_bottom(" <Enter> sets, <Esc> cancels ")
+        .borders(Borders::ALL)
+        .border_set(symbols::border::ROUNDED);
+    frame.render_widget(Clear, pop_area);
+    match &editor.input {
+        Input::Choice { choices, selected } => {
+            let rows = choices.iter().enumerate().map(|(i, (_, name))| {
+                let row = Row::new(vec![*name]);
+                if i == *selected {
+                    row.style(Style::new().reversed())
+                } else {
+                    row
+                }
+            });
+            frame.render_widget(
+                Table::new(rows, [Constraint::Fill(1)]).block(block),
+                pop_area,
+            )
+        }
+        // Long text scrolls so the end of it stays in sight
+        Input::Text(text) => {
+            let overflow =
+                (text.chars().count() + 1).saturating_sub(block.inner(pop_area).width as usize);
+            frame.render_widget(
+                Paragraph::new(text::Line::from(vec![
+                    Span::raw(text.as_str()),
+                    Span::raw(" ").reversed(),
+                ]))
+                .scroll((0, overflow as u16))
+                .block(block),
+                pop_area,
+            )
+        }
+    }
+}
+
+/// Asks whether to go ahead with a bulk operation
+fn render_confirm_popup(message: Message, frame: &mut Frame) {
+    let question = match message {
+        Message::ClearAll => "Clear every field?",
+        Message::RandomizeAll => "Randomize every field?",
+        _ => "Go ahead?",
+    };
+    let pop_area = centered_rect(frame.area(), 40, 100);
+    let pop_area = Rect {
+        y: pop_area.y + pop_area.height.saturating_sub(3) / 2,
+        height: pop_area.height.min(3),
+        ..pop_area
+    };
+    let block = Block::new()
+        .title("Confirm")
+        .title_style(Style::new().bold())
+        .title_bottom(" y goes ahead, n cancels ")
+        .borders(Borders::ALL)
+        .border_set(symbols::border::ROUNDED);
+    frame.render_widget(Clear, pop_area);
+    frame.render_widget(Paragraph::new(question).block(block), pop_area);
+}
+
+/// Frame times and events per second over the last second, top right
+fn render_debug_overlay(app: &Application, frame: &mut Frame) {
+    let perf = &app.ui.perf;
+    let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.;
+    let lines = vec![
+        Line::from(format!(
+            "{:.1} ms/frame, max {:.1}",
+            ms(perf.frame_time()),
+            ms(perf.slowest_frame())
+        )),
+        Line::from(format!(
+            "{} fps, {} events/s",
+            perf.frames_per_sec(),
+            perf.events_per_sec()
+        )),
+    ];
+    let area = frame.area();
+    let width = 30.min(area.width);
+    let pop_area = Rect {
+        x: area.right() - width,
+        y: area.y,
+        width,
+        height: 4.min(area.height),
+    };
+    let block = Block::new()
+        .title("Debug")
+        .title_style(Style::new().bold())
+        .borders(Borders::ALL)
+        .border_set(symbols::border::ROUNDED);
+    frame.render_widget(Clear, pop_area);
+    frame.render_widget(Paragraph::new(lines).block(block), pop_area);
+}
+
+/// The metadata table, with the notes about the image below it if it has any
+fn render_table_and_notes(
+    app: &mut Application,
+    frame: &mut Frame,
+    table_state: &mut TableState,
+    area: Rect,
+) {
+    let notes = app.document.sidecar.notes.len().min(MAX_NOTES_SHOWN);
+    if notes == 0 {
+        render_metadata_table(app, frame, table_state, area);
+        return;
+    }
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(notes as u16 + 2)])
+        .split(area);
+    render_metadata_table(app, frame, table_state, layout[0]);
+    render_notes(app, frame, layout[1]);
+}
+
 pub fn view(app: &mut Application, frame: &mut Frame, table_state: &mut TableState) {
-    if app.show_mini {
+    app.ui.links.clear();
+    if app.ui.show_mini {
         let layout = Layout::default()
             .direction(Direction::Vertical)
             .constraints(vec![
@@ -190,10 +486,11 @@ pub fn view(app: &mut Application, frame: &mut Frame, table_state: &mut TableSta
                 Constraint::Max(5),
             ])
             .split(frame.area());
-        render_metadata_table(app, frame, table_state, layout[0]);
-        match app.render_state {
+        render_table_and_notes(app, frame, table_state, layout[0]);
+        match app.ui.render_state {
             RenderState::Globe => render_globe(app, frame, layout[1]),
             RenderState::Thumbnail => render_image(app, frame, layout[1]),
+            RenderState::Map => render_map(app, frame, layout[1]),
         };
         render_status_msg(app, frame, layout[2]);
     } else {
@@ -205,18 +502,380 @@ pub fn view(app: &mut Application, frame: &mut Frame, table_state: &mut TableSta
                 Constraint::Max(5),
             ])
             .split(frame.area());
-        render_metadata_table(app, frame, table_state, layout[0]);
+        render_table_and_notes(app, frame, table_state, layout[0]);
         render_status_msg(app, frame, layout[1]);
     }
 
-    if app.show_keybinds {
+    if let Some(editor) = &app.ui.editor {
+        render_editor_popup(editor, frame);
+    }
+    if let Some(message) = app.ui.confirming {
+        render_confirm_popup(message, frame);
+    }
+    if app.ui.show_debug {
+        render_debug_overlay(app, frame);
+    }
+    if let Some(profile) = &app.ui.elevation {
+        render_elevation_popup(profile, frame);
+    }
+    if app.ui.show_keybinds {
         render_keybind_popup(app, frame);
     }
 }
 
+/// Renders the main view into a `TestBackend` of `width` x `height` cells, with
+/// the first row of the table selected
+pub fn render_to_buffer(app: &mut Application, width: u16, height: u16) -> Result<Buffer> {
+    let mut terminal = Terminal::new(TestBackend::new(width, height))?;
+    let mut table_state = TableState::new().with_selected(Some(0));
+    terminal.draw(|frame| view(app, frame, &mut table_state))?;
+    Ok(terminal.backend().buffer().clone())
+}
+
+const CLUSTER_MARKER: char = '●';
+const SELECTED_CLUSTER_MARKER: char = '◉';
+const PATH_MARKER: char = '·';
+
+/// Globe markers for `clusters`, sized by the number of images in each, on top
+/// of arcs along `path`
+pub fn cluster_markers(clusters: &[Cluster], selected: usize, path: &[(f64, f64)]) -> Vec<Marker> {
+    let marker = |cluster: &Cluster, symbol| Marker {
+        lat: cluster.lat as f32,
+        lon: cluster.lon as f32,
+        radius: 2. + 2. * (cluster.images.len() as f32).ln(),
+        symbol,
+    };
+    // One degree apart is about as fine as the globe texture gets
+    let mut markers = path
+        .windows(2)
+        .flat_map(|w| cluster::great_circle(w[0], w[1], 1.))
+        .map(|(lat, lon)| Marker {
+            lat: lat as f32,
+            lon: lon as f32,
+            radius: 0.,
+            symbol: PATH_MARKER,
+        })
+        .collect::<Vec<_>>();
+    // The selected cluster goes last so that it's drawn on top
+    markers.extend(
+        clusters
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != selected)
+            .map(|(_, c)| marker(c, CLUSTER_MARKER)),
+    );
+    markers.extend(
+        clusters
+            .get(selected)
+            .map(|c| marker(c, SELECTED_CLUSTER_MARKER)),
+    );
+    markers
+}
+
+/// Globe with a marker per cluster next to the list of clusters and the
+/// images of the selected one
+pub fn render_clusters(
+    globe: &Globe,
+    clusters: &[Cluster],
+    untagged: usize,
+    table_state: &mut TableState,
+    frame: &mut Frame,
+) {
+    let [globe_area, list_area] =
+        Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .areas(frame.area());
+    let [clusters_area, images_area] =
+        Layout::vertical([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(list_area);
+
+    let block = Block::new()
+        .title("Clusters")
+        .title_style(Style::new().bold())
+        .title_bottom(" ↑/↓ to move, p to toggle the path, Enter to print, q to quit ")
+        .borders(Borders::ALL)
+        .border_set(symbols::border::ROUNDED);
+    frame.render_widget(
+        GlobeWidget::new(globe)
+            .block(block)
+            .marker_style(CLUSTER_MARKER, Style::new().red().bold())
+            .marker_style(PATH_MARKER, Style::new().cyan().bold())
+            .marker_style(SELECTED_CLUSTER_MARKER, Style::new().yellow().bold()),
+        globe_area,
+    );
+
+    let rows = clusters.iter().enumerate().map(|(i, cluster)| {
+        Row::new(vec![
+            (i + 1).to_string(),
+            format!("{:.4}, {:.4}", cluster.lat, cluster.lon),
+            cluster.images.len().to_string(),
+        ])
+    });
+    frame.render_stateful_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Length(4),
+                Constraint::Fill(1),
+                Constraint::Length(6),
+            ],
+        )
+        .header(Row::new(vec!["#", "Center", "Images"]).bold())
+        .highlight_style(Style::new().yellow().bold())
+        .highlight_symbol("> ")
+        .block(
+            Block::new()
+                .title(format!("{} clusters", clusters.len()))
+                .title_style(Style::new().bold())
+                .title_bottom(format!(" {} images without a location ", untagged))
+                .borders(Borders::ALL)
+                .border_set(symbols::border::ROUNDED),
+        ),
+        clusters_area,
+        table_state,
+    );
+
+    let images = table_state
+        .selected()
+        .and_then(|i| clusters.get(i))
+        .map_or(Vec::new(), |c| {
+            c.images
+                .iter()
+                .map(|path| Row::new(vec![path.display().to_string()]))
+                .collect()
+        });
+    frame.render_widget(
+        Table::new(images, [Constraint::Fill(1)]).block(
+            Block::new()
+                .title("Images")
+                .title_style(Style::new().bold())
+                .borders(Borders::ALL)
+                .border_set(symbols::border::ROUNDED),
+        ),
+        images_area,
+    );
+}
+
+/// Altitude over time of a photo session, drawn over the whole frame
+pub fn render_elevation(profile: &Profile, frame: &mut Frame) {
+    let points = profile.points();
+    frame.render_widget(elevation_chart(profile, &points, "q to quit"), frame.area());
+}
+
+/// The elevation of the open images over the rest of the interface
+fn render_elevation_popup(profile: &Profile, frame: &mut Frame) {
+    let points = profile.points();
+    let pop_area = centered_rect(frame.area(), 80, 80);
+    frame.render_widget(Clear, pop_area);
+    frame.render_widget(
+        elevation_chart(profile, &points, "a or Esc to close"),
+        pop_area,
+    );
+}
+
+/// Chart of `points`, the altitude of `profile` over time, with `hint` on how
+/// to close it at the bottom
+fn elevation_chart<'a>(profile: &Profile, points: &'a [(f64, f64)], hint: &str) -> Chart<'a> {
+    let (min, max) = profile.altitude_range();
+    let (gain, loss) = profile.gain_and_loss();
+    let duration = points.last().map_or(0., |p| p.0).max(1.);
+    // Leave some room above and below the track
+    let padding = ((max - min) * 0.1).max(5.);
+    let (y_min, y_max) = (min - padding, max + padding);
+
+    let time_format = match (profile.start(), profile.end()) {
+        (Some(start), Some(end)) if start.date() != end.date() => "%m-%d %H:%M",
+        _ => "%H:%M",
+    };
+    let time_label = |t: Option<chrono::NaiveDateTime>| {
+        t.map_or(String::new(), |t| t.format(time_format).to_string())
+    };
+    let mid = profile
+        .start()
+        .map(|s| s + chrono::Duration::seconds((duration * 30.) as i64));
+
+    let datasets = vec![
+        Dataset::default()
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::new().cyan())
+            .data(points),
+        Dataset::default()
+            .marker(symbols::Marker::Dot)
+            .graph_type(GraphType::Scatter)
+            .style(Style::new().red().bold())
+            .data(points),
+    ];
+    Chart::new(datasets)
+        .block(
+            Block::new()
+                .title("Elevation Profile")
+                .title_style(Style::new().bold())
+                .title_bottom(format!(
+                    " {} photos ({} skipped) | {:.0} m up, {:.0} m down | {} ",
+                    profile.samples.len(),
+                    profile.skipped,
+                    gain,
+                    loss,
+                    hint
+                ))
+                .borders(Borders::ALL)
+                .border_set(symbols::border::ROUNDED),
+        )
+        .x_axis(
+            Axis::default()
+                .title("Time")
+                .bounds([0., duration])
+                .labels(vec![
+                    time_label(profile.start()),
+                    time_label(mid),
+                    time_label(profile.end()),
+                ]),
+        )
+        .y_axis(
+            Axis::default()
+                .title("Altitude")
+                .bounds([y_min, y_max])
+                .labels(vec![
+                    format!("{:.0} m", y_min),
+                    format!("{:.0} m", (y_min + y_max) / 2.),
+                    format!("{:.0} m", y_max),
+                ]),
+        )
+}
+
+/// Bar per bucket of the timeline above the images of the `selected` bucket
+pub fn render_timeline(timeline: &Timeline, view: &View, frame: &mut Frame) {
+    let selected = view.bucket;
+    const BAR_WIDTH: u16 = 5;
+    let [chart_area, list_area] = Layout::vertical([Constraint::Percentage(50); 2])
+        .margin(1)
+        .areas(frame.area());
+
+    // Scroll the bars so that the selected one is always visible
+    let visible = usize::from((chart_area.width.saturating_sub(2) / (BAR_WIDTH + 1)).max(1));
+    let offset = selected.saturating_sub(visible - 1);
+    let bars = timeline
+        .buckets
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(visible)
+        .map(|(i, bucket)| {
+            let style = if i == selected {
+                Style::new().yellow().bold()
+            } else {
+                Style::new().cyan()
+            };
+            Bar::default()
+                .value(bucket.images.len() as u64)
+                .label(text::Line::from(timeline.label(bucket)))
+                .style(style)
+                .value_style(style.reversed())
+        })
+        .collect::<Vec<_>>();
+    frame.render_widget(
+        BarChart::default()
+            .block(
+                Block::new()
+                    .title("Timeline")
+                    .title_style(Style::new().bold())
+                    .title_bottom(format!(
+                        " {} images ({} without a capture time) | ←/→ to move, Enter to print, q to quit ",
+                        timeline.buckets.iter().map(|b| b.images.len()).sum::<usize>(),
+                        timeline.undated
+                    ))
+                    .borders(Borders::ALL)
+                    .border_set(symbols::border::ROUNDED),
+            )
+            .data(BarGroup::default().bars(&bars))
+            .bar_width(BAR_WIDTH)
+            .bar_gap(1),
+        chart_area,
+    );
+
+    let Some(bucket) = timeline.buckets.get(selected) else {
+        return;
+    };
+    let title = if timeline.hourly {
+        bucket.start.format("%Y-%m-%d %H:00").to_string()
+    } else {
+        bucket.start.format("%Y-%m-%d").to_string()
+    };
+    let lines = bucket.lines(&view.expanded);
+    let in_burst = |i: usize| bucket.bursts.iter().any(|b| b.contains(&i));
+    let rows = lines.iter().map(|line| {
+        let (first, last) = match line {
+            Entry::Image(i) => (*i, *i),
+            Entry::Burst(range) => (range.start, range.end - 1),
+        };
+        let (time, path) = &bucket.images[first];
+        // A burst carries a flag only if all its frames have the same one
+        let flag = timeline.flags.get(path).filter(|&flag| {
+            (first..=last).all(|i| timeline.flags.get(&bucket.images[i].1) == Some(flag))
+        });
+        let (time, name) = match line {
+            Entry::Image(i) if in_burst(*i) => (
+                time.format("%H:%M:%S%.3f").to_string(),
+                format!("  {}", path.display()),
+            ),
+            Entry::Image(_) => (
+                time.format("%H:%M:%S").to_string(),
+                path.display().to_string(),
+            ),
+            Entry::Burst(range) => {
+                let expanded = view.expanded.contains(path);
+                (
+                    time.format("%H:%M:%S").to_string(),
+                    format!(
+                        "{} Burst of {} - {} … {}",
+                        if expanded { "▾" } else { "▸" },
+                        range.len(),
+                        path.display(),
+                        bucket.images[last].1.display()
+                    ),
+                )
+            }
+        };
+        let row = Row::new(vec![time, flag.map_or("", |f| f.name()).to_owned(), name]);
+        match flag {
+            Some(Flag::Keep) => row.green(),
+            Some(Flag::Scrub) => row.red(),
+            Some(Flag::Review) => row.yellow(),
+            None => row,
+        }
+    });
+    let hint = match view.status.is_empty() {
+        true => String::from(" ↑/↓ to pick, Space to expand a burst, f to flag, s to scrub "),
+        false => format!(" {} ", view.status),
+    };
+    // Scrolls the list so the highlighted line stays in sight
+    let mut state = TableState::default().with_selected(view.line);
+    frame.render_stateful_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Length(12),
+                Constraint::Length(6),
+                Constraint::Fill(1),
+            ],
+        )
+        .column_spacing(2)
+        .highlight_style(Style::new().reversed())
+        .block(
+            Block::new()
+                .title(title)
+                .title_style(Style::new().bold())
+                .title_bottom(hint)
+                .borders(Borders::ALL)
+                .border_set(symbols::border::ROUNDED),
+        ),
+        list_area,
+        &mut state,
+    );
+}
+
 /// # Usage
 ///
-/// ```rust
+/// ```rust,ignore
 /// let rect = centered_rect(f.area(), 50, 50);
 /// ```
 fn centered_rect(r: Rect, percent_x: u16, percent_y: u16) -> Rect {
@@ -0,0 +1,119 @@
+use anyhow::{bail, Context as _, Result};
+use chrono::Utc;
+use exif::{Reader, Tag};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::vault::{self, Manifest};
+
+// Undo
+//
+// A batch scrub run by mistake can be taken back. With `--undo-journal` the
+// scrub writes down, for every copy, the values of the fields it dropped or
+// replaced - only those, so the journal stays small for a large library.
+// `bresson undo-batch` puts them back into the copies.
+//
+// The journal is JSON, or sealed like a vault with `--encrypt-undo` since it
+// holds the very locations and serial numbers the scrub took out. It's written
+// once the scrub is done, also when some files failed, and `--resume` adds to
+// the one it finds.
+
+const VERSION: u32 = 1;
+
+/// A copy and the original values of the fields the scrub changed in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoEntry {
+    pub original: PathBuf,
+    pub copy: PathBuf,
+    pub manifest: Manifest,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoJournal {
+    pub version: u32,
+    pub created: String,
+    pub entries: Vec<UndoEntry>,
+}
+
+impl Default for UndoJournal {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            created: Utc::now().to_rfc3339(),
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl UndoJournal {
+    /// Reads a plain or sealed journal, asking for the passphrase of a sealed one
+    /// unless `passphrase` is given
+    pub fn load(path: &Path, passphrase: Option<&str>) -> Result<Self> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("Unable to read undo journal {:?}", path))?;
+        let json = if vault::is_vault(&data) {
+            let passphrase = match passphrase {
+                Some(passphrase) => passphrase.to_owned(),
+                None => vault::read_passphrase(false)?,
+            };
+            vault::open(&data, &passphrase)?
+        } else {
+            data
+        };
+        let journal: UndoJournal = serde_json::from_slice(&json)
+            .with_context(|| format!("Invalid undo journal {:?}", path))?;
+        if journal.version > VERSION {
+            bail!("Unsupported undo journal version {}", journal.version);
+        }
+        Ok(journal)
+    }
+
+    /// Writes the journal, sealed with `passphrase` if there is one
+    pub fn write(&self, path: &Path, passphrase: Option<&str>) -> Result<()> {
+        let json = serde_json::to_vec(self)?;
+        let data = match passphrase {
+            Some(passphrase) => vault::seal(&json, passphrase)?,
+            None => json,
+        };
+        std::fs::write(path, data)
+            .with_context(|| format!("Unable to write undo journal {:?}", path))
+    }
+
+    /// Notes the original values of `changed` fields of the image at `original`,
+    /// scrubbed into `copy`
+    pub fn record(&mut self, original: &Path, copy: &Path, changed: &[Tag]) -> Result<()> {
+        if changed.is_empty() {
+            return Ok(());
+        }
+        let img_buf = std::fs::read(original)?;
+        let exif = Reader::new()
+            .read_from_container(&mut Cursor::new(&img_buf))
+            .with_context(|| format!("Unable to read the metadata of {:?}", original))?;
+        let mut manifest = Manifest::from_exif(original, &exif);
+        manifest
+            .fields
+            .retain(|f| f.to_field().is_some_and(|f| changed.contains(&f.tag)));
+        // A copy scrubbed again replaces what was noted the first time
+        self.entries.retain(|e| e.copy != copy);
+        self.entries.push(UndoEntry {
+            original: original.to_path_buf(),
+            copy: copy.to_path_buf(),
+            manifest,
+        });
+        Ok(())
+    }
+}
+
+impl UndoEntry {
+    /// Puts the original values back into the copy
+    pub fn undo(&self) -> Result<()> {
+        let img_buf = std::fs::read(&self.copy)
+            .with_context(|| format!("Unable to read copy {:?}", self.copy))?;
+        let restored = vault::restore(&img_buf, &self.manifest)?;
+        std::fs::write(&self.copy, restored)
+            .with_context(|| format!("Unable to write copy {:?}", self.copy))
+    }
+}
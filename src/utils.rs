@@ -1,9 +1,306 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use exif::{Context, Field, Rational, Tag, Value};
+use image::DynamicImage;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
 // const MULTIPLIER: f32 = 0.125;
 
-pub fn clean_disp(dv: &String) -> String {
+pub fn clean_disp(dv: &str) -> String {
     dv.trim_matches('"').replace("\\x00", "")
 }
 
+/// Name for a copy of `path` in the same directory, i.e. `copy-<name>-<timestamp>.<ext>`
+pub fn copy_file_name(path: &Path) -> PathBuf {
+    let file_stem = path
+        .file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let extension = path
+        .extension()
+        .map_or(String::new(), |ext| ext.to_string_lossy().into_owned());
+    let now: DateTime<Utc> = Utc::now();
+    let formatted_timestamp = now.format("%Y%m%d%H%M%S").to_string();
+
+    path.with_file_name(format!(
+        "copy-{}-{}.{}",
+        file_stem, formatted_timestamp, extension
+    ))
+}
+
+/// Looks up a well known tag by its Exif name, e.g. "DateTimeOriginal"
+pub fn tag_from_name(name: &str) -> Option<Tag> {
+    static TAGS: OnceLock<HashMap<String, Tag>> = OnceLock::new();
+    TAGS.get_or_init(|| {
+        let mut tags = HashMap::new();
+        for ctx in [Context::Tiff, Context::Exif, Context::Gps, Context::Interop] {
+            for number in 0..=u16::MAX {
+                let tag = Tag(ctx, number);
+                if tag.description().is_some() {
+                    tags.insert(tag.to_string(), tag);
+                }
+            }
+        }
+        tags.extend(XP_NAMES.iter().map(|name| name.to_string()).zip(XP_TAGS));
+        tags
+    })
+    .get(name)
+    .copied()
+}
+
+/// When the image was taken, as recorded by the camera, along with the UTC
+/// offset if one was stored. `field` looks up the primary field for a tag.
+pub fn capture_time<'a>(
+    field: impl Fn(Tag) -> Option<&'a Field>,
+) -> Option<(NaiveDateTime, Option<FixedOffset>)> {
+    let ascii = |tag| match field(tag).map(|f| &f.value) {
+        Some(Value::Ascii(v)) => v.first().cloned(),
+        _ => None,
+    };
+    let (dt, offset) = [
+        (Tag::DateTimeOriginal, Tag::OffsetTimeOriginal),
+        (Tag::DateTimeDigitized, Tag::OffsetTimeDigitized),
+        (Tag::DateTime, Tag::OffsetTime),
+    ]
+    .into_iter()
+    .find_map(|(dt, offset)| Some((ascii(dt)?, ascii(offset))))?;
+
+    let mut dt = exif::DateTime::from_ascii(&dt).ok()?;
+    let offset = offset
+        .and_then(|o| dt.parse_offset(&o).ok())
+        .and_then(|_| FixedOffset::east_opt(i32::from(dt.offset?) * 60));
+    let local = NaiveDate::from_ymd_opt(dt.year.into(), dt.month.into(), dt.day.into())?
+        .and_hms_opt(dt.hour.into(), dt.minute.into(), dt.second.into())?;
+    Some((local, offset))
+}
+
+/// Latitude and longitude in signed degrees, i.e. south and west are negative.
+/// `field` looks up the primary field for a tag.
+pub fn location<'a>(field: impl Fn(Tag) -> Option<&'a Field>) -> Option<(f64, f64)> {
+    // Degrees, minutes and seconds. Missing parts count as zero and parts
+    // divided by zero are skipped, so damaged fields are read as far as they go.
+    let degrees = |tag| match field(tag).map(|f| &f.value) {
+        Some(Value::Rational(v)) if !v.is_empty() => Some(
+            v.iter()
+                .zip([1., 60., 3600.])
+                .map(|(r, unit)| r.to_f64() / unit)
+                .filter(|part| part.is_finite())
+                .sum::<f64>(),
+        ),
+        _ => None,
+    };
+    let is_ref = |tag, negative: u8| match field(tag).map(|f| &f.value) {
+        Some(Value::Ascii(v)) => v.first().and_then(|r| r.first()) == Some(&negative),
+        _ => false,
+    };
+    let mut lat = degrees(Tag::GPSLatitude)?;
+    let mut lon = degrees(Tag::GPSLongitude)?;
+    if is_ref(Tag::GPSLatitudeRef, b'S') {
+        lat = -lat;
+    }
+    if is_ref(Tag::GPSLongitudeRef, b'W') {
+        lon = -lon;
+    }
+    Some((lat, lon))
+}
+
+/// Text in the tags whose value starts with an 8 byte character code, e.g.
+/// GPSProcessingMethod. UCS-2 text is in the byte order of the Exif data unless
+/// it starts with a byte order mark, JIS and unknown codes are shown as they are.
+pub fn decode_encoded_text(bytes: &[u8], little_endian: bool) -> String {
+    let (code, text) = bytes.split_at(bytes.len().min(8));
+    let text = match code {
+        b"UNICODE\0" => {
+            let (little_endian, text) = match text {
+                [0xFF, 0xFE, rest @ ..] => (true, rest),
+                [0xFE, 0xFF, rest @ ..] => (false, rest),
+                _ => (little_endian, text),
+            };
+            let units = text
+                .chunks_exact(2)
+                .map(|c| match little_endian {
+                    true => u16::from_le_bytes([c[0], c[1]]),
+                    false => u16::from_be_bytes([c[0], c[1]]),
+                })
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        }
+        // Cameras write Latin-1 into ASCII text often enough to decode it as such
+        b"ASCII\0\0\0" => text.iter().map(|&b| char::from(b)).collect(),
+        _ => String::from_utf8_lossy(text).into_owned(),
+    };
+    text.trim_end_matches(['\0', ' ']).to_owned()
+}
+
+/// Text of an ASCII field. Exif allows nothing but ASCII, yet cameras and
+/// editors write UTF-8 or Latin-1 into it - bytes that are valid UTF-8 are read
+/// as such, anything else as Latin-1. Trailing NULs are dropped.
+pub fn decode_ascii(bytes: &[u8]) -> String {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_owned(),
+        Err(_) => bytes.iter().map(|&b| char::from(b)).collect(),
+    };
+    text.trim_end_matches('\0').to_owned()
+}
+
+/// Whether the text of an ASCII field is Latin-1 rather than ASCII or UTF-8
+pub fn is_latin1(bytes: &[u8]) -> bool {
+    std::str::from_utf8(bytes).is_err()
+}
+
+/// The tags Windows Explorer writes its Title, Comments, Authors, Tags and
+/// Subject into, as UTF-16LE text in BYTE arrays
+pub const XP_TAGS: [Tag; 5] = [
+    Tag(Context::Tiff, 0x9C9B),
+    Tag(Context::Tiff, 0x9C9C),
+    Tag(Context::Tiff, 0x9C9D),
+    Tag(Context::Tiff, 0x9C9E),
+    Tag(Context::Tiff, 0x9C9F),
+];
+
+/// Names of the `XP_TAGS`, which the Exif standard doesn't know
+pub const XP_NAMES: [&str; 5] = [
+    "XPTitle",
+    "XPComment",
+    "XPAuthor",
+    "XPKeywords",
+    "XPSubject",
+];
+
+/// Name of `tag`, e.g. "DateTimeOriginal" or "XPAuthor", the reverse of
+/// `tag_from_name`
+pub fn tag_name(tag: Tag) -> String {
+    match XP_TAGS.iter().position(|t| *t == tag) {
+        Some(i) => XP_NAMES[i].to_owned(),
+        None => tag.to_string(),
+    }
+}
+
+/// Text of an XP tag, which is little endian whatever the byte order of the
+/// Exif data, up to the terminating NUL
+pub fn decode_xp(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// `text` as the value of an XP tag, NUL terminated
+pub fn encode_xp(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .chain([0])
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Value of `field` as text, decoding text beyond ASCII and XP text the way
+/// `decode_ascii` and `decode_xp` do, for when there's no backend to ask
+pub fn display_text(field: &Field) -> String {
+    match &field.value {
+        Value::Ascii(strings) if !strings.iter().all(|s| s.is_ascii()) => strings
+            .iter()
+            .map(|s| decode_ascii(s))
+            .collect::<Vec<_>>()
+            .join(", "),
+        Value::Byte(bytes) if XP_TAGS.contains(&field.tag) => decode_xp(bytes),
+        _ => clean_disp(&field.display_value().to_string()),
+    }
+}
+
+/// The Flash bitfield in plain words, e.g. "Fired, auto, red-eye reduction"
+pub fn describe_flash(flash: u32) -> String {
+    if flash & 0x20 != 0 {
+        return String::from("No flash");
+    }
+    let mut parts = vec![if flash & 0x01 != 0 {
+        "Fired"
+    } else {
+        "Did not fire"
+    }];
+    match (flash >> 3) & 0b11 {
+        1 => parts.push("forced on"),
+        2 => parts.push("forced off"),
+        3 => parts.push("auto"),
+        _ => {}
+    }
+    match (flash >> 1) & 0b11 {
+        2 => parts.push("return light not detected"),
+        3 => parts.push("return light detected"),
+        _ => {}
+    }
+    if flash & 0x40 != 0 {
+        parts.push("red-eye reduction");
+    }
+    parts.join(", ")
+}
+
+/// CompositeImage in plain words, e.g. "Composite of multiple frames"
+pub fn describe_composite(composite: u32) -> String {
+    match composite {
+        0 => String::from("Unknown"),
+        1 => String::from("Not a composite"),
+        2 => String::from("Composite of multiple frames"),
+        3 => String::from("Composite of frames captured while shooting"),
+        n => format!("Reserved ({})", n),
+    }
+}
+
+/// How an image with the Orientation `orientation` is turned to be shown
+pub fn describe_orientation(orientation: u32) -> &'static str {
+    match orientation {
+        2 => "mirrored",
+        3 => "upside down",
+        4 => "mirrored upside down",
+        5 => "mirrored, turned counterclockwise",
+        6 => "turned clockwise",
+        7 => "mirrored, turned clockwise",
+        8 => "turned counterclockwise",
+        _ => "upright",
+    }
+}
+
+/// `image` turned the way the Orientation `orientation` says it's shown
+pub fn orient(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// Degrees as the degrees, minutes and seconds rationals used by Exif
+pub fn to_dms(degrees: f64) -> Value {
+    // Round once, in ten-thousandths of a second, so that rounding up carries
+    // into the minutes and degrees instead of leaving 60 seconds behind
+    let total = (degrees.abs() * 3600. * 10_000.).round() as u64;
+    let seconds = total % (60 * 10_000);
+    let minutes = total / (60 * 10_000) % 60;
+    let degrees = total / (3600 * 10_000);
+    Value::Rational(vec![
+        Rational {
+            num: degrees as u32,
+            denom: 1,
+        },
+        Rational {
+            num: minutes as u32,
+            denom: 1,
+        },
+        Rational {
+            num: seconds as u32,
+            denom: 10_000,
+        },
+    ])
+}
+
 // fn mean(list: &[i32]) -> f64 {
 //     let sum: i32 = Iterator::sum(list.iter());
 //     f64::from(sum) / (list.len() as f64)
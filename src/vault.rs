@@ -0,0 +1,369 @@
+use anyhow::{anyhow, bail, Result};
+use argon2::Argon2;
+use chacha20poly1305::{aead::Aead, ChaCha20Poly1305, Key, KeyInit, Nonce};
+use chrono::Utc;
+use exif::{Context, Exif, Field, In, Rational, Reader, SRational, Tag, Value};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use crate::{utils, writer};
+
+// Vault
+//
+// A vault is an encrypted archive of the original (pre-scrub) metadata of an
+// image. It is written next to the scrubbed copy so that the owner can later
+// restore provenance, while anyone who only receives the copy can't read it.
+//
+// The archived metadata is a JSON Manifest. The same manifest can be stored
+// unencrypted (manifest.json) or sealed with a passphrase into a vault.
+//
+// Vault layout: MAGIC (8) | salt (16) | nonce (12) | ChaCha20-Poly1305 ciphertext
+// The key is derived from the passphrase with Argon2id using the stored salt.
+
+const VAULT_MAGIC: &[u8; 8] = b"BRSNVLT1";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const MANIFEST_VERSION: u32 = 1;
+
+pub const PASSPHRASE_ENV: &str = "BRESSON_VAULT_PASSPHRASE";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    pub source: String,
+    pub created: String,
+    pub little_endian: bool,
+    pub fields: Vec<ManifestField>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestField {
+    pub name: String,
+    pub context: String,
+    pub tag: u16,
+    pub ifd: u16,
+    pub value: ManifestValue,
+}
+
+/// A tag by its name, IFD context and number, like in a manifest field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestTag {
+    pub name: String,
+    pub context: String,
+    pub tag: u16,
+}
+
+impl ManifestTag {
+    pub fn from_tag(tag: Tag) -> Self {
+        Self {
+            name: utils::tag_name(tag),
+            context: context_name(tag.context()).to_owned(),
+            tag: tag.number(),
+        }
+    }
+
+    pub fn to_tag(&self) -> Option<Tag> {
+        Some(Tag(context_from_name(&self.context)?, self.tag))
+    }
+}
+
+/// One string of an ASCII value. UTF-8 is kept as text, anything else, like
+/// Latin-1, as its bytes, so it's restored byte for byte.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ManifestText {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl ManifestText {
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        match String::from_utf8(bytes.to_vec()) {
+            Ok(text) => ManifestText::Text(text),
+            Err(e) => ManifestText::Bytes(e.into_bytes()),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            ManifestText::Text(text) => text.as_bytes(),
+            ManifestText::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum ManifestValue {
+    Byte(Vec<u8>),
+    Ascii(Vec<ManifestText>),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>),
+    SByte(Vec<i8>),
+    Undefined(Vec<u8>),
+    SShort(Vec<i16>),
+    SLong(Vec<i32>),
+    SRational(Vec<(i32, i32)>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl ManifestValue {
+    pub fn from_value(value: &Value) -> Option<Self> {
+        Some(match value {
+            Value::Byte(x) => ManifestValue::Byte(x.clone()),
+            Value::Ascii(x) => {
+                ManifestValue::Ascii(x.iter().map(|s| ManifestText::from_bytes(s)).collect())
+            }
+            Value::Short(x) => ManifestValue::Short(x.clone()),
+            Value::Long(x) => ManifestValue::Long(x.clone()),
+            Value::Rational(x) => {
+                ManifestValue::Rational(x.iter().map(|r| (r.num, r.denom)).collect())
+            }
+            Value::SByte(x) => ManifestValue::SByte(x.clone()),
+            Value::Undefined(x, _) => ManifestValue::Undefined(x.clone()),
+            Value::SShort(x) => ManifestValue::SShort(x.clone()),
+            Value::SLong(x) => ManifestValue::SLong(x.clone()),
+            Value::SRational(x) => {
+                ManifestValue::SRational(x.iter().map(|r| (r.num, r.denom)).collect())
+            }
+            Value::Float(x) => ManifestValue::Float(x.clone()),
+            Value::Double(x) => ManifestValue::Double(x.clone()),
+            Value::Unknown(..) => return None,
+        })
+    }
+
+    pub fn to_value(&self) -> Value {
+        match self {
+            ManifestValue::Byte(x) => Value::Byte(x.clone()),
+            ManifestValue::Ascii(x) => {
+                Value::Ascii(x.iter().map(|s| s.as_bytes().to_vec()).collect())
+            }
+            ManifestValue::Short(x) => Value::Short(x.clone()),
+            ManifestValue::Long(x) => Value::Long(x.clone()),
+            ManifestValue::Rational(x) => Value::Rational(
+                x.iter()
+                    .map(|&(num, denom)| Rational { num, denom })
+                    .collect(),
+            ),
+            ManifestValue::SByte(x) => Value::SByte(x.clone()),
+            ManifestValue::Undefined(x) => Value::Undefined(x.clone(), 0),
+            ManifestValue::SShort(x) => Value::SShort(x.clone()),
+            ManifestValue::SLong(x) => Value::SLong(x.clone()),
+            ManifestValue::SRational(x) => Value::SRational(
+                x.iter()
+                    .map(|&(num, denom)| SRational { num, denom })
+                    .collect(),
+            ),
+            ManifestValue::Float(x) => Value::Float(x.clone()),
+            ManifestValue::Double(x) => Value::Double(x.clone()),
+        }
+    }
+}
+
+fn context_name(ctx: Context) -> &'static str {
+    match ctx {
+        Context::Tiff => "tiff",
+        Context::Exif => "exif",
+        Context::Gps => "gps",
+        Context::Interop => "interop",
+        _ => "unknown",
+    }
+}
+
+fn context_from_name(name: &str) -> Option<Context> {
+    match name {
+        "tiff" => Some(Context::Tiff),
+        "exif" => Some(Context::Exif),
+        "gps" => Some(Context::Gps),
+        "interop" => Some(Context::Interop),
+        _ => None,
+    }
+}
+
+impl ManifestField {
+    /// The field as archived, unless it only points at other data in the file
+    pub fn from_field(f: &Field) -> Option<Self> {
+        if writer::is_structural(f.tag) {
+            return None;
+        }
+        Self::encode(f)
+    }
+
+    /// Any field, structural ones too, unless its value is of an unknown type
+    pub fn encode(f: &Field) -> Option<Self> {
+        Some(Self {
+            name: utils::tag_name(f.tag),
+            context: context_name(f.tag.context()).to_owned(),
+            tag: f.tag.number(),
+            ifd: f.ifd_num.index(),
+            value: ManifestValue::from_value(&f.value)?,
+        })
+    }
+
+    pub fn to_field(&self) -> Option<Field> {
+        let ctx = context_from_name(&self.context)?;
+        Some(Field {
+            tag: Tag(ctx, self.tag),
+            ifd_num: In(self.ifd),
+            value: self.value.to_value(),
+        })
+    }
+}
+
+impl Manifest {
+    pub fn from_exif(source: &Path, exif: &Exif) -> Self {
+        let fields: Vec<Field> = exif.fields().cloned().collect();
+        Self::from_fields(source, &fields, exif.little_endian())
+    }
+
+    /// Manifest of `fields`, read from `source` in the given byte order
+    pub fn from_fields(source: &Path, fields: &[Field], little_endian: bool) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            source: source
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            created: Utc::now().to_rfc3339(),
+            little_endian,
+            fields: fields
+                .iter()
+                .filter_map(ManifestField::from_field)
+                .collect(),
+        }
+    }
+
+    pub fn to_fields(&self) -> Vec<Field> {
+        self.fields.iter().filter_map(|f| f.to_field()).collect()
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(data: &[u8]) -> Result<Self> {
+        let manifest: Manifest = serde_json::from_slice(data)?;
+        if manifest.version > MANIFEST_VERSION {
+            bail!("Unsupported manifest version {}", manifest.version);
+        }
+        Ok(manifest)
+    }
+
+    /// Encrypts the manifest with a key derived from `passphrase`
+    pub fn seal(&self, passphrase: &str) -> Result<Vec<u8>> {
+        seal(self.to_json()?.as_bytes(), passphrase)
+    }
+
+    /// Decrypts a vault produced by [Manifest::seal]
+    pub fn open(sealed: &[u8], passphrase: &str) -> Result<Self> {
+        Manifest::from_json(&open(sealed, passphrase)?)
+    }
+}
+
+/// Encrypts `plaintext` into a vault with a key derived from `passphrase`
+pub fn seal(plaintext: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, &salt)?);
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| anyhow!("Unable to encrypt vault"))?;
+
+    let mut sealed =
+        Vec::with_capacity(VAULT_MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+    sealed.extend_from_slice(VAULT_MAGIC);
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypts a vault produced by [seal]
+pub fn open(sealed: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    if !is_vault(sealed) || sealed.len() < VAULT_MAGIC.len() + SALT_LEN + NONCE_LEN {
+        bail!("Not a bresson vault");
+    }
+    let (salt, rest) = sealed[VAULT_MAGIC.len()..].split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+
+    let cipher = ChaCha20Poly1305::new(&derive_key(passphrase, salt)?);
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| anyhow!("Wrong passphrase or corrupted vault"))
+}
+
+/// Loads a manifest from either a vault or a plain JSON manifest
+pub fn load(data: &[u8]) -> Result<Manifest> {
+    if is_vault(data) {
+        Manifest::open(data, &read_passphrase(false)?)
+    } else {
+        Manifest::from_json(data)
+    }
+}
+
+/// Rebuilds the Exif data of `img_buf` from the archived fields of `manifest`.
+///
+/// Archived fields replace the current ones. Fields the archive doesn't know
+/// about, and the embedded thumbnail, are kept.
+pub fn restore(img_buf: &[u8], manifest: &Manifest) -> Result<Vec<u8>> {
+    let current = Reader::new()
+        .read_from_container(&mut Cursor::new(img_buf))
+        .ok();
+    let archived = manifest.to_fields();
+
+    let mut fields: Vec<&Field> = archived.iter().collect();
+    if let Some(exif) = &current {
+        fields.extend(exif.fields().filter(|f| {
+            !archived
+                .iter()
+                .any(|a| a.tag == f.tag && a.ifd_num == f.ifd_num)
+        }));
+    }
+    writer::rewrite(img_buf, current.as_ref(), fields, manifest.little_endian)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Key> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Unable to derive vault key: {}", e))?;
+    Ok(key)
+}
+
+pub fn is_vault(data: &[u8]) -> bool {
+    data.starts_with(VAULT_MAGIC)
+}
+
+/// The vault for a copy lives right next to it, e.g. `copy-a-123.jpg.vault`
+pub fn vault_path_for(copy: &Path) -> PathBuf {
+    let mut name = copy.file_name().unwrap_or_default().to_os_string();
+    name.push(".vault");
+    copy.with_file_name(name)
+}
+
+/// Reads the vault passphrase from the environment, or prompts for it on the terminal
+pub fn read_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        if passphrase.is_empty() {
+            bail!("{} must not be empty", PASSPHRASE_ENV);
+        }
+        return Ok(passphrase);
+    }
+    let passphrase = rpassword::prompt_password("Vault passphrase: ")?;
+    if passphrase.is_empty() {
+        bail!("Vault passphrase must not be empty");
+    }
+    if confirm && rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        bail!("Passphrases do not match");
+    }
+    Ok(passphrase)
+}
@@ -0,0 +1,54 @@
+use ratatui_image::picker::{Picker, ProtocolType};
+use std::{fmt::Write as _, io::IsTerminal};
+
+use crate::{audio, batch, document};
+
+// Version
+//
+// `bresson --version --verbose` tells what this build can do, for bug reports
+// and for finding out why an image doesn't show - the optional parts it was
+// built with, the formats it reads and the graphics protocol the thumbnail is
+// drawn with in this terminal.
+
+/// The cargo features in Cargo.toml and whether this build has them
+pub const FEATURES: [(&str, bool); 3] = [
+    ("async", cfg!(feature = "async")),
+    ("ffi", cfg!(feature = "ffi")),
+    ("clipboard", cfg!(feature = "clipboard")),
+];
+
+/// The protocol images are drawn with in this terminal, if it is one
+pub fn detect_protocol() -> Option<ProtocolType> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let mut picker = Picker::from_termios().unwrap_or_else(|_| Picker::new((8, 16)));
+    Some(picker.guess_protocol())
+}
+
+fn names(items: impl IntoIterator<Item = impl AsRef<str>>) -> String {
+    let names: Vec<_> = items.into_iter().map(|s| s.as_ref().to_owned()).collect();
+    if names.is_empty() {
+        "none".to_owned()
+    } else {
+        names.join(", ")
+    }
+}
+
+/// Everything `--version --verbose` prints, with `protocol` as detected
+pub fn verbose(protocol: Option<ProtocolType>) -> String {
+    let pick = |enabled| FEATURES.iter().filter(move |(_, e)| *e == enabled);
+    let mut out = String::new();
+    let _ = writeln!(out, "bresson {}", env!("CARGO_PKG_VERSION"));
+    let _ = writeln!(out, "Features:  {}", names(pick(true).map(|(n, _)| n)));
+    let _ = writeln!(out, "Without:   {}", names(pick(false).map(|(n, _)| n)));
+    let _ = writeln!(out, "Images:    {}", names(batch::IMAGE_EXTENSIONS));
+    let _ = writeln!(out, "Audio:     {}", names(audio::EXTENSIONS));
+    let _ = writeln!(out, "Documents: {}", names(document::EXTENSIONS));
+    let protocol = match protocol {
+        Some(protocol) => format!("{:?}", protocol).to_lowercase(),
+        None => "none, not a terminal".to_owned(),
+    };
+    let _ = writeln!(out, "Graphics:  {}", protocol);
+    out
+}
@@ -0,0 +1,150 @@
+use anyhow::{bail, Result};
+
+// WebP container helpers
+//
+// A WebP file is a RIFF container: "RIFF", the 4 byte little endian size of
+// what follows, "WEBP" and then chunks, each a 4 byte type, a 4 byte little
+// endian length and the data, padded to an even length. Metadata only fits
+// into the extended format, which starts with a VP8X chunk whose flags tell
+// whether there's an EXIF chunk and an "XMP " chunk, so they have to be kept
+// in step with the chunks.
+
+const EXIF: &[u8; 4] = b"EXIF";
+const XMP: &[u8; 4] = b"XMP ";
+const EXTENDED: &[u8; 4] = b"VP8X";
+
+/// Flags in the first byte of the VP8X chunk
+const EXIF_FLAG: u8 = 0x08;
+const XMP_FLAG: u8 = 0x04;
+
+/// A chunk in a WebP file. `offset` and `len` cover the whole chunk, from the
+/// type to the padding.
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    kind: [u8; 4],
+    offset: usize,
+    len: usize,
+}
+
+pub fn is_webp(buf: &[u8]) -> bool {
+    buf.len() >= 12 && buf.starts_with(b"RIFF") && &buf[8..12] == b"WEBP"
+}
+
+fn chunks(buf: &[u8]) -> Result<Vec<Chunk>> {
+    if !is_webp(buf) {
+        bail!("Not a WebP image");
+    }
+    let mut chunks = Vec::new();
+    let mut pos = 12;
+    while pos < buf.len() {
+        let Some(header) = buf.get(pos..pos + 8) else {
+            bail!("WebP chunk at offset {} is cut off", pos);
+        };
+        let data_len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        if pos + 8 + data_len > buf.len() {
+            bail!("WebP chunk at offset {} runs past the end of the file", pos);
+        }
+        // Some writers leave out the padding after the last chunk
+        let len = (8 + data_len + data_len % 2).min(buf.len() - pos);
+        chunks.push(Chunk {
+            kind: [header[0], header[1], header[2], header[3]],
+            offset: pos,
+            len,
+        });
+        pos += len;
+    }
+    Ok(chunks)
+}
+
+fn chunk(kind: &[u8; 4], data: &[u8]) -> Result<Vec<u8>> {
+    let Ok(len) = u32::try_from(data.len()) else {
+        bail!("{} bytes don't fit into a WebP chunk", data.len());
+    };
+    let mut out = Vec::with_capacity(data.len() + 9);
+    out.extend_from_slice(kind);
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+    Ok(out)
+}
+
+/// Puts the file back together without the `dropped` kinds of chunks, with the
+/// chunk in `new` put before the one at its offset, or at the end. The RIFF
+/// size and the VP8X flags are updated to match.
+fn rebuild(
+    buf: &[u8],
+    chunks: &[Chunk],
+    dropped: &[&[u8; 4]],
+    new: Option<(usize, Vec<u8>)>,
+) -> Result<Vec<u8>> {
+    let kept = |kind: &[u8; 4]| {
+        chunks
+            .iter()
+            .any(|c| &c.kind == kind && !dropped.contains(&kind))
+    };
+    let exif = kept(EXIF) || new.is_some();
+    let xmp = kept(XMP);
+
+    let mut out = Vec::with_capacity(buf.len());
+    out.extend_from_slice(&buf[..12]);
+    let mut new = new;
+    for c in chunks {
+        if let Some((_, data)) = new.take_if(|(at, _)| *at == c.offset) {
+            out.extend_from_slice(&data);
+        }
+        if !dropped.contains(&&c.kind) {
+            out.extend_from_slice(&buf[c.offset..c.offset + c.len]);
+        }
+    }
+    if let Some((_, data)) = new {
+        out.extend_from_slice(&data);
+    }
+
+    let Ok(size) = u32::try_from(out.len() - 8) else {
+        bail!("WebP is too large");
+    };
+    out[4..8].copy_from_slice(&size.to_le_bytes());
+    // The VP8X chunk comes first and stays where it was
+    if let Some(header) = chunks.first().filter(|c| &c.kind == EXTENDED) {
+        let flags = &mut out[header.offset + 8];
+        *flags &= !(EXIF_FLAG | XMP_FLAG);
+        if exif {
+            *flags |= EXIF_FLAG;
+        }
+        if xmp {
+            *flags |= XMP_FLAG;
+        }
+    }
+    Ok(out)
+}
+
+/// Replaces the EXIF chunk with `tiff`, adding one at the end if the file has
+/// none. Only extended WebP files can hold one.
+pub fn replace_exif(buf: &[u8], tiff: &[u8]) -> Result<Vec<u8>> {
+    let chunks = chunks(buf)?;
+    if chunks.first().is_none_or(|c| &c.kind != EXTENDED) {
+        bail!("Only extended WebP images can hold Exif data");
+    }
+    let at = chunks
+        .iter()
+        .find(|c| &c.kind == EXIF)
+        .map_or(buf.len(), |c| c.offset);
+    rebuild(buf, &chunks, &[EXIF], Some((at, chunk(EXIF, tiff)?)))
+}
+
+/// Drops the EXIF chunk
+pub fn remove_exif(buf: &[u8]) -> Result<Vec<u8>> {
+    rebuild(buf, &chunks(buf)?, &[EXIF], None)
+}
+
+/// Drops the XMP packet. Returns the copy and what was dropped.
+pub fn remove_xmp(buf: &[u8]) -> Result<(Vec<u8>, Vec<String>)> {
+    let chunks = chunks(buf)?;
+    let removed = match chunks.iter().any(|c| &c.kind == XMP) {
+        true => vec![String::from("XMP")],
+        false => Vec::new(),
+    };
+    Ok((rebuild(buf, &chunks, &[XMP], None)?, removed))
+}
@@ -0,0 +1,131 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    widgets::{Block, StatefulWidget, Widget},
+};
+
+use crate::globe::{Canvas, Globe};
+
+// Widget
+//
+// The globe as a ratatui widget, so other apps can show it without the rest of
+// bresson. The globe itself - camera, lighting, markers - stays the caller's to
+// set up and turn, the widget draws it as it is, as high as the area and as wide
+// as that takes to look round.
+//
+//     let mut globe = Globe::new(1., 0., false);
+//     globe.look_at(48.86, 2.29, 1.5);
+//     frame.render_widget(GlobeWidget::new(&globe).block(Block::bordered()), area);
+
+/// Draws a `Globe`, with the faint characters dimmed and markers in their styles
+#[derive(Clone)]
+pub struct GlobeWidget<'a> {
+    globe: &'a Globe,
+    block: Option<Block<'a>>,
+    /// Makes the character at the middle of the globe stand out
+    highlight_center: bool,
+    marker_styles: Vec<(char, Style)>,
+}
+
+/// Where the last frame drew the globe
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GlobeState {
+    /// Inside the block, if there is one
+    pub area: Rect,
+    /// Cell in the middle of the globe, the point the camera looks at
+    pub center: Option<(u16, u16)>,
+}
+
+impl<'a> GlobeWidget<'a> {
+    pub fn new(globe: &'a Globe) -> Self {
+        Self {
+            globe,
+            block: None,
+            highlight_center: false,
+            marker_styles: Vec::new(),
+        }
+    }
+
+    pub fn block(mut self, block: Block<'a>) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    pub fn highlight_center(mut self, highlight: bool) -> Self {
+        self.highlight_center = highlight;
+        self
+    }
+
+    /// Draws markers with `symbol` in `style`
+    pub fn marker_style(mut self, symbol: char, style: Style) -> Self {
+        self.marker_styles.push((symbol, style));
+        self
+    }
+
+    fn style(&self, c: char) -> Style {
+        if let Some((_, style)) = self.marker_styles.iter().find(|(s, _)| *s == c) {
+            return *style;
+        }
+        match c {
+            '.' | ':' | ';' => Style::new().add_modifier(Modifier::DIM),
+            _ => Style::new(),
+        }
+    }
+}
+
+impl StatefulWidget for GlobeWidget<'_> {
+    type State = GlobeState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut GlobeState) {
+        let inner = match &self.block {
+            Some(block) => {
+                let inner = block.inner(area);
+                block.clone().render(area, buf);
+                inner
+            }
+            None => area,
+        };
+        *state = GlobeState {
+            area: inner,
+            center: None,
+        };
+        if inner.is_empty() {
+            return;
+        }
+
+        let (width, height) = (inner.width as usize, inner.height as usize);
+        // Cells are `font_ratio` times as high as wide
+        let columns = ((height as f64 * self.globe.font_ratio).round() as usize).clamp(1, width);
+        let mut canvas = Canvas::new(width, height, Some((1, 1))).with_globe_width(columns);
+        self.globe.render_sphere(&mut canvas);
+
+        let center = (
+            (width / 2).saturating_sub(1),
+            (height / 2).saturating_sub(1),
+        );
+        state.center = Some((inner.x + center.0 as u16, inner.y + center.1 as u16));
+        for (y, row) in canvas.matrix.iter().enumerate() {
+            for (x, &c) in row.iter().enumerate() {
+                if c == ' ' {
+                    continue;
+                }
+                let mut style = self.style(c);
+                if self.highlight_center && (x, y) == center && style == Style::new() {
+                    style = Style::new()
+                        .fg(Color::Red)
+                        .add_modifier(Modifier::BOLD | Modifier::RAPID_BLINK);
+                }
+                buf[(inner.x + x as u16, inner.y + y as u16)]
+                    .set_char(c)
+                    .set_style(style);
+            }
+        }
+    }
+}
+
+impl Widget for GlobeWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        StatefulWidget::render(self, area, buf, &mut GlobeState::default());
+    }
+}
@@ -0,0 +1,512 @@
+use anyhow::{anyhow, bail, Result};
+use exif::{experimental::Writer, Context, Exif, Field, In, Tag, Value};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, io};
+
+use crate::{
+    backend, jpeg, png, utils,
+    vault::{ManifestText, ManifestValue},
+    webp,
+};
+
+// Writer
+//
+// Encodes a set of fields into TIFF formatted Exif data and splices it back into
+// the image container. Strips, tiles and the embedded JPEG thumbnail are carried
+// over from the Exif data the image was read with.
+//
+// https://github.com/kamadak/exif-rs/blob/a8883a6597f2ba9eb8c9b1cb38bfa61a5cc67837/tests/rwrcmp.rs#L90
+//
+// Every copy is read back after it's written and compared with the fields it
+// was meant to have, so a value the writer mangled or a field it let through
+// shows up in the status bar instead of in someone else's hands.
+//
+// A JPEG keeps its Exif data in a single APP1 segment of at most 64 KB, and
+// readers don't put data split over several of them back together. Data that
+// outgrew the segment, usually because of a large MakerNote or an XMP packet
+// kept inside it the way TIFF files do, is either refused or made room for by
+// dropping the MakerNote or moving the XMP packet into a segment of its own.
+
+/// Tags describing the layout of the Exif data itself. The writer synthesizes
+/// these, so they are never edited or archived directly.
+pub fn is_structural(tag: Tag) -> bool {
+    matches!(
+        tag,
+        Tag::ExifIFDPointer
+            | Tag::GPSInfoIFDPointer
+            | Tag::InteropIFDPointer
+            | Tag::StripOffsets
+            | Tag::StripByteCounts
+            | Tag::TileOffsets
+            | Tag::TileByteCounts
+            | Tag::JPEGInterchangeFormat
+            | Tag::JPEGInterchangeFormatLength
+    )
+}
+
+/// Tags a reader needs to decode the image data they describe, e.g. the
+/// Compression of the thumbnail. Unlike structural tags they're copied as they
+/// are, so clearing or changing them can leave a file that doesn't open.
+pub fn is_required(tag: Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Compression
+            | Tag::ImageWidth
+            | Tag::ImageLength
+            | Tag::BitsPerSample
+            | Tag::SamplesPerPixel
+            | Tag::PhotometricInterpretation
+            | Tag::PlanarConfiguration
+            | Tag::RowsPerStrip
+            | Tag::YCbCrSubSampling
+    )
+}
+
+/// Tag 700, an XMP packet kept inside the Exif data
+pub const XML_PACKET: Tag = Tag(Context::Tiff, 0x02BC);
+
+/// What to do when the Exif data doesn't fit into the APP1 segment of a JPEG
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Oversized {
+    /// Fail with an error, no copy is written
+    #[default]
+    Refuse,
+    DropMakerNote,
+    /// Move the XMP packet into an APP1 segment of its own
+    MoveXmp,
+}
+
+impl Oversized {
+    pub const ALL: [Oversized; 3] = [
+        Oversized::Refuse,
+        Oversized::DropMakerNote,
+        Oversized::MoveXmp,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Oversized::Refuse => "refuse",
+            Oversized::DropMakerNote => "drop-maker-note",
+            Oversized::MoveXmp => "move-xmp",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|o| o.name() == name)
+    }
+
+    /// What happens to `field` once it's taken out, e.g. "dropped the
+    /// MakerNote (40960 bytes)"
+    pub fn describe(&self, field: &Field) -> String {
+        let len = field_len(field) - 12;
+        match self {
+            Oversized::MoveXmp => {
+                format!("moved the XMP packet out of the Exif data ({} bytes)", len)
+            }
+            _ => format!("dropped the {} ({} bytes)", utils::tag_name(field.tag), len),
+        }
+    }
+}
+
+/// Takes the field `oversized` names out of `fields` if, with them, the APP1
+/// segment of a JPEG would hold `len` bytes, more than it can. Returns the
+/// field, for `put_back`.
+pub fn make_room(
+    fields: &mut Vec<Field>,
+    len: usize,
+    oversized: Oversized,
+) -> Result<Option<Field>> {
+    if len <= jpeg::MAX_SEGMENT_PAYLOAD {
+        return Ok(None);
+    }
+    let (tag, name) = match oversized {
+        Oversized::Refuse => bail!(
+            "Exif data is {} bytes, more than the {} a JPEG segment holds. Shorten or \
+             clear fields, or drop the MakerNote or move the XMP packet out to make room",
+            len,
+            jpeg::MAX_SEGMENT_PAYLOAD
+        ),
+        Oversized::DropMakerNote => (Tag::MakerNote, "MakerNote"),
+        Oversized::MoveXmp => (XML_PACKET, "XMP packet"),
+    };
+    let Some(at) = fields.iter().position(|f| f.tag == tag) else {
+        bail!(
+            "Exif data is {} bytes, more than the {} a JPEG segment holds, and there's no {} \
+             to make room",
+            len,
+            jpeg::MAX_SEGMENT_PAYLOAD,
+            name
+        );
+    };
+    Ok(Some(fields.remove(at)))
+}
+
+/// Puts the field `make_room` took out of the Exif data of `img_buf` back where
+/// there's room for it, i.e. the XMP packet into a segment of its own. Anything
+/// else stays dropped.
+pub fn put_back(img_buf: Vec<u8>, taken: Option<&Field>) -> Result<Vec<u8>> {
+    let Some(field) = taken.filter(|f| f.tag == XML_PACKET) else {
+        return Ok(img_buf);
+    };
+    let packet = match &field.value {
+        Value::Byte(bytes) | Value::Undefined(bytes, _) => bytes,
+        _ => bail!("The XMP packet in the Exif data isn't stored as bytes"),
+    };
+    jpeg::insert_xmp(&img_buf, packet)
+}
+
+/// Byte order of TIFF formatted Exif data. Data is written in the byte order it
+/// was read in unless it's converted on purpose, as some readers only cope with
+/// one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ByteOrder {
+    #[serde(rename = "little")]
+    LittleEndian,
+    #[serde(rename = "big")]
+    BigEndian,
+}
+
+impl ByteOrder {
+    pub const ALL: [ByteOrder; 2] = [ByteOrder::LittleEndian, ByteOrder::BigEndian];
+
+    pub fn of(exif: &Exif) -> Self {
+        Self::from_little_endian(exif.little_endian())
+    }
+
+    pub fn from_little_endian(little_endian: bool) -> Self {
+        match little_endian {
+            true => ByteOrder::LittleEndian,
+            false => ByteOrder::BigEndian,
+        }
+    }
+
+    pub fn is_little_endian(&self) -> bool {
+        *self == ByteOrder::LittleEndian
+    }
+
+    pub fn other(&self) -> Self {
+        Self::from_little_endian(!self.is_little_endian())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ByteOrder::LittleEndian => "little",
+            ByteOrder::BigEndian => "big",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|o| o.name() == name)
+    }
+}
+
+impl fmt::Display for ByteOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ByteOrder::LittleEndian => write!(f, "little endian"),
+            ByteOrder::BigEndian => write!(f, "big endian"),
+        }
+    }
+}
+
+/// Gets `fields`, read from Exif data in the byte order `from`, ready to be
+/// encoded in `to`. The writer converts numbers by itself, but UCS-2 text in
+/// tags like UserComment is in the byte order of the Exif data and has to be
+/// swapped, unless it starts with a byte order mark. MakerNotes are opaque and
+/// stay as they are, which may leave them unreadable.
+pub fn convert_byte_order(fields: &mut [Field], from: ByteOrder, to: ByteOrder) {
+    if from == to {
+        return;
+    }
+    for field in fields {
+        let encoded = matches!(
+            field.tag,
+            Tag::UserComment | Tag::GPSProcessingMethod | Tag::GPSAreaInformation
+        );
+        let Value::Undefined(bytes, _) = &mut field.value else {
+            continue;
+        };
+        if !encoded || !bytes.starts_with(b"UNICODE\0") {
+            continue;
+        }
+        let text = &mut bytes[8..];
+        if text.starts_with(&[0xFF, 0xFE]) || text.starts_with(&[0xFE, 0xFF]) {
+            continue;
+        }
+        for unit in text.chunks_exact_mut(2) {
+            unit.swap(0, 1);
+        }
+    }
+}
+
+/// Bytes `field` adds to the TIFF data: a 12 byte directory entry, and its
+/// value padded to an even length unless it fits into the 4 bytes of the entry
+pub fn field_len(field: &Field) -> usize {
+    let value = match &field.value {
+        Value::Byte(v) => v.len(),
+        Value::SByte(v) => v.len(),
+        Value::Undefined(v, _) => v.len(),
+        Value::Ascii(v) => v.iter().map(|s| s.len() + 1).sum(),
+        Value::Short(v) => v.len() * 2,
+        Value::SShort(v) => v.len() * 2,
+        Value::Long(v) => v.len() * 4,
+        Value::SLong(v) => v.len() * 4,
+        Value::Float(v) => v.len() * 4,
+        Value::Rational(v) => v.len() * 8,
+        Value::SRational(v) => v.len() * 8,
+        Value::Double(v) => v.len() * 8,
+        Value::Unknown(..) => 0,
+    };
+    match value {
+        0..=4 => 12,
+        _ => 12 + value + value % 2,
+    }
+}
+
+/// Encodes `fields` into TIFF formatted Exif data
+pub fn encode<'a>(
+    fields: impl IntoIterator<Item = &'a Field>,
+    source: Option<&Exif>,
+    little_endian: bool,
+) -> Result<Vec<u8>> {
+    let (strips, tn_strips, tiles, tn_jpeg) = match source {
+        Some(e) => (
+            get_strips(e, In::PRIMARY)?,
+            get_strips(e, In::THUMBNAIL)?,
+            get_tiles(e, In::PRIMARY)?,
+            get_jpeg(e, In::THUMBNAIL)?,
+        ),
+        None => (None, None, None, None),
+    };
+
+    let mut exif_writer = Writer::new();
+    for f in fields {
+        exif_writer.push_field(f);
+    }
+    if let Some(ref strips) = strips {
+        exif_writer.set_strips(strips, In::PRIMARY);
+    }
+    if let Some(ref tn_strips) = tn_strips {
+        exif_writer.set_strips(tn_strips, In::THUMBNAIL);
+    }
+    if let Some(ref tiles) = tiles {
+        exif_writer.set_tiles(tiles, In::PRIMARY);
+    }
+    if let Some(tn_jpeg) = tn_jpeg {
+        exif_writer.set_jpeg(tn_jpeg, In::THUMBNAIL);
+    }
+
+    let mut new_exif_buf = io::Cursor::new(Vec::new());
+    exif_writer.write(&mut new_exif_buf, little_endian)?;
+    Ok(new_exif_buf.into_inner())
+}
+
+/// Replaces the Exif data inside `img_buf` with `tiff`
+pub fn splice(img_buf: &[u8], old_exif: Option<&Exif>, tiff: &[u8]) -> Result<Vec<u8>> {
+    if jpeg::is_jpeg(img_buf) {
+        return jpeg::replace_exif(img_buf, tiff);
+    }
+    if png::is_png(img_buf) {
+        return png::replace_exif(img_buf, tiff);
+    }
+    if webp::is_webp(img_buf) {
+        return webp::replace_exif(img_buf, tiff);
+    }
+
+    // Other containers: swap the old Exif buffer for the new one in place
+    let old_buf = old_exif
+        .map(|e| e.buf())
+        .filter(|buf| !buf.is_empty())
+        .ok_or_else(|| anyhow!("Image has no Exif data to replace"))?;
+    let position_of_exif = img_buf
+        .windows(old_buf.len())
+        .position(|x| x == old_buf)
+        .ok_or_else(|| anyhow!("Unable to locate the Exif data inside the image"))?;
+
+    let mut new_img_buf = Vec::with_capacity(img_buf.len() + tiff.len());
+    new_img_buf.extend_from_slice(&img_buf[..position_of_exif]);
+    new_img_buf.extend_from_slice(tiff);
+    new_img_buf.extend_from_slice(&img_buf[position_of_exif + old_buf.len()..]);
+    Ok(new_img_buf)
+}
+
+/// Encodes `fields` and splices them into `img_buf` in one go
+pub fn rewrite<'a>(
+    img_buf: &[u8],
+    source: Option<&Exif>,
+    fields: impl IntoIterator<Item = &'a Field>,
+    little_endian: bool,
+) -> Result<Vec<u8>> {
+    let tiff = encode(fields, source, little_endian)?;
+    splice(img_buf, source, &tiff)
+}
+
+/// How a copy read back compares with the fields it was written with
+#[derive(Debug, Default, PartialEq)]
+pub struct Roundtrip {
+    /// Fields that read back with another value, or not at all
+    pub mismatched: Vec<Tag>,
+    /// Fields in the copy that it wasn't written with
+    pub unexpected: Vec<Tag>,
+}
+
+impl Roundtrip {
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+impl fmt::Display for Roundtrip {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = |tags: &[Tag]| {
+            let names: Vec<_> = tags.iter().map(|t| utils::tag_name(*t)).collect();
+            names.join(", ")
+        };
+        match (self.mismatched.is_empty(), self.unexpected.is_empty()) {
+            (true, true) => write!(f, "every field read back as written"),
+            (false, true) => write!(f, "changed on the way: {}", names(&self.mismatched)),
+            (true, false) => write!(f, "not meant to be there: {}", names(&self.unexpected)),
+            (false, false) => write!(
+                f,
+                "changed on the way: {}, not meant to be there: {}",
+                names(&self.mismatched),
+                names(&self.unexpected)
+            ),
+        }
+    }
+}
+
+/// Reads `written` back and compares its fields with `fields`, the ones it was
+/// written with. Structural fields are left out, the writer makes them up.
+pub fn verify_roundtrip(written: &[u8], fields: &[Field]) -> Result<Roundtrip> {
+    let read = match backend::parse(written) {
+        Ok(backend) => backend.fields(),
+        // A copy written without any fields has no Exif data to find
+        Err(e) if matches!(e.downcast_ref(), Some(exif::Error::NotFound(_))) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    // NULs end strings, so ["a\0"] is written the same as ["a", ""]
+    let value = |f: &Field| match ManifestValue::from_value(&f.value) {
+        Some(ManifestValue::Ascii(strings)) => {
+            let mut joined = strings
+                .iter()
+                .map(ManifestText::as_bytes)
+                .collect::<Vec<_>>()
+                .join(&0);
+            while joined.last() == Some(&0) {
+                joined.pop();
+            }
+            Some(ManifestValue::Ascii(vec![ManifestText::from_bytes(
+                &joined,
+            )]))
+        }
+        value => value,
+    };
+    let read: HashMap<_, _> = read
+        .iter()
+        .filter(|f| !is_structural(f.tag))
+        .map(|f| ((f.tag, f.ifd_num), value(f)))
+        .collect();
+    let intended: HashMap<_, _> = fields
+        .iter()
+        .filter(|f| !is_structural(f.tag))
+        .map(|f| ((f.tag, f.ifd_num), value(f)))
+        .collect();
+
+    let mut roundtrip = Roundtrip::default();
+    for (key, value) in &intended {
+        if read.get(key) != Some(value) {
+            roundtrip.mismatched.push(key.0);
+        }
+    }
+    for key in read.keys() {
+        if !intended.contains_key(key) {
+            roundtrip.unexpected.push(key.0);
+        }
+    }
+    roundtrip.mismatched.sort_by_key(|t| t.to_string());
+    roundtrip.unexpected.sort_by_key(|t| t.to_string());
+    Ok(roundtrip)
+}
+
+/// Removes the Exif data from `img_buf` entirely
+pub fn strip(img_buf: &[u8]) -> Result<Vec<u8>> {
+    if jpeg::is_jpeg(img_buf) {
+        return jpeg::remove_exif(img_buf);
+    }
+    if png::is_png(img_buf) {
+        return png::remove_exif(img_buf);
+    }
+    if webp::is_webp(img_buf) {
+        return webp::remove_exif(img_buf);
+    }
+    Err(anyhow!(
+        "Removing all Exif data is only supported for JPEG, PNG and WebP images"
+    ))
+}
+
+/// Slices `buf` at every offset and byte count pair, failing on data that
+/// doesn't add up instead of trusting it
+fn get_chunks<'a>(
+    buf: &'a [u8],
+    offsets: Option<impl ExactSizeIterator<Item = u32>>,
+    counts: Option<impl ExactSizeIterator<Item = u32>>,
+    what: &str,
+) -> Result<Option<Vec<&'a [u8]>>> {
+    let (offsets, counts) = match (offsets, counts) {
+        (Some(offsets), Some(counts)) => (offsets, counts),
+        (None, None) => return Ok(None),
+        _ => bail!("Inconsistent {} offsets and byte counts", what),
+    };
+    if offsets.len() != counts.len() {
+        bail!(
+            "{} {} offsets but {} byte counts",
+            offsets.len(),
+            what,
+            counts.len()
+        );
+    }
+    offsets
+        .zip(counts)
+        .map(|(ofs, cnt)| {
+            let (ofs, cnt) = (ofs as usize, cnt as usize);
+            ofs.checked_add(cnt)
+                .and_then(|end| buf.get(ofs..end))
+                .ok_or_else(|| anyhow!("A {} points outside of the Exif data", what))
+        })
+        .collect::<Result<_>>()
+        .map(Some)
+}
+
+fn get_strips(exif: &Exif, ifd_num: In) -> Result<Option<Vec<&[u8]>>> {
+    let offsets = exif
+        .get_field(Tag::StripOffsets, ifd_num)
+        .and_then(|f| f.value.iter_uint());
+    let counts = exif
+        .get_field(Tag::StripByteCounts, ifd_num)
+        .and_then(|f| f.value.iter_uint());
+    get_chunks(exif.buf(), offsets, counts, "strip")
+}
+
+fn get_tiles(exif: &Exif, ifd_num: In) -> Result<Option<Vec<&[u8]>>> {
+    let offsets = exif
+        .get_field(Tag::TileOffsets, ifd_num)
+        .and_then(|f| f.value.iter_uint());
+    let counts = exif
+        .get_field(Tag::TileByteCounts, ifd_num)
+        .and_then(|f| f.value.iter_uint());
+    get_chunks(exif.buf(), offsets, counts, "tile")
+}
+
+pub fn get_jpeg(exif: &Exif, ifd_num: In) -> Result<Option<&[u8]>> {
+    let offset = exif
+        .get_field(Tag::JPEGInterchangeFormat, ifd_num)
+        .and_then(|f| f.value.get_uint(0));
+    let len = exif
+        .get_field(Tag::JPEGInterchangeFormatLength, ifd_num)
+        .and_then(|f| f.value.get_uint(0));
+    let offsets = offset.map(std::iter::once);
+    let counts = len.map(std::iter::once);
+    Ok(get_chunks(exif.buf(), offsets, counts, "JPEG thumbnail")?.and_then(|c| c.first().copied()))
+}
@@ -0,0 +1,179 @@
+// Every file in tests/fixtures/corrupt is broken on purpose - truncated, with
+// offsets pointing nowhere, counts that don't add up and so on. Reading, editing,
+// rendering and saving them has to end in an error, never in a panic.
+
+use bresson::{
+    globe::Globe,
+    jpeg,
+    state::{Application, Document},
+    stego, ui, writer,
+};
+use ratatui_image::picker::Picker;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::mpsc,
+};
+
+fn corpus() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/corrupt");
+    let mut paths = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/corrupt")
+        .join(name)
+}
+
+/// Writes the edited fields of `path` back into it, in memory
+fn save(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut document = Document::open(path)?;
+    document.update_gps();
+    document.clear_gps();
+    let file = std::fs::read(path)?;
+    document.backend.write(
+        &file,
+        &mut document.modified_fields.values().map(|m| &m.field),
+    )
+}
+
+fn exercise(path: &Path) {
+    let Ok(mut document) = Document::open(path) else {
+        return;
+    };
+    document.update_gps();
+    document.location();
+    document.process_rows(80, true, "");
+    document.randomize_all();
+    document.clear_all_fields();
+    document.undo_operation();
+    document.undo_operation();
+    document.apply_policy();
+    let _ = save(path);
+
+    let (tx_worker, _rx_worker) = mpsc::channel();
+    let globe = Globe::new(1., 0., false);
+    if let Ok(mut app) = Application::with_picker(path, globe, tx_worker, Picker::new((8, 16))) {
+        app.document.update_gps();
+        app.transform_coordinates();
+        let _ = ui::render_to_buffer(&mut app, 80, 30);
+    }
+
+    if let Ok(file) = std::fs::read(path) {
+        let _ = writer::strip(&file);
+    }
+}
+
+#[test]
+fn corrupt_files_dont_panic() {
+    let corpus = corpus();
+    assert!(!corpus.is_empty());
+    let panicked = corpus
+        .into_iter()
+        .filter(|path| panic::catch_unwind(AssertUnwindSafe(|| exercise(path))).is_err())
+        .collect::<Vec<_>>();
+    assert!(panicked.is_empty(), "Panicked on {:?}", panicked);
+}
+
+#[test]
+fn inconsistent_strips_are_reported() {
+    for (name, error) in [
+        ("strip-offsets-without-counts.jpg", "Inconsistent strip"),
+        (
+            "strip-count-mismatch.jpg",
+            "2 strip offsets but 1 byte counts",
+        ),
+        ("strips-out-of-range.jpg", "outside of the Exif data"),
+        ("thumbnail-out-of-range.jpg", "outside of the Exif data"),
+    ] {
+        let e = save(&fixture(name)).unwrap_err();
+        assert!(e.to_string().contains(error), "{}: {}", name, e);
+    }
+}
+
+#[test]
+fn unreadable_files_are_reported() {
+    for name in ["empty.jpg", "garbage.jpg", "truncated.jpg", "ifd-loop.jpg"] {
+        assert!(Document::open(&fixture(name)).is_err(), "{}", name);
+    }
+}
+
+#[test]
+fn partial_gps_is_read_as_far_as_it_goes() {
+    let mut document = Document::open(&fixture("short-gps.jpg")).unwrap();
+    document.update_gps();
+    let (lat, _) = document.location();
+    assert_eq!(lat, 48.);
+}
+
+#[test]
+fn appended_data_is_found() {
+    let clean = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gps.jpg"))
+        .unwrap();
+    assert_eq!(stego::scan(&clean), Vec::new());
+    assert_eq!(jpeg::end_of_image(&clean).unwrap(), clean.len());
+
+    // A ZIP appended by a steganography tool
+    let mut hidden = clean.clone();
+    hidden.extend_from_slice(b"PK\x03\x04secret.txt");
+    let findings = stego::scan(&hidden);
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].offset, clean.len());
+    assert_eq!(findings[0].len, 14);
+    assert_eq!(findings[1].what, "Start of a ZIP archive");
+
+    // Padding hides nothing
+    let mut padded = clean.clone();
+    padded.extend_from_slice(&[0; 64]);
+    assert_eq!(stego::scan(&padded), Vec::new());
+}
+
+#[test]
+fn duplicate_exif_segments_are_removed_on_save() {
+    let clean = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gps.jpg"))
+        .unwrap();
+    let exif_segments = |buf: &[u8]| {
+        jpeg::segments(buf)
+            .unwrap()
+            .iter()
+            .filter(|s| s.is_exif(buf))
+            .count()
+    };
+    // A second Exif segment from another fixture, right after the first one
+    let other = bresson::fixture::generate(&Default::default()).unwrap();
+    let second = jpeg::segments(&other)
+        .unwrap()
+        .into_iter()
+        .find(|s| s.is_exif(&other))
+        .unwrap();
+    let first = jpeg::segments(&clean)
+        .unwrap()
+        .into_iter()
+        .find(|s| s.is_exif(&clean))
+        .unwrap();
+    let at = first.offset + first.len;
+    let mut doubled = clean[..at].to_vec();
+    doubled.extend_from_slice(&other[second.offset..second.offset + second.len]);
+    doubled.extend_from_slice(&clean[at..]);
+    assert_eq!(exif_segments(&doubled), 2);
+
+    let findings = stego::scan(&doubled);
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].offset, at);
+
+    let dir = std::env::temp_dir().join(format!("bresson-doubled-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("doubled.jpg");
+    std::fs::write(&path, &doubled).unwrap();
+    let mut document = Document::open(&path).unwrap();
+    document.clear_gps();
+    let request = document.save_request().unwrap();
+    request.run(|_, _| true).unwrap();
+    assert_eq!(exif_segments(&std::fs::read(&request.copy).unwrap()), 1);
+}
@@ -0,0 +1,1256 @@
+// Saving copies, in the foreground and on a worker thread
+
+use bresson::{
+    backend,
+    batch::{self, Export},
+    fixture, icc, index,
+    journal::{self, Journal},
+    jpeg,
+    motion::{self, Video},
+    order, png,
+    policy::{Policy, PolicyProcessor},
+    processor::{Registry, TagProcessor},
+    randomize::RandomMetadata,
+    record::Recording,
+    repl::{Library, Outcome},
+    report::{self, Report},
+    save::{self, Destination, SaveEvent, SaveJob},
+    serve,
+    sidecar::{Flag, Sidecar},
+    state::{Document, MetadataVal, Operation},
+    undo::UndoJournal,
+    utils,
+    vault::Manifest,
+    writer::{self, ByteOrder, Oversized},
+};
+use exif::{In, Reader, Tag, Value};
+use std::path::{Path, PathBuf};
+
+/// A fresh directory holding a copy of the GPS fixture, so saved copies land there
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bresson-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let image = dir.join("gps.jpg");
+    std::fs::copy(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gps.jpg"),
+        &image,
+    )
+    .unwrap();
+    image
+}
+
+fn files_in(dir: &Path) -> usize {
+    std::fs::read_dir(dir).unwrap().count()
+}
+
+#[test]
+fn saves_a_copy() {
+    let image = scratch("save");
+    let mut document = Document::open(&image).unwrap();
+    document.clear_gps();
+    let status = document.save_state().unwrap();
+    assert!(status.starts_with("Saved a copy"));
+    assert!(status.ends_with("every field read back as written"));
+    assert_eq!(files_in(image.parent().unwrap()), 2);
+}
+
+#[test]
+fn random_edits_read_back_as_written() {
+    let image = scratch("roundtrip");
+    let file = std::fs::read(&image).unwrap();
+    let mut document = Document::open(&image).unwrap();
+    for round in 0..40 {
+        match round % 4 {
+            0 => document.clear_gps(),
+            1 => document.clear_all_fields(),
+            2 => document.toggle_byte_order(),
+            _ => document.randomize_all(),
+        };
+        document.randomize_all();
+        let request = document.save_request().unwrap();
+        let copy = batch::write_bytes(
+            &*request.backend,
+            &file,
+            &request.fields,
+            request.byte_order,
+        )
+        .unwrap();
+        let mut fields = request.fields.clone();
+        if let Some(order) = request.byte_order {
+            writer::convert_byte_order(&mut fields, order.other(), order);
+        }
+        let roundtrip = writer::verify_roundtrip(&copy, &fields).unwrap();
+        assert!(roundtrip.is_ok(), "round {}: {}", round, roundtrip);
+    }
+}
+
+#[test]
+fn roundtrip_tells_changed_and_extra_fields() {
+    let image = scratch("tamper");
+    let file = std::fs::read(&image).unwrap();
+    let request = Document::open(&image).unwrap().save_request().unwrap();
+    let copy = batch::write_bytes(&*request.backend, &file, &request.fields, None).unwrap();
+
+    let mut fields = request.fields.clone();
+    let model = fields.iter_mut().find(|f| f.tag == Tag::Model).unwrap();
+    model.value = Value::Ascii(vec![b"Something else".to_vec()]);
+    fields.retain(|f| f.tag != Tag::Make);
+    let roundtrip = writer::verify_roundtrip(&copy, &fields).unwrap();
+    assert_eq!(roundtrip.mismatched, [Tag::Model]);
+    assert_eq!(roundtrip.unexpected, [Tag::Make]);
+    assert_eq!(
+        roundtrip.to_string(),
+        "changed on the way: Model, not meant to be there: Make"
+    );
+}
+
+#[test]
+fn cancelling_removes_the_partial_copy() {
+    let image = scratch("cancel");
+    let document = Document::open(&image).unwrap();
+    let error = document
+        .save_request()
+        .unwrap()
+        .run(|_, _| false)
+        .unwrap_err();
+    assert_eq!(error.to_string(), "Save cancelled");
+    assert_eq!(files_in(image.parent().unwrap()), 1);
+}
+
+#[test]
+fn saves_over_the_original_with_a_backup() {
+    let image = scratch("in-place");
+    let original = std::fs::read(&image).unwrap();
+    let mut document = Document::open(&image).unwrap();
+    document.clear_gps();
+    let in_place = Destination::InPlace { backup: true };
+
+    // Cancelling leaves the original as it was
+    let request = document.save_request_to(in_place).unwrap();
+    assert!(request.run(|_, _| false).is_err());
+    assert_eq!(std::fs::read(&image).unwrap(), original);
+    assert_eq!(files_in(image.parent().unwrap()), 1);
+
+    let status = document
+        .save_request_to(in_place)
+        .unwrap()
+        .run(|_, _| true)
+        .unwrap();
+    assert!(status.starts_with("Saved over"), "{}", status);
+    assert!(status.ends_with("every field read back as written"));
+    assert_eq!(files_in(image.parent().unwrap()), 2);
+    assert_eq!(std::fs::read(save::backup_path(&image)).unwrap(), original);
+    let saved = Document::open(&image).unwrap();
+    assert!(saved
+        .original_fields
+        .get(&Tag::GPSLatitude)
+        .is_none_or(MetadataVal::is_cleared));
+}
+
+#[test]
+fn background_save_reports_progress() {
+    let image = scratch("job");
+    let document = Document::open(&image).unwrap();
+    let mut job = SaveJob::spawn(document.save_request().unwrap());
+    let mut progress = false;
+    let result = loop {
+        match job.poll() {
+            Some(SaveEvent::Progress { written, total }) => {
+                progress = true;
+                assert!(written <= total);
+            }
+            Some(SaveEvent::Done(result)) => break result,
+            None => std::thread::yield_now(),
+        }
+    };
+    assert!(progress);
+    assert!(result.is_ok());
+    assert_eq!(files_in(image.parent().unwrap()), 2);
+}
+
+#[test]
+fn randomized_versions_are_written_as_undefined() {
+    let image = scratch("versions");
+    std::fs::write(
+        &image,
+        fixture::generate(&fixture::Options::default()).unwrap(),
+    )
+    .unwrap();
+    let tags = [
+        Tag::ExifVersion,
+        Tag::FlashpixVersion,
+        Tag::ComponentsConfiguration,
+    ];
+    let mut document = Document::open(&image).unwrap();
+    for tag in tags {
+        let index = order::EXIF_FIELDS_ORDERED
+            .iter()
+            .position(|t| *t == tag)
+            .unwrap();
+        assert_eq!(
+            document.randomize(index, false),
+            format!("Randomized {}", tag)
+        );
+    }
+    document.save_state().unwrap();
+
+    let copy = std::fs::read_dir(image.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path != &image)
+        .unwrap();
+    let file = std::fs::File::open(copy).unwrap();
+    let exif = Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .unwrap();
+    for tag in tags {
+        match exif.get_field(tag, In::PRIMARY).map(|f| &f.value) {
+            Some(Value::Undefined(bytes, _)) => assert_eq!(bytes.len(), 4, "{}", tag),
+            other => panic!("{} read back as {:?}", tag, other),
+        }
+    }
+}
+
+#[test]
+fn randomized_capture_time_replaces_subseconds() {
+    let image = scratch("subsec");
+    std::fs::write(
+        &image,
+        fixture::generate(&fixture::Options::default()).unwrap(),
+    )
+    .unwrap();
+    let mut document = Document::open(&image).unwrap();
+    let index = order::EXIF_FIELDS_ORDERED
+        .iter()
+        .position(|t| *t == Tag::DateTimeOriginal)
+        .unwrap();
+    document.randomize(index, false);
+    assert!(document.modified_fields[&Tag::SubSecTimeOriginal].changed);
+    document.save_state().unwrap();
+
+    let copy = std::fs::read_dir(image.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path != &image)
+        .unwrap();
+    let file = std::fs::File::open(copy).unwrap();
+    let exif = Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .unwrap();
+    // As many digits as the camera wrote
+    match exif
+        .get_field(Tag::SubSecTimeOriginal, In::PRIMARY)
+        .map(|f| &f.value)
+    {
+        Some(Value::Ascii(v)) => assert!(v[0].len() == 2 && v[0].iter().all(u8::is_ascii_digit)),
+        other => panic!("SubSecTimeOriginal read back as {:?}", other),
+    }
+}
+
+#[test]
+fn notes_are_kept_in_a_sidecar() {
+    let image = scratch("notes");
+    let sidecar = Sidecar::path(&image);
+    let mut document = Document::open(&image).unwrap();
+    document.add_note("Street sign gives the location away");
+    assert!(sidecar.is_file());
+
+    let mut document = Document::open(&image).unwrap();
+    assert_eq!(
+        document.sidecar.notes[0].text,
+        "Street sign gives the location away"
+    );
+    document.remove_last_note();
+    assert!(!sidecar.is_file());
+}
+
+#[test]
+fn flags_cycle_and_unflag() {
+    let image = scratch("flags");
+    let mut document = Document::open(&image).unwrap();
+    assert_eq!(document.cycle_flag(), "Flagged to keep");
+    assert_eq!(document.cycle_flag(), "Flagged to scrub");
+    assert_eq!(Sidecar::open(&image).unwrap().flag, Some(Flag::Scrub));
+    document.cycle_flag();
+    assert_eq!(document.cycle_flag(), "Removed the flag");
+    assert!(!Sidecar::path(&image).is_file());
+}
+
+#[test]
+fn interrupted_scrub_resumes_from_journal() {
+    let image = scratch("journal");
+    let dir = image.parent().unwrap();
+    let path = Journal::path(Some(dir));
+    let options = journal::Options {
+        paths: vec![dir.to_path_buf()],
+        processors: vec!["gps".into()],
+        policy: None,
+        out_dir: Some(dir.to_path_buf()),
+        to_jpeg: false,
+        quality: 90,
+        max_dimension: None,
+        icc: None,
+        stamp: false,
+        byte_order: None,
+        strip_video: false,
+        oversized_exif: None,
+        randomizer: Vec::new(),
+    };
+    let mut started = Journal::start(&path, &options).unwrap();
+    started.record(&image, Ok(None)).unwrap();
+    started
+        .record(&dir.join("broken.jpg"), Err("No Exif data".into()))
+        .unwrap();
+    drop(started);
+    // A line cut short by the interruption is ignored
+    let mut data = std::fs::read_to_string(&path).unwrap();
+    data.push_str("{\"path\":\"/tmp/");
+    std::fs::write(&path, data).unwrap();
+
+    let resumed = Journal::resume(&path, &options).unwrap();
+    assert!(resumed.done.contains(&image));
+    assert_eq!((resumed.done.len(), resumed.failed), (1, 1));
+    resumed.finish().unwrap();
+    assert!(!path.exists());
+
+    let other = journal::Options {
+        processors: vec!["all".into()],
+        ..options.clone()
+    };
+    Journal::start(&path, &options).unwrap();
+    assert!(Journal::resume(&path, &other).is_err());
+}
+
+#[test]
+fn converts_other_formats_to_jpeg() {
+    let image = scratch("convert");
+    let dir = image.parent().unwrap();
+    let png = dir.join("flat.png");
+    image::RgbaImage::from_pixel(16, 8, image::Rgba([200, 40, 40, 128]))
+        .save(&png)
+        .unwrap();
+
+    let to_jpeg = Export {
+        to_jpeg: true,
+        ..Default::default()
+    };
+    let report = batch::process_file(&mut [], &png, None, false, &to_jpeg).unwrap();
+    let output = report.output.unwrap();
+    assert_eq!(output.extension().unwrap(), "jpg");
+    let converted = image::open(&output).unwrap();
+    assert_eq!((converted.width(), converted.height()), (16, 8));
+
+    // JPEGs are left alone when nothing changed
+    let report = batch::process_file(&mut [], &image, None, false, &to_jpeg).unwrap();
+    assert!(report.output.is_none());
+}
+
+#[test]
+fn pdfs_are_edited_scrubbed_and_audited_as_fields() {
+    use lopdf::{dictionary, Object, Stream, StringFormat};
+
+    let dir = scratch("pdf").parent().unwrap().to_path_buf();
+    let mut pdf = lopdf::Document::with_version("1.5");
+    let pages = pdf.new_object_id();
+    let page = pdf.add_object(dictionary! { "Type" => "Page", "Parent" => pages });
+    pdf.objects.insert(
+        pages,
+        Object::Dictionary(
+            dictionary! { "Type" => "Pages", "Kids" => vec![page.into()], "Count" => 1 },
+        ),
+    );
+    let xmp = pdf.add_object(Stream::new(
+        dictionary! { "Type" => "Metadata" },
+        b"<x:xmpmeta/>".to_vec(),
+    ));
+    let catalog =
+        pdf.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages, "Metadata" => xmp });
+    let text = |s: &str| Object::String(s.as_bytes().to_vec(), StringFormat::Literal);
+    let info = pdf.add_object(dictionary! {
+        "Author" => text("Jane Doe"),
+        "Title" => text("Quarterly report"),
+        "CreationDate" => text("D:20240501093000+02'00'"),
+    });
+    pdf.trailer.set("Root", catalog);
+    pdf.trailer.set("Info", info);
+    let path = dir.join("report.pdf");
+    pdf.save(&path).unwrap();
+
+    let file = std::fs::read(&path).unwrap();
+    let read = backend::parse(&file).unwrap();
+    assert_eq!(read.name(), "PDF");
+    let fields = read.fields();
+    let display = |tag| {
+        let field = fields.iter().find(|f| f.tag == tag).unwrap();
+        read.display(field)
+    };
+    assert_eq!(display(Tag::Artist), "Jane Doe");
+    assert_eq!(
+        display(Tag(exif::Context::Tiff, 0x9c9b)),
+        "Quarterly report"
+    );
+    assert_eq!(display(Tag::DateTimeOriginal), "2024-05-01 09:30:00");
+
+    // The author is an owner tag, which the default audit flags
+    let audit = batch::audit_file(&Policy::audit_default(), &path).unwrap();
+    assert!(audit.iter().any(|(tag, _)| *tag == Tag::Artist));
+
+    // Edits are written to the Info dictionary, untouched entries keep their time zone
+    let mut document = Document::open(&path).unwrap();
+    document.set_value(Tag::Artist, Value::Ascii(vec![b"Anonymous".to_vec()]));
+    let status = document.save_state().unwrap();
+    assert!(
+        status.ends_with("every field read back as written"),
+        "{status}"
+    );
+    let copy = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p != &path && p.extension().is_some_and(|e| e == "pdf"))
+        .unwrap();
+    let edited = lopdf::Document::load(&copy).unwrap();
+    let info = edited
+        .trailer
+        .get_deref(b"Info", &edited)
+        .unwrap()
+        .as_dict()
+        .unwrap();
+    assert_eq!(info.get(b"Author").unwrap().as_str().unwrap(), b"Anonymous");
+    assert_eq!(
+        info.get(b"CreationDate").unwrap().as_str().unwrap(),
+        b"D:20240501093000+02'00'"
+    );
+    assert!(edited.catalog().unwrap().get(b"Metadata").is_err());
+
+    // Scrubbing with a policy drops the forbidden entries
+    let scrubbed = batch::scrub_bytes(&file, &Policy::audit_default()).unwrap();
+    let scrubbed = backend::parse(&scrubbed).unwrap();
+    assert!(scrubbed.fields().iter().all(|f| f.tag != Tag::Artist));
+    assert!(scrubbed
+        .fields()
+        .iter()
+        .any(|f| f.tag == Tag::DateTimeOriginal));
+}
+
+#[test]
+fn audio_tags_are_edited_scrubbed_and_audited_as_fields() {
+    use id3::{frame::ExtendedText, Content, Frame, TagLike, Version};
+
+    let dir = scratch("audio").parent().unwrap().to_path_buf();
+    let mut tag = id3::Tag::new();
+    tag.set_artist("Jane Doe");
+    tag.set_title("Field recording");
+    tag.set_album("Walks");
+    tag.add_frame(Frame::text("TDRC", "2024-05-01T09:30:00"));
+    tag.add_frame(Frame::with_content(
+        "TXXX",
+        Content::ExtendedText(ExtendedText {
+            description: String::from("LOCATION"),
+            value: String::from("Champ de Mars"),
+        }),
+    ));
+    let mut mp3 = Vec::new();
+    tag.write_to(&mut mp3, Version::Id3v24).unwrap();
+    // One silent MPEG-1 layer III frame
+    mp3.extend([0xFF, 0xFB, 0x90, 0x00]);
+    mp3.extend([0; 413]);
+    let path = dir.join("walk.mp3");
+    std::fs::write(&path, &mp3).unwrap();
+
+    let read = backend::parse(&mp3).unwrap();
+    assert_eq!(read.name(), "ID3");
+    let fields = read.fields();
+    let display = |tag| {
+        let field = fields.iter().find(|f| f.tag == tag).unwrap();
+        read.display(field)
+    };
+    assert_eq!(display(Tag::Artist), "Jane Doe");
+    assert_eq!(display(Tag::GPSAreaInformation), "Champ de Mars");
+    assert_eq!(display(Tag::DateTimeOriginal), "2024-05-01 09:30:00");
+
+    let audit = batch::audit_file(&Policy::audit_default(), &path).unwrap();
+    assert!(audit.iter().any(|(tag, _)| *tag == Tag::Artist));
+
+    // Edits replace their frame, the rest is kept as it was
+    let mut document = Document::open(&path).unwrap();
+    document.set_value(Tag::Artist, Value::Ascii(vec![b"Anonymous".to_vec()]));
+    let status = document.save_state().unwrap();
+    assert!(
+        status.ends_with("every field read back as written"),
+        "{status}"
+    );
+    let copy = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p != &path && p.extension().is_some_and(|e| e == "mp3"))
+        .unwrap();
+    let edited = id3::Tag::read_from_path(&copy).unwrap();
+    assert_eq!(edited.artist(), Some("Anonymous"));
+    assert_eq!(edited.album(), Some("Walks"));
+    assert_eq!(
+        edited.get("TDRC").unwrap().content().text(),
+        Some("2024-05-01T09:30:00")
+    );
+    assert!(std::fs::read(&copy)
+        .unwrap()
+        .ends_with(&mp3[mp3.len() - 417..]));
+
+    // FLAC files keep their tags as Vorbis comments
+    let mut comments = 7u32.to_le_bytes().to_vec();
+    comments.extend(b"bresson");
+    comments.extend(2u32.to_le_bytes());
+    for comment in ["TITLE=Field recording", "ARTIST=Jane Doe"] {
+        comments.extend((comment.len() as u32).to_le_bytes());
+        comments.extend(comment.as_bytes());
+    }
+    let mut flac = b"fLaC".to_vec();
+    flac.extend([0, 0, 0, 34]);
+    flac.extend([0; 34]);
+    flac.extend([0x84, 0, 0, comments.len() as u8]);
+    flac.extend(&comments);
+    flac.extend([0xFF, 0xF8, 0x69, 0x08]);
+    let read = backend::parse(&flac).unwrap();
+    assert_eq!(read.name(), "Vorbis");
+    assert!(read.fields().iter().any(|f| f.tag == Tag::Artist));
+
+    // Scrubbing with a policy drops the forbidden tags
+    for file in [mp3, flac] {
+        let scrubbed = batch::scrub_bytes(&file, &Policy::audit_default()).unwrap();
+        let scrubbed = backend::parse(&scrubbed).unwrap();
+        assert!(scrubbed.fields().iter().all(|f| f.tag != Tag::Artist));
+        assert!(scrubbed.fields().iter().any(|f| f.tag == utils::XP_TAGS[0]));
+    }
+}
+
+#[test]
+fn screenshot_preset_strips_text_chunks() {
+    let image = scratch("screenshot");
+    let dir = image.parent().unwrap();
+    let mut flat = Vec::new();
+    image::RgbImage::from_pixel(4, 4, image::Rgb([20, 20, 20]))
+        .write_to(
+            &mut std::io::Cursor::new(&mut flat),
+            image::ImageFormat::Png,
+        )
+        .unwrap();
+    // The Exif data of the fixture, and what screenshot tools write besides it
+    let exif = Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(std::fs::read(&image).unwrap()))
+        .unwrap();
+    let fields: Vec<_> = exif
+        .fields()
+        .filter(|f| !writer::is_structural(f.tag))
+        .collect();
+    let tiff = writer::encode(fields, None, false).unwrap();
+    let mut screenshot = png::replace_exif(&flat, &tiff).unwrap();
+    let end = png::chunks(&screenshot).unwrap().last().unwrap().offset;
+    let text = [
+        png::chunk(b"tEXt", b"Software\0gnome-screenshot").unwrap(),
+        png::chunk(
+            b"iTXt",
+            b"XML:com.adobe.xmp\0\0\0\0\0<dc:title>Inbox</dc:title>",
+        )
+        .unwrap(),
+    ]
+    .concat();
+    screenshot.splice(end..end, text);
+    let path = dir.join("screenshot.png");
+    std::fs::write(&path, &screenshot).unwrap();
+
+    let policy = Policy::preset("screenshot").unwrap();
+    let export = Export {
+        strip_text: policy.strip_text,
+        ..Default::default()
+    };
+    let mut processors: Vec<Box<dyn TagProcessor>> = vec![Box::new(PolicyProcessor::new(policy))];
+    let report = batch::process_file(&mut processors, &path, None, false, &export).unwrap();
+    assert_eq!(report.text, ["Software", "XML:com.adobe.xmp"]);
+    assert!(report.dropped.contains(&Tag::Model));
+    assert!(report.dropped.contains(&Tag::DateTimeOriginal));
+
+    let copy = std::fs::read(report.output.unwrap()).unwrap();
+    assert_eq!(image::load_from_memory(&copy).unwrap().width(), 4);
+    let kinds: Vec<_> = png::chunks(&copy).unwrap().iter().map(|c| c.kind).collect();
+    assert_eq!(kinds, [*b"IHDR", *b"eXIf", *b"IDAT", *b"IEND"]);
+    let exif = Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(&copy))
+        .unwrap();
+    assert!(exif.get_field(Tag::Orientation, In::PRIMARY).is_some());
+    for tag in [Tag::Make, Tag::Software, Tag::DateTime, Tag::GPSLatitude] {
+        assert!(exif.fields().all(|f| f.tag != tag), "{} was kept", tag);
+    }
+}
+
+#[test]
+fn color_profile_is_replaced_or_dropped() {
+    let image = scratch("icc");
+    let srgb = icc::srgb();
+    assert_eq!(icc::description(&srgb).unwrap(), "sRGB");
+
+    let report = batch::process_file(
+        &mut [],
+        &image,
+        None,
+        false,
+        &Export {
+            icc: Some(icc::Change::Srgb),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let copy = std::fs::read(report.output.unwrap()).unwrap();
+    assert_eq!(jpeg::icc_profile(&copy).unwrap(), Some(srgb));
+    // The Exif data is still in place
+    Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(&copy))
+        .unwrap();
+
+    let dropped = jpeg::replace_icc(&copy, None).unwrap();
+    assert_eq!(jpeg::icc_profile(&dropped).unwrap(), None);
+    assert!(image::load_from_memory(&dropped).is_ok());
+}
+
+#[test]
+fn downscales_and_updates_dimensions() {
+    let dir = scratch("resize").parent().unwrap().to_path_buf();
+    let image = dir.join("fixture.jpg");
+    std::fs::write(&image, fixture::generate(&Default::default()).unwrap()).unwrap();
+
+    let export = Export {
+        max_dimension: Some(8),
+        ..Default::default()
+    };
+    let report = batch::process_file(&mut [], &image, None, false, &export).unwrap();
+    assert_eq!(report.resized, Some((8, 8)));
+    let copy = std::fs::read(report.output.unwrap()).unwrap();
+    let resized = image::load_from_memory(&copy).unwrap();
+    assert_eq!((resized.width(), resized.height()), (8, 8));
+    let exif = Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(&copy))
+        .unwrap();
+    let width = exif.get_field(Tag::PixelXDimension, In::PRIMARY).unwrap();
+    assert_eq!(width.value.get_uint(0), Some(8));
+    assert!(exif.get_field(Tag::Artist, In::PRIMARY).is_some());
+
+    // Images that are small enough are left as they are
+    let export = Export {
+        max_dimension: Some(16),
+        ..Default::default()
+    };
+    let report = batch::process_file(&mut [], &image, None, false, &export).unwrap();
+    assert_eq!(report.resized, None);
+}
+
+#[test]
+fn metadata_changes_never_reencode_pixels() {
+    let image = scratch("pixels");
+    let original = jpeg::image_data(&std::fs::read(&image).unwrap()).unwrap();
+    let image_data = |path: &Path| jpeg::image_data(&std::fs::read(path).unwrap()).unwrap();
+
+    // Saving from the TUI
+    let mut document = Document::open(&image).unwrap();
+    document.clear_gps();
+    let request = document.save_request().unwrap();
+    request.run(|_, _| true).unwrap();
+    assert_eq!(image_data(&request.copy), original);
+
+    // Scrubbing, with a color profile added
+    let mut processors = Registry::new(RandomMetadata::default())
+        .select(&["strip-all".into()])
+        .unwrap();
+    let export = Export {
+        icc: Some(icc::Change::Srgb),
+        ..Default::default()
+    };
+    let report = batch::process_file(&mut processors, &image, None, false, &export).unwrap();
+    assert_eq!(image_data(&report.output.unwrap()), original);
+
+    // Downscaling is the one that re-encodes
+    let export = Export {
+        max_dimension: Some(8),
+        ..Default::default()
+    };
+    let report = batch::process_file(&mut processors, &image, None, false, &export).unwrap();
+    assert_ne!(image_data(&report.output.unwrap()), original);
+}
+
+#[test]
+fn stamp_records_how_a_copy_was_scrubbed() {
+    let image = scratch("stamp");
+    let mut processors = Registry::new(RandomMetadata::default())
+        .select(&["strip-all".into()])
+        .unwrap();
+    let stamp = batch::stamp(Some("newsroom"), &[]);
+    assert!(stamp.starts_with("Scrubbed by bresson "));
+    assert!(stamp.ends_with(" per policy newsroom"));
+    let export = Export {
+        stamp: Some(stamp.clone()),
+        ..Default::default()
+    };
+    let report = batch::process_file(&mut processors, &image, None, false, &export).unwrap();
+    let copy = std::fs::read(report.output.unwrap()).unwrap();
+    let exif = Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(&copy))
+        .unwrap();
+    let software = exif.get_field(Tag::Software, In::PRIMARY).unwrap();
+    assert_eq!(
+        software.display_value().to_string(),
+        format!("\"{}\"", stamp)
+    );
+    assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_none());
+}
+
+/// Every field of `exif` along with its value, to compare Exif data read in
+/// different byte orders
+fn dump(exif: &exif::Exif) -> Vec<String> {
+    exif.fields()
+        .filter(|f| !bresson::writer::is_structural(f.tag))
+        .map(|f| format!("{} {:?} {:?}", f.tag, f.ifd_num, f.value))
+        .collect()
+}
+
+#[test]
+fn byte_order_is_kept_or_converted_both_ways() {
+    let dir = scratch("byte-order").parent().unwrap().to_path_buf();
+    let image = dir.join("big.jpg");
+    let options = fixture::Options {
+        gps: true,
+        ..Default::default()
+    };
+    std::fs::write(&image, fixture::generate(&options).unwrap()).unwrap();
+    let read = |path: &Path| {
+        Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(std::fs::read(path).unwrap()))
+            .unwrap()
+    };
+    let original = read(&image);
+    assert!(!original.little_endian());
+
+    // Saving keeps the byte order
+    let mut document = Document::open(&image).unwrap();
+    let exif = document.backend.as_exif().unwrap();
+    assert_eq!(exif.byte_order(), ByteOrder::BigEndian);
+    document.clear_gps();
+    let request = document.save_request().unwrap();
+    request.run(|_, _| true).unwrap();
+    assert!(!read(&request.copy).little_endian());
+
+    // Unless it's converted, which swaps the UCS-2 text along with the numbers
+    let to = |order| Export {
+        byte_order: Some(order),
+        ..Default::default()
+    };
+    let little = batch::process_file(&mut [], &image, None, false, &to(ByteOrder::LittleEndian))
+        .unwrap()
+        .output
+        .unwrap();
+    let converted = read(&little);
+    assert!(converted.little_endian());
+    let area = converted
+        .get_field(Tag::GPSAreaInformation, In::PRIMARY)
+        .unwrap();
+    let Value::Undefined(bytes, _) = &area.value else {
+        panic!("GPSAreaInformation isn't UNDEFINED");
+    };
+    assert_eq!(
+        bresson::utils::decode_encoded_text(bytes, true),
+        "Champ de Mars"
+    );
+
+    let back = dir.join("back");
+    let big = batch::process_file(
+        &mut [],
+        &little,
+        Some(&back),
+        false,
+        &to(ByteOrder::BigEndian),
+    )
+    .unwrap()
+    .output
+    .unwrap();
+    assert_eq!(dump(&read(&big)), dump(&original));
+}
+
+#[test]
+fn repl_queries_and_clears_tags() {
+    let image = scratch("repl");
+    let dir = image.parent().unwrap();
+    let library = Library::load(&[dir.to_path_buf()]).unwrap();
+    let output = |line: &str| match library.run(line).unwrap() {
+        Outcome::Output(output) => output,
+        Outcome::Quit => panic!("{} quit", line),
+    };
+
+    assert_eq!(output("count where GPSLatitudeRef=N"), "1\n");
+    assert_eq!(output("count where GPSLatitudeRef != \"N\""), "0\n");
+    assert!(output("count by GPSLatitudeRef").contains("1  N"));
+    assert!(library.run("count where GPSLatitudeRef").is_err());
+    assert!(matches!(library.run("quit").unwrap(), Outcome::Quit));
+
+    assert!(output("clear GPS where GPSLatitudeRef~\"^[NS]$\"").contains("cleared"));
+    assert_eq!(files_in(dir), 2);
+    let copy = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| *p != image)
+        .unwrap();
+    let exif = Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(std::fs::read(copy).unwrap()))
+        .unwrap();
+    assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_none());
+}
+
+#[test]
+fn index_writes_files_and_tags() {
+    let image = scratch("index");
+    let dir = image.parent().unwrap();
+    let db = dir.join("photos.sqlite");
+    let summary = index::index(&[dir.to_path_buf()], &db).unwrap();
+    assert_eq!((summary.images, summary.without_exif), (1, 0));
+    // Indexing again replaces the rows instead of adding to them
+    index::index(&[image], &db).unwrap();
+
+    let conn = rusqlite::Connection::open(&db).unwrap();
+    let (files, latitude): (i64, Option<f64>) = conn
+        .query_row("SELECT count(*), max(latitude) FROM files", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .unwrap();
+    assert_eq!(files, 1);
+    assert!(latitude.is_some());
+    let tags: i64 = conn
+        .query_row("SELECT count(*) FROM tags", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(tags as usize, summary.tags);
+}
+
+#[test]
+fn report_lists_files_and_escapes_them() {
+    let image = scratch("report");
+    let dir = image.parent().unwrap();
+    let mut processors = Registry::new(RandomMetadata::default())
+        .select(&["strip-owner".into()])
+        .unwrap();
+    let file =
+        batch::process_file(&mut processors, &image, None, true, &Export::default()).unwrap();
+    let report = Report {
+        how: String::from("Scrubbed with <strip-owner>"),
+        dry_run: true,
+        entries: vec![
+            report::Entry::new(&file, Some((48.8567, 2.2945))),
+            report::Entry::failed(&dir.join("broken.jpg"), String::from("No Exif data")),
+        ],
+    };
+    let html = report.render();
+    assert!(!html.contains("{{"));
+    assert!(html.contains("&lt;strip-owner&gt;"));
+    assert!(html.contains("Copy still has the location"));
+    assert!(html.contains("<circle class=\"kept\""));
+    assert!(html.contains("1 failed"));
+}
+
+#[test]
+fn recording_turns_around_the_location() {
+    let dir = scratch("record").parent().unwrap().to_path_buf();
+    let recording = Recording {
+        lat: 48.8567,
+        lon: 2.2945,
+        seconds: 1.,
+        fps: 4,
+        columns: 40,
+        rows: 20,
+    };
+    let frames = recording.frames();
+    assert_eq!(frames.len(), 4);
+    // The turn starts on the location, marked in the middle
+    assert!(frames[0][8..12].iter().flatten().any(|&c| c == '#'));
+
+    let cast = dir.join("globe.cast");
+    recording.write(&cast).unwrap();
+    let text = std::fs::read_to_string(&cast).unwrap();
+    let lines: Vec<serde_json::Value> = text
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines[0]["version"], 2);
+    assert_eq!(lines.len(), 5);
+    assert_eq!(lines[4][0], 0.75);
+
+    let gif = dir.join("globe.gif");
+    recording.write(&gif).unwrap();
+    let decoded = image::open(&gif).unwrap();
+    assert_eq!((decoded.width(), decoded.height()), (160, 160));
+    assert!(recording.write(&dir.join("globe.mp4")).is_err());
+}
+
+#[test]
+fn undo_journal_puts_scrubbed_values_back() {
+    let image = scratch("undo");
+    let dir = image.parent().unwrap();
+    let mut processors = Registry::new(RandomMetadata::default())
+        .select(&[String::from("strip-gps")])
+        .unwrap();
+    let report =
+        batch::process_file(&mut processors, &image, None, false, &Export::default()).unwrap();
+    let copy = report.output.clone().unwrap();
+    let latitude = |path: &Path| {
+        let exif = Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(std::fs::read(path).unwrap()))
+            .unwrap();
+        exif.get_field(Tag::GPSLatitude, In::PRIMARY)
+            .map(|f| f.display_value().to_string())
+    };
+    assert!(latitude(&copy).is_none());
+
+    let mut undo = UndoJournal::default();
+    let changed = [report.dropped.as_slice(), &report.replaced].concat();
+    undo.record(&image, &copy, &changed).unwrap();
+    // Only what the scrub changed is noted down
+    let entry = &undo.entries[0];
+    assert_eq!(entry.manifest.fields.len(), changed.len());
+    assert!(entry.manifest.fields.iter().all(|f| f.context == "gps"));
+
+    let path = dir.join("undo.json");
+    undo.write(&path, Some("secret")).unwrap();
+    assert!(!std::fs::read_to_string(&path).is_ok_and(|s| s.contains("GPSLatitude")));
+    assert!(UndoJournal::load(&path, Some("wrong")).is_err());
+    let undo = UndoJournal::load(&path, Some("secret")).unwrap();
+
+    undo.entries[0].undo().unwrap();
+    assert_eq!(latitude(&copy), latitude(&image));
+}
+
+#[test]
+fn core_types_go_through_json_and_back() {
+    let image = scratch("serial");
+    let mut document = Document::open(&image).unwrap();
+    document.set_location(48.8584, 2.2945);
+    document.clear_gps();
+
+    let val = &document.modified_fields[&Tag::Model];
+    let json = serde_json::to_string(val).unwrap();
+    assert!(json.contains(r#""name":"Model""#) && json.contains(r#""changed":false"#));
+    let back: MetadataVal = serde_json::from_str(&json).unwrap();
+    assert_eq!(&back, val);
+
+    // The edit log reads back as the same operations
+    let ops: Vec<_> = document.operations().collect();
+    assert_eq!(ops.len(), 2);
+    let json = serde_json::to_string(&ops).unwrap();
+    assert!(json.contains(r#""op":"set_fields""#) && json.contains(r#""op":"clear_gps""#));
+    let back: Vec<Operation> = serde_json::from_str(&json).unwrap();
+    assert_eq!(serde_json::to_string(&back).unwrap(), json);
+    assert!(matches!(&back[0], Operation::SetFields(what, _) if what == "location"));
+
+    let export: Export =
+        serde_json::from_str(r#"{"icc":"srgb","byte_order":"big","quality":80}"#).unwrap();
+    assert_eq!(export.icc, Some(icc::Change::Srgb));
+    assert_eq!(export.byte_order, Some(ByteOrder::BigEndian));
+    assert_eq!(export.quality, 80);
+    assert!(serde_json::from_str::<Export>(r#"{"colour":"srgb"}"#).is_err());
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_scrub_reads_and_writes_through_the_backend() {
+    use bresson::nonblocking;
+
+    let image = scratch("async");
+    let dir = image.parent().unwrap().to_path_buf();
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let policy = Policy::from_toml(r#"clear = ["GPS*"]"#).unwrap();
+    let report = runtime
+        .block_on(nonblocking::scrub(&image, None, policy))
+        .unwrap();
+    let copy = report.output.clone().unwrap();
+    assert!(copy.starts_with(&dir));
+    assert!(report.dropped.contains(&Tag::GPSLatitude));
+
+    let backend = runtime.block_on(nonblocking::read(&copy)).unwrap();
+    let fields = backend.fields();
+    assert!(fields.iter().all(|f| f.tag.context() != exif::Context::Gps));
+    assert!(fields.iter().any(|f| f.tag == Tag::Model));
+
+    // Writing the fields back unchanged keeps them all
+    let again = dir.join("again.jpg");
+    runtime
+        .block_on(nonblocking::write(backend, &copy, &again, fields.clone()))
+        .unwrap();
+    let backend = runtime.block_on(nonblocking::read(&again)).unwrap();
+    assert_eq!(backend.fields().len(), fields.len());
+    assert!(runtime
+        .block_on(nonblocking::read(&dir.join("missing.jpg")))
+        .is_err());
+}
+
+#[cfg(feature = "ffi")]
+#[test]
+fn c_interface_clears_tags_in_a_buffer() {
+    use bresson::ffi::*;
+    use std::ffi::{CStr, CString};
+
+    let file = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gps.jpg"))
+        .unwrap();
+    unsafe {
+        let image = bresson_open(file.as_ptr(), file.len());
+        assert!(!image.is_null());
+        let count = bresson_tag_count(image);
+        let names: Vec<String> = (0..count)
+            .map(|i| {
+                let name = bresson_tag_name(image, i);
+                let owned = CStr::from_ptr(name).to_str().unwrap().to_owned();
+                bresson_string_free(name);
+                owned
+            })
+            .collect();
+        assert!(names.iter().any(|n| n == "GPSLatitude"));
+        assert!(bresson_tag_name(image, count).is_null());
+        assert!(CStr::from_ptr(bresson_last_error())
+            .to_str()
+            .unwrap()
+            .contains("No tag"));
+
+        let pattern = CString::new("gps*").unwrap();
+        let gps = names.iter().filter(|n| n.starts_with("GPS")).count();
+        assert_eq!(bresson_clear_tags(image, pattern.as_ptr()), gps as i32);
+
+        let (mut out, mut len) = (std::ptr::null_mut(), 0);
+        assert_eq!(bresson_write(image, &mut out, &mut len), 0);
+        let copy = std::slice::from_raw_parts(out, len).to_vec();
+        bresson_buffer_free(out, len);
+        bresson_free(image);
+
+        let exif = Reader::new()
+            .read_from_container(&mut std::io::Cursor::new(copy))
+            .unwrap();
+        assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_none());
+        assert!(exif.get_field(Tag::Model, In::PRIMARY).is_some());
+
+        assert!(bresson_open(b"not an image".as_ptr(), 12).is_null());
+    }
+}
+
+#[test]
+fn scrub_bytes_works_without_files() {
+    let file = std::fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/gps.jpg"))
+        .unwrap();
+    let policy = Policy::from_toml(r#"clear = ["GPS*"]"#).unwrap();
+    let copy = batch::scrub_bytes(&file, &policy).unwrap();
+    let exif = Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(&copy))
+        .unwrap();
+    assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_none());
+    assert!(exif.get_field(Tag::Model, In::PRIMARY).is_some());
+    assert_eq!(
+        jpeg::image_data(&copy).unwrap(),
+        jpeg::image_data(&file).unwrap()
+    );
+    assert!(batch::scrub_bytes(b"not an image", &policy).is_err());
+}
+
+#[test]
+fn serve_answers_json_rpc_requests() {
+    let image = scratch("serve");
+    let call = |method: &str, params: serde_json::Value| {
+        let request =
+            serde_json::json!({"jsonrpc": "2.0", "id": 7, "method": method, "params": params});
+        let answer = serve::handle(&request.to_string()).unwrap();
+        serde_json::from_str::<serde_json::Value>(&answer).unwrap()
+    };
+
+    let answer = call("inspect", serde_json::json!({"path": image}));
+    assert_eq!(answer["id"], 7);
+    let fields = answer["result"]["fields"].as_array().unwrap();
+    assert!(fields.iter().any(|f| f["name"] == "GPSLatitude"));
+
+    let answer = call(
+        "scrub",
+        serde_json::json!({"path": image, "processors": ["strip-gps"], "export": {"stamp": "served"}}),
+    );
+    let output = answer["result"]["output"].as_str().unwrap();
+    assert!(answer["result"]["dropped"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|t| t["name"] == "GPSLatitude" && t["context"] == "gps"));
+    let exif = Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(std::fs::read(output).unwrap()))
+        .unwrap();
+    assert!(exif.get_field(Tag::GPSLatitude, In::PRIMARY).is_none());
+
+    assert_eq!(
+        call("nope", serde_json::Value::Null)["error"]["code"],
+        -32601
+    );
+    assert_eq!(
+        call("inspect", serde_json::json!({}))["error"]["code"],
+        -32602
+    );
+    let answer = call("inspect", serde_json::json!({"path": "missing.jpg"}));
+    assert_eq!(answer["error"]["code"], -32000);
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&serve::handle("{").unwrap()).unwrap()["error"]
+            ["code"],
+        -32700
+    );
+    // Notifications get no answer
+    assert!(serve::handle(r#"{"jsonrpc":"2.0","method":"stats","params":{"paths":[]}}"#).is_none());
+}
+
+#[test]
+fn motion_photo_video_is_found_and_dropped() {
+    let image = scratch("motion");
+    let dir = image.parent().unwrap();
+    let still = std::fs::read(&image).unwrap();
+    let video = [b"\0\0\0\x18ftypmp42".as_slice(), &[7; 64]].concat();
+    let xml = format!(
+        "<rdf:Description GCamera:MicroVideo=\"1\" GCamera:MicroVideoOffset=\"{}\"/>",
+        video.len()
+    );
+    let payload = [jpeg::XMP_HEADER, xml.as_bytes()].concat();
+    let len = (payload.len() as u16 + 2).to_be_bytes();
+    let segment = [&[0xFF, jpeg::APP1, len[0], len[1]], payload.as_slice()].concat();
+    let photo = [&still[..2], &segment, &still[2..], &video].concat();
+    let path = dir.join("PXL_motion.jpg");
+    std::fs::write(&path, &photo).unwrap();
+
+    let offset = photo.len() - video.len();
+    let found = motion::detect(&path, &photo).unwrap();
+    assert_eq!(
+        found.video,
+        Video::Embedded {
+            offset,
+            len: video.len()
+        }
+    );
+
+    // Copied along unless asked otherwise
+    let scrub = |strip_video| {
+        let out = dir.join(format!("out-{}", strip_video));
+        std::fs::create_dir_all(&out).unwrap();
+        let export = Export {
+            strip_video,
+            ..Default::default()
+        };
+        let mut processors = Registry::default().select(&["strip-gps".into()]).unwrap();
+        let report =
+            batch::process_file(&mut processors, &path, Some(&out), false, &export).unwrap();
+        (
+            report.motion.is_some(),
+            report.video_dropped,
+            std::fs::read(report.output.unwrap()).unwrap(),
+        )
+    };
+    let (motion, dropped, copy) = scrub(false);
+    assert!(motion);
+    assert_eq!(dropped, None);
+    assert!(copy.ends_with(&video));
+
+    let (_, dropped, copy) = scrub(true);
+    assert_eq!(dropped, Some(video.len()));
+    assert!(motion::detect(&path, &copy).is_none());
+    assert!(String::from_utf8_lossy(&copy).contains("GCamera:MicroVideo=\"0\""));
+    assert_eq!(
+        jpeg::image_data(&copy).unwrap(),
+        jpeg::image_data(&photo[..offset]).unwrap()
+    );
+
+    // A Live Photo keeps its video next to the still
+    let live = dir.join("IMG_0001.JPG");
+    std::fs::write(&live, &still).unwrap();
+    std::fs::write(dir.join("IMG_0001.MOV"), &video).unwrap();
+    let found = motion::detect(&live, &still).unwrap();
+    assert_eq!(found.video, Video::Paired(dir.join("IMG_0001.MOV")));
+    assert!(motion::strip_video(&still).is_none());
+}
+
+#[test]
+fn oversized_exif_is_refused_or_made_room_for() {
+    let options = fixture::Options {
+        maker_note: Some(20_000),
+        ..Default::default()
+    };
+    let file = fixture::generate(&options).unwrap();
+    let backend = bresson::backend::parse(&file).unwrap();
+    let mut packet = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">".to_vec();
+    packet.resize(30_000, b' ');
+    packet.extend_from_slice(b"</x:xmpmeta>");
+    let mut fields: Vec<_> = backend
+        .fields()
+        .into_iter()
+        .filter(|f| !writer::is_structural(f.tag))
+        .collect();
+    fields.extend([
+        exif::Field {
+            tag: Tag::ImageDescription,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![vec![b'x'; 20_000]]),
+        },
+        exif::Field {
+            tag: writer::XML_PACKET,
+            ifd_num: In::PRIMARY,
+            value: Value::Byte(packet.clone()),
+        },
+    ]);
+    let write = |oversized| batch::write_fitting(&*backend, &file, &fields, None, oversized);
+
+    let refused = write(Oversized::Refuse).unwrap_err().to_string();
+    assert!(refused.contains("more than the 65533 a JPEG segment holds"));
+    assert!(batch::write_bytes(&*backend, &file, &fields, None).is_err());
+
+    let (copy, taken) = write(Oversized::DropMakerNote).unwrap();
+    assert_eq!(taken.unwrap().tag, Tag::MakerNote);
+    let read = bresson::backend::parse(&copy).unwrap();
+    let tags: Vec<_> = read.fields().iter().map(|f| f.tag).collect();
+    assert!(!tags.contains(&Tag::MakerNote));
+    assert!(tags.contains(&writer::XML_PACKET));
+
+    // The packet ends up in a segment of its own, as XMP in a JPEG should be
+    let (copy, taken) = write(Oversized::MoveXmp).unwrap();
+    let taken = taken.unwrap();
+    assert_eq!(
+        Oversized::MoveXmp.describe(&taken),
+        "moved the XMP packet out of the Exif data (30012 bytes)"
+    );
+    let xmp: Vec<_> = jpeg::segments(&copy)
+        .unwrap()
+        .into_iter()
+        .map(|s| s.payload(&copy))
+        .filter(|p| p.starts_with(jpeg::XMP_HEADER))
+        .collect();
+    assert_eq!(xmp, [[jpeg::XMP_HEADER, &packet].concat()]);
+    let read = bresson::backend::parse(&copy).unwrap();
+    let tags: Vec<_> = read.fields().iter().map(|f| f.tag).collect();
+    assert!(tags.contains(&Tag::MakerNote));
+    assert!(!tags.contains(&writer::XML_PACKET));
+    // And can't be moved next to another one
+    assert!(jpeg::insert_xmp(&copy, &packet).is_err());
+}
+
+#[test]
+fn vault_restores_text_byte_for_byte() {
+    let image = fixture::generate(&fixture::Options {
+        weird_encoding: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let manifest = backend::parse(&image)
+        .unwrap()
+        .manifest(Path::new("weird.jpg"));
+    let sealed = manifest.seal("correct horse").unwrap();
+
+    let opened = Manifest::open(&sealed, "correct horse").unwrap();
+    let artist = opened.fields.iter().find(|f| f.name == "Artist").unwrap();
+    // Latin-1, which isn't UTF-8
+    match artist.value.to_value() {
+        Value::Ascii(strings) => assert_eq!(strings, [b"Zo\xEB M\xFCller".to_vec()]),
+        value => panic!("{:?} isn't ASCII", value),
+    }
+    let values = |m: &Manifest| m.fields.iter().map(|f| f.value.clone()).collect::<Vec<_>>();
+    assert_eq!(values(&opened), values(&manifest));
+
+    assert!(Manifest::open(&sealed, "wrong horse").is_err());
+}
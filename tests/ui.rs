@@ -0,0 +1,1403 @@
+// Snapshot tests of the main view, rendered into a TestBackend
+//
+// Snapshots are plain text files in tests/snapshots, one line per terminal row.
+// Run with UPDATE_SNAPSHOTS=1 to write them anew after an intended change.
+
+use bresson::{
+    backend,
+    config::{self, Config},
+    editor,
+    fixture::{self, Options},
+    globe::{self, Globe, Marker, Region},
+    image::{embedded_thumbnail, split_view, Background, ResizeRequest},
+    input::{Chord, Input, InputState, CHORD_TIMEOUT},
+    jpeg, order,
+    perf::PerfCounters,
+    policy::Policy,
+    processor,
+    sidecar::Flag,
+    state::{Application, Cardinal, GPSInfo, Message, MetadataVal},
+    timeline::{Entry, Timeline, View},
+    ui, utils, version,
+    widget::{GlobeState, GlobeWidget},
+};
+use crossterm::event::{KeyCode, KeyEvent};
+use exif::{Field, In, Tag, Value};
+use image::{DynamicImage, Rgb, Rgba, RgbaImage};
+use ratatui::{
+    backend::TestBackend,
+    buffer::Buffer,
+    layout::Rect,
+    style::{Color, Style},
+    widgets::{Block, StatefulWidget},
+    Terminal,
+};
+use ratatui_image::picker::{Picker, ProtocolType};
+use std::{
+    path::Path,
+    sync::mpsc::{self, Receiver},
+    time::{Duration, Instant},
+};
+
+type Worker = Receiver<ResizeRequest>;
+
+const WIDTH: u16 = 100;
+const HEIGHT: u16 = 40;
+
+/// Opens a fixture the way `main` does, returning the worker channel with it so
+/// resize requests have somewhere to go
+fn open(fixture: &str) -> (Application, Worker) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(fixture);
+    let mut globe = Globe::new(1., 0., false);
+    globe.camera.update(1.5, 0., 0.);
+    let (tx_worker, rx_worker) = mpsc::channel();
+    let mut app = Application::with_picker(&path, globe, tx_worker, Picker::new((8, 16))).unwrap();
+    app.document.update_gps();
+    app.transform_coordinates();
+    (app, rx_worker)
+}
+
+fn to_text(buffer: &Buffer) -> String {
+    let area = buffer.area;
+    let mut text = String::new();
+    for y in area.top()..area.bottom() {
+        for x in area.left()..area.right() {
+            text.push_str(buffer[(x, y)].symbol());
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn assert_snapshot(name: &str, buffer: &Buffer) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{}.txt", name));
+    let actual = to_text(buffer);
+    if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("No snapshot {:?}, run with UPDATE_SNAPSHOTS=1", path));
+    assert!(
+        actual == expected,
+        "{} doesn't match its snapshot:\n{}",
+        name,
+        actual
+    );
+}
+
+#[test]
+fn view_with_gps() {
+    let (mut app, _worker) = open("gps.jpg");
+    let buffer = ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap();
+    assert_snapshot("view_with_gps", &buffer);
+}
+
+#[test]
+fn view_without_gps() {
+    let (mut app, _worker) = open("nogps.jpg");
+    let buffer = ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap();
+    assert_snapshot("view_without_gps", &buffer);
+}
+
+#[test]
+fn view_without_globe() {
+    let (mut app, _worker) = open("gps.jpg");
+    app.update(Message::ToggleGlobe);
+    let buffer = ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap();
+    assert_snapshot("view_without_globe", &buffer);
+}
+
+#[test]
+fn keybinds_popup() {
+    let (mut app, _worker) = open("gps.jpg");
+    app.update(Message::ToggleKeybinds);
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("Keybinds"));
+    assert!(text.contains("Randomize selected Metadata"));
+    // The last row still fits
+    assert!(text.contains("Quit"));
+}
+
+#[test]
+fn clearing_gps_shows_in_status() {
+    let (mut app, _worker) = open("gps.jpg");
+    app.update(Message::ClearGps);
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("Cleared 6 GPS fields"));
+}
+
+#[test]
+fn markdown_marks_changed_values() {
+    let (mut app, _worker) = open("gps.jpg");
+    app.update(Message::ClearGps);
+    let markdown = app.document.markdown();
+    assert!(markdown.starts_with("| Tag | Original | Modified |\n|---|---|---|\n"));
+    let latitude = markdown
+        .lines()
+        .find(|l| l.starts_with("| North or south latitude |"))
+        .unwrap();
+    assert!(latitude.ends_with("| *cleared* |"));
+    assert!(markdown
+        .lines()
+        .skip(2)
+        .all(|l| l.matches(" | ").count() == 2));
+}
+
+#[test]
+fn hyperlinks_cover_file_name_and_location() {
+    let (mut app, _worker) = open("gps.jpg");
+    let buffer = ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap();
+    assert!(app.ui.links.is_empty());
+
+    app.ui.hyperlinks = true;
+    let buffer_with_links = ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap();
+    // Links are drawn over the frame afterwards, it stays the same
+    assert_eq!(buffer, buffer_with_links);
+    assert_eq!(app.ui.links.len(), 2);
+    for link in &app.ui.links {
+        let drawn: String = (0..link.text.chars().count() as u16)
+            .map(|i| buffer[(link.x + i, link.y)].symbol())
+            .collect();
+        assert_eq!(drawn, link.text);
+    }
+    assert!(app
+        .ui
+        .links
+        .iter()
+        .any(|l| l.url.starts_with("file:///") && l.url.ends_with("/gps.jpg")));
+    assert!(app
+        .ui
+        .links
+        .iter()
+        .any(|l| l.url.starts_with("https://www.openstreetmap.org/?mlat=")));
+}
+
+#[test]
+fn compares_image_with_embedded_thumbnail() {
+    let (mut app, _worker) = open("gps.jpg");
+    app.update(Message::Compare);
+    assert!(app.ui.status_msg.contains("no embedded thumbnail"));
+    assert_eq!(app.ui.compare, None);
+
+    let path = std::env::temp_dir().join("bresson-ui-embedded.jpg");
+    let options = fixture::Options {
+        thumbnail: true,
+        ..Default::default()
+    };
+    std::fs::write(&path, fixture::generate(&options).unwrap()).unwrap();
+    let (tx_worker, _worker) = mpsc::channel();
+    let mut app = Application::with_picker(
+        &path,
+        Globe::new(1., 0., false),
+        tx_worker,
+        Picker::new((8, 16)),
+    )
+    .unwrap();
+    app.update(Message::Compare);
+    assert!(app.ui.status_msg.starts_with("Embedded thumbnail (8x8)"));
+    assert_eq!(app.ui.compare, Some(50));
+    app.update(Message::MoveDivider(-10));
+    let buffer = ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap();
+    assert!(to_text(&buffer).contains("Thumbnail 40% | Embedded Thumbnail"));
+    app.update(Message::Compare);
+    assert_eq!(app.ui.compare, None);
+
+    // The white corner only the embedded thumbnail has shows right of the line
+    let image = image::open(&path).unwrap();
+    let embedded = embedded_thumbnail(&std::fs::read(&path).unwrap()).unwrap();
+    let brightness = |view: &DynamicImage| view.to_rgb8().get_pixel(1, 1).0[1];
+    assert!(brightness(&split_view(&image, &embedded, 0)) > 200);
+    assert!(brightness(&split_view(&image, &embedded, 100)) < 100);
+}
+
+#[test]
+fn config_restricts_randomized_tags() {
+    let (mut app, _worker) = open("gps.jpg");
+    let config = Config::from_toml(
+        r#"
+        [randomize]
+        add = ["NotATag"]
+        remove = ["Make", "DateTime*"]
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.apply(&mut app.document.randomizer), ["NotATag"]);
+
+    app.update(Message::RandomizeAll);
+    app.update(Message::Answer(true));
+    let changed = |tag| app.document.modified_fields[&tag].changed;
+    assert!(!changed(Tag::Make));
+    assert!(!changed(Tag::DateTimeOriginal));
+    assert!(changed(Tag::ExposureTime) && changed(Tag::GPSLatitude));
+}
+
+#[test]
+fn config_bounds_random_dates() {
+    let (mut app, _worker) = open("gps.jpg");
+    let config = Config::from_toml(
+        r#"
+        [randomize]
+        earliest = "2022-02-28"
+        latest = "2022-02-28"
+        "#,
+    )
+    .unwrap();
+    config.apply(&mut app.document.randomizer);
+    app.update(Message::RandomizeAll);
+    app.update(Message::Answer(true));
+    for tag in [Tag::DateTime, Tag::DateTimeOriginal, Tag::DateTimeDigitized] {
+        let value = app.document.modified_fields[&tag].field.value.clone();
+        assert!(value.display_as(tag).to_string().starts_with("2022-02-28"));
+    }
+
+    assert!(Config::from_toml("[randomize]\nearliest = \"2022-02-30\"").is_err());
+    assert!(
+        Config::from_toml("[randomize]\nearliest = \"2023-01-01\"\nlatest = \"2022-01-01\"")
+            .is_err()
+    );
+}
+
+#[test]
+fn config_sets_thumbnail_background() {
+    let (mut app, _worker) = open("gps.jpg");
+    assert_eq!(app.ui.background(), Background::default());
+    let config = Config::from_toml("[thumbnail]\nbackground = \"#1E1E2E\"").unwrap();
+    let background = config.thumbnail.background().unwrap();
+    assert_eq!(background, Background::Color(Rgb([0x1e, 0x1e, 0x2e])));
+    app.ui.set_background(background);
+    assert_eq!(app.ui.background(), background);
+
+    // Half transparent red over white comes out pink, and opaque pixels stay
+    let mut pixels = RgbaImage::new(2, 1);
+    pixels.put_pixel(0, 0, Rgba([255, 0, 0, 128]));
+    pixels.put_pixel(1, 0, Rgba([0, 0, 255, 255]));
+    let white = Background::from_name("white").unwrap();
+    let blended = white.composite(DynamicImage::ImageRgba8(pixels.clone()));
+    assert_eq!(blended.to_rgb8().get_pixel(0, 0), &Rgb([255, 127, 127]));
+    assert_eq!(blended.to_rgb8().get_pixel(1, 0), &Rgb([0, 0, 255]));
+    let kept = Background::Terminal.composite(DynamicImage::ImageRgba8(pixels));
+    assert!(kept.color().has_alpha());
+
+    assert!(Config::from_toml("[thumbnail]\nbackground = \"#12345\"").is_err());
+    assert!(Config::from_toml("[thumbnail]\nbackground = \"teal-ish\"").is_err());
+}
+
+#[test]
+fn globe_width_follows_font_ratio() {
+    let (mut app, _worker) = open("gps.jpg");
+    // Columns between the leftmost and rightmost drawn cell of the globe rows
+    let globe_width = |app: &mut Application| {
+        let text = to_text(&ui::render_to_buffer(app, WIDTH, HEIGHT).unwrap());
+        let columns: Vec<usize> = text
+            .lines()
+            .skip_while(|l| !l.contains("Image Location"))
+            .skip(1)
+            .take_while(|l| !l.contains("Day,"))
+            .flat_map(|l| {
+                l.chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c != ' ' && c != '│')
+                    .map(|(i, _)| i)
+            })
+            .collect();
+        columns.iter().max().unwrap() - columns.iter().min().unwrap()
+    };
+    assert_eq!(app.ui.globe.font_ratio, 2.);
+    let narrow = globe_width(&mut app);
+
+    let config = Config::from_toml("[globe]\nfont_ratio = 3.0").unwrap();
+    app.ui.globe.font_ratio = config.globe.font_ratio.unwrap();
+    assert!(globe_width(&mut app) > narrow);
+
+    assert!(Config::from_toml("[globe]\nfont_ratio = 0.0").is_err());
+}
+
+#[test]
+fn default_config_is_valid_with_every_setting_uncommented() {
+    let commented = Config::from_toml(config::DEFAULT_TOML).unwrap();
+    assert_eq!(commented.confirm.bulk, None);
+
+    let uncommented: String = config::DEFAULT_TOML
+        .lines()
+        .map(|line| match line.strip_prefix("# ") {
+            Some(setting)
+                if setting.split_once(" = ").is_some_and(|(key, _)| {
+                    key.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+                }) =>
+            {
+                setting
+            }
+            _ => line,
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let config = Config::from_toml(&uncommented).unwrap();
+    assert_eq!(config.randomize.add, ["ImageUniqueID"]);
+    assert_eq!(config.thumbnail.background.as_deref(), Some("black"));
+    assert_eq!(config.globe.font_ratio, Some(2.));
+    assert_eq!(config.confirm.bulk, Some(true));
+}
+
+#[test]
+fn verbose_version_lists_formats_and_protocol() {
+    let text = version::verbose(Some(ProtocolType::Kitty));
+    assert!(text.starts_with(&format!("bresson {}\n", env!("CARGO_PKG_VERSION"))));
+    // Every optional part is on the line it belongs on in this build
+    let line = |label: &str| text.lines().find(|l| l.starts_with(label)).unwrap();
+    let clipboard = cfg!(feature = "clipboard");
+    assert_eq!(line("Features:").contains("clipboard"), clipboard);
+    assert_eq!(line("Without:").contains("clipboard"), !clipboard);
+    assert!(text.contains("jpg, jpeg, png"));
+    assert!(text.contains("Documents: pdf\n"));
+    assert!(text.ends_with("Graphics:  kitty\n"));
+    assert!(version::verbose(None).ends_with("Graphics:  none, not a terminal\n"));
+}
+
+#[test]
+fn clipboard_takes_the_place_of_an_image() {
+    use bresson::cli::Cli;
+    use clap::Parser;
+
+    let cli = Cli::try_parse_from(["bresson", "--from-clipboard", "--markdown"]).unwrap();
+    assert!(cli.from_clipboard && cli.images.is_empty());
+    assert!(Cli::try_parse_from(["bresson", "--markdown"]).is_err());
+    assert!(Cli::try_parse_from(["bresson", "photo.jpg", "--from-clipboard"]).is_err());
+    if !cfg!(feature = "clipboard") {
+        let error = bresson::clipboard::read().unwrap_err();
+        assert!(error.to_string().contains("--features clipboard"));
+    }
+}
+
+#[test]
+fn debug_overlay_shows_the_last_second() {
+    let mut perf = PerfCounters::default();
+    let start = Instant::now();
+    perf.frame(start, Duration::from_millis(10));
+    perf.event(start);
+    perf.frame(
+        start + Duration::from_millis(500),
+        Duration::from_millis(30),
+    );
+    assert_eq!(perf.frame_time(), Duration::from_millis(20));
+    assert_eq!(perf.slowest_frame(), Duration::from_millis(30));
+    assert_eq!(perf.events_per_sec(), 1);
+
+    // Once a second has passed the first frame and the event are forgotten
+    perf.frame(
+        start + Duration::from_millis(1200),
+        Duration::from_millis(30),
+    );
+    assert_eq!(perf.frames_per_sec(), 2);
+    assert_eq!(perf.frame_time(), Duration::from_millis(30));
+    assert_eq!(perf.events_per_sec(), 0);
+
+    let (mut app, _worker) = open("gps.jpg");
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(!text.contains("ms/frame"));
+    app.update(Message::ToggleDebug);
+    app.ui.perf = perf;
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("30.0 ms/frame, max 30.0"));
+    assert!(text.contains("2 fps, 0 events/s"));
+}
+
+#[test]
+fn thumbnail_is_resized_by_worker_one_request_at_a_time() {
+    let (mut app, worker) = open("gps.jpg");
+    app.update(Message::ToggleRenderState);
+    ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap();
+    // Renders at other sizes while it's away don't queue more work
+    ui::render_to_buffer(&mut app, 80, 30).unwrap();
+    let stale = worker.try_recv().unwrap();
+    assert!(worker.try_recv().is_err());
+    assert!(app.ui.async_state.is_resizing());
+
+    // The thumbnail of the old background coming back doesn't replace the new one
+    app.ui.set_background(Background::Terminal);
+    ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap();
+    let current = worker.try_recv().unwrap();
+    app.ui.async_state.set_protocol(stale.resize_encode(None));
+    assert!(app.ui.async_state.is_resizing());
+    app.ui.async_state.set_protocol(current.resize_encode(None));
+    assert!(!app.ui.async_state.is_resizing());
+
+    // Without a worker it says so instead of panicking
+    drop(worker);
+    app.ui.set_background(Background::default());
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(app.ui.async_state.error().is_some());
+    assert!(text.contains("worker stopped"));
+}
+
+#[test]
+fn held_keys_count_once_a_frame_and_chords_wait_for_second_key() {
+    let key = |c| KeyEvent::from(KeyCode::Char(c));
+    let start = Instant::now();
+    let mut input = InputState::default();
+
+    // A held `+` floods a frame with repeats
+    let inputs: Vec<Input> = (0..5).flat_map(|_| input.press(key('+'), start)).collect();
+    assert_eq!(inputs, [Input::Key(key('+'))]);
+    assert_eq!(input.tick(start), None);
+    assert_eq!(input.press(key('+'), start), [Input::Key(key('+'))]);
+
+    // `g` waits, then makes a chord with the next key
+    assert!(input.press(key('g'), start).is_empty());
+    assert_eq!(input.pending(), Some('g'));
+    assert_eq!(
+        input.press(key('g'), start),
+        [Input::Chord(Chord::FirstRow)]
+    );
+    input.tick(start);
+    assert!(input.press(key('g'), start).is_empty());
+    assert_eq!(input.press(key('G'), start), [Input::Chord(Chord::LastRow)]);
+
+    // Or counts on its own with a key that makes no chord with it
+    assert!(input.press(key('g'), start).is_empty());
+    assert_eq!(
+        input.press(key('t'), start),
+        [Input::Key(key('g')), Input::Key(key('t'))]
+    );
+
+    // Or once nothing followed it in time
+    input.tick(start);
+    assert!(input.press(key('g'), start).is_empty());
+    assert_eq!(input.tick(start), None);
+    assert_eq!(
+        input.tick(start + CHORD_TIMEOUT),
+        Some(Input::Key(key('g')))
+    );
+    assert_eq!(input.pending(), None);
+}
+
+#[test]
+fn palette_lookup_matches_palette() {
+    for (i, &c) in globe::PALETTE.iter().enumerate() {
+        assert_eq!(globe::palette_index(c), Some(i));
+    }
+    assert_eq!(globe::palette_index('+'), None);
+    assert_eq!(globe::palette_index('é'), None);
+}
+
+#[test]
+fn globe_widget_renders_without_application() {
+    let mut globe = Globe::new(1., 0., false);
+    globe.markers.push(Marker {
+        lat: 48.86,
+        lon: 2.29,
+        radius: 2.,
+        symbol: '#',
+    });
+    globe.look_at(48.86, 2.29, 1.5);
+    let area = Rect::new(0, 0, 60, 20);
+    let mut buffer = Buffer::empty(area);
+    let mut state = GlobeState::default();
+    GlobeWidget::new(&globe)
+        .block(Block::bordered())
+        .marker_style('#', Style::new().fg(Color::Red))
+        .render(area, &mut buffer, &mut state);
+
+    assert_eq!(state.area, Rect::new(1, 1, 58, 18));
+    assert_eq!(state.center, Some((29, 9)));
+    // The marker is right where the camera looks
+    let (x, y) = state.center.unwrap();
+    assert_eq!(buffer[(x, y)].symbol(), "#");
+    assert_eq!(buffer[(x, y)].fg, Color::Red);
+    // And the globe is round, 18 rows high and twice as wide
+    let text = to_text(&buffer);
+    let columns: Vec<usize> = text
+        .lines()
+        .skip(1)
+        .take(18)
+        .flat_map(|l| {
+            l.chars()
+                .enumerate()
+                .filter(|&(_, c)| c != ' ' && c != '│')
+                .map(|(i, _)| i)
+        })
+        .collect();
+    let width = columns.iter().max().unwrap() - columns.iter().min().unwrap() + 1;
+    assert!((32..=36).contains(&width), "{}", width);
+}
+
+#[test]
+fn starfield_surrounds_globe() {
+    let (mut app, _worker) = open("gps.jpg");
+    let drawn = |app: &mut Application| {
+        let text = to_text(&ui::render_to_buffer(app, WIDTH, HEIGHT).unwrap());
+        text.lines()
+            .skip_while(|l| !l.contains("Image Location"))
+            .skip(1)
+            .take_while(|l| !l.contains("Day,"))
+            .flat_map(|l| l.chars().filter(|&c| c != ' ' && c != '│'))
+            .count()
+    };
+    let globe = drawn(&mut app);
+    app.update(Message::ToggleStarfield);
+    assert_eq!(app.ui.status_msg, "Showing stars and atmosphere");
+    let with_stars = drawn(&mut app);
+    assert!(with_stars > globe);
+    app.update(Message::ToggleStarfield);
+    assert_eq!(drawn(&mut app), globe);
+}
+
+#[test]
+fn regional_texture_shows_when_zoomed_in() {
+    let (mut app, _worker) = open("gps.jpg");
+    // Around Paris, drawn in a character the earth texture doesn't use
+    app.ui.globe.regions = vec![Region::parse("paris", "51 46 -1 6\n####\n####").unwrap()];
+    let regional = |app: &mut Application| {
+        let text = to_text(&ui::render_to_buffer(app, WIDTH, HEIGHT).unwrap());
+        text.matches('#').count()
+    };
+    app.ui.globe.look_at(48.86, 2.29, 1.5);
+    assert_eq!(regional(&mut app), 0);
+    app.ui.globe.look_at(48.86, 2.29, 1.1);
+    assert!(regional(&mut app) > 0);
+
+    assert!(Region::parse("bad", "46 51 -1 6\n##").is_err());
+    assert!(Region::parse("bad", "51 46 -1\n##").is_err());
+    assert!(Region::parse("bad", "51 46 -1 6\n##\n#").is_err());
+}
+
+#[test]
+fn scene_preset_sets_settings_and_location_together() {
+    let (mut app, _worker) = open("nogps.jpg");
+    app.update(Message::ApplyScene);
+    let fields = &app.document.modified_fields;
+    let short = |tag| fields[&tag].field.value.get_uint(0).unwrap();
+    // Sunny landscapes are shot without flash, somewhere outdoors
+    assert_eq!(short(Tag::SceneCaptureType), 1);
+    assert_eq!(short(Tag::Flash) & 1, 0);
+    assert!(short(Tag::PhotographicSensitivity) <= 200);
+    assert!(app.document.has_gps);
+    assert!(utils::location(|tag| fields.get(&tag).map(|m| &m.field)).is_some());
+
+    app.update(Message::Undo);
+    assert!(!app.document.has_gps);
+    assert!(!app
+        .document
+        .modified_fields
+        .contains_key(&Tag::SceneCaptureType));
+}
+
+#[test]
+fn rotating_and_mirroring_cycle_orientation() {
+    let (mut app, _worker) = open("gps.jpg");
+    assert_eq!(app.document.orientation(), 1);
+    app.update(Message::Rotate);
+    assert_eq!(app.document.orientation(), 6);
+    app.update(Message::Mirror);
+    assert_eq!(app.document.orientation(), 7);
+    for _ in 0..3 {
+        app.update(Message::Rotate);
+    }
+    assert_eq!(app.document.orientation(), 2);
+
+    app.update(Message::Restore);
+    assert_eq!(app.document.orientation(), 1);
+    assert!(!app.document.modified_fields[&Tag::Orientation].changed);
+}
+
+#[test]
+fn editor_sets_named_value() {
+    let (mut app, _worker) = open("gps.jpg");
+    let row = order::EXIF_FIELDS_ORDERED
+        .iter()
+        .position(|&tag| tag == Tag::WhiteBalance)
+        .unwrap();
+    let white_balance = |app: &Application| {
+        app.document.modified_fields[&Tag::WhiteBalance]
+            .field
+            .value
+            .get_uint(0)
+    };
+    let original = white_balance(&app).unwrap();
+
+    app.update(Message::Edit(row));
+    assert!(app.ui.editor.is_some());
+    app.update(Message::NextChoice);
+    app.update(Message::ConfirmEdit);
+    assert!(app.ui.editor.is_none());
+    assert_eq!(white_balance(&app), Some(1 - original));
+    assert!(app.ui.status_msg.starts_with("Set WhiteBalance"));
+
+    app.update(Message::Undo);
+    assert_eq!(white_balance(&app), Some(original));
+    assert!(!app.document.modified_fields[&Tag::WhiteBalance].changed);
+}
+
+#[test]
+fn rational_editor_reads_fractions_and_decimals() {
+    let parse = |tag, text| {
+        let r = editor::parse_rational(tag, text).unwrap();
+        (r.num, r.denom)
+    };
+    assert_eq!(parse(Tag::ExposureTime, "1/250"), (1, 250));
+    assert_eq!(parse(Tag::ExposureTime, "0.004"), (1, 250));
+    assert_eq!(parse(Tag::ExposureTime, "2.5s"), (25, 10));
+    assert_eq!(parse(Tag::FNumber, "f/2.8"), (28, 10));
+    assert_eq!(parse(Tag::FocalLength, "35mm"), (35, 1));
+    assert!(editor::parse_rational(Tag::FNumber, "f/").is_err());
+    assert!(editor::parse_rational(Tag::ExposureTime, "1/0").is_err());
+
+    let (mut app, _worker) = open("gps.jpg");
+    let row = order::EXIF_FIELDS_ORDERED
+        .iter()
+        .position(|&tag| tag == Tag::ExposureTime)
+        .unwrap();
+    app.update(Message::Edit(row));
+    for _ in 0..10 {
+        app.update(Message::DeleteChar);
+    }
+    for c in "1/x".chars() {
+        app.update(Message::Type(c));
+    }
+    app.update(Message::ConfirmEdit);
+    // A typo keeps the editor open
+    assert!(app.ui.editor.is_some());
+    app.update(Message::DeleteChar);
+    for c in "60".chars() {
+        app.update(Message::Type(c));
+    }
+    app.update(Message::ConfirmEdit);
+    assert!(app.ui.editor.is_none());
+    let field = &app.document.modified_fields[&Tag::ExposureTime].field;
+    assert_eq!(field.display_value().to_string(), "1/60");
+}
+
+#[test]
+fn editor_types_any_field_as_its_type() {
+    let (mut app, _worker) = open("gps.jpg");
+    let row = |tag| {
+        order::EXIF_FIELDS_ORDERED
+            .iter()
+            .position(|&t| t == tag)
+            .unwrap()
+    };
+    let retype = |app: &mut Application, text: &str| {
+        while !app.ui.editor.as_ref().unwrap().text().is_empty() {
+            app.update(Message::DeleteChar);
+        }
+        for c in text.chars() {
+            app.update(Message::Type(c));
+        }
+        app.update(Message::ConfirmEdit);
+    };
+    let shown = |app: &Application, tag| {
+        app.document.modified_fields[&tag]
+            .field
+            .display_value()
+            .to_string()
+    };
+
+    app.update(Message::Edit(row(Tag::Artist)));
+    assert_eq!(app.ui.editor.as_ref().unwrap().text(), "Jane Doe");
+    retype(&mut app, "Jane Roe");
+    assert!(app.ui.editor.is_none());
+    assert_eq!(shown(&app, Tag::Artist), "\"Jane Roe\"");
+
+    // Dates are checked, and take dashes
+    app.update(Message::Edit(row(Tag::DateTimeOriginal)));
+    retype(&mut app, "2024:13:01 10:00:00");
+    assert!(app.ui.editor.is_some());
+    assert!(app.ui.status_msg.starts_with("Invalid DateTimeOriginal"));
+    retype(&mut app, "2024-05-31 18:30:00");
+    assert!(app.ui.editor.is_none());
+    assert_eq!(shown(&app, Tag::DateTimeOriginal), "2024-05-31 18:30:00");
+
+    // Fields holding several values take as many
+    app.update(Message::Edit(row(Tag::GPSLatitude)));
+    assert_eq!(app.ui.editor.as_ref().unwrap().text(), "48, 51, 24.12");
+    retype(&mut app, "41, 24");
+    assert!(app.ui.editor.is_some());
+    retype(&mut app, "41, 24, 30.5");
+    assert!(app.ui.editor.is_none());
+
+    app.update(Message::Edit(row(Tag::PhotographicSensitivity)));
+    retype(&mut app, "70000");
+    assert!(app.ui.editor.is_some());
+    retype(&mut app, "800");
+    assert_eq!(shown(&app, Tag::PhotographicSensitivity), "800");
+
+    app.update(Message::Edit(row(Tag::ExifVersion)));
+    assert!(app.ui.editor.is_none());
+    assert!(app.ui.status_msg.contains("can't be typed in"));
+}
+
+/// Adds the Compression of a thumbnail, which the fixture doesn't have
+fn add_compression(app: &mut Application) {
+    let compression = MetadataVal {
+        field: Field {
+            tag: Tag::Compression,
+            ifd_num: In::THUMBNAIL,
+            value: Value::Short(vec![6]),
+        },
+        changed: false,
+    };
+    app.document
+        .original_fields
+        .insert(Tag::Compression, compression.clone());
+    app.document
+        .modified_fields
+        .insert(Tag::Compression, compression);
+}
+
+#[test]
+fn saving_with_required_tag_cleared_asks_again() {
+    let (mut app, _worker) = open("gps.jpg");
+    add_compression(&mut app);
+    let row = order::EXIF_FIELDS_ORDERED
+        .iter()
+        .position(|&tag| tag == Tag::Compression)
+        .unwrap();
+    app.update(Message::Clear(row));
+    assert_eq!(app.document.changed_required(), vec![Tag::Compression]);
+
+    app.update(Message::Save);
+    assert!(app.save_job.is_none());
+    assert!(app.ui.status_msg.contains("press s again"));
+    // Only right after the warning
+    app.update(Message::ZoomIn);
+    app.update(Message::Save);
+    assert!(app.save_job.is_none());
+}
+
+#[test]
+fn internal_fields_are_collapsed_and_left_alone() {
+    let (mut app, _worker) = open("gps.jpg");
+    add_compression(&mut app);
+    // Tall enough for the whole table
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, 200).unwrap());
+    assert!(text.contains("press i to show them"));
+    assert!(!text.contains("Compression"));
+
+    app.update(Message::ClearAll);
+    app.update(Message::Answer(true));
+    assert!(app.document.changed_required().is_empty());
+    // Everything else is cleared, however far down the field order it is
+    for tag in [Tag::Artist, Tag::DateTime, Tag::GPSAltitude] {
+        let document = &app.document;
+        assert!(
+            document.original_fields.get(&tag) != document.modified_fields.get(&tag),
+            "{}",
+            tag
+        );
+    }
+
+    app.update(Message::ToggleInternal);
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, 200).unwrap());
+    assert!(text.contains("Compression"));
+    assert!(!text.contains("press i to show them"));
+}
+
+#[test]
+fn bulk_operations_ask_first() {
+    let (mut app, _worker) = open("gps.jpg");
+    let changed = |app: &Application| {
+        app.document
+            .modified_fields
+            .values()
+            .filter(|m| m.changed)
+            .count()
+    };
+
+    app.update(Message::ClearAll);
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("Clear every field?"));
+    assert_eq!(changed(&app), 0);
+    app.update(Message::Answer(false));
+    assert_eq!(app.ui.confirming, None);
+    assert_eq!(changed(&app), 0);
+
+    app.update(Message::RandomizeAll);
+    app.update(Message::Answer(true));
+    assert!(changed(&app) > 0);
+
+    // Unless the config says not to
+    let config = Config::from_toml("[confirm]\nbulk = false").unwrap();
+    app.ui.confirm_bulk = config.confirm.bulk.unwrap();
+    app.update(Message::Restore);
+    app.update(Message::ClearAll);
+    assert_eq!(app.ui.confirming, None);
+    assert_eq!(app.ui.status_msg, "Cleared All Metadata");
+}
+
+#[test]
+fn changed_field_shows_original_value() {
+    let (mut app, _worker) = open("gps.jpg");
+    app.update(Message::Clear(0));
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    let row = text
+        .lines()
+        .find(|l| l.contains("Manufacturer of image input"))
+        .unwrap();
+    assert!(row.contains("(Canon)"), "{}", row);
+
+    app.update(Message::Undo);
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(!text.contains("(Canon)"));
+}
+
+#[test]
+fn table_rows_are_built_again_only_after_a_change() {
+    let (mut app, _worker) = open("gps.jpg");
+    let version = app.document.modified_fields.version();
+    let rows = app.document.process_rows(WIDTH, false, "").len();
+    assert!(app.document.modified_fields.contains_key(&Tag::Make));
+    assert_eq!(app.document.modified_fields.version(), version);
+
+    // A clone holds the same fields, so it keeps the version until it changes
+    let mut copy = app.document.modified_fields.clone();
+    assert_eq!(copy.version(), version);
+    copy.remove(&Tag::Make);
+    assert_ne!(copy.version(), version);
+
+    assert_eq!(app.document.process_rows(WIDTH, false, "").len(), rows);
+
+    app.update(Message::ClearAll);
+    app.update(Message::Answer(true));
+    assert_ne!(app.document.modified_fields.version(), version);
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("(Canon)"));
+}
+
+#[test]
+fn session_summary_counts_unsaved_changes() {
+    let (mut app, _worker) = open("gps.jpg");
+    app.update(Message::ClearGps);
+    let summary = app.finish_session().to_string();
+    assert!(summary.starts_with("Inspected 1 image\nNo copies written\n"));
+    assert!(summary.contains("changed fields were not saved"));
+
+    app.update(Message::Restore);
+    assert!(!app.finish_session().to_string().contains("not saved"));
+}
+
+#[test]
+fn gps_info_converts_to_and_from_decimal() {
+    let (app, _worker) = open("gps.jpg");
+    let gps = app.document.gps_info;
+    assert_eq!(gps.as_decimal(), app.document.location());
+    assert!(gps.latitude() >= 0. && gps.longitude() >= 0.);
+    // The same degrees as the subcommands read, kept as f32
+    let fields = &app.document.modified_fields;
+    let (lat, lon) = utils::location(|tag| fields.get(&tag).map(|m| &m.field)).unwrap();
+    let (doc_lat, doc_lon) = app.document.location();
+    assert!((doc_lat - lat).abs() < 1e-5 && (doc_lon - lon).abs() < 1e-5);
+
+    let gps = GPSInfo::from_decimal(-33.8568, -70.5);
+    assert_eq!(gps.lat_direction(), Cardinal::South);
+    assert_eq!(gps.long_direction(), Cardinal::West);
+    let (lat, lon) = gps.as_decimal();
+    assert!((lat + 33.8568).abs() < 1e-4 && (lon + 70.5).abs() < 1e-4);
+    assert_eq!(gps.to_string(), "Location: 33°51'24\"S, 70°30'0\"W");
+
+    // Past the antimeridian and the poles
+    let (lat, lon) = GPSInfo::from_decimal(95., 190.).as_decimal();
+    assert_eq!((lat, lon), (90., -170.));
+
+    let json = serde_json::to_string(&gps).unwrap();
+    assert_eq!(serde_json::from_str::<GPSInfo>(&json).unwrap(), gps);
+
+    // Seconds that round up to a whole minute carry over, all the way to degrees
+    let dms = |degrees: f64| match utils::to_dms(degrees) {
+        Value::Rational(v) => v.iter().map(|r| (r.num, r.denom)).collect::<Vec<_>>(),
+        _ => unreachable!(),
+    };
+    assert_eq!(
+        dms(12. + 34. / 60. + 59.99996 / 3600.),
+        [(12, 1), (35, 1), (0, 10_000)]
+    );
+    assert_eq!(
+        dms(-(12. + 59. / 60. + 59.99996 / 3600.)),
+        [(13, 1), (0, 1), (0, 10_000)]
+    );
+    assert_eq!(dms(48.8567), [(48, 1), (51, 1), (241_200, 10_000)]);
+}
+
+#[test]
+fn location_is_worked_out_again_only_after_a_change() {
+    let (mut app, _worker) = open("gps.jpg");
+    assert!(!app.document.refresh_gps());
+    app.update(Message::ClearGps);
+    assert!(app.document.refresh_gps());
+    assert!(!app.document.has_gps);
+    assert!(!app.document.refresh_gps());
+}
+
+#[test]
+fn following_the_location_keeps_zoom_and_rotation_speed() {
+    let (mut app, _worker) = open("gps.jpg");
+    for _ in 0..10 {
+        app.update(Message::ZoomIn);
+    }
+    app.update(Message::FasterRotation);
+    let zoomed = ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap();
+
+    app.transform_coordinates();
+    assert_eq!(
+        ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap(),
+        zoomed
+    );
+    assert_eq!(app.ui.camera_settings.globe_rot_speed, 0.001);
+    assert!(app.ui.camera_on_location);
+    app.update(Message::Pan(Cardinal::East));
+    assert!(!app.ui.camera_on_location);
+}
+
+#[test]
+fn panning_sets_location_under_crosshair() {
+    let (mut app, _worker) = open("gps.jpg");
+    let location = |app: &Application| {
+        utils::location(|tag| app.document.modified_fields.get(&tag).map(|m| &m.field)).unwrap()
+    };
+    let (lat, lon) = location(&app);
+
+    // Panning starts from the image location, one step east of it
+    app.update(Message::Pan(Cardinal::East));
+    let (c_lat, c_lon) = app.ui.crosshair().unwrap();
+    assert!((c_lat as f64 - lat).abs() < 1e-4);
+    assert!(c_lon as f64 > lon && (c_lon as f64 - lon) < 5.);
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("Crosshair"));
+
+    app.update(Message::SetLocationHere);
+    let (new_lat, new_lon) = location(&app);
+    assert!((new_lat - c_lat as f64).abs() < 1e-4);
+    assert!((new_lon - c_lon as f64).abs() < 1e-4);
+    // What the marker and status show is read back from the fields just written,
+    // as the main loop does every frame
+    assert!(app.document.refresh_gps());
+    let (doc_lat, doc_lon) = app.document.location();
+    assert!((doc_lat - c_lat as f64).abs() < 1e-4);
+    assert!((doc_lon - c_lon as f64).abs() < 1e-4);
+
+    app.update(Message::Undo);
+    assert_eq!(location(&app), (lat, lon));
+}
+
+#[test]
+fn picking_adds_location_to_image_without_gps() {
+    let (mut app, _worker) = open("nogps.jpg");
+    app.update(Message::PickLocation);
+    assert!(app.ui.picking);
+    app.update(Message::Pan(Cardinal::North));
+    let (lat, lon) = app.ui.crosshair().unwrap();
+
+    app.update(Message::SetLocationHere);
+    assert!(!app.ui.picking);
+    assert!(app.document.has_gps);
+    let fields = &app.document.modified_fields;
+    let (new_lat, new_lon) = utils::location(|tag| fields.get(&tag).map(|m| &m.field)).unwrap();
+    assert!((new_lat - lat as f64).abs() < 1e-4);
+    assert!((new_lon - lon as f64).abs() < 1e-4);
+
+    // Undoing removes the fields that were added
+    app.update(Message::Undo);
+    assert!(!app.document.has_gps);
+    assert!(!app.document.modified_fields.contains_key(&Tag::GPSLatitude));
+}
+
+#[test]
+fn picking_on_map_moves_cursor() {
+    let (mut app, _worker) = open("gps.jpg");
+    let (lat, lon) = app.document.location();
+    app.ui.online = true;
+    app.update(Message::ToggleRenderState);
+    app.update(Message::ToggleRenderState);
+    assert!(app.map_request().is_some());
+    let (tx_map, _map_worker) = mpsc::channel();
+    app.ui
+        .set_map(image::DynamicImage::new_rgb8(256, 256), tx_map);
+
+    app.update(Message::PickLocation);
+    let (c_lat, c_lon) = app.ui.crosshair().unwrap();
+    assert!((c_lat as f64 - lat).abs() < 1e-4 && (c_lon as f64 - lon).abs() < 1e-4);
+    app.update(Message::Pan(Cardinal::East));
+    let (e_lat, e_lon) = app.ui.crosshair().unwrap();
+    assert_eq!(e_lat, c_lat);
+    assert!(e_lon > c_lon && e_lon - c_lon < 0.01);
+
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("Cursor"));
+}
+
+#[test]
+fn timeline_collapses_bursts() {
+    let dir = std::env::temp_dir().join(format!("bresson-burst-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let plain = fixture::generate(&Options {
+        no_exif: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let shoot = |name: &str, model: &[u8], time: &[u8], subsec: Option<&[u8]>| {
+        let ascii = |tag, text: &[u8]| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.to_vec()]),
+        };
+        let mut fields = vec![ascii(Tag::Model, model), ascii(Tag::DateTimeOriginal, time)];
+        fields.extend(subsec.map(|s| ascii(Tag::SubSecTimeOriginal, s)));
+        let tiff = bresson::writer::encode(&fields, None, false).unwrap();
+        std::fs::write(dir.join(name), jpeg::replace_exif(&plain, &tiff).unwrap()).unwrap();
+    };
+    shoot("a.jpg", b"R5", b"2023:06:14 18:22:31", Some(b"10"));
+    shoot("b.jpg", b"R5", b"2023:06:14 18:22:31", Some(b"35"));
+    shoot("c.jpg", b"R5", b"2023:06:14 18:22:32", Some(b"05"));
+    // Too late, another camera and no fraction of a second
+    shoot("d.jpg", b"R5", b"2023:06:14 18:22:40", Some(b"00"));
+    shoot("e.jpg", b"Z9", b"2023:06:14 18:22:40", Some(b"20"));
+    shoot("f.jpg", b"Z9", b"2023:06:14 18:22:41", None);
+
+    let mut timeline = Timeline::load(std::slice::from_ref(&dir)).unwrap();
+    let bucket = &timeline.buckets[0];
+    assert_eq!(bucket.images.len(), 6);
+    assert_eq!(bucket.bursts.len(), 1);
+    assert_eq!(bucket.bursts[0], 0..3);
+
+    let mut view = View::default();
+    assert_eq!(bucket.lines(&view.expanded).len(), 4);
+    view.move_line(&timeline, 0);
+    assert_eq!(view.selected(&timeline).unwrap().1, Entry::Burst(0..3));
+    view.toggle_burst(&timeline);
+    let bucket = &timeline.buckets[0];
+    assert_eq!(bucket.lines(&view.expanded).len(), 7);
+    let mut terminal = Terminal::new(TestBackend::new(WIDTH, HEIGHT)).unwrap();
+    terminal
+        .draw(|frame| ui::render_timeline(&timeline, &view, frame))
+        .unwrap();
+    assert!(to_text(terminal.backend().buffer()).contains("▾ Burst of 3"));
+
+    // Flagging the burst flags every frame
+    let (bucket, entry) = view.selected(&timeline).unwrap();
+    let paths = bucket.paths(&entry);
+    assert!(timeline
+        .cycle_flags(&paths)
+        .starts_with("Flagged 3 image(s)"));
+    assert!(paths
+        .iter()
+        .all(|p| timeline.flags.get(p) == Some(&Flag::Keep)));
+    assert!(!timeline.flags.contains_key(&dir.join("d.jpg")));
+}
+
+#[test]
+fn elevation_of_the_open_images_is_charted() {
+    let dir = std::env::temp_dir().join(format!("bresson-hike-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let plain = fixture::generate(&Options {
+        no_exif: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let shoot = |name: &str, time: &[u8], altitude: u32| {
+        let fields = [
+            Field {
+                tag: Tag::DateTimeOriginal,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![time.to_vec()]),
+            },
+            Field {
+                tag: Tag::GPSAltitude,
+                ifd_num: In::PRIMARY,
+                value: Value::Rational(vec![(altitude, 1).into()]),
+            },
+        ];
+        let tiff = bresson::writer::encode(&fields, None, false).unwrap();
+        std::fs::write(dir.join(name), jpeg::replace_exif(&plain, &tiff).unwrap()).unwrap();
+    };
+    shoot("a.jpg", b"2023:06:14 08:00:00", 1200);
+    shoot("b.jpg", b"2023:06:14 10:30:00", 2100);
+
+    let (mut app, _worker) = open("gps.jpg");
+    app.update(Message::ToggleElevation);
+    assert!(app.ui.elevation.is_none());
+    assert!(app.ui.status_msg.starts_with("Need at least two"));
+
+    app.images = vec![dir.join("a.jpg"), dir.join("b.jpg")];
+    app.update(Message::ToggleElevation);
+    assert_eq!(app.ui.elevation.as_ref().unwrap().samples.len(), 2);
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("Elevation Profile"));
+    assert!(text.contains("900 m up"));
+
+    app.update(Message::ToggleElevation);
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(!text.contains("Elevation Profile"));
+}
+
+#[test]
+fn composite_tags_are_described_and_audited() {
+    let plain = fixture::generate(&Options {
+        no_exif: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let fields = [
+        field(Tag::SceneType, Value::Undefined(vec![1], 0)),
+        field(Tag::CompositeImage, Value::Short(vec![2])),
+        field(
+            Tag::SourceImageNumberOfCompositeImage,
+            Value::Short(vec![9, 6]),
+        ),
+    ];
+    let tiff = bresson::writer::encode(&fields, None, false).unwrap();
+    let backend = backend::parse(&jpeg::replace_exif(&plain, &tiff).unwrap()).unwrap();
+    let shown = |tag| {
+        let field = backend.fields().into_iter().find(|f| f.tag == tag).unwrap();
+        backend.display(&field)
+    };
+    assert_eq!(shown(Tag::SceneType), "Directly photographed");
+    assert_eq!(shown(Tag::CompositeImage), "Composite of multiple frames");
+    assert_eq!(
+        shown(Tag::SourceImageNumberOfCompositeImage),
+        "9 frames, 6 used"
+    );
+    assert_eq!(utils::describe_composite(7), "Reserved (7)");
+
+    // They give away a phone's night or HDR mode, the scene type doesn't
+    let policy = Policy::audit_default();
+    assert_eq!(policy.audit(&fields[0]), None);
+    assert_eq!(policy.audit(&fields[1]), Some("must be cleared"));
+    assert_eq!(policy.audit(&fields[2]), Some("must be cleared"));
+}
+
+#[test]
+fn field_sizes_and_segment_budget() {
+    let (mut app, _worker) = open("gps.jpg");
+    let row = order::EXIF_FIELDS_ORDERED
+        .iter()
+        .position(|&t| t == Tag::Artist)
+        .unwrap();
+    // "Jane Doe" and its terminating NUL, after the 12 byte entry
+    let artist = &app.document.modified_fields[&Tag::Artist].field;
+    assert_eq!(bresson::writer::field_len(artist), 12 + 10);
+    let (len, limit) = app.document.segment_budget().unwrap();
+    assert!(len < 1024);
+    assert_eq!(limit, jpeg::MAX_SEGMENT_PAYLOAD);
+
+    app.update(Message::Edit(row));
+    for _ in 0..limit {
+        app.update(Message::Type('x'));
+    }
+    app.update(Message::ConfirmEdit);
+    let (len, _) = app.document.segment_budget().unwrap();
+    assert!(len > limit);
+    assert!(app
+        .ui
+        .status_msg
+        .contains("more than the 65533 a JPEG segment holds"));
+    assert!(app.document.save_state().is_err());
+
+    app.update(Message::Undo);
+    assert!(app.document.segment_budget().unwrap().0 < 1024);
+}
+
+#[test]
+fn switches_between_images_keeping_their_edits() {
+    let (mut app, _worker) = open("gps.jpg");
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    app.images = vec![
+        fixtures.join("gps.jpg"),
+        fixtures.join("corrupt/garbage.jpg"),
+        fixtures.join("nogps.jpg"),
+    ];
+    let gps_cleared = |app: &Application| {
+        app.document
+            .modified_fields
+            .get(&Tag::GPSLatitude)
+            .is_none_or(MetadataVal::is_cleared)
+    };
+    app.update(Message::ClearGps);
+    assert!(gps_cleared(&app));
+
+    let row = app.update(Message::SwitchImage {
+        forward: true,
+        row: Some(5),
+    });
+    assert_eq!(row, Some(0));
+    assert!(app.ui.status_msg.contains("(3 of 3)"));
+    assert!(app.ui.status_msg.contains("skipped 1"));
+    assert!(app.document.path_to_image.ends_with("nogps.jpg"));
+    assert!(!app.document.has_gps);
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("Image Metadata (3/3)"));
+
+    // Coming back finds the image as it was left
+    let row = app.update(Message::SwitchImage {
+        forward: true,
+        row: None,
+    });
+    assert_eq!(row, Some(5));
+    assert!(app.document.path_to_image.ends_with("gps.jpg"));
+    assert!(gps_cleared(&app));
+}
+
+#[test]
+fn text_beyond_ascii_is_shown_and_edited_as_written() {
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let utf8 = field(Tag::Artist, Value::Ascii(vec!["Zoë Müller".into()]));
+    let latin1 = field(Tag::Copyright, Value::Ascii(vec![b"\xA9 Caf\xE9".to_vec()]));
+    let xp = field(
+        utils::XP_TAGS[0],
+        Value::Byte(utils::encode_xp("Été à Zürich")),
+    );
+    let tiff = bresson::writer::encode([&utf8, &latin1, &xp], None, true).unwrap();
+    let plain = fixture::generate(&Options {
+        no_exif: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let backend = backend::parse(&jpeg::replace_exif(&plain, &tiff).unwrap()).unwrap();
+    let fields = backend.fields();
+    let shown = |tag| backend.display(fields.iter().find(|f| f.tag == tag).unwrap());
+    assert_eq!(shown(Tag::Artist), "Zoë Müller");
+    assert_eq!(shown(Tag::Copyright), "© Café");
+    assert_eq!(shown(utils::XP_TAGS[0]), "Été à Zürich");
+
+    // Untouched text is written back byte for byte, edits keep the encoding
+    for field in [&utf8, &latin1, &xp] {
+        let editor = editor::Editor::new(field).unwrap();
+        assert_eq!(
+            format!("{:?}", editor.value().unwrap()),
+            format!("{:?}", field.value)
+        );
+    }
+    let mut editor = editor::Editor::new(&latin1).unwrap();
+    editor.push('!');
+    let typed = |editor: &editor::Editor| match editor.value().unwrap() {
+        Value::Ascii(strings) => strings[0].clone(),
+        value => panic!("{:?} isn't ASCII", value),
+    };
+    assert_eq!(typed(&editor), b"\xA9 Caf\xE9!");
+    // Latin-1 has no place for everything, the editor says so instead of
+    // writing the field in another encoding
+    editor.push('€');
+    let e = editor.value().unwrap_err().to_string();
+    assert!(e.contains("Latin-1") && e.contains('€'), "{}", e);
+    editor.pop();
+    assert_eq!(typed(&editor), b"\xA9 Caf\xE9!");
+    editor.push('\u{7}');
+    assert!(editor.value().is_err());
+}
+
+#[test]
+fn windows_xp_tags_are_listed_and_edited() {
+    let field = |tag, value| Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value,
+    };
+    let make = field(Tag::Make, Value::Ascii(vec![b"Canon".to_vec()]));
+    let artist = field(Tag::Artist, Value::Ascii(vec![b"Jane Doe".to_vec()]));
+    let author = field(utils::XP_TAGS[2], Value::Byte(utils::encode_xp("Jane Doe")));
+    let tiff = bresson::writer::encode([&make, &artist, &author], None, true).unwrap();
+    let plain = fixture::generate(&Options {
+        no_exif: true,
+        ..Default::default()
+    })
+    .unwrap();
+    let path = std::env::temp_dir().join("bresson-ui-xp.jpg");
+    std::fs::write(&path, jpeg::replace_exif(&plain, &tiff).unwrap()).unwrap();
+    let (tx_worker, _worker) = mpsc::channel();
+    let mut app = Application::with_picker(
+        &path,
+        Globe::new(1., 0., false),
+        tx_worker,
+        Picker::new((8, 16)),
+    )
+    .unwrap();
+    assert_eq!(utils::tag_from_name("XPAuthor"), Some(utils::XP_TAGS[2]));
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("XPAuthor") && text.contains("Jane Doe"));
+
+    let index = order::EXIF_FIELDS_ORDERED
+        .iter()
+        .position(|t| *t == utils::XP_TAGS[2])
+        .unwrap();
+    let shown = |app: &Application| {
+        utils::display_text(&app.document.modified_fields[&utils::XP_TAGS[2]].field)
+    };
+    let status = app
+        .document
+        .set_value(utils::XP_TAGS[2], Value::Byte(utils::encode_xp("Zoë")));
+    assert_eq!(status, "Set XPAuthor to Zoë");
+    assert_eq!(app.document.clear_field(index, false), "Cleared XPAuthor");
+    assert_eq!(shown(&app), "");
+    assert_eq!(app.document.randomize(index, false), "Randomized XPAuthor");
+    assert!(!shown(&app).is_empty() && shown(&app) != "Jane Doe");
+
+    // Clearing everything reaches it, though the image has few fields
+    app.update(Message::Restore);
+    app.update(Message::ClearAll);
+    app.update(Message::Answer(true));
+    assert_eq!(shown(&app), "");
+    match &app.document.modified_fields[&Tag::Artist].field.value {
+        Value::Ascii(strings) => assert!(strings.iter().flatten().all(|&b| b == 0)),
+        value => panic!("{:?} isn't ASCII", value),
+    }
+
+    // Scrubbing owner names takes it along with Artist
+    assert!(processor::OWNER_TAGS.contains(&utils::XP_TAGS[2]));
+}
+
+#[test]
+fn search_filters_the_table() {
+    let (mut app, _worker) = open("gps.jpg");
+    // Rows leave out the fields the image doesn't have, F number is the fifth
+    let f_number = order::EXIF_FIELDS_ORDERED
+        .iter()
+        .position(|&t| t == Tag::FNumber);
+    assert_eq!(app.field_at(Some(4)), f_number);
+
+    app.update(Message::Search);
+    for c in "DATE".chars() {
+        assert_eq!(app.update(Message::Type(c)), Some(0));
+    }
+    assert!(app
+        .ui
+        .status_msg
+        .starts_with("/DATE - 3 of 19 fields match"));
+    app.update(Message::ConfirmSearch);
+    assert!(!app.ui.is_searching());
+    let text = to_text(&ui::render_to_buffer(&mut app, WIDTH, HEIGHT).unwrap());
+    assert!(text.contains("Image Metadata /DATE"));
+    assert!(text.contains("File change date and time"));
+    assert!(!text.contains("Canon"));
+
+    // Values match too, and the rows stand for the fields found
+    app.update(Message::Search);
+    for _ in 0..4 {
+        app.update(Message::DeleteChar);
+    }
+    for c in "jane".chars() {
+        app.update(Message::Type(c));
+    }
+    let artist = order::EXIF_FIELDS_ORDERED
+        .iter()
+        .position(|&t| t == Tag::Artist);
+    assert_eq!(app.row_count(), 1);
+    assert_eq!(app.field_at(Some(0)), artist);
+    assert_eq!(app.field_at(Some(1)), None);
+
+    assert_eq!(app.update(Message::ClearSearch { row: Some(0) }), Some(13));
+    assert_eq!(app.ui.query(), "");
+    assert_eq!(app.field_at(Some(13)), artist);
+}